@@ -0,0 +1,360 @@
+// Generated by dragon-yacc from calc.y. Do not edit by hand.
+
+use dragon::{
+    grammar::Terminal,
+    token::{State, Step},
+};
+
+/// Reasons the input could not be parsed.
+#[derive(Debug, PartialEq)]
+pub enum Error<'src, E> {
+    /// The lexer could not recognize a token.
+    Lex(E, &'src str),
+    /// A token does not stand for any terminal of the grammar.
+    UnknownTerminal(String, &'src str),
+    /// A token appeared where the grammar does not allow it, with the names of the terminals that
+    /// would have been allowed. The lexeme is empty at the end of the input.
+    Unexpected(&'src str, Vec<&'static str>),
+}
+
+impl<E: std::fmt::Debug> std::fmt::Display for Error<'_, E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Lex(e, s) => write!(f, "could not recognize a token at `{}` ({:?})", s, e),
+            Self::UnknownTerminal(name, s) => {
+                write!(f, "token `{}` ({}) is not a terminal of the grammar", s, name)
+            }
+            Self::Unexpected("", expected) => {
+                write!(f, "unexpected end of input, expected one of: {}", expected.join(" "))
+            }
+            Self::Unexpected(s, expected) => {
+                write!(f, "unexpected `{}`, expected one of: {}", s, expected.join(" "))
+            }
+        }
+    }
+}
+
+/// Parse a stream of tokens, whose names (as given by `dragon::grammar::Terminal`) are those of
+/// the terminals of the grammar.
+pub fn parse<'src, T, E, I>(tokens: I) -> Result<Vec<Result<i64, String>>, Error<'src, E>>
+where
+    T: dragon::grammar::Terminal,
+    I: IntoIterator<Item = dragon::token::TokenResult<'src, T, E>>,
+{
+    let mut tokens = tokens.into_iter();
+    let mut states = vec![0];
+    let mut values: Vec<Value> = Vec::new();
+    let (mut terminal, mut lexeme) = next_token(&mut tokens)?;
+
+    loop {
+        let top = *states.last().unwrap();
+
+        match ACTION[top][terminal] {
+            ACCEPT => return Ok(values.pop().unwrap().into_n0()),
+            0 => {
+                let expected = (0..TERMINALS.len())
+                    .filter(|&t| ACTION[top][t] != 0)
+                    .map(|t| TERMINALS[t])
+                    .collect();
+                return Err(Error::Unexpected(lexeme, expected));
+            }
+            action if action > 0 => {
+                states.push(action as usize - 1);
+                values.push(Value::Token(lexeme));
+                let next = next_token(&mut tokens)?;
+                terminal = next.0;
+                lexeme = next.1;
+            }
+            action => {
+                let production = (-action) as usize - 1;
+                let (lhs, len) = PRODUCTIONS[production];
+                states.truncate(states.len() - len);
+                let value = reduce(production, &mut values);
+                values.push(value);
+                let top = *states.last().unwrap();
+                states.push(GOTO[top][lhs] as usize);
+            }
+        }
+    }
+}
+
+fn next_token<'src, T, E>(
+    tokens: &mut impl Iterator<Item = dragon::token::TokenResult<'src, T, E>>,
+) -> Result<(usize, &'src str), Error<'src, E>>
+where
+    T: dragon::grammar::Terminal,
+{
+    match tokens.next() {
+        None => Ok((0, "")),
+        Some((Err(e), lexeme)) => Err(Error::Lex(e, lexeme)),
+        Some((Ok(token), lexeme)) => {
+            let name = token.name(lexeme);
+            match TERMINALS.iter().position(|&t| t == name) {
+                Some(terminal) => Ok((terminal, lexeme)),
+                None => Err(Error::UnknownTerminal(name.to_string(), lexeme)),
+            }
+        }
+    }
+}
+
+/// Lex and parse a string.
+pub fn parse_str<'src>(
+    src: &'src str,
+) -> Result<Vec<Result<i64, String>>, Error<'src, <CalcLexer as dragon::token::State>::Error>> {
+    parse(dragon::token::lex::<CalcLexer>(src))
+}
+
+const ACCEPT: i32 = i32::MAX;
+
+/// Names of the terminals, by index.
+pub static TERMINALS: [&str; 11] = [
+    "$",
+    "NUM",
+    "+",
+    "-",
+    "*",
+    "/",
+    "%",
+    "UMINUS",
+    ";",
+    "(",
+    ")",
+];
+
+static ACTION: [[i32; 11]; 20] = [
+    [-1, -1, 0, -1, 0, 0, 0, 0, 0, -1, 0],
+    [ACCEPT, 6, 0, 4, 0, 0, 0, 0, 0, 5, 0],
+    [0, 0, 8, 9, 10, 11, 12, 0, 7, 0, 0],
+    [0, 6, 0, 4, 0, 0, 0, 0, 0, 5, 0],
+    [0, 6, 0, 4, 0, 0, 0, 0, 0, 5, 0],
+    [0, 0, -10, -10, -10, -10, -10, 0, -10, 0, -10],
+    [-2, -2, 0, -2, 0, 0, 0, 0, 0, -2, 0],
+    [0, 6, 0, 4, 0, 0, 0, 0, 0, 5, 0],
+    [0, 6, 0, 4, 0, 0, 0, 0, 0, 5, 0],
+    [0, 6, 0, 4, 0, 0, 0, 0, 0, 5, 0],
+    [0, 6, 0, 4, 0, 0, 0, 0, 0, 5, 0],
+    [0, 6, 0, 4, 0, 0, 0, 0, 0, 5, 0],
+    [0, 0, -8, -8, -8, -8, -8, 0, -8, 0, -8],
+    [0, 0, 8, 9, 10, 11, 12, 0, 0, 0, 20],
+    [0, 0, -3, -3, 10, 11, 12, 0, -3, 0, -3],
+    [0, 0, -4, -4, 10, 11, 12, 0, -4, 0, -4],
+    [0, 0, -5, -5, -5, -5, -5, 0, -5, 0, -5],
+    [0, 0, -6, -6, -6, -6, -6, 0, -6, 0, -6],
+    [0, 0, -7, -7, -7, -7, -7, 0, -7, 0, -7],
+    [0, 0, -9, -9, -9, -9, -9, 0, -9, 0, -9],
+];
+
+static GOTO: [[i32; 2]; 20] = [
+    [1, -1],
+    [-1, 2],
+    [-1, -1],
+    [-1, 12],
+    [-1, 13],
+    [-1, -1],
+    [-1, -1],
+    [-1, 14],
+    [-1, 15],
+    [-1, 16],
+    [-1, 17],
+    [-1, 18],
+    [-1, -1],
+    [-1, -1],
+    [-1, -1],
+    [-1, -1],
+    [-1, -1],
+    [-1, -1],
+    [-1, -1],
+    [-1, -1],
+];
+
+/// The left side and length of each production.
+static PRODUCTIONS: [(usize, usize); 10] = [
+    (0, 0),
+    (0, 3),
+    (1, 3),
+    (1, 3),
+    (1, 3),
+    (1, 3),
+    (1, 3),
+    (1, 2),
+    (1, 3),
+    (1, 1),
+];
+
+enum Value<'src> {
+    Token(&'src str),
+    N0(Vec<Result<i64, String>>),
+    N1(Result<i64, String>),
+}
+
+#[allow(dead_code)]
+impl<'src> Value<'src> {
+    fn into_token(self) -> &'src str {
+        match self {
+            Value::Token(value) => value,
+            _ => unreachable!(),
+        }
+    }
+
+    fn into_n0(self) -> Vec<Result<i64, String>> {
+        match self {
+            Value::N0(value) => value,
+            _ => unreachable!(),
+        }
+    }
+
+    fn into_n1(self) -> Result<i64, String> {
+        match self {
+            Value::N1(value) => value,
+            _ => unreachable!(),
+        }
+    }
+
+}
+
+#[allow(unused_braces, clippy::all)]
+fn reduce<'src>(production: usize, values: &mut Vec<Value<'src>>) -> Value<'src> {
+    match production {
+        // lines -> ε
+        0 => {
+            Value::N0({ Vec::new() })
+        }
+        // lines -> lines expr ;
+        1 => {
+            let _3 = values.pop().unwrap().into_token();
+            let _2 = values.pop().unwrap().into_n1();
+            let _1 = values.pop().unwrap().into_n0();
+            Value::N0({ let mut lines = _1; lines.push(_2); lines })
+        }
+        // expr -> expr + expr
+        2 => {
+            let _3 = values.pop().unwrap().into_n1();
+            let _2 = values.pop().unwrap().into_token();
+            let _1 = values.pop().unwrap().into_n1();
+            Value::N1({ checked(_1, _3, i64::checked_add) })
+        }
+        // expr -> expr - expr
+        3 => {
+            let _3 = values.pop().unwrap().into_n1();
+            let _2 = values.pop().unwrap().into_token();
+            let _1 = values.pop().unwrap().into_n1();
+            Value::N1({ checked(_1, _3, i64::checked_sub) })
+        }
+        // expr -> expr * expr
+        4 => {
+            let _3 = values.pop().unwrap().into_n1();
+            let _2 = values.pop().unwrap().into_token();
+            let _1 = values.pop().unwrap().into_n1();
+            Value::N1({ checked(_1, _3, i64::checked_mul) })
+        }
+        // expr -> expr / expr
+        5 => {
+            let _3 = values.pop().unwrap().into_n1();
+            let _2 = values.pop().unwrap().into_token();
+            let _1 = values.pop().unwrap().into_n1();
+            Value::N1({ checked(_1, _3, i64::checked_div) })
+        }
+        // expr -> expr % expr
+        6 => {
+            let _3 = values.pop().unwrap().into_n1();
+            let _2 = values.pop().unwrap().into_token();
+            let _1 = values.pop().unwrap().into_n1();
+            Value::N1({ checked(_1, _3, i64::checked_rem) })
+        }
+        // expr -> - expr
+        7 => {
+            let _2 = values.pop().unwrap().into_n1();
+            let _1 = values.pop().unwrap().into_token();
+            Value::N1({ checked(Ok(0), _2, i64::checked_sub) })
+        }
+        // expr -> ( expr )
+        8 => {
+            let _3 = values.pop().unwrap().into_token();
+            let _2 = values.pop().unwrap().into_n1();
+            let _1 = values.pop().unwrap().into_token();
+            Value::N1({ _2 })
+        }
+        // expr -> NUM
+        9 => {
+            let _1 = values.pop().unwrap().into_token();
+            Value::N1({ _1.parse().map_err(|_| format!("`{}` is too large", _1)) })
+        }
+        _ => unreachable!(),
+    }
+}
+
+fn main() {
+    let mut src = String::new();
+    std::io::Read::read_to_string(&mut std::io::stdin(), &mut src).unwrap();
+
+    match parse_str(&src) {
+        Ok(values) => {
+            for value in values {
+                match value {
+                    Ok(n) => println!("{}", n),
+                    Err(e) => println!("error: {}", e),
+                }
+            }
+        }
+        Err(e) => eprintln!("error: {}", e),
+    }
+}
+
+/// Apply an operator that returns `None` on overflow or division by zero.
+fn checked(
+    a: Result<i64, String>,
+    b: Result<i64, String>,
+    op: fn(i64, i64) -> Option<i64>,
+) -> Result<i64, String> {
+    let (a, b) = (a?, b?);
+    op(a, b).ok_or_else(|| match b {
+        0 => "division by zero".to_string(),
+        _ => "overflow".to_string(),
+    })
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum CalcToken {
+    Num,
+    Punct,
+}
+
+impl Terminal for CalcToken {
+    fn name<'a>(&'a self, lexeme: &'a str) -> &'a str {
+        match self {
+            Self::Num => "NUM",
+            Self::Punct => lexeme,
+        }
+    }
+}
+
+#[derive(Default)]
+pub enum CalcLexer {
+    #[default]
+    Start,
+    Num,
+}
+
+impl State for CalcLexer {
+    type Token = CalcToken;
+    type Error = char;
+
+    fn handle_char(&self, c: char) -> Step<Self> {
+        match (self, c) {
+            (_, '0'..='9') => Step::Continue(Some(Self::Num)),
+            (Self::Num, _) => Step::Finish(CalcToken::Num, false),
+            (_, c) if c.is_whitespace() => Step::Discard,
+            (_, '+') | (_, '-') | (_, '*') | (_, '/') | (_, '%') | (_, '(') | (_, ')') | (_, ';') => {
+                Step::Finish(CalcToken::Punct, true)
+            }
+            (_, c) => Step::Abort(c),
+        }
+    }
+
+    fn try_finish(&self) -> Option<CalcToken> {
+        match self {
+            Self::Num => Some(CalcToken::Num),
+            Self::Start => None,
+        }
+    }
+}
@@ -0,0 +1,117 @@
+/*
+ * A calculator for integer expressions, one per line, separated by semicolons.
+ *
+ * Regenerate `calc.rs` with `cargo run --bin dragon-yacc examples/calc.y`, then run it with
+ * `echo '1 + 2 * -3; (1 + 2) * 3;' | cargo run --example calc`.
+ */
+
+%{
+use dragon::{
+    grammar::Terminal,
+    token::{State, Step},
+};
+%}
+
+%lexer CalcLexer
+
+%token NUM
+%left '+' '-'
+%left '*' '/' '%'
+%right UMINUS
+
+%type <Vec<Result<i64, String>>> lines
+%type <Result<i64, String>> expr
+
+%%
+
+lines : /* empty */             { Vec::new() }
+      | lines expr ';'          { let mut lines = $1; lines.push($2); lines }
+      ;
+
+expr  : expr '+' expr           { checked($1, $3, i64::checked_add) }
+      | expr '-' expr           { checked($1, $3, i64::checked_sub) }
+      | expr '*' expr           { checked($1, $3, i64::checked_mul) }
+      | expr '/' expr           { checked($1, $3, i64::checked_div) }
+      | expr '%' expr           { checked($1, $3, i64::checked_rem) }
+      | '-' expr %prec UMINUS   { checked(Ok(0), $2, i64::checked_sub) }
+      | '(' expr ')'            { $2 }
+      | NUM                     { $1.parse().map_err(|_| format!("`{}` is too large", $1)) }
+      ;
+
+%%
+
+fn main() {
+    let mut src = String::new();
+    std::io::Read::read_to_string(&mut std::io::stdin(), &mut src).unwrap();
+
+    match parse_str(&src) {
+        Ok(values) => {
+            for value in values {
+                match value {
+                    Ok(n) => println!("{}", n),
+                    Err(e) => println!("error: {}", e),
+                }
+            }
+        }
+        Err(e) => eprintln!("error: {}", e),
+    }
+}
+
+/// Apply an operator that returns `None` on overflow or division by zero.
+fn checked(
+    a: Result<i64, String>,
+    b: Result<i64, String>,
+    op: fn(i64, i64) -> Option<i64>,
+) -> Result<i64, String> {
+    let (a, b) = (a?, b?);
+    op(a, b).ok_or_else(|| match b {
+        0 => "division by zero".to_string(),
+        _ => "overflow".to_string(),
+    })
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum CalcToken {
+    Num,
+    Punct,
+}
+
+impl Terminal for CalcToken {
+    fn name<'a>(&'a self, lexeme: &'a str) -> &'a str {
+        match self {
+            Self::Num => "NUM",
+            Self::Punct => lexeme,
+        }
+    }
+}
+
+#[derive(Default)]
+pub enum CalcLexer {
+    #[default]
+    Start,
+    Num,
+}
+
+impl State for CalcLexer {
+    type Token = CalcToken;
+    type Error = char;
+
+    fn handle_char(&self, c: char) -> Step<Self> {
+        match (self, c) {
+            (_, '0'..='9') => Step::Continue(Some(Self::Num)),
+            (Self::Num, _) => Step::Finish(CalcToken::Num, false),
+            (_, c) if c.is_whitespace() => Step::Discard,
+            (_, '+') | (_, '-') | (_, '*') | (_, '/') | (_, '%') | (_, '(') | (_, ')') | (_, ';') => {
+                Step::Finish(CalcToken::Punct, true)
+            }
+            (_, c) => Step::Abort(c),
+        }
+    }
+
+    fn try_finish(&self) -> Option<CalcToken> {
+        match self {
+            Self::Num => Some(CalcToken::Num),
+            Self::Start => None,
+        }
+    }
+}
@@ -0,0 +1,12 @@
+int a[100]; int i; int j; int n; int s;
+n := 10;
+i := 0;
+while (i < n) {
+    j := 0;
+    while (j < n) { a[i * n + j] := i + j; j := j + 1; }
+    i := i + 1;
+}
+s := 0;
+i := 0;
+while (i < 100) { s := s + a[i]; i := i + 1; }
+s;
@@ -0,0 +1,7 @@
+int a[10]; int i; int j; int s; int x; int y;
+x := 2; y := 3;
+i := 0;
+while (i < 10) { a[i] := i; i := i + 1; }
+j := 0; s := 0;
+while (j < 10) { s := s + a[j] * (x * y); j := j + 1; }
+s;
@@ -0,0 +1,900 @@
+//! Syntax-directed definitions: attribute grammars evaluated over parse trees.
+//!
+//! A syntax-directed definition attaches *attributes* to the symbols of a grammar, and a set of
+//! *semantic rules* to each production that compute some attributes of its symbols from others.
+//! An attribute is *synthesized* when the rules for it belong to the productions for its
+//! nonterminal, so that it is computed from the children of a node, and *inherited* when the rules
+//! for it belong to the productions that have its nonterminal on their right side, so that it is
+//! computed from the parent and siblings of a node. Terminals only have *lexical* attributes,
+//! computed from their lexemes.
+//!
+//! Each node of a parse tree has an instance of every attribute of its symbol. The rules of the
+//! productions applied in the tree link the instances into a *dependency graph*; [`evaluate`]
+//! computes the instances in a topological order of the graph, and reports a cycle if there is
+//! none.
+//!
+//! The order is known in advance for two classes of definitions, which can then be evaluated
+//! while parsing, without building the tree:
+//!
+//! - a definition is *S-attributed* when it has only synthesized attributes, computed from the
+//!   children of the node. [`evaluate_lr`] computes them with a bottom-up parser, keeping the
+//!   attributes of each symbol on the stack next to its state;
+//! - a definition is *L-attributed* when, in addition, the inherited attributes of a symbol are
+//!   computed only from the inherited attributes of the parent and from the symbols to its left.
+//!   [`evaluate_ll`] computes them with a predictive parser, in the order in which it visits
+//!   the nodes.
+//!
+//! [`evaluate`]: ./struct.Definition.html#method.evaluate
+//! [`evaluate_lr`]: ./struct.Definition.html#method.evaluate_lr
+//! [`evaluate_ll`]: ./struct.Definition.html#method.evaluate_ll
+//!
+//! ## Example
+//!
+//! Products such as `3 * 5 * 7`, with a grammar that has no left recursion. The value of the
+//! left operand is passed down the tree as the inherited attribute `inh` of `T'`, and the value
+//! of the whole product comes back up as its synthesized attribute `syn`.
+//!
+//! ```
+//! # use dragon::{attr::*, grammar::*, parse::{ll, lr}, token::*};
+//! # #[derive(Default)]
+//! # struct Lex(bool);
+//! # #[derive(Clone, Copy)]
+//! # enum Tok { Num, Punct }
+//! # impl Terminal for Tok {
+//! #     fn name<'a>(&'a self, lexeme: &'a str) -> &'a str {
+//! #         match self { Self::Num => "num", Self::Punct => lexeme }
+//! #     }
+//! # }
+//! # impl State for Lex {
+//! #     type Token = Tok;
+//! #     type Error = char;
+//! #     fn handle_char(&self, c: char) -> Step<Self> {
+//! #         match (self.0, c) {
+//! #             (_, '0'..='9') => Step::Continue(Some(Self(true))),
+//! #             (true, _) => Step::Finish(Tok::Num, false),
+//! #             (_, c) if c.is_whitespace() => Step::Discard,
+//! #             (_, _) => Step::Finish(Tok::Punct, true),
+//! #         }
+//! #     }
+//! #     fn try_finish(&self) -> Option<Tok> { if self.0 { Some(Tok::Num) } else { None } }
+//! # }
+//! let grammar: Grammar = "
+//!     T  -> F T'
+//!     T' -> * F T' | ε
+//!     F  -> num
+//! "
+//! .parse()
+//! .unwrap();
+//!
+//! let definition = Definition::new(&grammar)
+//!     .synthesized("T", "val")
+//!     .synthesized("F", "val")
+//!     .inherited("T'", "inh")
+//!     .synthesized("T'", "syn")
+//!     .lexical("num", "lexval", |s| s.parse::<i64>().unwrap())
+//!     // T -> F T'
+//!     .rule(0, (2, "inh"), &[(1, "val")], |v| v[0])
+//!     .rule(0, (0, "val"), &[(2, "syn")], |v| v[0])
+//!     // T' -> * F T'
+//!     .rule(1, (3, "inh"), &[(0, "inh"), (2, "val")], |v| v[0] * v[1])
+//!     .rule(1, (0, "syn"), &[(3, "syn")], |v| v[0])
+//!     // T' -> ε
+//!     .rule(2, (0, "syn"), &[(0, "inh")], |v| v[0])
+//!     // F -> num
+//!     .rule(3, (0, "val"), &[(1, "lexval")], |v| v[0]);
+//!
+//! assert!(definition.is_l_attributed());
+//! assert!(!definition.is_s_attributed());
+//!
+//! let table = ll::Table::new(&grammar).unwrap();
+//! let tree = table.parse(lex::<Lex>("3 * 5 * 7")).unwrap();
+//! let annotated = definition.evaluate(&tree).unwrap();
+//! assert_eq!(annotated.get("val"), Some(&105));
+//! assert_eq!(annotated.children[1].get("inh"), Some(&3));
+//!
+//! // The same values, computed while parsing.
+//! let values = definition.evaluate_ll(&table, lex::<Lex>("3 * 5 * 7")).unwrap();
+//! assert_eq!(values["val"], 105);
+//!
+//! // A definition for a left-recursive grammar, with synthesized attributes only.
+//! let grammar: Grammar = "E -> E + num | num".parse().unwrap();
+//! let definition = Definition::new(&grammar)
+//!     .synthesized("E", "val")
+//!     .lexical("num", "lexval", |s| s.parse::<i64>().unwrap())
+//!     .rule(0, (0, "val"), &[(1, "val"), (3, "lexval")], |v| v[0] + v[1])
+//!     .rule(1, (0, "val"), &[(1, "lexval")], |v| v[0]);
+//! assert!(definition.is_s_attributed());
+//!
+//! let table = lr::Table::new(&grammar, lr::Method::Lalr);
+//! let values = definition.evaluate_lr(&table, lex::<Lex>("1 + 2 + 3")).unwrap();
+//! assert_eq!(values["val"], 6);
+//!
+//! // Attributes that depend on each other cannot be evaluated.
+//! let grammar: Grammar = "S -> A \n A -> num".parse().unwrap();
+//! let definition = Definition::new(&grammar)
+//!     .inherited("A", "i")
+//!     .synthesized("A", "s")
+//!     .rule(0, (1, "i"), &[(1, "s")], |v| v[0])
+//!     .rule(1, (0, "s"), &[(0, "i")], |v: &[i64]| v[0]);
+//! assert!(!definition.is_l_attributed());
+//!
+//! let tree = ll::Table::new(&grammar).unwrap().parse(lex::<Lex>("1")).unwrap();
+//! let error = definition.evaluate(&tree).unwrap_err();
+//! assert_eq!(error.message(&grammar), "circular dependency: A.s -> A.i -> A.s");
+//! ```
+
+use {
+    crate::{
+        grammar::{Grammar, Symbol, Terminal},
+        parse::{self, ll, lr, Input, Token, Tree},
+        token::TokenResult,
+    },
+    std::{
+        collections::{BTreeMap, BTreeSet, HashMap},
+        convert::Infallible,
+        fmt, mem,
+    },
+};
+
+/// The values of the attributes of a node, by name.
+pub type Values<V> = BTreeMap<String, V>;
+
+type Function<V> = Box<dyn Fn(&[V]) -> V>;
+
+type LexicalFunction<V> = Box<dyn Fn(&str) -> V>;
+
+/// Whether an attribute is computed at the productions for its symbol, or at the productions that
+/// use it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    /// Computed from the children of a node, by the rules of its production. The attributes of
+    /// terminals, computed from their lexemes, are synthesized.
+    Synthesized,
+    /// Computed from the parent and siblings of a node, by the rules of its parent's production.
+    Inherited,
+}
+
+struct Attribute<V> {
+    symbol: Symbol,
+    name: String,
+    kind: Kind,
+    lexical: Option<LexicalFunction<V>>,
+}
+
+/// A semantic rule, with attributes identified by the position of their symbol in the production
+/// (0 for the left side) and their index in the definition.
+struct Rule<V> {
+    target: (usize, usize),
+    args: Vec<(usize, usize)>,
+    function: Function<V>,
+}
+
+/// A syntax-directed definition: attributes for the symbols of a grammar, and the semantic rules
+/// of each production.
+pub struct Definition<V> {
+    grammar: Grammar,
+    attributes: Vec<Attribute<V>>,
+    rules: Vec<Vec<Rule<V>>>,
+}
+
+impl<V: Clone> Definition<V> {
+    /// Start a definition with no attributes.
+    pub fn new(grammar: &Grammar) -> Self {
+        Self {
+            grammar: grammar.clone(),
+            attributes: Vec::new(),
+            rules: grammar.productions().iter().map(|_| Vec::new()).collect(),
+        }
+    }
+
+    /// The grammar this definition is for.
+    pub fn grammar(&self) -> &Grammar {
+        &self.grammar
+    }
+
+    /// Declare a synthesized attribute of a nonterminal.
+    ///
+    /// # Panics
+    ///
+    /// If `nonterminal` is not a nonterminal of the grammar, or already has an attribute `name`.
+    pub fn synthesized(self, nonterminal: &str, name: &str) -> Self {
+        self.declare(nonterminal, name, Kind::Synthesized)
+    }
+
+    /// Declare an inherited attribute of a nonterminal.
+    ///
+    /// # Panics
+    ///
+    /// If `nonterminal` is not a nonterminal of the grammar, or already has an attribute `name`.
+    pub fn inherited(self, nonterminal: &str, name: &str) -> Self {
+        self.declare(nonterminal, name, Kind::Inherited)
+    }
+
+    /// Declare an attribute of a terminal, computed from the lexeme of each token.
+    ///
+    /// # Panics
+    ///
+    /// If `terminal` is not a terminal of the grammar, or already has an attribute `name`.
+    pub fn lexical(mut self, terminal: &str, name: &str, f: impl Fn(&str) -> V + 'static) -> Self {
+        let symbol = match self.grammar.terminal(terminal) {
+            Some(t) => Symbol::Terminal(t),
+            None => panic!("`{}` is not a terminal of the grammar", terminal),
+        };
+        self.push(symbol, name, Kind::Synthesized, Some(Box::new(f)));
+        self
+    }
+
+    /// Add a semantic rule to a production, computing the attribute `target` from the attributes
+    /// `args`, which are passed to `f` in order. Each attribute is given as the position of its
+    /// symbol in the production, with 0 for the left side and 1 for the first symbol on the right
+    /// side, and its name.
+    ///
+    /// # Panics
+    ///
+    /// If the production does not exist, if a position is past the end of the production or its
+    /// symbol has no attribute of that name, or if the target is not a synthesized attribute of
+    /// the left side or an inherited attribute of a symbol on the right side.
+    pub fn rule(
+        mut self,
+        production: usize,
+        target: (usize, &str),
+        args: &[(usize, &str)],
+        f: impl Fn(&[V]) -> V + 'static,
+    ) -> Self {
+        let target = (target.0, self.find(production, target));
+        let expected = if target.0 == 0 {
+            Kind::Synthesized
+        } else {
+            Kind::Inherited
+        };
+        let attribute = &self.attributes[target.1];
+        if attribute.kind != expected || attribute.lexical.is_some() {
+            panic!(
+                "a rule of `{}` cannot define `{}`",
+                self.grammar.show_production(production),
+                self.describe(target.1),
+            );
+        }
+
+        let args = args
+            .iter()
+            .map(|&arg| (arg.0, self.find(production, arg)))
+            .collect();
+        self.rules[production].push(Rule {
+            target,
+            args,
+            function: Box::new(f),
+        });
+        self
+    }
+
+    /// Whether every attribute is synthesized, and computed only from the attributes of the
+    /// children of a node.
+    pub fn is_s_attributed(&self) -> bool {
+        self.rules
+            .iter()
+            .flatten()
+            .all(|rule| rule.target.0 == 0 && rule.args.iter().all(|&(pos, _)| pos > 0))
+    }
+
+    /// Whether every synthesized attribute is computed from the inherited attributes of the node
+    /// and the attributes of its children, and every inherited attribute from the inherited
+    /// attributes of the parent and the attributes of the siblings to its left.
+    pub fn is_l_attributed(&self) -> bool {
+        self.rules.iter().flatten().all(|rule| {
+            rule.args.iter().all(|&(pos, attribute)| {
+                if pos == 0 {
+                    self.attributes[attribute].kind == Kind::Inherited
+                } else {
+                    rule.target.0 == 0 || pos < rule.target.0
+                }
+            })
+        })
+    }
+
+    /// Build the dependency graph of the attribute instances in a parse tree.
+    pub fn dependencies(&self, tree: &Tree<'_>) -> Dependencies {
+        self.graph(tree).1
+    }
+
+    /// Compute the attributes of every node of a parse tree, in a topological order of their
+    /// dependency graph.
+    ///
+    /// Attributes with no rule to compute them are left out of the result, unless a rule needs
+    /// them.
+    pub fn evaluate(&self, tree: &Tree<'_>) -> Result<Annotated<V>, Error<'static, Infallible>> {
+        let (nodes, dependencies, definers) = self.graph(tree);
+        let order = dependencies.order().map_err(|cycle| {
+            Error::Cycle(
+                cycle
+                    .into_iter()
+                    .map(|i| dependencies.instances[i].1.clone())
+                    .collect(),
+            )
+        })?;
+
+        let mut values: Vec<Option<V>> = vec![None; definers.len()];
+        for instance in order {
+            values[instance] = match definers[instance] {
+                Definer::Lexical(attribute, node) => {
+                    let lexeme = nodes[node].lexeme;
+                    Some((self.attributes[attribute].lexical.as_ref().unwrap())(
+                        lexeme,
+                    ))
+                }
+                Definer::Rule(node, production, rule) => {
+                    let rule = &self.rules[production][rule];
+                    let args = rule
+                        .args
+                        .iter()
+                        .map(|&(pos, attribute)| {
+                            let i = nodes[node].instance(pos, attribute, &nodes);
+                            values[i].clone().ok_or_else(|| {
+                                Error::Undefined(dependencies.instances[i].1.clone())
+                            })
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+                    Some((rule.function)(&args))
+                }
+                Definer::None => None,
+            };
+        }
+
+        Ok(self.annotate(&nodes, 0, &mut values))
+    }
+
+    /// Parse a stream of tokens bottom-up, computing the attributes of each node when it is
+    /// reduced, and return the attributes of the root.
+    ///
+    /// The table must be built from the grammar of this definition.
+    ///
+    /// # Panics
+    ///
+    /// If the definition is not S-attributed.
+    pub fn evaluate_lr<'src, T, E, I>(
+        &self,
+        table: &lr::Table,
+        tokens: I,
+    ) -> Result<Values<V>, Error<'src, E>>
+    where
+        T: Terminal,
+        I: IntoIterator<Item = TokenResult<'src, T, E>>,
+    {
+        assert!(self.is_s_attributed(), "the definition is not S-attributed");
+
+        let mut input = Input::new(table.grammar(), tokens.into_iter());
+        let mut states = vec![0];
+        let mut stack: Vec<BTreeMap<usize, V>> = Vec::new();
+        let mut lookahead = input.next_token().map_err(Error::Parse)?;
+
+        loop {
+            let top = *states.last().unwrap();
+
+            match table.action(top, lookahead.terminal) {
+                Some(lr::Action::Shift(state)) => {
+                    states.push(state);
+                    stack.push(self.lexical_values(lookahead));
+                    lookahead = input.next_token().map_err(Error::Parse)?;
+                }
+                Some(lr::Action::Reduce(production)) => {
+                    let production_ref = &self.grammar.productions()[production];
+                    let len = production_ref.rhs.len();
+
+                    states.truncate(states.len() - len);
+                    let mut frame = vec![BTreeMap::new()];
+                    frame.extend(stack.drain(stack.len() - len..));
+                    self.apply(production, 0, &mut frame)?;
+
+                    let top = *states.last().unwrap();
+                    states.push(table.goto(top, production_ref.lhs).unwrap());
+                    stack.push(frame.swap_remove(0));
+                }
+                Some(lr::Action::Accept) => return Ok(self.named(stack.pop().unwrap())),
+                None => {
+                    return Err(Error::Parse(parse::Error::Unexpected(
+                        lookahead,
+                        table.expected(top),
+                    )))
+                }
+            }
+        }
+    }
+
+    /// Parse a stream of tokens top-down, computing the inherited attributes of each node before
+    /// it is expanded and its synthesized attributes once its children are done, and return the
+    /// attributes of the root.
+    ///
+    /// The table must be built from the grammar of this definition. The attributes of a node are
+    /// dropped once its parent is done with them.
+    ///
+    /// # Panics
+    ///
+    /// If the definition is not L-attributed.
+    pub fn evaluate_ll<'src, T, E, I>(
+        &self,
+        table: &ll::Table,
+        tokens: I,
+    ) -> Result<Values<V>, Error<'src, E>>
+    where
+        T: Terminal,
+        I: IntoIterator<Item = TokenResult<'src, T, E>>,
+    {
+        assert!(self.is_l_attributed(), "the definition is not L-attributed");
+
+        let mut input = Input::new(table.grammar(), tokens.into_iter());
+        let mut records = vec![Record {
+            symbol: Symbol::Nonterminal(self.grammar.start()),
+            parent: None,
+            production: None,
+            children: Vec::new(),
+            values: BTreeMap::new(),
+        }];
+        let mut stack = vec![Step::Visit(0)];
+        let mut lookahead = input.next_token().map_err(Error::Parse)?;
+
+        while let Some(step) = stack.pop() {
+            match step {
+                Step::Visit(record) => {
+                    if let Some((parent, position)) = records[record].parent {
+                        let production = records[parent].production.unwrap();
+                        self.apply_at(&mut records, parent, production, position)?;
+                    }
+
+                    match records[record].symbol {
+                        Symbol::Terminal(t) if t == lookahead.terminal => {
+                            records[record].values = self.lexical_values(lookahead);
+                            lookahead = input.next_token().map_err(Error::Parse)?;
+                        }
+                        Symbol::Terminal(t) => {
+                            return Err(Error::Parse(parse::Error::Unexpected(lookahead, vec![t])))
+                        }
+                        Symbol::Nonterminal(n) => {
+                            let production = match table.entry(n, lookahead.terminal) {
+                                Some(production) => production,
+                                None => {
+                                    let expected = (0..self.grammar.terminals().len())
+                                        .filter(|&t| table.entry(n, t).is_some())
+                                        .collect();
+                                    return Err(Error::Parse(parse::Error::Unexpected(
+                                        lookahead, expected,
+                                    )));
+                                }
+                            };
+
+                            let rhs = &self.grammar.productions()[production].rhs;
+                            let first = records.len();
+                            records.extend(rhs.iter().enumerate().map(|(i, &symbol)| Record {
+                                symbol,
+                                parent: Some((record, i + 1)),
+                                production: None,
+                                children: Vec::new(),
+                                values: BTreeMap::new(),
+                            }));
+                            records[record].production = Some(production);
+                            records[record].children = (first..records.len()).collect();
+
+                            stack.push(Step::Finish(record));
+                            stack.extend((first..records.len()).rev().map(Step::Visit));
+                        }
+                    }
+                }
+                Step::Finish(record) => {
+                    if let Some(production) = records[record].production {
+                        self.apply_at(&mut records, record, production, 0)?;
+                        for child in mem::take(&mut records[record].children) {
+                            records[child].values.clear();
+                        }
+                    }
+                }
+            }
+        }
+
+        if lookahead.terminal != Grammar::END {
+            return Err(Error::Parse(parse::Error::Unexpected(
+                lookahead,
+                vec![Grammar::END],
+            )));
+        }
+
+        Ok(self.named(mem::take(&mut records[0].values)))
+    }
+
+    fn declare(mut self, nonterminal: &str, name: &str, kind: Kind) -> Self {
+        let symbol = match self.grammar.nonterminal(nonterminal) {
+            Some(n) => Symbol::Nonterminal(n),
+            None => panic!("`{}` is not a nonterminal of the grammar", nonterminal),
+        };
+        self.push(symbol, name, kind, None);
+        self
+    }
+
+    fn push(
+        &mut self,
+        symbol: Symbol,
+        name: &str,
+        kind: Kind,
+        lexical: Option<LexicalFunction<V>>,
+    ) {
+        if self.lookup(symbol, name).is_some() {
+            panic!(
+                "`{}` already has an attribute `{}`",
+                self.grammar.name(symbol),
+                name
+            );
+        }
+
+        self.attributes.push(Attribute {
+            symbol,
+            name: name.to_string(),
+            kind,
+            lexical,
+        });
+    }
+
+    fn lookup(&self, symbol: Symbol, name: &str) -> Option<usize> {
+        self.attributes
+            .iter()
+            .position(|a| a.symbol == symbol && a.name == name)
+    }
+
+    /// The attribute named at a position of a production.
+    fn find(&self, production: usize, (position, name): (usize, &str)) -> usize {
+        let production_ref = &self.grammar.productions()[production];
+        let symbol = if position == 0 {
+            Symbol::Nonterminal(production_ref.lhs)
+        } else {
+            match production_ref.rhs.get(position - 1) {
+                Some(&symbol) => symbol,
+                None => panic!(
+                    "`{}` has no symbol at position {}",
+                    self.grammar.show_production(production),
+                    position
+                ),
+            }
+        };
+
+        match self.lookup(symbol, name) {
+            Some(attribute) => attribute,
+            None => panic!(
+                "`{}` has no attribute `{}`",
+                self.grammar.name(symbol),
+                name
+            ),
+        }
+    }
+
+    fn describe(&self, attribute: usize) -> String {
+        let attribute = &self.attributes[attribute];
+        format!("{}.{}", self.grammar.name(attribute.symbol), attribute.name)
+    }
+
+    fn lexical_values(&self, token: Token<'_>) -> BTreeMap<usize, V> {
+        self.attributes
+            .iter()
+            .enumerate()
+            .filter(|(_, a)| a.symbol == Symbol::Terminal(token.terminal))
+            .filter_map(|(i, a)| Some((i, (a.lexical.as_ref()?)(token.lexeme))))
+            .collect()
+    }
+
+    fn named(&self, values: BTreeMap<usize, V>) -> Values<V> {
+        values
+            .into_iter()
+            .map(|(i, v)| (self.attributes[i].name.clone(), v))
+            .collect()
+    }
+
+    /// Run the rules of a production that define attributes at one position, given the attributes
+    /// of its symbols by position.
+    fn apply<'src, E>(
+        &self,
+        production: usize,
+        position: usize,
+        frame: &mut [BTreeMap<usize, V>],
+    ) -> Result<(), Error<'src, E>> {
+        for rule in &self.rules[production] {
+            if rule.target.0 != position {
+                continue;
+            }
+
+            let args = rule
+                .args
+                .iter()
+                .map(|&(pos, attribute)| {
+                    frame[pos]
+                        .get(&attribute)
+                        .cloned()
+                        .ok_or_else(|| Error::Undefined(self.describe(attribute)))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            frame[position].insert(rule.target.1, (rule.function)(&args));
+        }
+
+        Ok(())
+    }
+
+    /// Run `apply` on the record of a node and those of its children.
+    fn apply_at<'src, E>(
+        &self,
+        records: &mut [Record<V>],
+        node: usize,
+        production: usize,
+        position: usize,
+    ) -> Result<(), Error<'src, E>> {
+        let ids = std::iter::once(node)
+            .chain(records[node].children.iter().copied())
+            .collect::<Vec<_>>();
+        let mut frame = ids
+            .iter()
+            .map(|&i| mem::take(&mut records[i].values))
+            .collect::<Vec<_>>();
+        let result = self.apply(production, position, &mut frame);
+        for (i, values) in ids.into_iter().zip(frame) {
+            records[i].values = values;
+        }
+        result
+    }
+
+    /// Number the nodes of a tree in preorder and the attribute instances of each node, and link
+    /// the instances by the rules that compute them.
+    fn graph<'src>(&self, tree: &Tree<'src>) -> (Vec<Flat<'src>>, Dependencies, Vec<Definer>) {
+        let mut nodes = Vec::new();
+        self.flatten(tree, &mut nodes);
+
+        let mut instances = Vec::new();
+        let mut definers = Vec::new();
+        for (n, node) in nodes.iter_mut().enumerate() {
+            for (attribute, a) in self.attributes.iter().enumerate() {
+                if a.symbol == node.symbol {
+                    node.instances.push((attribute, instances.len()));
+                    instances.push((n, self.describe(attribute)));
+                    definers.push(match node.production {
+                        None if a.lexical.is_some() && !node.missing => {
+                            Definer::Lexical(attribute, n)
+                        }
+                        _ => Definer::None,
+                    });
+                }
+            }
+        }
+
+        let mut edges = Vec::new();
+        for (n, node) in nodes.iter().enumerate() {
+            let production = match node.production {
+                Some(production) => production,
+                None => continue,
+            };
+
+            for (r, rule) in self.rules[production].iter().enumerate() {
+                let target = node.instance(rule.target.0, rule.target.1, &nodes);
+                definers[target] = Definer::Rule(n, production, r);
+                for &(pos, attribute) in &rule.args {
+                    edges.push((node.instance(pos, attribute, &nodes), target));
+                }
+            }
+        }
+
+        (nodes, Dependencies { instances, edges }, definers)
+    }
+
+    fn flatten<'src>(&self, tree: &Tree<'src>, nodes: &mut Vec<Flat<'src>>) -> usize {
+        let index = nodes.len();
+        let (symbol, production, lexeme) = match tree {
+            Tree::Leaf(token) => (Symbol::Terminal(token.terminal), None, token.lexeme),
+            Tree::Node(production, _) => (
+                Symbol::Nonterminal(self.grammar.productions()[*production].lhs),
+                Some(*production),
+                "",
+            ),
+            Tree::Missing(symbol) => (*symbol, None, ""),
+        };
+        nodes.push(Flat {
+            symbol,
+            production,
+            lexeme,
+            missing: matches!(tree, Tree::Missing(_)),
+            children: Vec::new(),
+            instances: Vec::new(),
+        });
+
+        if let Tree::Node(_, children) = tree {
+            let children = children
+                .iter()
+                .map(|child| self.flatten(child, nodes))
+                .collect();
+            nodes[index].children = children;
+        }
+
+        index
+    }
+
+    fn annotate(&self, nodes: &[Flat<'_>], node: usize, values: &mut [Option<V>]) -> Annotated<V> {
+        Annotated {
+            symbol: nodes[node].symbol,
+            values: nodes[node]
+                .instances
+                .iter()
+                .filter_map(|&(attribute, i)| {
+                    Some((self.attributes[attribute].name.clone(), values[i].take()?))
+                })
+                .collect(),
+            children: nodes[node]
+                .children
+                .iter()
+                .map(|&child| self.annotate(nodes, child, values))
+                .collect(),
+        }
+    }
+}
+
+impl<V> fmt::Debug for Definition<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Definition")
+            .field("attributes", &self.attributes.len())
+            .field("rules", &self.rules.iter().map(Vec::len).sum::<usize>())
+            .finish()
+    }
+}
+
+/// A node of a parse tree being evaluated, numbered in preorder.
+struct Flat<'src> {
+    symbol: Symbol,
+    production: Option<usize>,
+    lexeme: &'src str,
+    missing: bool,
+    children: Vec<usize>,
+    /// The attributes of the node, and the index of their instances.
+    instances: Vec<(usize, usize)>,
+}
+
+impl Flat<'_> {
+    /// The instance of an attribute at a position of this node's production.
+    fn instance(&self, position: usize, attribute: usize, nodes: &[Flat<'_>]) -> usize {
+        let node = if position == 0 {
+            self
+        } else {
+            &nodes[self.children[position - 1]]
+        };
+        node.instances
+            .iter()
+            .find(|&&(a, _)| a == attribute)
+            .unwrap()
+            .1
+    }
+}
+
+/// What computes an attribute instance.
+#[derive(Clone, Copy)]
+enum Definer {
+    /// A lexical attribute of a leaf.
+    Lexical(usize, usize),
+    /// A rule of a production, applied at a node.
+    Rule(usize, usize, usize),
+    None,
+}
+
+/// A node of a parse tree during predictive parsing.
+struct Record<V> {
+    symbol: Symbol,
+    /// The parent, and the position of this node in its production.
+    parent: Option<(usize, usize)>,
+    production: Option<usize>,
+    children: Vec<usize>,
+    values: BTreeMap<usize, V>,
+}
+
+enum Step {
+    Visit(usize),
+    Finish(usize),
+}
+
+/// The dependency graph of the attribute instances in a parse tree.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Dependencies {
+    /// Each instance: the index of its node, numbered in preorder, and a description of its
+    /// attribute such as `E.val`.
+    pub instances: Vec<(usize, String)>,
+    /// Pairs of instances `(a, b)` such that `b` is computed from `a`.
+    pub edges: Vec<(usize, usize)>,
+}
+
+impl Dependencies {
+    /// Sort the instances so that each comes after those it is computed from, or find a cycle of
+    /// instances, each computed from the one before it and the first from the last.
+    pub fn order(&self) -> Result<Vec<usize>, Vec<usize>> {
+        let mut successors = vec![Vec::new(); self.instances.len()];
+        let mut predecessors = vec![Vec::new(); self.instances.len()];
+        let mut indegree = vec![0; self.instances.len()];
+        for &(from, to) in &self.edges {
+            successors[from].push(to);
+            predecessors[to].push(from);
+            indegree[to] += 1;
+        }
+
+        let mut ready = (0..self.instances.len())
+            .filter(|&i| indegree[i] == 0)
+            .collect::<BTreeSet<_>>();
+        let mut order = Vec::new();
+        while let Some(instance) = ready.iter().next().copied() {
+            ready.remove(&instance);
+            order.push(instance);
+            for &next in &successors[instance] {
+                indegree[next] -= 1;
+                if indegree[next] == 0 {
+                    ready.insert(next);
+                }
+            }
+        }
+
+        if order.len() == self.instances.len() {
+            return Ok(order);
+        }
+
+        // Every instance left over is computed from another one left over, so walking back from
+        // any of them must come around to an instance already seen.
+        let mut path = vec![(0..indegree.len()).find(|&i| indegree[i] > 0).unwrap()];
+        let mut seen = HashMap::new();
+        loop {
+            let current = *path.last().unwrap();
+            if let Some(&start) = seen.get(&current) {
+                let mut cycle = path[start..path.len() - 1].to_vec();
+                cycle.reverse();
+                return Err(cycle);
+            }
+            seen.insert(current, path.len() - 1);
+
+            let previous = predecessors[current]
+                .iter()
+                .copied()
+                .find(|&p| indegree[p] > 0)
+                .unwrap();
+            path.push(previous);
+        }
+    }
+}
+
+/// A parse tree with the values of the attributes at each node.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Annotated<V> {
+    /// The symbol of the node.
+    pub symbol: Symbol,
+    /// The attributes of the node that were computed.
+    pub values: Values<V>,
+    /// The children of the node, one per symbol on the right side of its production.
+    pub children: Vec<Annotated<V>>,
+}
+
+impl<V> Annotated<V> {
+    /// The value of an attribute of this node.
+    pub fn get(&self, name: &str) -> Option<&V> {
+        self.values.get(name)
+    }
+}
+
+/// Reasons attributes could not be evaluated.
+#[derive(Debug, PartialEq)]
+pub enum Error<'src, E> {
+    /// The input could not be parsed while evaluating.
+    Parse(parse::Error<'src, E>),
+    /// A rule needs an attribute that no rule computes, such as an inherited attribute of the
+    /// root.
+    Undefined(String),
+    /// Attributes are computed from each other, each from the one before it and the first from
+    /// the last.
+    Cycle(Vec<String>),
+}
+
+impl<'src, E: fmt::Debug> Error<'src, E> {
+    /// Describe this error, using the names of terminals from a grammar.
+    pub fn message(&self, grammar: &Grammar) -> String {
+        match self {
+            Self::Parse(e) => e.message(grammar),
+            Self::Undefined(attribute) => format!("no rule computes `{}`", attribute),
+            Self::Cycle(attributes) => format!(
+                "circular dependency: {} -> {}",
+                attributes.join(" -> "),
+                attributes[0]
+            ),
+        }
+    }
+}
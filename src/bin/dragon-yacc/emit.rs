@@ -0,0 +1,284 @@
+//! Generating a Rust module from a specification and its tables.
+//!
+//! The ACTION table is a matrix of `i32`: zero for an error, `n > 0` to shift and move to state
+//! `n - 1`, `n < 0` to reduce by production `-n - 1`, and `ACCEPT` to accept. The GOTO table holds
+//! `-1` where there is no transition.
+
+use {
+    super::spec::Spec,
+    dragon::{
+        grammar::Symbol,
+        parse::lr::{Action, Table},
+    },
+    std::fmt::Write,
+};
+
+/// Render the module that parses a specification's language.
+pub fn emit(spec: &Spec, table: &Table, source: &str) -> String {
+    let grammar = &spec.grammar;
+    let mut out = String::new();
+
+    writeln!(
+        out,
+        "// Generated by dragon-yacc from {}. Do not edit by hand.\n",
+        source
+    )
+    .unwrap();
+    out.push_str(spec.prologue.trim());
+    out.push_str("\n\n");
+
+    let start = &spec.types[grammar.start()];
+    let lexer = spec.lexer.as_deref();
+    out.push_str(&driver(start, grammar.start(), lexer));
+
+    writeln!(out, "const ACCEPT: i32 = i32::MAX;\n").unwrap();
+
+    let terminals = grammar.terminals();
+    writeln!(
+        out,
+        "/// Names of the terminals, by index.\npub static TERMINALS: [&str; {}] = [",
+        terminals.len()
+    )
+    .unwrap();
+    for name in terminals {
+        writeln!(out, "    {:?},", name).unwrap();
+    }
+    out.push_str("];\n\n");
+
+    writeln!(
+        out,
+        "static ACTION: [[i32; {}]; {}] = [",
+        terminals.len(),
+        table.len()
+    )
+    .unwrap();
+    for state in 0..table.len() {
+        let row = (0..terminals.len())
+            .map(|t| match table.action(state, t) {
+                None => "0".to_string(),
+                Some(Action::Shift(s)) => (s + 1).to_string(),
+                Some(Action::Reduce(p)) => format!("-{}", p + 1),
+                Some(Action::Accept) => "ACCEPT".to_string(),
+            })
+            .collect::<Vec<_>>();
+        writeln!(out, "    [{}],", row.join(", ")).unwrap();
+    }
+    out.push_str("];\n\n");
+
+    let nonterminals = grammar.nonterminals();
+    writeln!(
+        out,
+        "static GOTO: [[i32; {}]; {}] = [",
+        nonterminals.len(),
+        table.len()
+    )
+    .unwrap();
+    for state in 0..table.len() {
+        let row = (0..nonterminals.len())
+            .map(|n| table.goto(state, n).map_or(-1, |s| s as i32).to_string())
+            .collect::<Vec<_>>();
+        writeln!(out, "    [{}],", row.join(", ")).unwrap();
+    }
+    out.push_str("];\n\n");
+
+    let productions = grammar.productions();
+    writeln!(
+        out,
+        "/// The left side and length of each production.\nstatic PRODUCTIONS: [(usize, usize); {}] = [",
+        productions.len()
+    )
+    .unwrap();
+    for production in productions {
+        writeln!(out, "    ({}, {}),", production.lhs, production.rhs.len()).unwrap();
+    }
+    out.push_str("];\n\n");
+
+    out.push_str("enum Value<'src> {\n    Token(&'src str),\n");
+    for (n, ty) in spec.types.iter().enumerate() {
+        writeln!(out, "    N{}({}),", n, ty).unwrap();
+    }
+    out.push_str("}\n\n#[allow(dead_code)]\nimpl<'src> Value<'src> {\n");
+    out.push_str(&accessor("token", "&'src str", "Token"));
+    for (n, ty) in spec.types.iter().enumerate() {
+        out.push_str(&accessor(&format!("n{}", n), ty, &format!("N{}", n)));
+    }
+    out.push_str("}\n\n");
+
+    out.push_str(
+        "#[allow(unused_braces, clippy::all)]\n\
+         fn reduce<'src>(production: usize, values: &mut Vec<Value<'src>>) -> Value<'src> {\n    \
+         match production {\n",
+    );
+    for (p, production) in productions.iter().enumerate() {
+        writeln!(out, "        // {}", grammar.show_production(p)).unwrap();
+        writeln!(out, "        {} => {{", p).unwrap();
+
+        for (i, &symbol) in production.rhs.iter().enumerate().rev() {
+            let into = match symbol {
+                Symbol::Terminal(_) => "into_token".to_string(),
+                Symbol::Nonterminal(n) => format!("into_n{}", n),
+            };
+            writeln!(
+                out,
+                "            let _{} = values.pop().unwrap().{}();",
+                i + 1,
+                into
+            )
+            .unwrap();
+        }
+
+        let ty = &spec.types[production.lhs];
+        let value = match &spec.actions[p] {
+            Some(action) if action.assigns => format!(
+                "{{\n                let _0: {};\n                {{ {} }}\n                _0\n            }}",
+                ty, action.code
+            ),
+            Some(action) => format!("{{ {} }}", action.code),
+            None if ty == "()" => "()".to_string(),
+            None if !production.rhs.is_empty() => "_1".to_string(),
+            None => "Default::default()".to_string(),
+        };
+        writeln!(out, "            Value::N{}({})", production.lhs, value).unwrap();
+        out.push_str("        }\n");
+    }
+    out.push_str("        _ => unreachable!(),\n    }\n}\n");
+
+    let epilogue = spec.epilogue.trim();
+    if !epilogue.is_empty() {
+        out.push('\n');
+        out.push_str(epilogue);
+        out.push('\n');
+    }
+
+    out
+}
+
+fn accessor(name: &str, ty: &str, variant: &str) -> String {
+    format!(
+        "    fn into_{}(self) -> {} {{\n        match self {{\n            \
+         Value::{}(value) => value,\n            _ => unreachable!(),\n        }}\n    }}\n\n",
+        name, ty, variant
+    )
+}
+
+/// The parse functions and error type, which do not depend on the grammar.
+fn driver(start: &str, start_index: usize, lexer: Option<&str>) -> String {
+    let mut out = String::new();
+
+    writeln!(
+        out,
+        r#"/// Reasons the input could not be parsed.
+#[derive(Debug, PartialEq)]
+pub enum Error<'src, E> {{
+    /// The lexer could not recognize a token.
+    Lex(E, &'src str),
+    /// A token does not stand for any terminal of the grammar.
+    UnknownTerminal(String, &'src str),
+    /// A token appeared where the grammar does not allow it, with the names of the terminals that
+    /// would have been allowed. The lexeme is empty at the end of the input.
+    Unexpected(&'src str, Vec<&'static str>),
+}}
+
+impl<E: std::fmt::Debug> std::fmt::Display for Error<'_, E> {{
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {{
+        match self {{
+            Self::Lex(e, s) => write!(f, "could not recognize a token at `{{}}` ({{:?}})", s, e),
+            Self::UnknownTerminal(name, s) => {{
+                write!(f, "token `{{}}` ({{}}) is not a terminal of the grammar", s, name)
+            }}
+            Self::Unexpected("", expected) => {{
+                write!(f, "unexpected end of input, expected one of: {{}}", expected.join(" "))
+            }}
+            Self::Unexpected(s, expected) => {{
+                write!(f, "unexpected `{{}}`, expected one of: {{}}", s, expected.join(" "))
+            }}
+        }}
+    }}
+}}
+
+/// Parse a stream of tokens, whose names (as given by `dragon::grammar::Terminal`) are those of
+/// the terminals of the grammar.
+pub fn parse<'src, T, E, I>(tokens: I) -> Result<{start}, Error<'src, E>>
+where
+    T: dragon::grammar::Terminal,
+    I: IntoIterator<Item = dragon::token::TokenResult<'src, T, E>>,
+{{
+    let mut tokens = tokens.into_iter();
+    let mut states = vec![0];
+    let mut values: Vec<Value> = Vec::new();
+    let (mut terminal, mut lexeme) = next_token(&mut tokens)?;
+
+    loop {{
+        let top = *states.last().unwrap();
+
+        match ACTION[top][terminal] {{
+            ACCEPT => return Ok(values.pop().unwrap().into_n{start_index}()),
+            0 => {{
+                let expected = (0..TERMINALS.len())
+                    .filter(|&t| ACTION[top][t] != 0)
+                    .map(|t| TERMINALS[t])
+                    .collect();
+                return Err(Error::Unexpected(lexeme, expected));
+            }}
+            action if action > 0 => {{
+                states.push(action as usize - 1);
+                values.push(Value::Token(lexeme));
+                let next = next_token(&mut tokens)?;
+                terminal = next.0;
+                lexeme = next.1;
+            }}
+            action => {{
+                let production = (-action) as usize - 1;
+                let (lhs, len) = PRODUCTIONS[production];
+                states.truncate(states.len() - len);
+                let value = reduce(production, &mut values);
+                values.push(value);
+                let top = *states.last().unwrap();
+                states.push(GOTO[top][lhs] as usize);
+            }}
+        }}
+    }}
+}}
+
+fn next_token<'src, T, E>(
+    tokens: &mut impl Iterator<Item = dragon::token::TokenResult<'src, T, E>>,
+) -> Result<(usize, &'src str), Error<'src, E>>
+where
+    T: dragon::grammar::Terminal,
+{{
+    match tokens.next() {{
+        None => Ok((0, "")),
+        Some((Err(e), lexeme)) => Err(Error::Lex(e, lexeme)),
+        Some((Ok(token), lexeme)) => {{
+            let name = token.name(lexeme);
+            match TERMINALS.iter().position(|&t| t == name) {{
+                Some(terminal) => Ok((terminal, lexeme)),
+                None => Err(Error::UnknownTerminal(name.to_string(), lexeme)),
+            }}
+        }}
+    }}
+}}
+"#,
+        start = start,
+        start_index = start_index,
+    )
+    .unwrap();
+
+    if let Some(lexer) = lexer {
+        writeln!(
+            out,
+            r#"/// Lex and parse a string.
+pub fn parse_str<'src>(
+    src: &'src str,
+) -> Result<{start}, Error<'src, <{lexer} as dragon::token::State>::Error>> {{
+    parse(dragon::token::lex::<{lexer}>(src))
+}}
+"#,
+            start = start,
+            lexer = lexer,
+        )
+        .unwrap();
+    }
+
+    out
+}
@@ -0,0 +1,70 @@
+//! A parser generator in the manner of yacc.
+//!
+//! `dragon-yacc GRAMMAR.y [OUTPUT.rs]` reads a grammar with Rust semantic actions (see
+//! [`spec`](spec/index.html) for the format), builds its LALR(1) tables, and writes a Rust module
+//! with a typed `parse` function to `OUTPUT.rs` (by default, `GRAMMAR.rs`). A description of the
+//! states and conflicts is written alongside it, with the extension `.output`.
+//!
+//! Conflicts that precedence declarations do not resolve are resolved the way yacc does, and
+//! counted on standard error.
+
+mod emit;
+mod report;
+mod spec;
+
+use {
+    dragon::parse::lr::{Method, Table},
+    spec::Spec,
+    std::{
+        env, fs,
+        io::{self, Error, ErrorKind},
+        path::PathBuf,
+        process,
+    },
+};
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("dragon-yacc: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run() -> io::Result<()> {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    let (input, output) = match args.as_slice() {
+        [input] => (
+            PathBuf::from(input),
+            PathBuf::from(input).with_extension("rs"),
+        ),
+        [input, output] => (PathBuf::from(input), PathBuf::from(output)),
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "usage: dragon-yacc GRAMMAR.y [OUTPUT.rs]",
+            ))
+        }
+    };
+
+    let src = fs::read_to_string(&input)?;
+    let spec = Spec::parse(&src)
+        .map_err(|e| Error::new(ErrorKind::InvalidData, format!("{}:{}", input.display(), e)))?;
+
+    let table = Table::with_precedence(&spec.grammar, Method::Lalr, &spec.precedence);
+    let source = input.file_name().unwrap_or_default().to_string_lossy();
+
+    fs::write(&output, emit::emit(&spec, &table, &source))?;
+    fs::write(output.with_extension("output"), report::report(&table))?;
+
+    let conflicts = table.conflicts();
+    if !conflicts.is_empty() {
+        let shift_reduce = conflicts.iter().filter(|c| c.is_shift_reduce()).count();
+        eprintln!(
+            "dragon-yacc: conflicts: {} shift/reduce, {} reduce/reduce",
+            shift_reduce,
+            conflicts.len() - shift_reduce
+        );
+    }
+
+    Ok(())
+}
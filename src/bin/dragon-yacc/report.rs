@@ -0,0 +1,84 @@
+//! Describing the tables in the manner of yacc's `y.output`.
+
+use {
+    dragon::{
+        grammar::Symbol,
+        parse::lr::{Action, Table},
+    },
+    std::fmt::Write,
+};
+
+/// List the productions, the conflicts, and the items and actions of every state.
+pub fn report(table: &Table) -> String {
+    let automaton = table.automaton();
+    let grammar = table.grammar();
+    let mut out = String::new();
+
+    out.push_str("Grammar\n\n");
+    for p in 0..grammar.productions().len() {
+        writeln!(out, "  {:>3}  {}", p, grammar.show_production(p)).unwrap();
+    }
+
+    if !table.conflicts().is_empty() {
+        out.push_str("\nConflicts\n\n");
+        for conflict in table.conflicts() {
+            writeln!(out, "{}\n", conflict.message(table)).unwrap();
+        }
+    }
+
+    if !table.resolutions().is_empty() {
+        out.push_str("\nResolved conflicts\n\n");
+        for resolution in table.resolutions() {
+            writeln!(out, "{}", resolution.message(table)).unwrap();
+        }
+    }
+
+    let width = grammar
+        .terminals()
+        .iter()
+        .chain(grammar.nonterminals())
+        .map(|name| name.chars().count())
+        .max()
+        .unwrap_or(0);
+
+    for (index, state) in automaton.states().iter().enumerate() {
+        writeln!(out, "\nState {}\n", index).unwrap();
+
+        for (item, lookaheads) in &state.items[..state.kernel] {
+            writeln!(out, "    {}", automaton.show_item(item, lookaheads)).unwrap();
+        }
+        out.push('\n');
+
+        for (terminal, name) in grammar.terminals().iter().enumerate() {
+            for (i, &action) in table.actions(index, terminal).iter().enumerate() {
+                let action = match action {
+                    Action::Shift(s) => format!("shift, and go to state {}", s),
+                    Action::Reduce(p) => format!("reduce using rule {}", p),
+                    Action::Accept => "accept".to_string(),
+                };
+
+                if i == 0 {
+                    writeln!(out, "    {:width$}  {}", name, action, width = width).unwrap();
+                } else {
+                    writeln!(out, "    {:width$}  [{}]", name, action, width = width).unwrap();
+                }
+            }
+        }
+
+        for (&symbol, &target) in &state.goto {
+            if let Symbol::Nonterminal(n) = symbol {
+                let name = &grammar.nonterminals()[n];
+                writeln!(
+                    out,
+                    "    {:width$}  go to state {}",
+                    name,
+                    target,
+                    width = width
+                )
+                .unwrap();
+            }
+        }
+    }
+
+    out
+}
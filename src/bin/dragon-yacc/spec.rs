@@ -0,0 +1,642 @@
+//! Reading grammar specifications.
+//!
+//! A specification has three sections, separated by lines of `%%`: declarations, rules, and code
+//! that is copied to the end of the generated module.
+//!
+//! ```text
+//! %{
+//! // Copied to the start of the generated module.
+//! %}
+//!
+//! %lexer CalcLexer              // a `dragon::token::State` for `parse_str`
+//! %token NUM                    // named terminals
+//! %left '+' '-'                 // precedence levels, lowest first
+//! %left '*' '/'
+//! %right UMINUS
+//! %type <i64> expr              // the type of a nonterminal's value (default `()`)
+//! %start expr                   // the start symbol (default: the first rule's)
+//!
+//! %%
+//!
+//! expr : expr '+' expr          { $1 + $3 }
+//!      | '-' expr %prec UMINUS  { -$2 }
+//!      | NUM                    { $1.parse().unwrap() }
+//!      ;
+//!
+//! %%
+//! ```
+//!
+//! Quoted terminals stand for themselves, and need not be declared. The value of a terminal in an
+//! action is its lexeme, a `&'src str`.
+
+use {
+    dragon::{
+        grammar::{Grammar, Symbol},
+        parse::{lr::Precedence, Assoc},
+    },
+    std::fmt,
+};
+
+/// A grammar with semantic actions.
+pub struct Spec {
+    /// Code to copy before the generated items.
+    pub prologue: String,
+    /// Code to copy after the generated items.
+    pub epilogue: String,
+    /// The type implementing `dragon::token::State`, if any.
+    pub lexer: Option<String>,
+    pub grammar: Grammar,
+    pub precedence: Precedence,
+    /// The type of each nonterminal's value, by index.
+    pub types: Vec<String>,
+    /// The action of each production, by index.
+    pub actions: Vec<Option<SemanticAction>>,
+}
+
+/// The code run when reducing by a production, with `$$` and `$n` replaced by `_0` and `_n`.
+pub struct SemanticAction {
+    pub code: String,
+    /// Whether the code assigns to `$$`, rather than being an expression for its value.
+    pub assigns: bool,
+}
+
+/// A mistake in a specification.
+#[derive(Debug)]
+pub struct Error {
+    line: usize,
+    message: String,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.line, self.message)
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+struct Rule {
+    line: usize,
+    lhs: String,
+    rhs: Vec<(String, bool)>,
+    prec: Option<String>,
+    /// The action's code, and the line it starts on.
+    action: Option<(usize, String)>,
+}
+
+impl Spec {
+    /// Read a specification.
+    pub fn parse(src: &str) -> Result<Self> {
+        let mut scanner = Scanner {
+            src,
+            pos: 0,
+            line: 1,
+        };
+
+        let mut prologue = String::new();
+        let mut lexer = None;
+        let mut tokens = Vec::new();
+        let mut precedence = Precedence::new();
+        let mut typed = Vec::new();
+        let mut start = None;
+
+        loop {
+            scanner.skip_trivia();
+            let line = scanner.line;
+
+            if scanner.eat("%%") {
+                break;
+            } else if scanner.eat("%{") {
+                prologue.push_str(scanner.until("%}")?);
+                prologue.push('\n');
+            } else if scanner.eat("%token") {
+                tokens.extend(scanner.names()?);
+            } else if scanner.eat("%left") {
+                let names = scanner.names()?;
+                tokens.extend(names.iter().cloned());
+                precedence = precedence.level(Assoc::Left, &refs(&names));
+            } else if scanner.eat("%right") {
+                let names = scanner.names()?;
+                tokens.extend(names.iter().cloned());
+                precedence = precedence.level(Assoc::Right, &refs(&names));
+            } else if scanner.eat("%nonassoc") {
+                let names = scanner.names()?;
+                tokens.extend(names.iter().cloned());
+                precedence = precedence.level(Assoc::Neither, &refs(&names));
+            } else if scanner.eat("%type") {
+                scanner.skip_trivia();
+                if !scanner.eat("<") {
+                    return Err(scanner.error("expected `<` after `%type`"));
+                }
+                let ty = scanner.angled()?;
+                for name in scanner.names()? {
+                    typed.push((line, name, ty.clone()));
+                }
+            } else if scanner.eat("%start") {
+                scanner.skip_trivia();
+                start = Some((line, scanner.ident()?));
+            } else if scanner.eat("%lexer") {
+                lexer = Some(scanner.path()?);
+            } else if scanner.peek().is_none() {
+                return Err(scanner.error("expected `%%` before the rules"));
+            } else {
+                return Err(scanner.error("expected a declaration"));
+            }
+        }
+
+        let rules = scanner.rules()?;
+        if rules.is_empty() {
+            return Err(scanner.error("the grammar has no rules"));
+        }
+        let epilogue = scanner.src[scanner.pos..].to_string();
+
+        Self::resolve(Declarations {
+            prologue,
+            epilogue,
+            lexer,
+            tokens,
+            precedence,
+            typed,
+            start,
+            rules,
+        })
+    }
+
+    fn resolve(decls: Declarations) -> Result<Self> {
+        let mut grammar = Grammar::new();
+        let mut precedence = decls.precedence;
+
+        for token in &decls.tokens {
+            grammar.add_terminal(token);
+        }
+
+        for rule in &decls.rules {
+            if decls.tokens.contains(&rule.lhs) {
+                return Err(Error {
+                    line: rule.line,
+                    message: format!("`{}` is declared as a token", rule.lhs),
+                });
+            }
+            grammar.add_nonterminal(&rule.lhs);
+        }
+
+        if let Some((line, name)) = &decls.start {
+            match grammar.nonterminal(name) {
+                Some(start) => grammar.set_start(start),
+                None => {
+                    return Err(Error {
+                        line: *line,
+                        message: format!("start symbol `{}` has no rules", name),
+                    })
+                }
+            }
+        }
+
+        let mut actions = Vec::new();
+        for rule in decls.rules {
+            let lhs = grammar.nonterminal(&rule.lhs).unwrap();
+            let mut rhs = Vec::new();
+
+            for (name, quoted) in &rule.rhs {
+                let symbol = match grammar.nonterminal(name) {
+                    Some(n) if !quoted => Symbol::Nonterminal(n),
+                    _ if *quoted || decls.tokens.contains(name) => {
+                        Symbol::Terminal(grammar.add_terminal(name))
+                    }
+                    _ => {
+                        return Err(Error {
+                            line: rule.line,
+                            message: format!("`{}` is neither a token nor a nonterminal", name),
+                        })
+                    }
+                };
+                rhs.push(symbol);
+            }
+
+            let action = match rule.action {
+                Some((line, action)) => {
+                    let (code, assigns) = substitute(&action, line, rhs.len())?;
+                    Some(SemanticAction { code, assigns })
+                }
+                None => None,
+            };
+
+            let production = grammar.add_production(lhs, rhs);
+            if let Some(name) = rule.prec {
+                precedence = precedence.prec(production, &name);
+            }
+            actions.push(action);
+        }
+
+        let mut types = vec!["()".to_string(); grammar.nonterminals().len()];
+        for (line, name, ty) in decls.typed {
+            match grammar.nonterminal(&name) {
+                Some(n) => types[n] = ty,
+                None => {
+                    return Err(Error {
+                        line,
+                        message: format!("`{}` has a type but no rules", name),
+                    })
+                }
+            }
+        }
+
+        Ok(Self {
+            prologue: decls.prologue,
+            epilogue: decls.epilogue,
+            lexer: decls.lexer,
+            grammar,
+            precedence,
+            types,
+            actions,
+        })
+    }
+}
+
+struct Declarations {
+    prologue: String,
+    epilogue: String,
+    lexer: Option<String>,
+    tokens: Vec<String>,
+    precedence: Precedence,
+    typed: Vec<(usize, String, String)>,
+    start: Option<(usize, String)>,
+    rules: Vec<Rule>,
+}
+
+fn refs(names: &[String]) -> Vec<&str> {
+    names.iter().map(|n| n.as_str()).collect()
+}
+
+/// Replace `$$` with `_0` and `$n` with `_n` in an action starting on `line`, leaving strings,
+/// characters and comments alone. Also returns whether the action mentions `$$`.
+fn substitute(action: &str, line: usize, len: usize) -> Result<(String, bool)> {
+    let mut scanner = Scanner {
+        src: action,
+        pos: 0,
+        line,
+    };
+    let mut out = String::new();
+    let mut assigns = false;
+
+    loop {
+        let start = scanner.pos;
+        match scanner.bump() {
+            None => return Ok((out, assigns)),
+            Some('$') if scanner.eat("$") => {
+                out.push_str("_0");
+                assigns = true;
+            }
+            Some('$') => {
+                let digits = scanner.pos;
+                while scanner.peek().filter(|d| d.is_ascii_digit()).is_some() {
+                    scanner.bump();
+                }
+
+                match action[digits..scanner.pos].parse::<usize>() {
+                    Ok(n) if n >= 1 && n <= len => {
+                        out.push('_');
+                        out.push_str(&action[digits..scanner.pos]);
+                    }
+                    Ok(n) => {
+                        return Err(
+                            scanner.error(&format!("`${}` is out of range for {} symbols", n, len))
+                        )
+                    }
+                    Err(_) => {
+                        return Err(scanner.error("expected `$$` or `$` followed by a number"))
+                    }
+                }
+            }
+            Some(c) => {
+                scanner.skip_literal(c)?;
+                out.push_str(&action[start..scanner.pos]);
+            }
+        }
+    }
+}
+
+struct Scanner<'a> {
+    src: &'a str,
+    pos: usize,
+    line: usize,
+}
+
+impl<'a> Scanner<'a> {
+    fn rules(&mut self) -> Result<Vec<Rule>> {
+        let mut rules = Vec::new();
+
+        loop {
+            self.skip_trivia();
+            if self.peek().is_none() || self.eat("%%") {
+                return Ok(rules);
+            }
+
+            let lhs = self.ident()?;
+            self.skip_trivia();
+            if !self.eat(":") {
+                return Err(self.error(&format!("expected `:` after `{}`", lhs)));
+            }
+
+            loop {
+                self.skip_trivia();
+                let mut rule = Rule {
+                    line: self.line,
+                    lhs: lhs.clone(),
+                    rhs: Vec::new(),
+                    prec: None,
+                    action: None,
+                };
+
+                loop {
+                    self.skip_trivia();
+
+                    match self.peek() {
+                        Some('|') | Some(';') => break,
+                        Some('{') if rule.action.is_some() => {
+                            return Err(self.error("only one action is allowed, at the end"))
+                        }
+                        Some('{') => {
+                            self.bump();
+                            rule.action = Some(self.braced()?);
+                        }
+                        _ if rule.action.is_some() => {
+                            return Err(self.error("expected `|` or `;` after an action"))
+                        }
+                        Some('%') if self.eat("%prec") => {
+                            self.skip_trivia();
+                            rule.prec = Some(self.name()?.0);
+                        }
+                        Some('%') => return Err(self.error("expected `%prec`")),
+                        Some(_) => {
+                            let name = self.name()?;
+                            if rule.prec.is_some() {
+                                return Err(self.error("`%prec` must follow the symbols"));
+                            }
+                            rule.rhs.push(name);
+                        }
+                        None => return Err(self.error("expected `;` at the end of the rules")),
+                    }
+                }
+
+                rules.push(rule);
+                if self.eat(";") {
+                    break;
+                }
+                self.bump();
+            }
+        }
+    }
+
+    /// A list of names or quoted terminals, up to the end of the line.
+    fn names(&mut self) -> Result<Vec<String>> {
+        let mut names = Vec::new();
+
+        loop {
+            while let Some(' ') | Some('\t') = self.peek() {
+                self.bump();
+            }
+            if self.src[self.pos..].starts_with("//") {
+                self.rest_of_line();
+            }
+
+            match self.peek() {
+                None | Some('\n') | Some('\r') => break,
+                _ => names.push(self.name()?.0),
+            }
+        }
+
+        if names.is_empty() {
+            Err(self.error("expected at least one name"))
+        } else {
+            Ok(names)
+        }
+    }
+
+    /// An identifier or quoted terminal, and whether it was quoted.
+    fn name(&mut self) -> Result<(String, bool)> {
+        match self.peek() {
+            Some(quote @ '\'') | Some(quote @ '"') => {
+                self.bump();
+                let start = self.pos;
+                while let Some(c) = self.peek() {
+                    if c == quote || c == '\n' {
+                        break;
+                    }
+                    self.bump();
+                }
+
+                let name = self.src[start..self.pos].to_string();
+                if !self.eat(&quote.to_string()) || name.is_empty() {
+                    return Err(self.error("unterminated quoted terminal"));
+                }
+                Ok((name, true))
+            }
+            _ => Ok((self.ident()?, false)),
+        }
+    }
+
+    fn ident(&mut self) -> Result<String> {
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            if !(c.is_alphanumeric() || c == '_' || c == '.') {
+                break;
+            }
+            self.bump();
+        }
+
+        if start == self.pos {
+            Err(self.error("expected a name"))
+        } else {
+            Ok(self.src[start..self.pos].to_string())
+        }
+    }
+
+    /// A type path, such as `lex::CalcLexer`, on the current line.
+    fn path(&mut self) -> Result<String> {
+        while let Some(' ') | Some('\t') = self.peek() {
+            self.bump();
+        }
+        if self.src[self.pos..].starts_with("//") || self.peek() == Some('\n') {
+            return Err(self.error("expected a type after `%lexer`"));
+        }
+
+        let mut path = self.ident()?;
+        while self.eat("::") {
+            path.push_str("::");
+            path.push_str(&self.ident()?);
+        }
+        Ok(path)
+    }
+
+    /// The text up to a matching `>`, after a `<`.
+    fn angled(&mut self) -> Result<String> {
+        let start = self.pos;
+        let mut depth = 1;
+
+        while let Some(c) = self.bump() {
+            match c {
+                '<' => depth += 1,
+                '>' if self.src[..self.pos - 1].ends_with('-') => (),
+                '>' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Ok(self.src[start..self.pos - 1].trim().to_string());
+                    }
+                }
+                '\n' => break,
+                _ => (),
+            }
+        }
+
+        Err(self.error("unterminated type"))
+    }
+
+    /// The text up to a matching `}`, after a `{`, skipping over strings, characters and comments,
+    /// and the line the text starts on.
+    fn braced(&mut self) -> Result<(usize, String)> {
+        let start = self.pos;
+        let line = self.line;
+        let mut depth = 1;
+
+        while let Some(c) = self.bump() {
+            match c {
+                '{' => depth += 1,
+                '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        let text = &self.src[start..self.pos - 1];
+                        let code = text.trim_start();
+                        let skipped = text[..text.len() - code.len()].matches('\n').count();
+                        return Ok((line + skipped, code.trim_end().to_string()));
+                    }
+                }
+                _ => self.skip_literal(c)?,
+            }
+        }
+
+        Err(Error {
+            line,
+            message: "unterminated action".to_string(),
+        })
+    }
+
+    /// The rest of a string, character or comment that starts with `c`, if it does.
+    fn skip_literal(&mut self, c: char) -> Result<()> {
+        match c {
+            '"' => {
+                while let Some(c) = self.bump() {
+                    match c {
+                        '\\' => {
+                            self.bump();
+                        }
+                        '"' => break,
+                        _ => (),
+                    }
+                }
+            }
+            '\'' => {
+                let rest = &self.src[self.pos..];
+                let mut chars = rest.chars();
+                match (chars.next(), chars.next()) {
+                    (Some('\\'), _) => {
+                        self.bump();
+                        self.bump();
+                        while let Some(c) = self.bump() {
+                            if c == '\'' {
+                                break;
+                            }
+                        }
+                    }
+                    (Some(_), Some('\'')) => {
+                        self.bump();
+                        self.bump();
+                    }
+                    _ => (),
+                }
+            }
+            '/' if self.peek() == Some('/') => {
+                self.rest_of_line();
+            }
+            '/' if self.peek() == Some('*') => {
+                self.until("*/")?;
+            }
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    /// The text up to a delimiter, consuming the delimiter.
+    fn until(&mut self, delimiter: &str) -> Result<&'a str> {
+        let line = self.line;
+        let rest = &self.src[self.pos..];
+
+        match rest.find(delimiter) {
+            Some(end) => {
+                self.line += rest[..end].matches('\n').count();
+                self.pos += end + delimiter.len();
+                Ok(&rest[..end])
+            }
+            None => Err(Error {
+                line,
+                message: format!("expected `{}`", delimiter),
+            }),
+        }
+    }
+
+    fn rest_of_line(&mut self) -> &'a str {
+        let rest = &self.src[self.pos..];
+        let end = rest.find('\n').unwrap_or(rest.len());
+        self.pos += end;
+        &rest[..end]
+    }
+
+    fn skip_trivia(&mut self) {
+        loop {
+            match self.peek() {
+                Some(c) if c.is_whitespace() => {
+                    self.bump();
+                }
+                Some('/') if self.src[self.pos..].starts_with("//") => {
+                    self.rest_of_line();
+                }
+                Some('/') if self.src[self.pos..].starts_with("/*") => {
+                    if self.until("*/").is_err() {
+                        self.pos = self.src.len();
+                    }
+                }
+                _ => return,
+            }
+        }
+    }
+
+    fn eat(&mut self, s: &str) -> bool {
+        if self.src[self.pos..].starts_with(s) {
+            self.pos += s.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.src[self.pos..].chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        if c == '\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    fn error(&self, message: &str) -> Error {
+        Error {
+            line: self.line,
+            message: message.to_string(),
+        }
+    }
+}
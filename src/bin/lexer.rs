@@ -4,7 +4,7 @@ use dragon::token::{State, Step};
 
 fn main() -> io::Result<()> {
     for s in io::stdin().lock().lines() {
-        for token in dragon::token::lex::<MyState>(&s?) {
+        for token in dragon::token::lex::<MyState>(&s?).with_trivia() {
             println!("{:?}", token);
         }
     }
@@ -23,7 +23,9 @@ enum MyToken {
     Num,
 }
 
+#[derive(Default)]
 enum MyState {
+    #[default]
     Start,           // 0, 9, and 12
     Lt,              // 1
     Gt,              // 6
@@ -42,12 +44,6 @@ enum MyState {
     BlockCommentEnd,
 }
 
-impl Default for MyState {
-    fn default() -> Self {
-        Self::Start
-    }
-}
-
 impl State for MyState {
     type Token = MyToken;
     type Error = char;
@@ -0,0 +1,243 @@
+use {
+    dragon::{
+        grammar::{Grammar, Symbol},
+        parse::lr::{Action, Method, Table},
+    },
+    std::{
+        env,
+        io::{self, Error, ErrorKind, Read},
+    },
+};
+
+/*
+ *  lr2dot [--slr | --lalr | --lr1] [--dot | --html | --markdown] < grammar
+ *
+ *  Reads a grammar in the notation of `dragon::grammar`, and prints its automaton of item sets
+ *  as a Graphviz digraph, or its ACTION and GOTO tables. States with conflicts are highlighted.
+ */
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Dot,
+    Html,
+    Markdown,
+}
+
+fn main() -> io::Result<()> {
+    let mut method = Method::Lalr;
+    let mut format = Format::Dot;
+
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--slr" | "--lr0" => method = Method::Slr,
+            "--lalr" => method = Method::Lalr,
+            "--lr1" | "--canonical" => method = Method::Canonical,
+            "--dot" => format = Format::Dot,
+            "--html" => format = Format::Html,
+            "--markdown" | "--md" => format = Format::Markdown,
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("unrecognized option `{}`", arg),
+                ))
+            }
+        }
+    }
+
+    let mut src = String::new();
+    io::stdin().read_to_string(&mut src)?;
+    let grammar: Grammar = src
+        .parse()
+        .map_err(|e| Error::new(ErrorKind::InvalidData, format!("{}", e)))?;
+
+    let table = Table::new(&grammar, method);
+    match format {
+        Format::Dot => emit_dot(&table),
+        Format::Html => emit_html(&table),
+        Format::Markdown => emit_markdown(&table),
+    }
+
+    Ok(())
+}
+
+fn emit_dot(table: &Table) {
+    let automaton = table.automaton();
+    let grammar = automaton.grammar();
+
+    println!("digraph {{");
+    println!("\trankdir = LR;");
+    println!("\tnode [shape = box, fontname = monospace];");
+
+    for (index, state) in automaton.states().iter().enumerate() {
+        let mut label = format!("{}\\n", index);
+        for (i, (item, lookaheads)) in state.items.iter().enumerate() {
+            if i == state.kernel {
+                label.push_str("\\l");
+            }
+            label.push_str(&dot_escape(&automaton.show_item(item, lookaheads)));
+            label.push_str("\\l");
+        }
+
+        let style = if has_conflict(table, index) {
+            ", color = red, penwidth = 2"
+        } else {
+            ""
+        };
+        println!("\t{} [label = \"{}\"{}];", index, label, style);
+    }
+
+    for (index, state) in automaton.states().iter().enumerate() {
+        for (&symbol, &target) in &state.goto {
+            let label = dot_escape(grammar.name(symbol));
+            match symbol {
+                Symbol::Terminal(_) => {
+                    println!("\t{} -> {} [label = \"{}\"];", index, target, label)
+                }
+                Symbol::Nonterminal(_) => println!(
+                    "\t{} -> {} [label = \"{}\", style = dashed];",
+                    index, target, label
+                ),
+            }
+        }
+    }
+
+    println!("}}");
+}
+
+fn emit_html(table: &Table) {
+    let grammar = table.grammar();
+    let terminals = grammar.terminals();
+    let nonterminals = &grammar.nonterminals()[..grammar.nonterminals().len() - 1];
+
+    println!("<!DOCTYPE html>");
+    println!("<style>");
+    println!("  table {{ border-collapse: collapse; font-family: monospace; }}");
+    println!("  th, td {{ border: 1px solid #999; padding: 0.2em 0.5em; text-align: center; }}");
+    println!("  td.conflict {{ background: #fbb; font-weight: bold; }}");
+    println!("</style>");
+    println!("<table>");
+    println!(
+        "  <tr><th rowspan=\"2\">State</th><th colspan=\"{}\">ACTION</th><th colspan=\"{}\">GOTO</th></tr>",
+        terminals.len(),
+        nonterminals.len()
+    );
+
+    let header = terminals
+        .iter()
+        .chain(nonterminals)
+        .map(|name| format!("<th>{}</th>", html_escape(name)))
+        .collect::<String>();
+    println!("  <tr>{}</tr>", header);
+
+    for state in 0..table.len() {
+        let mut row = format!("<th>{}</th>", state);
+
+        for terminal in 0..terminals.len() {
+            let actions = table.actions(state, terminal);
+            if actions.len() > 1 {
+                row.push_str("<td class=\"conflict\">");
+            } else {
+                row.push_str("<td>");
+            }
+            row.push_str(&show_actions(actions));
+            row.push_str("</td>");
+        }
+
+        for nonterminal in 0..nonterminals.len() {
+            let target = table.goto(state, nonterminal);
+            row.push_str(&format!("<td>{}</td>", show_goto(target)));
+        }
+
+        println!("  <tr>{}</tr>", row);
+    }
+
+    println!("</table>");
+    println!("<ol start=\"0\">");
+    for production in 0..grammar.productions().len() - 1 {
+        println!(
+            "  <li><code>{}</code></li>",
+            html_escape(&grammar.show_production(production))
+        );
+    }
+    println!("</ol>");
+}
+
+fn emit_markdown(table: &Table) {
+    let grammar = table.grammar();
+    let terminals = grammar.terminals();
+    let nonterminals = &grammar.nonterminals()[..grammar.nonterminals().len() - 1];
+
+    let header = terminals
+        .iter()
+        .map(|name| format!(" {} |", markdown_escape(name)))
+        .chain(
+            nonterminals
+                .iter()
+                .map(|name| format!(" {} |", markdown_escape(name))),
+        )
+        .collect::<String>();
+    println!("| State |{}", header);
+    println!(
+        "|------:|{}",
+        ":---:|".repeat(terminals.len() + nonterminals.len())
+    );
+
+    for state in 0..table.len() {
+        let mut row = format!("| {} |", state);
+
+        for terminal in 0..terminals.len() {
+            let actions = table.actions(state, terminal);
+            if actions.len() > 1 {
+                row.push_str(&format!(" **{}** |", show_actions(actions)));
+            } else {
+                row.push_str(&format!(" {} |", show_actions(actions)));
+            }
+        }
+
+        for nonterminal in 0..nonterminals.len() {
+            row.push_str(&format!(" {} |", show_goto(table.goto(state, nonterminal))));
+        }
+
+        println!("{}", row);
+    }
+
+    println!();
+    for production in 0..grammar.productions().len() - 1 {
+        println!("{}. `{}`", production, grammar.show_production(production));
+    }
+}
+
+fn has_conflict(table: &Table, state: usize) -> bool {
+    table.conflicts().iter().any(|c| c.state == state)
+}
+
+/// Dragon-book notation: `s5` to shift and go to state 5, `r2` to reduce by production 2.
+fn show_actions(actions: &[Action]) -> String {
+    actions
+        .iter()
+        .map(|action| match action {
+            Action::Shift(state) => format!("s{}", state),
+            Action::Reduce(production) => format!("r{}", production),
+            Action::Accept => "acc".to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn show_goto(target: Option<usize>) -> String {
+    target.map_or(String::new(), |s| s.to_string())
+}
+
+fn dot_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn markdown_escape(s: &str) -> String {
+    s.replace('|', "\\|")
+}
@@ -1,29 +1,276 @@
 use std::{
     cell::RefCell,
-    fmt,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    convert::TryFrom,
+    env, fmt,
     io::{self, Error, ErrorKind, Read},
     iter::Peekable,
     mem,
     rc::Rc,
 };
 
+use dragon::{
+    ir::{
+        dag,
+        dataflow::{self, AvailableExpressions, LiveVariables, ReachingDefinitions},
+        flow::{Graph, Node},
+        global::{self, Change},
+        loops, BinaryOp, Instr, Label, Name, Operand, Program, State, UnaryOp, Value,
+    },
+    types::{Names, Type},
+};
+
+/*
+ *  simple [--dag] [--invariants] [--strength] [--induction] [--constants] [--cse] [--copies]
+ *         [--dead] [--optimize]
+ *         [--quadruples | --triples | --cfg | --loops | --dataflow | --run] < program
+ *
+ *  Translates a program of declarations (`int x;`, or `int a[10];` for an array), assignments
+ *  (`x := e;`, `a[i] := e;`), expressions whose values are printed (`e;`), `if (b) s else s`,
+ *  `while (b) s`, blocks `{ s s ... }`, labeled statements `l: s` and `goto l;` into
+ *  three-address code, and prints it as instructions, quadruples or triples. With `--cfg`,
+ *  prints its flow graph as a Graphviz digraph, and with `--loops`, its dominator tree and
+ *  natural loops with their induction variables, and with `--dataflow`, the reaching
+ *  definitions, live variables and available expressions at the start and end of each block.
+ *  With `--run`, runs it instead and prints the values.
+ *
+ *  The other options turn on optimizations, which run in the order listed, and report what they
+ *  changed on stderr:
+ *
+ *  --dag        optimize each basic block by way of its DAG
+ *  --invariants loop-invariant code motion
+ *  --strength   strength reduction of induction variables
+ *  --induction  induction-variable elimination
+ *  --constants  conditional constant propagation, which also removes unreachable code
+ *  --cse        global common subexpression elimination
+ *  --copies     copy propagation
+ *  --dead       dead-store elimination
+ *  --optimize   all of the above
+ *
+ *  Boolean expressions (`and`, `or`, `not` and the comparisons `<`, `<=`, `=`, `<>`, `>=`, `>`)
+ *  are translated into jumping code that short-circuits, with the targets of the jumps filled in
+ *  by backpatching.
+ */
+
+/// The optimizations, in the order they run in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Pass {
+    Dag,
+    Invariants,
+    Strength,
+    Induction,
+    Constants,
+    Cse,
+    Copies,
+    Dead,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Instructions,
+    Quadruples,
+    Triples,
+    Cfg,
+    Loops,
+    Dataflow,
+    Run,
+}
+
+/// The number of instructions `--run` executes before giving up.
+const FUEL: usize = 1_000_000;
+
 fn main() -> EmptyIoResult {
+    let mut format = Format::Instructions;
+    let mut passes = BTreeSet::new();
+
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--quadruples" => format = Format::Quadruples,
+            "--triples" => format = Format::Triples,
+            "--dag" => {
+                passes.insert(Pass::Dag);
+            }
+            "--invariants" => {
+                passes.insert(Pass::Invariants);
+            }
+            "--strength" => {
+                passes.insert(Pass::Strength);
+            }
+            "--induction" => {
+                passes.insert(Pass::Induction);
+            }
+            "--constants" => {
+                passes.insert(Pass::Constants);
+            }
+            "--cse" => {
+                passes.insert(Pass::Cse);
+            }
+            "--copies" => {
+                passes.insert(Pass::Copies);
+            }
+            "--dead" => {
+                passes.insert(Pass::Dead);
+            }
+            "--optimize" => passes.extend(vec![
+                Pass::Dag,
+                Pass::Invariants,
+                Pass::Strength,
+                Pass::Induction,
+                Pass::Constants,
+                Pass::Cse,
+                Pass::Copies,
+                Pass::Dead,
+            ]),
+            "--cfg" => format = Format::Cfg,
+            "--loops" => format = Format::Loops,
+            "--dataflow" => format = Format::Dataflow,
+            "--run" => format = Format::Run,
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("unrecognized option `{}`", arg),
+                ))
+            }
+        }
+    }
+
     let mut s = String::new();
     io::stdin().read_to_string(&mut s)?;
 
     let mut symbols = SymTable::default();
-    symbols.insert("div".to_string());
-    symbols.insert("mod".to_string());
+    for keyword in KEYWORDS {
+        symbols.insert(keyword.to_string());
+    }
 
     let lexer = Lexer::new(s.chars(), symbols.clone());
 
-    Parser {
+    let mut parser = Parser {
         iter: lexer.peekable(),
         symbols,
+        declared: HashMap::new(),
+        program: Program::new(),
+        targets: BTreeMap::new(),
+        labels: HashMap::new(),
+    };
+    parser.list()?;
+    let program = parser.program;
+
+    let mut graph = Graph::new(&program);
+    for pass in passes {
+        let before = count(&graph.program());
+        let changes: Vec<Change> = match pass {
+            Pass::Dag => {
+                dag::optimize(&mut graph);
+                Vec::new()
+            }
+            Pass::Invariants => loops::move_invariants(&mut graph),
+            Pass::Strength => loops::reduce_strength(&mut graph),
+            Pass::Induction => loops::eliminate_induction_variables(&mut graph),
+            Pass::Constants => global::propagate_constants(&mut graph),
+            Pass::Cse => global::eliminate_common_subexpressions(&mut graph),
+            Pass::Copies => global::propagate_copies(&mut graph),
+            Pass::Dead => global::remove_dead_stores(&mut graph),
+        };
+
+        let name = format!("{:?}", pass).to_lowercase();
+        for change in changes {
+            eprintln!("{}: {}", name, change);
+        }
+        eprintln!(
+            "{}: {} instructions before, {} after",
+            name,
+            before,
+            count(&graph.program())
+        );
     }
-    .list()
+    let program = graph.program();
+
+    match format {
+        Format::Instructions => print!("{}", program),
+        Format::Quadruples => {
+            for (i, quadruple) in program.quadruples().iter().enumerate() {
+                println!("({})\t{}", i, quadruple);
+            }
+        }
+        Format::Triples => {
+            for (i, triple) in program.triples().iter().enumerate() {
+                println!("({})\t{}", i, triple);
+            }
+        }
+        Format::Cfg => print!("{}", Graph::new(&program).dot()),
+        Format::Loops => {
+            let graph = Graph::new(&program);
+            let dominators = graph.dominators();
+            print!("{}", dominators);
+            for l in graph.loops(&dominators) {
+                println!("loop {}", l);
+                for v in loops::induction_variables(&graph, &l) {
+                    println!("    {}", v);
+                }
+            }
+        }
+        Format::Dataflow => {
+            let graph = Graph::new(&program);
+            let reaching = dataflow::solve(&ReachingDefinitions::new(&graph), &graph);
+            let live = dataflow::solve(&LiveVariables, &graph);
+            let available = dataflow::solve(&AvailableExpressions::new(&graph), &graph);
+
+            for (b, block) in graph.blocks.iter().enumerate() {
+                println!("{}", Node::Block(b));
+                println!(
+                    "  reaching definitions   in {}  out {}",
+                    set(reaching.at_start(b)),
+                    set(reaching.at_end(b))
+                );
+                println!(
+                    "  live variables         in {}  out {}",
+                    set(live.at_start(b)),
+                    set(live.at_end(b))
+                );
+                println!(
+                    "  available expressions  in {}  out {}",
+                    set(available.at_start(b)),
+                    set(available.at_end(b))
+                );
+                print!("{}", block);
+            }
+        }
+        Format::Run => {
+            let mut state = State::default();
+            program
+                .run(&mut state, FUEL)
+                .map_err(|e| Error::new(ErrorKind::InvalidData, e.to_string()))?;
+            for value in state.output {
+                println!("{}", value);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The number of instructions in a program, not counting labels.
+fn count(program: &Program) -> usize {
+    program
+        .instrs
+        .iter()
+        .filter(|instr| !matches!(instr, Instr::Label(_)))
+        .count()
 }
 
+/// A set of facts, sorted, as in `{a, b}`.
+fn set<T: fmt::Display>(facts: impl IntoIterator<Item = T>) -> String {
+    let mut facts: Vec<String> = facts.into_iter().map(|fact| fact.to_string()).collect();
+    facts.sort();
+    format!("{{{}}}", facts.join(", "))
+}
+
+/// Identifiers that cannot be declared as variables.
+const KEYWORDS: [&str; 14] = [
+    "div", "mod", "int", "float", "bool", "true", "false", "if", "else", "while", "and", "or",
+    "not", "goto",
+];
+
 type EmptyIoResult = io::Result<()>;
 
 #[derive(Clone, Default)]
@@ -83,6 +330,10 @@ impl<I: Iterator<Item = char>> Iterator for Lexer<I> {
                                 num += (*c as u8 - b'0') as usize;
                                 self.iter.next();
                             }
+                            Some('.') => {
+                                self.iter.next();
+                                return Some((self.fraction(num), self.line));
+                            }
                             _ => break 'num,
                         }
                     }
@@ -96,6 +347,32 @@ impl<I: Iterator<Item = char>> Iterator for Lexer<I> {
                 '(' => return Some((Ok(Token::LParen), self.line)),
                 ')' => return Some((Ok(Token::RParen), self.line)),
                 ';' => return Some((Ok(Token::Semi), self.line)),
+                '{' => return Some((Ok(Token::LBrace), self.line)),
+                '}' => return Some((Ok(Token::RBrace), self.line)),
+                '[' => return Some((Ok(Token::LBracket), self.line)),
+                ']' => return Some((Ok(Token::RBracket), self.line)),
+                ':' if self.iter.peek() == Some(&'=') => {
+                    self.iter.next();
+                    return Some((Ok(Token::Assign), self.line));
+                }
+                ':' => return Some((Ok(Token::Colon), self.line)),
+                '=' => return Some((Ok(Token::Rel(BinaryOp::Eq)), self.line)),
+                '<' => {
+                    let op = match self.iter.peek() {
+                        Some('=') => BinaryOp::Le,
+                        Some('>') => BinaryOp::Ne,
+                        _ => return Some((Ok(Token::Rel(BinaryOp::Lt)), self.line)),
+                    };
+                    self.iter.next();
+                    return Some((Ok(Token::Rel(op)), self.line));
+                }
+                '>' => {
+                    if self.iter.peek() == Some(&'=') {
+                        self.iter.next();
+                        return Some((Ok(Token::Rel(BinaryOp::Ge)), self.line));
+                    }
+                    return Some((Ok(Token::Rel(BinaryOp::Gt)), self.line));
+                }
                 c if c.is_alphabetic() => {
                     let mut ident = String::new();
                     ident.push(c);
@@ -122,6 +399,26 @@ impl<I: Iterator<Item = char>> Iterator for Lexer<I> {
     }
 }
 
+impl<I: Iterator<Item = char>> Lexer<I> {
+    /// Read the digits after the decimal point of a real number.
+    fn fraction(&mut self, whole: usize) -> Result<Token, char> {
+        let mut digits = String::new();
+
+        while let Some(&c @ '0'..='9') = self.iter.peek() {
+            digits.push(c);
+            self.iter.next();
+        }
+
+        if digits.is_empty() {
+            return Err('.');
+        }
+
+        Ok(Token::Real(
+            format!("{}.{}", whole, digits).parse().unwrap(),
+        ))
+    }
+}
+
 type FallibleToken = (Result<Token, char>, usize);
 
 #[derive(Clone, Copy, Debug)]
@@ -133,8 +430,16 @@ enum Token {
     LParen,
     RParen,
     Semi,
+    Assign,
+    Colon,
+    LBrace,
+    RBrace,
+    LBracket,
+    RBracket,
+    Rel(BinaryOp),
 
     Num(usize),
+    Real(f64),
     Sym(usize),
 }
 
@@ -148,7 +453,17 @@ impl fmt::Display for Token {
             Self::LParen => write!(f, "("),
             Self::RParen => write!(f, ")"),
             Self::Semi => write!(f, ";"),
+            Self::Assign => write!(f, ":="),
+            Self::Colon => write!(f, ":"),
+            Self::LBrace => write!(f, "{{"),
+            Self::RBrace => write!(f, "}}"),
+            Self::LBracket => write!(f, "["),
+            Self::RBracket => write!(f, "]"),
+            Self::Rel(BinaryOp::Eq) => write!(f, "="),
+            Self::Rel(BinaryOp::Ne) => write!(f, "<>"),
+            Self::Rel(op) => write!(f, "{}", op),
             Self::Num(n) => write!(f, "{}", n),
+            Self::Real(r) => write!(f, "{:?}", r),
             Self::Sym(i) => write!(f, "<symbol {}>", i),
         }
     }
@@ -157,6 +472,45 @@ impl fmt::Display for Token {
 struct Parser<I: Iterator<Item = FallibleToken>> {
     iter: Peekable<I>,
     symbols: SymTable,
+    /// The types of the variables declared so far, by symbol.
+    declared: HashMap<usize, Type>,
+    program: Program,
+    /// The labels for the instructions that jumps have been backpatched to, by index. They are
+    /// placed in the program once it is complete, so that indices stay valid until then.
+    targets: BTreeMap<usize, Label>,
+    /// The labels of the program for the labels of the source, by symbol.
+    labels: HashMap<usize, Label>,
+}
+
+/// Where an assignment stores its value.
+enum Place {
+    Var(Name),
+    /// An element of an array, at an offset.
+    Element(Name, Operand),
+}
+
+impl fmt::Display for Place {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Var(x) => write!(f, "{}", x),
+            Self::Element(a, _) => write!(f, "{}[...]", a),
+        }
+    }
+}
+
+/// A list of the indices of jump instructions whose targets are not known yet.
+type Holes = Vec<usize>;
+
+/// The target of jumps that have not been backpatched.
+const HOLE: Label = Label(0);
+
+/// The translation of an expression.
+enum Expr {
+    /// The code computes a value into an operand.
+    Value(Operand, Type),
+    /// The code of a Boolean expression jumps to the holes in the first list when it is true, and
+    /// to those in the second when it is false.
+    Jump(Holes, Holes),
 }
 
 impl<I> Parser<I>
@@ -164,86 +518,567 @@ where
     I: Iterator<Item = FallibleToken>,
 {
     fn list(&mut self) -> EmptyIoResult {
+        let mut errors = 0;
+
         while self.peek().is_some() {
-            self.expr()?;
-            self._match(Token::Semi)?;
+            let start = self.program.instrs.len();
+            match self.stmt() {
+                Ok(next) => self.backpatch(&next, self.program.instrs.len()),
+                Err(e) => {
+                    eprintln!("error: {}", e);
+                    errors += 1;
+                    self.program.instrs.truncate(start);
+                    self.targets.split_off(&(start + 1));
+                    self.synchronize();
+                }
+            }
+        }
+
+        // Place the labels from the last one up, so that the indices before it stay valid.
+        for (&index, &label) in self.targets.iter().rev() {
+            self.program.instrs.insert(index, Instr::Label(label));
+        }
+
+        for (&s, &label) in &self.labels {
+            let jumped_to = self
+                .program
+                .instrs
+                .iter()
+                .any(|instr| instr.target() == Some(label));
+            if jumped_to && self.program.position(label).is_none() {
+                eprintln!("error: label `{}` is not defined", self.resolve_sym(s)?);
+                errors += 1;
+            }
         }
 
-        Ok(())
+        if errors == 0 {
+            Ok(())
+        } else {
+            Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("{} statement(s) could not be translated", errors),
+            ))
+        }
     }
 
-    fn expr(&mut self) -> EmptyIoResult {
-        self.term()?;
+    /// Skip past the next semicolon, so that translation can resume with the next statement.
+    fn synchronize(&mut self) {
+        for (token, _) in self.iter.by_ref() {
+            if let Ok(Token::Semi) = token {
+                break;
+            }
+        }
+    }
 
-        while let Some(t) = self.peek() {
-            match t? {
-                Token::Plus => {
-                    self._match(Token::Plus)?;
-                    self.term()?;
-                    println!("+")
+    /// Make the jumps in a list go to the instruction at an index.
+    fn backpatch(&mut self, holes: &[usize], index: usize) {
+        if holes.is_empty() {
+            return;
+        }
+
+        let program = &mut self.program;
+        let label = *self.targets.entry(index).or_insert_with(|| program.label());
+        for &hole in holes {
+            *program.instrs[hole].target_mut().unwrap() = label;
+        }
+    }
+
+    /// Emit a jump with no target yet, and return the list of just that jump.
+    fn jump(&mut self, instr: Instr) -> Holes {
+        vec![self.program.emit(instr)]
+    }
+
+    /// Translate a statement, returning the jumps to whatever follows it.
+    fn stmt(&mut self) -> io::Result<Holes> {
+        match self.peek_non_null()? {
+            Token::LBrace => return self.block(),
+            Token::Sym(s) => match self.resolve_sym(s)?.as_ref() {
+                "if" => return self.if_stmt(),
+                "while" => return self.while_stmt(),
+                "goto" => return self.goto_stmt(),
+                keyword => {
+                    if let Some(typ) = basic_type(keyword) {
+                        self.decl(typ)?;
+                        return Ok(Vec::new());
+                    }
+                    if !KEYWORDS.contains(&keyword) && !self.declared.contains_key(&s) {
+                        return self.labeled_stmt(s);
+                    }
                 }
-                Token::Minus => {
-                    self._match(Token::Minus)?;
-                    self.term()?;
-                    println!("-")
+            },
+            _ => (),
+        }
+
+        let start = self.program.instrs.len();
+        let expr = self.bool_expr()?;
+
+        if let Some(Ok(Token::Assign)) = self.peek() {
+            let line = self.line();
+            let (target, typ) = match expr {
+                Expr::Value(Operand::Name(name @ Name::Var(_)), typ)
+                    if self.program.instrs.len() == start =>
+                {
+                    (Place::Var(name), typ)
                 }
-                _ => break,
+                // The element was loaded by the last instruction, which becomes a store.
+                Expr::Value(Operand::Name(temp), typ) if self.loaded(start, &temp) => {
+                    match self.program.instrs.pop() {
+                        Some(Instr::Load(_, a, offset)) => (Place::Element(a, offset), typ),
+                        _ => unreachable!(),
+                    }
+                }
+                _ => {
+                    return Err(type_error(
+                        line,
+                        "the left side of `:=` is not a variable".to_string(),
+                    ))
+                }
+            };
+
+            self._match(Token::Assign)?;
+            let (value, value_typ) = self.bool_value()?;
+            if !value_typ.widens_to(&typ) {
+                return Err(type_error(
+                    line,
+                    format!(
+                        "cannot assign `{}` to `{}` of type `{}`",
+                        value_typ, target, typ
+                    ),
+                ));
+            }
+            let value = self.convert(value, &value_typ, &typ);
+            self.program.emit(match target {
+                Place::Var(x) => Instr::Copy(x, value),
+                Place::Element(a, offset) => Instr::Store(a, offset, value),
+            });
+        } else {
+            let (value, _) = self.value(expr);
+            self.program.emit(Instr::Param(value));
+            self.program.emit(Instr::Call(None, "print".to_string(), 1));
+        }
+
+        self._match(Token::Semi)?;
+        Ok(Vec::new())
+    }
+
+    /// `{ S S ... }`
+    fn block(&mut self) -> io::Result<Holes> {
+        self._match(Token::LBrace)?;
+        let mut next = Vec::new();
+
+        loop {
+            if let Token::RBrace = self.peek_non_null()? {
+                self._match(Token::RBrace)?;
+                return Ok(next);
+            }
+
+            self.backpatch(&next, self.program.instrs.len());
+            next = self.stmt()?;
+        }
+    }
+
+    /// `if ( B ) S` or `if ( B ) S else S`
+    fn if_stmt(&mut self) -> io::Result<Holes> {
+        self.keyword("if")?;
+        let (truelist, falselist) = self.condition()?;
+
+        self.backpatch(&truelist, self.program.instrs.len());
+        let mut next = self.stmt()?;
+
+        if let Some(Ok(Token::Sym(s))) = self.peek() {
+            if self.resolve_sym(s)? == "else" {
+                self.keyword("else")?;
+                next.extend(self.jump(Instr::Goto(HOLE)));
+                self.backpatch(&falselist, self.program.instrs.len());
+                next.extend(self.stmt()?);
+                return Ok(next);
+            }
+        }
+
+        next.extend(falselist);
+        Ok(next)
+    }
+
+    /// `while ( B ) S`
+    fn while_stmt(&mut self) -> io::Result<Holes> {
+        self.keyword("while")?;
+        let begin = self.program.instrs.len();
+        let (truelist, falselist) = self.condition()?;
+
+        self.backpatch(&truelist, self.program.instrs.len());
+        let body = self.stmt()?;
+        self.backpatch(&body, begin);
+        let back = self.jump(Instr::Goto(HOLE));
+        self.backpatch(&back, begin);
+
+        Ok(falselist)
+    }
+
+    /// `l : S`
+    fn labeled_stmt(&mut self, s: usize) -> io::Result<Holes> {
+        let line = self.line();
+        self._match(Token::Sym(0))?;
+        if !matches!(self.peek(), Some(Ok(Token::Colon))) {
+            return Err(type_error(
+                line,
+                format!("`{}` is not declared", self.resolve_sym(s)?),
+            ));
+        }
+        self._match(Token::Colon)?;
+
+        let label = self.source_label(s);
+        if self.program.position(label).is_some() {
+            return Err(type_error(
+                line,
+                format!("label `{}` is already defined", self.resolve_sym(s)?),
+            ));
+        }
+
+        self.program.emit(Instr::Label(label));
+        self.stmt()
+    }
+
+    /// `goto l ;`
+    fn goto_stmt(&mut self) -> io::Result<Holes> {
+        self.keyword("goto")?;
+        let line = self.line();
+
+        match self.peek_non_null()? {
+            Token::Sym(s) => {
+                let name = self.resolve_sym(s)?;
+                if KEYWORDS.contains(&name.as_str()) || self.declared.contains_key(&s) {
+                    return Err(type_error(line, format!("`{}` is not a label", name)));
+                }
+
+                self._match(Token::Sym(0))?;
+                self._match(Token::Semi)?;
+                let label = self.source_label(s);
+                self.program.emit(Instr::Goto(label));
+                Ok(Vec::new())
+            }
+            _ => Err(Error::new(ErrorKind::InvalidData, "expected a label")),
+        }
+    }
+
+    /// The label of the program for a label of the source.
+    fn source_label(&mut self, s: usize) -> Label {
+        let program = &mut self.program;
+        *self.labels.entry(s).or_insert_with(|| program.label())
+    }
+
+    /// `( B )`, translated into jumping code.
+    fn condition(&mut self) -> io::Result<(Holes, Holes)> {
+        self._match(Token::LParen)?;
+        let line = self.line();
+        let expr = self.bool_expr()?;
+        let jumps = self.jumps(expr, line)?;
+        self._match(Token::RParen)?;
+        Ok(jumps)
+    }
+
+    fn decl(&mut self, typ: Type) -> EmptyIoResult {
+        self._match(Token::Sym(0))?;
+        let line = self.line();
+
+        match self.peek_non_null()? {
+            Token::Sym(s) => {
+                let name = self.resolve_sym(s)?;
+                if KEYWORDS.contains(&name.as_str()) {
+                    return Err(type_error(
+                        line,
+                        format!("`{}` is a keyword, not a variable", name),
+                    ));
+                }
+                if self.declared.contains_key(&s) {
+                    return Err(type_error(line, format!("`{}` is already declared", name)));
+                }
+
+                self._match(Token::Sym(0))?;
+                let typ = if let Some(Ok(Token::LBracket)) = self.peek() {
+                    self._match(Token::LBracket)?;
+                    let len = match self.peek_non_null()? {
+                        Token::Num(n) => n,
+                        _ => {
+                            return Err(Error::new(
+                                ErrorKind::InvalidData,
+                                "expected the number of elements",
+                            ))
+                        }
+                    };
+                    self._match(Token::Num(0))?;
+                    self._match(Token::RBracket)?;
+                    Type::array(len, typ)
+                } else {
+                    typ
+                };
+                self._match(Token::Semi)?;
+                self.declared.insert(s, typ);
+                Ok(())
             }
+            _ => Err(Error::new(
+                ErrorKind::InvalidData,
+                "expected a variable name",
+            )),
         }
+    }
 
-        Ok(())
+    /// A Boolean expression, with its value computed into an operand.
+    fn bool_value(&mut self) -> io::Result<(Operand, Type)> {
+        let expr = self.bool_expr()?;
+        Ok(self.value(expr))
     }
 
-    fn term(&mut self) -> EmptyIoResult {
-        self.factor()?;
+    /// `B or B`
+    fn bool_expr(&mut self) -> io::Result<Expr> {
+        let line = self.line();
+        let mut left = self.bool_term()?;
 
+        while self.at_keyword("or")? {
+            self.keyword("or")?;
+            let (truelist, falselist) = self.jumps(left, line)?;
+            self.backpatch(&falselist, self.program.instrs.len());
+
+            let line = self.line();
+            let right = self.bool_term()?;
+            let (right_true, right_false) = self.jumps(right, line)?;
+            left = Expr::Jump([truelist, right_true].concat(), right_false);
+        }
+
+        Ok(left)
+    }
+
+    /// `B and B`
+    fn bool_term(&mut self) -> io::Result<Expr> {
+        let line = self.line();
+        let mut left = self.bool_factor()?;
+
+        while self.at_keyword("and")? {
+            self.keyword("and")?;
+            let (truelist, falselist) = self.jumps(left, line)?;
+            self.backpatch(&truelist, self.program.instrs.len());
+
+            let line = self.line();
+            let right = self.bool_factor()?;
+            let (right_true, right_false) = self.jumps(right, line)?;
+            left = Expr::Jump(right_true, [falselist, right_false].concat());
+        }
+
+        Ok(left)
+    }
+
+    /// `not B`, `( B )`, or a comparison `E relop E`
+    fn bool_factor(&mut self) -> io::Result<Expr> {
+        let line = self.line();
+
+        if self.at_keyword("not")? {
+            self.keyword("not")?;
+            let operand = self.bool_factor()?;
+            let (truelist, falselist) = self.jumps(operand, line)?;
+            return Ok(Expr::Jump(falselist, truelist));
+        }
+
+        let left = if let Some(Ok(Token::LParen)) = self.peek() {
+            // A parenthesized condition stays jumping code, unless it is an operand.
+            self._match(Token::LParen)?;
+            let inner = self.bool_expr()?;
+            self._match(Token::RParen)?;
+
+            match inner {
+                Expr::Jump(..) if !self.at_operator()? => return Ok(inner),
+                inner => {
+                    let inner = self.value(inner);
+                    let inner = self.term_after(inner)?;
+                    self.expr_after(inner)?
+                }
+            }
+        } else {
+            self.expr()?
+        };
+        let op = match self.peek() {
+            Some(Ok(Token::Rel(op))) => op,
+            _ => return Ok(Expr::Value(left.0, left.1)),
+        };
+
+        let line = self.line();
+        self.iter.next();
+        let right = self.expr()?;
+
+        let (left, right) = if left.1 == Type::BOOL
+            && right.1 == Type::BOOL
+            && (op == BinaryOp::Eq || op == BinaryOp::Ne)
+        {
+            (left.0, right.0)
+        } else {
+            let typ = left.1.max(&right.1).ok_or_else(|| {
+                type_error(
+                    line,
+                    format!(
+                        "cannot compare `{}` with `{}` using `{}`",
+                        left.1,
+                        right.1,
+                        Token::Rel(op)
+                    ),
+                )
+            })?;
+            let left = self.convert(left.0, &left.1, &typ);
+            let right = self.convert(right.0, &right.1, &typ);
+            (left, right)
+        };
+
+        let truelist = self.jump(Instr::IfRel(left, op, right, HOLE));
+        let falselist = self.jump(Instr::Goto(HOLE));
+        Ok(Expr::Jump(truelist, falselist))
+    }
+
+    /// Translate a Boolean expression into jumping code, if it is not already.
+    fn jumps(&mut self, expr: Expr, line: usize) -> io::Result<(Holes, Holes)> {
+        match expr {
+            Expr::Jump(truelist, falselist) => Ok((truelist, falselist)),
+            Expr::Value(Operand::Const(Value::Bool(true)), _) => {
+                Ok((self.jump(Instr::Goto(HOLE)), Vec::new()))
+            }
+            Expr::Value(Operand::Const(Value::Bool(false)), _) => {
+                Ok((Vec::new(), self.jump(Instr::Goto(HOLE))))
+            }
+            Expr::Value(value, Type::BOOL) => {
+                let truelist = self.jump(Instr::If(value, HOLE));
+                let falselist = self.jump(Instr::Goto(HOLE));
+                Ok((truelist, falselist))
+            }
+            Expr::Value(_, typ) => Err(type_error(
+                line,
+                format!("expected a `bool` condition, found `{}`", typ),
+            )),
+        }
+    }
+
+    /// Compute the value of an expression into an operand, if it is jumping code.
+    fn value(&mut self, expr: Expr) -> (Operand, Type) {
+        match expr {
+            Expr::Value(value, typ) => (value, typ),
+            Expr::Jump(truelist, falselist) => {
+                let temp = self.program.temp();
+
+                self.backpatch(&truelist, self.program.instrs.len());
+                self.program
+                    .emit(Instr::Copy(temp.clone(), Operand::Const(Value::Bool(true))));
+                let next = self.jump(Instr::Goto(HOLE));
+
+                self.backpatch(&falselist, self.program.instrs.len());
+                self.program.emit(Instr::Copy(
+                    temp.clone(),
+                    Operand::Const(Value::Bool(false)),
+                ));
+                self.backpatch(&next, self.program.instrs.len());
+
+                (Operand::Name(temp), Type::BOOL)
+            }
+        }
+    }
+
+    fn expr(&mut self) -> io::Result<(Operand, Type)> {
+        let left = self.term()?;
+        self.expr_after(left)
+    }
+
+    /// The rest of an expression whose first term has been translated.
+    fn expr_after(&mut self, mut left: (Operand, Type)) -> io::Result<(Operand, Type)> {
         while let Some(t) = self.peek() {
-            match t? {
+            let op = match t? {
+                Token::Plus => BinaryOp::Add,
+                Token::Minus => BinaryOp::Sub,
+                _ => break,
+            };
+
+            let line = self.line();
+            self.iter.next();
+            let right = self.term()?;
+            left = self.arithmetic(op, line, left, right)?;
+        }
+
+        Ok(left)
+    }
+
+    fn term(&mut self) -> io::Result<(Operand, Type)> {
+        let left = self.factor()?;
+        self.term_after(left)
+    }
+
+    /// The rest of a term whose first factor has been translated.
+    fn term_after(&mut self, mut left: (Operand, Type)) -> io::Result<(Operand, Type)> {
+        while let Some(t) = self.peek() {
+            let line = self.line();
+
+            left = match t? {
                 Token::Times => {
                     self._match(Token::Times)?;
-                    self.factor()?;
-                    println!("*")
+                    let right = self.factor()?;
+                    self.arithmetic(BinaryOp::Mul, line, left, right)?
                 }
                 Token::Div => {
                     self._match(Token::Div)?;
-                    self.factor()?;
-                    println!("/")
+                    let right = self.factor()?;
+                    self.arithmetic(BinaryOp::Div, line, left, right)?
                 }
                 Token::Sym(s) => match self.resolve_sym(s)?.as_ref() {
                     "div" => {
                         self._match(Token::Sym(0))?;
-                        self.factor()?;
-                        println!("DIV")
+                        let right = self.factor()?;
+                        self.integer(BinaryOp::IntDiv, line, left, right)?
                     }
                     "mod" => {
                         self._match(Token::Sym(0))?;
-                        self.factor()?;
-                        println!("MOD")
+                        let right = self.factor()?;
+                        self.integer(BinaryOp::Mod, line, left, right)?
                     }
                     _ => break,
                 },
                 _ => break,
-            }
+            };
         }
 
-        Ok(())
+        Ok(left)
     }
 
-    fn factor(&mut self) -> EmptyIoResult {
+    fn factor(&mut self) -> io::Result<(Operand, Type)> {
+        let line = self.line();
+
         match self.peek_non_null()? {
             Token::LParen => {
                 self._match(Token::LParen)?;
-                self.expr()?;
-                self._match(Token::RParen)
+                let value = self.bool_value()?;
+                self._match(Token::RParen)?;
+                Ok(value)
             }
             Token::Num(n) => {
-                println!("{}", n);
-                self._match(Token::Num(0))
+                self._match(Token::Num(0))?;
+                let n = i64::try_from(n).map_err(|_| {
+                    Error::new(
+                        ErrorKind::InvalidData,
+                        format!("number `{}` on line {} of input is too large", n, line),
+                    )
+                })?;
+                Ok((Operand::Const(Value::Int(n)), Type::INT))
+            }
+            Token::Real(r) => {
+                self._match(Token::Real(0.0))?;
+                Ok((Operand::Const(Value::Float(r)), Type::FLOAT))
             }
             Token::Sym(s) => {
                 let sym = self.resolve_sym(s)?;
-                println!("{}", sym);
-                self._match(Token::Sym(0))
+                self._match(Token::Sym(0))?;
+                match sym.as_ref() {
+                    "true" => Ok((Operand::Const(Value::Bool(true)), Type::BOOL)),
+                    "false" => Ok((Operand::Const(Value::Bool(false)), Type::BOOL)),
+                    _ => match self.declared.get(&s) {
+                        Some(Type::Array(len, element)) => {
+                            let (len, element) = (*len, (**element).clone());
+                            self.element(Name::Var(sym), len, element, line)
+                        }
+                        Some(typ) => Ok((Operand::Name(Name::Var(sym)), typ.clone())),
+                        None => Err(type_error(line, format!("`{}` is not declared", sym))),
+                    },
+                }
             }
             _ => Err(Error::new(
                 ErrorKind::InvalidData,
@@ -252,9 +1087,172 @@ where
         }
     }
 
+    /// `[ E ]` after the name of an array of `len` elements, loaded into a temporary.
+    fn element(
+        &mut self,
+        a: Name,
+        len: usize,
+        element: Type,
+        line: usize,
+    ) -> io::Result<(Operand, Type)> {
+        if !matches!(self.peek(), Some(Ok(Token::LBracket))) {
+            return Err(type_error(
+                line,
+                format!("`{}` is an array, and only its elements are values", a),
+            ));
+        }
+        self._match(Token::LBracket)?;
+        let line = self.line();
+        let (index, typ) = self.expr()?;
+        if typ != Type::INT {
+            return Err(type_error(
+                line,
+                format!("cannot index `{}` with `{}`", a, typ),
+            ));
+        }
+        self._match(Token::RBracket)?;
+
+        let width = Names::new().width(&element).unwrap() as i64;
+        let offset = match index {
+            Operand::Const(Value::Int(n)) if n < 0 || n as usize >= len => {
+                return Err(type_error(
+                    line,
+                    format!("`{}[{}]` is out of bounds, for {} elements", a, n, len),
+                ))
+            }
+            Operand::Const(Value::Int(n)) => match n.checked_mul(width) {
+                Some(offset) => Operand::Const(Value::Int(offset)),
+                None => {
+                    return Err(type_error(
+                        line,
+                        format!("the offset of `{}[{}]` is too large", a, n),
+                    ))
+                }
+            },
+            index => self.binary(BinaryOp::Mul, index, Operand::Const(Value::Int(width))),
+        };
+        let temp = self.program.temp();
+        self.program.emit(Instr::Load(temp.clone(), a, offset));
+        Ok((Operand::Name(temp), element))
+    }
+
+    /// Whether the last instruction, emitted since `start`, loads an element into a temporary.
+    fn loaded(&self, start: usize, temp: &Name) -> bool {
+        self.program.instrs.len() > start
+            && matches!(self.program.instrs.last(), Some(Instr::Load(x, ..)) if x == temp)
+    }
+
+    /// Check the operands of an arithmetic operator, converting the narrower one to the type of
+    /// the other, and emit the instruction that applies it.
+    fn arithmetic(
+        &mut self,
+        op: BinaryOp,
+        line: usize,
+        (left, left_typ): (Operand, Type),
+        (right, right_typ): (Operand, Type),
+    ) -> io::Result<(Operand, Type)> {
+        let typ = left_typ.max(&right_typ).ok_or_else(|| {
+            type_error(
+                line,
+                format!(
+                    "cannot apply `{}` to `{}` and `{}`",
+                    op, left_typ, right_typ
+                ),
+            )
+        })?;
+
+        let left = self.convert(left, &left_typ, &typ);
+        let right = self.convert(right, &right_typ, &typ);
+        Ok((self.binary(op, left, right), typ))
+    }
+
+    /// Check the operands of an operator that only applies to integers.
+    fn integer(
+        &mut self,
+        op: BinaryOp,
+        line: usize,
+        (left, left_typ): (Operand, Type),
+        (right, right_typ): (Operand, Type),
+    ) -> io::Result<(Operand, Type)> {
+        if left_typ != Type::INT || right_typ != Type::INT {
+            return Err(type_error(
+                line,
+                format!(
+                    "cannot apply `{}` to `{}` and `{}`",
+                    op, left_typ, right_typ
+                ),
+            ));
+        }
+
+        Ok((self.binary(op, left, right), Type::INT))
+    }
+
+    /// Emit `t = left op right` for a new temporary `t`.
+    fn binary(&mut self, op: BinaryOp, left: Operand, right: Operand) -> Operand {
+        let temp = self.program.temp();
+        self.program
+            .emit(Instr::Binary(temp.clone(), op, left, right));
+        Operand::Name(temp)
+    }
+
+    /// Widen a value to a type.
+    fn convert(&mut self, value: Operand, from: &Type, to: &Type) -> Operand {
+        if from == to {
+            return value;
+        }
+
+        match value {
+            Operand::Const(Value::Int(n)) => Operand::Const(Value::Float(n as f64)),
+            value => {
+                let temp = self.program.temp();
+                self.program
+                    .emit(Instr::Unary(temp.clone(), UnaryOp::IntToFloat, value));
+                Operand::Name(temp)
+            }
+        }
+    }
+
+    /// Whether the next token is a keyword.
+    fn at_keyword(&mut self, keyword: &str) -> io::Result<bool> {
+        match self.peek() {
+            Some(Ok(Token::Sym(s))) => Ok(self.resolve_sym(s)? == keyword),
+            _ => Ok(false),
+        }
+    }
+
+    /// Whether the next token is an arithmetic or relational operator.
+    fn at_operator(&mut self) -> io::Result<bool> {
+        match self.peek() {
+            Some(Ok(Token::Plus))
+            | Some(Ok(Token::Minus))
+            | Some(Ok(Token::Times))
+            | Some(Ok(Token::Div))
+            | Some(Ok(Token::Rel(_))) => Ok(true),
+            Some(Ok(Token::Sym(_))) => Ok(self.at_keyword("div")? || self.at_keyword("mod")?),
+            _ => Ok(false),
+        }
+    }
+
+    fn keyword(&mut self, keyword: &str) -> EmptyIoResult {
+        if self.at_keyword(keyword)? {
+            self.iter.next();
+            Ok(())
+        } else {
+            Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("expected the keyword `{}`.", keyword),
+            ))
+        }
+    }
+
+    /// The line of the next token.
+    fn line(&mut self) -> usize {
+        self.iter.peek().map_or(0, |(_, line)| *line)
+    }
+
     fn peek_non_null(&mut self) -> io::Result<Token> {
         self.peek()
-            .ok_or_else(|| Error::new(ErrorKind::UnexpectedEof, ""))?
+            .ok_or_else(|| Error::new(ErrorKind::UnexpectedEof, "unexpected end of input"))?
     }
 
     fn peek(&mut self) -> Option<io::Result<Token>> {
@@ -290,3 +1288,20 @@ where
         })
     }
 }
+
+/// The type named by a keyword that starts a declaration.
+fn basic_type(keyword: &str) -> Option<Type> {
+    match keyword {
+        "int" => Some(Type::INT),
+        "float" => Some(Type::FLOAT),
+        "bool" => Some(Type::BOOL),
+        _ => None,
+    }
+}
+
+fn type_error(line: usize, message: String) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("type error on line {}: {}", line, message),
+    )
+}
@@ -0,0 +1,604 @@
+//! Parser combinators over the token streams produced by [`token::Lexer`].
+//!
+//! A parser is any function from an [`Input`] to a [`Reply`], so small parsers are built by
+//! combining others with the functions in this module, and recursive ones are written as plain
+//! functions. The tokens of the input are buffered, which lets a parser go back to an earlier
+//! position.
+//!
+//! Choices are made the way a recursive-descent parser makes them: an alternative that fails
+//! without consuming any tokens lets the next one be tried, and one that fails after consuming
+//! tokens fails the whole choice. Wrapping a parser in [`attempt`] makes it give back the tokens
+//! it consumed when it fails, so that any number of tokens can be looked ahead.
+//!
+//! Whenever a token does not match, what was expected is recorded at its position in the input.
+//! The error that is reported is the one at the furthest position reached, listing everything
+//! that was expected there.
+//!
+//! [`token::Lexer`]: ../token/struct.Lexer.html
+//! [`Input`]: ./struct.Input.html
+//! [`Reply`]: ./type.Reply.html
+//! [`attempt`]: ./fn.attempt.html
+//!
+//! ## Example
+//!
+//! The regular expressions accepted by `nfa2dot`:
+//!
+//! ```
+//! # use dragon::{combinator::*, token::*};
+//! #[derive(Clone, Copy, Debug, PartialEq)]
+//! enum Tok { OpenParen, CloseParen, Star, Pipe, Char }
+//!
+//! #[derive(Default)]
+//! struct Lex;
+//!
+//! impl State for Lex {
+//!     type Token = Tok;
+//!     type Error = ();
+//!
+//!     fn handle_char(&self, c: char) -> Step<Self> {
+//!         match c {
+//!             '(' => Step::Finish(Tok::OpenParen, true),
+//!             ')' => Step::Finish(Tok::CloseParen, true),
+//!             '*' => Step::Finish(Tok::Star, true),
+//!             '|' => Step::Finish(Tok::Pipe, true),
+//!             _ => Step::Finish(Tok::Char, true),
+//!         }
+//!     }
+//!
+//!     fn try_finish(&self) -> Option<Tok> { None }
+//! }
+//!
+//! #[derive(Debug, PartialEq)]
+//! enum Regex {
+//!     Char(char),
+//!     Star(Box<Regex>),
+//!     Concat(Vec<Regex>),
+//!     Alt(Vec<Regex>),
+//! }
+//!
+//! // regex := term ( "|" term )*
+//! fn regex<'src>(input: &mut Input<'src, Tok, ()>) -> Reply<Regex> {
+//!     map(sep_by1(term, token(Tok::Pipe)), Regex::Alt).run(input)
+//! }
+//!
+//! // term := atom+
+//! fn term<'src>(input: &mut Input<'src, Tok, ()>) -> Reply<Regex> {
+//!     map(many1(atom), Regex::Concat).run(input)
+//! }
+//!
+//! // atom := ( char | "(" regex ")" ) "*"?
+//! fn atom<'src>(input: &mut Input<'src, Tok, ()>) -> Reply<Regex> {
+//!     let char = map(token(Tok::Char), |s: &str| Regex::Char(s.chars().next().unwrap()));
+//!     let group = map(
+//!         seq((token(Tok::OpenParen), regex, token(Tok::CloseParen))),
+//!         |(_, regex, _)| regex,
+//!     );
+//!     map(seq((alt((char, group)), opt(token(Tok::Star)))), |(atom, star)| match star {
+//!         Some(_) => Regex::Star(Box::new(atom)),
+//!         None => atom,
+//!     })
+//!     .run(input)
+//! }
+//!
+//! use Regex::*;
+//! assert_eq!(
+//!     parse(regex, lex::<Lex>("a|b*")),
+//!     Ok(Alt(vec![
+//!         Concat(vec![Char('a')]),
+//!         Concat(vec![Star(Box::new(Char('b')))]),
+//!     ])),
+//! );
+//!
+//! let error = parse(regex, lex::<Lex>("a(b|)")).unwrap_err();
+//! assert_eq!(error.message(), "unexpected `)`, expected one of: Char OpenParen");
+//!
+//! // Chains of operators can be grouped either way.
+//! let digit = || map(satisfy("digit", |_, s| s.parse::<i64>().is_ok()), |s| s.parse().unwrap());
+//! let minus = chainl1(digit(), map(lexeme("-"), |_| |a: i64, b: i64| a - b));
+//! assert_eq!(parse(minus, lex::<Lex>("8-3-2")), Ok(3));
+//! let power = chainr1(digit(), map(lexeme("^"), |_| |a: i64, b: i64| a.pow(b as u32)));
+//! assert_eq!(parse(power, lex::<Lex>("2^3^2")), Ok(512));
+//! ```
+
+use {crate::token::TokenResult, std::fmt};
+
+/// Buffered tokens, and the position of the next one to be read.
+pub struct Input<'src, T, E> {
+    tokens: Vec<(T, &'src str)>,
+    /// The error that ended the token stream, if any.
+    lex_error: Option<(E, &'src str)>,
+    position: usize,
+    /// The furthest position where a token did not match, and what was expected there.
+    furthest: usize,
+    expected: Vec<String>,
+}
+
+impl<'src, T, E> Input<'src, T, E> {
+    /// Read every token of a stream, up to the first that could not be recognized.
+    pub fn new<I>(tokens: I) -> Self
+    where
+        I: IntoIterator<Item = TokenResult<'src, T, E>>,
+    {
+        let mut buffer = Vec::new();
+        let mut lex_error = None;
+
+        for (token, lexeme) in tokens {
+            match token {
+                Ok(token) => buffer.push((token, lexeme)),
+                Err(e) => {
+                    lex_error = Some((e, lexeme));
+                    break;
+                }
+            }
+        }
+
+        Self {
+            tokens: buffer,
+            lex_error,
+            position: 0,
+            furthest: 0,
+            expected: Vec::new(),
+        }
+    }
+
+    /// The number of tokens consumed so far.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// The next token and its lexeme, without consuming it.
+    pub fn peek(&self) -> Option<(&T, &'src str)> {
+        self.tokens.get(self.position).map(|(t, s)| (t, *s))
+    }
+
+    /// Record that something else was expected at the current position.
+    pub fn expect(&mut self, what: &str) {
+        if self.position > self.furthest {
+            self.furthest = self.position;
+            self.expected.clear();
+        }
+
+        if self.position == self.furthest && !self.expected.iter().any(|e| e == what) {
+            self.expected.push(what.to_string());
+        }
+    }
+
+    fn into_error(self) -> Error<'src, E> {
+        match self.lex_error {
+            Some((e, s)) if self.furthest == self.tokens.len() => Error::Lex(e, s),
+            _ => Error::Unexpected(
+                self.tokens.get(self.furthest).map(|(_, s)| *s),
+                self.expected,
+            ),
+        }
+    }
+}
+
+/// The result of running a parser.
+pub type Reply<O> = Result<O, Failure>;
+
+/// Returned by a parser that did not match. What it expected is recorded in the [`Input`].
+///
+/// [`Input`]: ./struct.Input.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Failure;
+
+/// Reasons the input could not be parsed.
+#[derive(Debug, PartialEq)]
+pub enum Error<'src, E> {
+    /// The lexer could not recognize a token.
+    Lex(E, &'src str),
+    /// A token, or the end of the input, appeared where it was not allowed. What would have been
+    /// allowed is listed.
+    Unexpected(Option<&'src str>, Vec<String>),
+}
+
+impl<'src, E: fmt::Debug> Error<'src, E> {
+    /// Describe this error.
+    pub fn message(&self) -> String {
+        match self {
+            Self::Lex(e, s) => format!("could not recognize a token at `{}` ({:?})", s, e),
+            Self::Unexpected(found, expected) => {
+                let found = found.map_or("end of input".to_string(), |s| format!("`{}`", s));
+
+                if expected.is_empty() {
+                    format!("unexpected {}", found)
+                } else {
+                    format!(
+                        "unexpected {}, expected one of: {}",
+                        found,
+                        expected.join(" ")
+                    )
+                }
+            }
+        }
+    }
+}
+
+/// Something that recognizes a prefix of its input.
+///
+/// This is implemented for every function with the right signature, so parsers can be closures
+/// or named functions.
+pub trait Parser<'src, T, E> {
+    /// The value produced by a successful parse.
+    type Output;
+
+    /// Consume tokens from the input, and produce a value from them.
+    fn run(&self, input: &mut Input<'src, T, E>) -> Reply<Self::Output>;
+}
+
+impl<'src, T, E, O, F> Parser<'src, T, E> for F
+where
+    F: Fn(&mut Input<'src, T, E>) -> Reply<O>,
+{
+    type Output = O;
+
+    fn run(&self, input: &mut Input<'src, T, E>) -> Reply<O> {
+        self(input)
+    }
+}
+
+/// Run a parser over a stream of tokens, which it must consume entirely.
+pub fn parse<'src, T, E, P, I>(parser: P, tokens: I) -> Result<P::Output, Error<'src, E>>
+where
+    P: Parser<'src, T, E>,
+    I: IntoIterator<Item = TokenResult<'src, T, E>>,
+{
+    let mut input = Input::new(tokens);
+    let reply = parser.run(&mut input);
+
+    match reply.and_then(|output| end().run(&mut input).map(|()| output)) {
+        Ok(output) => Ok(output),
+        Err(Failure) => Err(input.into_error()),
+    }
+}
+
+/// Match a token for which a predicate holds, producing its lexeme. The description is reported
+/// as what was expected when the predicate does not hold.
+pub fn satisfy<'src, T, E, F>(
+    what: &str,
+    predicate: F,
+) -> impl Parser<'src, T, E, Output = &'src str>
+where
+    F: Fn(&T, &'src str) -> bool,
+{
+    let what = what.to_string();
+    move |input: &mut Input<'src, T, E>| match input.peek() {
+        Some((token, lexeme)) if predicate(token, lexeme) => {
+            input.position += 1;
+            Ok(lexeme)
+        }
+        _ => {
+            input.expect(&what);
+            Err(Failure)
+        }
+    }
+}
+
+/// Match a token of a kind, producing its lexeme.
+pub fn token<'src, T, E>(kind: T) -> impl Parser<'src, T, E, Output = &'src str>
+where
+    T: PartialEq + fmt::Debug,
+{
+    satisfy(&format!("{:?}", kind), move |token, _| *token == kind)
+}
+
+/// Match a token with some exact text, producing its lexeme.
+pub fn lexeme<'src, T, E>(text: &str) -> impl Parser<'src, T, E, Output = &'src str> {
+    let text = text.to_string();
+    satisfy(&text.clone(), move |_, lexeme| lexeme == text)
+}
+
+/// Match the end of the input.
+pub fn end<'src, T, E>() -> impl Parser<'src, T, E, Output = ()> {
+    |input: &mut Input<'src, T, E>| {
+        if input.position == input.tokens.len() && input.lex_error.is_none() {
+            Ok(())
+        } else {
+            input.expect("$");
+            Err(Failure)
+        }
+    }
+}
+
+/// Transform the value produced by a parser.
+pub fn map<'src, T, E, P, F, O>(parser: P, f: F) -> impl Parser<'src, T, E, Output = O>
+where
+    P: Parser<'src, T, E>,
+    F: Fn(P::Output) -> O,
+{
+    move |input: &mut Input<'src, T, E>| parser.run(input).map(&f)
+}
+
+/// Run several parsers one after another, producing a tuple of their values.
+pub fn seq<'src, T, E, S>(parsers: S) -> impl Parser<'src, T, E, Output = S::Output>
+where
+    S: Sequence<'src, T, E>,
+{
+    move |input: &mut Input<'src, T, E>| parsers.run_all(input)
+}
+
+/// Try several parsers in order, producing the value of the first that matches.
+///
+/// A parser that fails after consuming tokens is not followed by the rest, unless it is wrapped
+/// in [`attempt`](./fn.attempt.html).
+pub fn alt<'src, T, E, C>(parsers: C) -> impl Parser<'src, T, E, Output = C::Output>
+where
+    C: Choice<'src, T, E>,
+{
+    move |input: &mut Input<'src, T, E>| parsers.run_any(input)
+}
+
+/// Tuples of parsers to run in sequence, with [`seq`](./fn.seq.html).
+pub trait Sequence<'src, T, E> {
+    /// A tuple of the values of each parser.
+    type Output;
+
+    /// Run each parser in turn.
+    fn run_all(&self, input: &mut Input<'src, T, E>) -> Reply<Self::Output>;
+}
+
+/// Tuples of parsers to choose between, with [`alt`](./fn.alt.html).
+pub trait Choice<'src, T, E> {
+    /// The value produced by every parser.
+    type Output;
+
+    /// Run the parsers until one matches or consumes a token.
+    fn run_any(&self, input: &mut Input<'src, T, E>) -> Reply<Self::Output>;
+}
+
+macro_rules! tuples {
+    ($($parser:ident $value:ident),+) => {
+        impl<'src, T, E, $($parser),+> Sequence<'src, T, E> for ($($parser,)+)
+        where
+            $($parser: Parser<'src, T, E>),+
+        {
+            type Output = ($(<$parser as Parser<'src, T, E>>::Output,)+);
+
+            fn run_all(&self, input: &mut Input<'src, T, E>) -> Reply<Self::Output> {
+                let ($($value,)+) = self;
+                Ok(($($value.run(input)?,)+))
+            }
+        }
+
+        impl<'src, T, E, O, $($parser),+> Choice<'src, T, E> for ($($parser,)+)
+        where
+            $($parser: Parser<'src, T, E, Output = O>),+
+        {
+            type Output = O;
+
+            fn run_any(&self, input: &mut Input<'src, T, E>) -> Reply<O> {
+                let start = input.position;
+                let ($($value,)+) = self;
+                $(
+                    match $value.run(input) {
+                        Err(Failure) if input.position == start => (),
+                        reply => return reply,
+                    }
+                )+
+                Err(Failure)
+            }
+        }
+    };
+}
+
+tuples!(A a, B b);
+tuples!(A a, B b, C c);
+tuples!(A a, B b, C c, D d);
+tuples!(A a, B b, C c, D d, F f);
+tuples!(A a, B b, C c, D d, F f, G g);
+
+/// Match a parser zero or more times.
+pub fn many<'src, T, E, P>(parser: P) -> impl Parser<'src, T, E, Output = Vec<P::Output>>
+where
+    P: Parser<'src, T, E>,
+{
+    move |input: &mut Input<'src, T, E>| {
+        let mut values = Vec::new();
+        repeat(&parser, input, &mut values)?;
+        Ok(values)
+    }
+}
+
+/// Match a parser one or more times.
+pub fn many1<'src, T, E, P>(parser: P) -> impl Parser<'src, T, E, Output = Vec<P::Output>>
+where
+    P: Parser<'src, T, E>,
+{
+    move |input: &mut Input<'src, T, E>| {
+        let mut values = vec![parser.run(input)?];
+        repeat(&parser, input, &mut values)?;
+        Ok(values)
+    }
+}
+
+/// Run a parser until it fails without consuming any tokens, or stops consuming them.
+fn repeat<'src, T, E, P>(
+    parser: &P,
+    input: &mut Input<'src, T, E>,
+    values: &mut Vec<P::Output>,
+) -> Reply<()>
+where
+    P: Parser<'src, T, E>,
+{
+    loop {
+        let start = input.position;
+        match parser.run(input) {
+            Ok(value) => {
+                values.push(value);
+                if input.position == start {
+                    return Ok(());
+                }
+            }
+            Err(Failure) if input.position == start => return Ok(()),
+            Err(Failure) => return Err(Failure),
+        }
+    }
+}
+
+/// Match a parser, or nothing.
+pub fn opt<'src, T, E, P>(parser: P) -> impl Parser<'src, T, E, Output = Option<P::Output>>
+where
+    P: Parser<'src, T, E>,
+{
+    move |input: &mut Input<'src, T, E>| {
+        let start = input.position;
+        match parser.run(input) {
+            Ok(value) => Ok(Some(value)),
+            Err(Failure) if input.position == start => Ok(None),
+            Err(Failure) => Err(Failure),
+        }
+    }
+}
+
+/// Match a parser zero or more times, with a separator between each match.
+pub fn sep_by<'src, T, E, P, S>(
+    parser: P,
+    separator: S,
+) -> impl Parser<'src, T, E, Output = Vec<P::Output>>
+where
+    P: Parser<'src, T, E>,
+    S: Parser<'src, T, E>,
+{
+    move |input: &mut Input<'src, T, E>| {
+        let start = input.position;
+        match parser.run(input) {
+            Ok(first) => separated(first, &parser, &separator, input),
+            Err(Failure) if input.position == start => Ok(Vec::new()),
+            Err(Failure) => Err(Failure),
+        }
+    }
+}
+
+/// Match a parser one or more times, with a separator between each match.
+pub fn sep_by1<'src, T, E, P, S>(
+    parser: P,
+    separator: S,
+) -> impl Parser<'src, T, E, Output = Vec<P::Output>>
+where
+    P: Parser<'src, T, E>,
+    S: Parser<'src, T, E>,
+{
+    move |input: &mut Input<'src, T, E>| {
+        let first = parser.run(input)?;
+        separated(first, &parser, &separator, input)
+    }
+}
+
+/// The matches of a parser after the first, for as long as a separator comes before them.
+fn separated<'src, T, E, P, S>(
+    first: P::Output,
+    parser: &P,
+    separator: &S,
+    input: &mut Input<'src, T, E>,
+) -> Reply<Vec<P::Output>>
+where
+    P: Parser<'src, T, E>,
+    S: Parser<'src, T, E>,
+{
+    let mut values = vec![first];
+    loop {
+        let start = input.position;
+        match separator.run(input) {
+            Ok(_) => values.push(parser.run(input)?),
+            Err(Failure) if input.position == start => return Ok(values),
+            Err(Failure) => return Err(Failure),
+        }
+    }
+}
+
+/// Match one or more operands separated by operators, combining them from the left with the
+/// functions the operators produce.
+pub fn chainl1<'src, T, E, P, Op, F>(
+    operand: P,
+    operator: Op,
+) -> impl Parser<'src, T, E, Output = P::Output>
+where
+    P: Parser<'src, T, E>,
+    Op: Parser<'src, T, E, Output = F>,
+    F: Fn(P::Output, P::Output) -> P::Output,
+{
+    move |input: &mut Input<'src, T, E>| {
+        let mut lhs = operand.run(input)?;
+        loop {
+            let start = input.position;
+            match operator.run(input) {
+                Ok(combine) => lhs = combine(lhs, operand.run(input)?),
+                Err(Failure) if input.position == start => return Ok(lhs),
+                Err(Failure) => return Err(Failure),
+            }
+        }
+    }
+}
+
+/// Match one or more operands separated by operators, combining them from the right with the
+/// functions the operators produce.
+pub fn chainr1<'src, T, E, P, Op, F>(
+    operand: P,
+    operator: Op,
+) -> impl Parser<'src, T, E, Output = P::Output>
+where
+    P: Parser<'src, T, E>,
+    Op: Parser<'src, T, E, Output = F>,
+    F: Fn(P::Output, P::Output) -> P::Output,
+{
+    move |input: &mut Input<'src, T, E>| {
+        let mut operands = vec![operand.run(input)?];
+        let mut operators = Vec::new();
+        loop {
+            let start = input.position;
+            match operator.run(input) {
+                Ok(combine) => {
+                    operators.push(combine);
+                    operands.push(operand.run(input)?);
+                }
+                Err(Failure) if input.position == start => break,
+                Err(Failure) => return Err(Failure),
+            }
+        }
+
+        let mut rhs = operands.pop().unwrap();
+        while let Some(combine) = operators.pop() {
+            rhs = combine(operands.pop().unwrap(), rhs);
+        }
+        Ok(rhs)
+    }
+}
+
+/// Run a parser, going back to where it started if it fails, so that it can be followed by
+/// other alternatives.
+pub fn attempt<'src, T, E, P>(parser: P) -> impl Parser<'src, T, E, Output = P::Output>
+where
+    P: Parser<'src, T, E>,
+{
+    move |input: &mut Input<'src, T, E>| {
+        let start = input.position;
+        let reply = parser.run(input);
+        if reply.is_err() {
+            input.position = start;
+        }
+        reply
+    }
+}
+
+/// Report a parser that consumes nothing as expecting a description, instead of the tokens it
+/// would have started with.
+pub fn label<'src, T, E, P>(parser: P, what: &str) -> impl Parser<'src, T, E, Output = P::Output>
+where
+    P: Parser<'src, T, E>,
+{
+    let what = what.to_string();
+    move |input: &mut Input<'src, T, E>| {
+        let start = input.position;
+        let furthest = input.furthest;
+        let expected = input.expected.clone();
+
+        let reply = parser.run(input);
+        if input.position == start && input.furthest <= start {
+            input.furthest = furthest;
+            input.expected = expected;
+            input.expect(&what);
+        }
+        reply
+    }
+}
@@ -0,0 +1,676 @@
+//! Concrete syntax trees that keep every character of the input, for tools such as formatters
+//! and refactorings.
+//!
+//! A tree is stored in two layers. The *green* tree holds the kinds of nodes and tokens and their
+//! text, including the [trivia] of each token, but no positions: a [`GreenNode`] is immutable,
+//! knows only its children, and can be shared by any number of trees. Replacing part of a tree
+//! builds new green nodes on the path from it to the root, and reuses everything else.
+//!
+//! The *red* tree is a view of a green tree for navigating it. A [`SyntaxNode`] knows its parent
+//! and where it starts in the text, and is created on demand while walking down from the root.
+//!
+//! Green trees are built with a [`Builder`], or from a [`parse::Tree`] with [`from_tree`].
+//!
+//! [trivia]: ../token/struct.Lexer.html#method.with_trivia
+//! [`GreenNode`]: ./struct.GreenNode.html
+//! [`SyntaxNode`]: ./struct.SyntaxNode.html
+//! [`Builder`]: ./struct.Builder.html
+//! [`parse::Tree`]: ../parse/enum.Tree.html
+//! [`from_tree`]: ./fn.from_tree.html
+//!
+//! ## Example
+//!
+//! ```
+//! # use dragon::{cst::*, grammar::*, parse::lr::*, token::*};
+//! # #[derive(Default)]
+//! # enum Lex { #[default] Start, Id, Comment }
+//! # #[derive(Clone, Copy)]
+//! # enum Tok { Id, Punct }
+//! # impl Terminal for Tok {
+//! #     fn name<'a>(&'a self, lexeme: &'a str) -> &'a str {
+//! #         match self { Self::Id => "id", Self::Punct => lexeme }
+//! #     }
+//! # }
+//! # impl State for Lex {
+//! #     type Token = Tok;
+//! #     type Error = char;
+//! #     fn handle_char(&self, c: char) -> Step<Self> {
+//! #         match (self, c) {
+//! #             (Self::Comment, '\n') => Step::Discard,
+//! #             (Self::Comment, _) => Step::Continue(None),
+//! #             (Self::Start, '#') => Step::Continue(Some(Self::Comment)),
+//! #             (Self::Start, 'a'..='z') => Step::Continue(Some(Self::Id)),
+//! #             (Self::Id, 'a'..='z') => Step::Continue(None),
+//! #             (Self::Id, _) => Step::Finish(Tok::Id, false),
+//! #             (_, c) if c.is_whitespace() => Step::Discard,
+//! #             (_, _) => Step::Finish(Tok::Punct, true),
+//! #         }
+//! #     }
+//! #     fn try_finish(&self) -> Option<Tok> {
+//! #         match self { Self::Id => Some(Tok::Id), _ => None }
+//! #     }
+//! # }
+//! let grammar: Grammar = "E -> E + T | T \n T -> id".parse().unwrap();
+//! let table = Table::new(&grammar, Method::Lalr);
+//!
+//! let src = "a + b  # the sum\n  + c\n";
+//! let tokens = lex::<Lex>(src).with_trivia().collect::<Vec<_>>();
+//! let tree = table.parse(tokens.iter().filter_map(|t| t.token)).unwrap();
+//!
+//! let green = from_tree(&grammar, &tree, &tokens);
+//! assert_eq!(green.text(), src);
+//!
+//! let root = SyntaxNode::new_root(green);
+//! let b = root.tokens().into_iter().find(|t| t.text() == "b").unwrap();
+//! assert_eq!(b.trailing(), "  # the sum\n");
+//! assert_eq!(b.offset(), 4);
+//! assert_eq!(grammar.name(b.parent().kind()), "T");
+//!
+//! // Renaming `b` keeps the comment after it.
+//! let renamed = b.replace_with(GreenToken::new(b.kind(), b.leading(), "total", b.trailing()));
+//! assert_eq!(renamed.text(), "a + total  # the sum\n  + c\n");
+//! assert_eq!(root.text(), src);
+//! ```
+
+use {
+    crate::{
+        grammar::{Grammar, Symbol},
+        parse::{Token, Tree},
+        token::Lossless,
+    },
+    std::{fmt, mem, ops::Range, rc::Rc},
+};
+
+/// A token of a green tree: its kind, its text, and the trivia around it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GreenToken<K> {
+    kind: K,
+    leading: String,
+    text: String,
+    trailing: String,
+}
+
+impl<K: Copy> GreenToken<K> {
+    /// Create a token with trivia before and after its text.
+    pub fn new(kind: K, leading: &str, text: &str, trailing: &str) -> Self {
+        Self {
+            kind,
+            leading: leading.to_string(),
+            text: text.to_string(),
+            trailing: trailing.to_string(),
+        }
+    }
+
+    /// The kind of this token.
+    pub fn kind(&self) -> K {
+        self.kind
+    }
+
+    /// The text of this token, without its trivia.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The trivia before this token.
+    pub fn leading(&self) -> &str {
+        &self.leading
+    }
+
+    /// The trivia after this token.
+    pub fn trailing(&self) -> &str {
+        &self.trailing
+    }
+
+    /// The length of this token's text, including its trivia.
+    pub fn len(&self) -> usize {
+        self.leading.len() + self.text.len() + self.trailing.len()
+    }
+
+    /// Whether this token has neither text nor trivia, as when it was inserted by error recovery.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn write_text(&self, out: &mut String) {
+        out.push_str(&self.leading);
+        out.push_str(&self.text);
+        out.push_str(&self.trailing);
+    }
+}
+
+/// A child of a green node.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GreenElement<K> {
+    /// An interior node.
+    Node(Rc<GreenNode<K>>),
+    /// A token.
+    Token(Rc<GreenToken<K>>),
+}
+
+impl<K: Copy> GreenElement<K> {
+    /// The kind of this node or token.
+    pub fn kind(&self) -> K {
+        match self {
+            Self::Node(node) => node.kind(),
+            Self::Token(token) => token.kind(),
+        }
+    }
+
+    /// The length of the text of this node or token, including trivia.
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Node(node) => node.len(),
+            Self::Token(token) => token.len(),
+        }
+    }
+
+    /// Whether this node or token covers no text at all.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn write_text(&self, out: &mut String) {
+        match self {
+            Self::Node(node) => node.write_text(out),
+            Self::Token(token) => token.write_text(out),
+        }
+    }
+}
+
+/// An interior node of a green tree: its kind and its children.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GreenNode<K> {
+    kind: K,
+    len: usize,
+    children: Vec<GreenElement<K>>,
+}
+
+impl<K: Copy> GreenNode<K> {
+    /// Create a node with some children.
+    pub fn new(kind: K, children: Vec<GreenElement<K>>) -> Self {
+        Self {
+            kind,
+            len: children.iter().map(GreenElement::len).sum(),
+            children,
+        }
+    }
+
+    /// The kind of this node.
+    pub fn kind(&self) -> K {
+        self.kind
+    }
+
+    /// The length of the text of this node, including trivia.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether this node covers no text at all.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The children of this node.
+    pub fn children(&self) -> &[GreenElement<K>] {
+        &self.children
+    }
+
+    /// The exact text this node was built from.
+    pub fn text(&self) -> String {
+        let mut out = String::with_capacity(self.len);
+        self.write_text(&mut out);
+        out
+    }
+
+    fn write_text(&self, out: &mut String) {
+        self.children.iter().for_each(|child| child.write_text(out));
+    }
+
+    /// A copy of this node with one child replaced.
+    fn with_child(&self, index: usize, child: GreenElement<K>) -> Self {
+        let mut children = self.children.clone();
+        children[index] = child;
+        Self::new(self.kind, children)
+    }
+}
+
+/// Builds a green tree from the top down, as a parser recognizes it.
+pub struct Builder<K> {
+    /// The kind of each node that was started but not finished, and the index of its first child
+    /// in `children`.
+    parents: Vec<(K, usize)>,
+    children: Vec<GreenElement<K>>,
+}
+
+impl<K: Copy> Default for Builder<K> {
+    fn default() -> Self {
+        Self {
+            parents: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+}
+
+impl<K: Copy> Builder<K> {
+    /// Create a builder with no nodes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start a node. Everything added until it is finished becomes its children.
+    pub fn start_node(&mut self, kind: K) {
+        self.parents.push((kind, self.children.len()));
+    }
+
+    /// Add a token to the current node.
+    pub fn token(&mut self, token: GreenToken<K>) {
+        self.children.push(GreenElement::Token(Rc::new(token)));
+    }
+
+    /// Finish the current node.
+    ///
+    /// # Panics
+    ///
+    /// If no node was started.
+    pub fn finish_node(&mut self) {
+        let (kind, first) = self.parents.pop().expect("no node to finish");
+        let children = self.children.split_off(first);
+        self.children
+            .push(GreenElement::Node(Rc::new(GreenNode::new(kind, children))));
+    }
+
+    /// The root of the tree.
+    ///
+    /// # Panics
+    ///
+    /// If a node was not finished, or there is not exactly one node at the top level.
+    pub fn finish(mut self) -> Rc<GreenNode<K>> {
+        assert!(self.parents.is_empty(), "a node was not finished");
+        match (self.children.pop(), self.children.is_empty()) {
+            (Some(GreenElement::Node(root)), true) => root,
+            _ => panic!("a tree must have exactly one root node"),
+        }
+    }
+}
+
+/// Build a green tree from a parse tree and the tokens it was parsed from, labeling each node
+/// with the nonterminal on the left side of its production.
+///
+/// Leaves are matched with the tokens whose lexemes they hold. Tokens with no leaf, such as those
+/// discarded by error recovery, become the leading trivia of the `error` token shifted in their
+/// place, so that the text of the tree is still the whole input. Without one, they go to the next
+/// token in the tree, or to the last one if none follows. If no token in the tree was read from
+/// the input, the text is kept by an end-of-input token added to the root. Symbols inserted by
+/// error recovery become empty tokens and nodes.
+///
+/// ```
+/// # use dragon::{cst::*, grammar::*, parse::lr::*, token::*};
+/// # #[derive(Default)]
+/// # struct Lex;
+/// # #[derive(Clone, Copy)]
+/// # struct Punct;
+/// # impl Terminal for Punct {
+/// #     fn name<'a>(&'a self, lexeme: &'a str) -> &'a str { lexeme }
+/// # }
+/// # impl State for Lex {
+/// #     type Token = Punct;
+/// #     type Error = ();
+/// #     fn handle_char(&self, c: char) -> Step<Self> {
+/// #         if c.is_whitespace() { Step::Discard } else { Step::Finish(Punct, true) }
+/// #     }
+/// #     fn try_finish(&self) -> Option<Punct> { None }
+/// # }
+/// let grammar: Grammar = "L -> L S | S \n S -> x ; | error ;".parse().unwrap();
+/// let table = Table::new(&grammar, Method::Lalr);
+///
+/// let src = "x ; x x x ; x ;\n";
+/// let tokens = lex::<Lex>(src).with_trivia().collect::<Vec<_>>();
+/// let (tree, diagnostics) = table.parse_with_recovery(tokens.iter().filter_map(|t| t.token));
+/// assert_eq!(diagnostics.len(), 1);
+///
+/// let green = from_tree(&grammar, &tree.unwrap(), &tokens);
+/// assert_eq!(green.text(), src);
+///
+/// let error = SyntaxNode::new_root(green).tokens()[2].clone();
+/// assert_eq!(grammar.name(error.kind()), "error");
+/// assert_eq!(error.leading(), "x x x ");
+/// ```
+pub fn from_tree<T, E>(
+    grammar: &Grammar,
+    tree: &Tree,
+    tokens: &[Lossless<'_, T, E>],
+) -> Rc<GreenNode<Symbol>> {
+    let mut leaves = Vec::new();
+    collect_leaves(tree, &mut leaves);
+    let (green, rest) = match_tokens(&leaves, tokens);
+
+    let mut builder = Builder::new();
+    add_tree(&mut builder, grammar, tree, &mut green.into_iter());
+    let root = builder.finish();
+
+    if rest.is_empty() {
+        root
+    } else {
+        // No token in the tree was read from the input, so the text is kept by an end marker.
+        let mut children = root.children().to_vec();
+        let end = GreenToken::new(Symbol::Terminal(Grammar::END), &rest, "", "");
+        children.push(GreenElement::Token(Rc::new(end)));
+        Rc::new(GreenNode::new(root.kind(), children))
+    }
+}
+
+fn collect_leaves<'t, 'src>(tree: &'t Tree<'src>, leaves: &mut Vec<&'t Token<'src>>) {
+    match tree {
+        Tree::Leaf(leaf) => leaves.push(leaf),
+        Tree::Node(_, children) => children
+            .iter()
+            .for_each(|child| collect_leaves(child, leaves)),
+        Tree::Missing(_) => (),
+    }
+}
+
+/// The green token for each leaf, and any text that could not be given to one because no leaf was
+/// read from the input.
+fn match_tokens<T, E>(
+    leaves: &[&Token],
+    tokens: &[Lossless<'_, T, E>],
+) -> (Vec<GreenToken<Symbol>>, String) {
+    let mut green: Vec<GreenToken<Symbol>> = Vec::with_capacity(leaves.len());
+    let mut skipped = String::new();
+    let mut next = 0;
+    // The last leaf matched with a token, and the first empty one after it, such as `error`.
+    let mut last = None;
+    let mut empty: Option<usize> = None;
+
+    for leaf in leaves {
+        let kind = Symbol::Terminal(leaf.terminal);
+        let found = tokens[next..].iter().position(|token| match token.token {
+            Some((_, lexeme)) => {
+                !lexeme.is_empty()
+                    && lexeme.as_ptr() == leaf.lexeme.as_ptr()
+                    && lexeme.len() == leaf.lexeme.len()
+            }
+            None => false,
+        });
+
+        match found {
+            Some(i) => {
+                tokens[next..next + i]
+                    .iter()
+                    .for_each(|token| write_lossless(token, &mut skipped));
+                let token = &tokens[next + i];
+                let leading = match empty.take() {
+                    Some(empty) => {
+                        green[empty].leading = mem::take(&mut skipped);
+                        token.leading.to_string()
+                    }
+                    None => mem::take(&mut skipped) + token.leading,
+                };
+
+                green.push(GreenToken::new(kind, &leading, leaf.lexeme, token.trailing));
+                last = Some(green.len() - 1);
+                next += i + 1;
+            }
+            None => {
+                if leaf.lexeme.is_empty() && empty.is_none() {
+                    empty = Some(green.len());
+                }
+                green.push(GreenToken::new(kind, "", leaf.lexeme, ""));
+            }
+        }
+    }
+
+    tokens[next..]
+        .iter()
+        .for_each(|token| write_lossless(token, &mut skipped));
+    match (empty, last) {
+        (Some(empty), _) => green[empty].leading = skipped,
+        (None, Some(last)) => green[last].trailing.push_str(&skipped),
+        (None, None) => return (green, skipped),
+    }
+    (green, String::new())
+}
+
+fn write_lossless<T, E>(token: &Lossless<'_, T, E>, out: &mut String) {
+    out.push_str(token.leading);
+    if let Some((_, lexeme)) = token.token {
+        out.push_str(lexeme);
+    }
+    out.push_str(token.trailing);
+}
+
+fn add_tree(
+    builder: &mut Builder<Symbol>,
+    grammar: &Grammar,
+    tree: &Tree,
+    leaves: &mut impl Iterator<Item = GreenToken<Symbol>>,
+) {
+    match tree {
+        Tree::Leaf(_) => builder.token(leaves.next().expect("a token for every leaf")),
+        Tree::Node(production, children) => {
+            let lhs = grammar.productions()[*production].lhs;
+            builder.start_node(Symbol::Nonterminal(lhs));
+            for child in children {
+                add_tree(builder, grammar, child, leaves);
+            }
+            builder.finish_node();
+        }
+        Tree::Missing(symbol @ Symbol::Terminal(_)) => {
+            builder.token(GreenToken::new(*symbol, "", "", ""))
+        }
+        Tree::Missing(symbol) => {
+            builder.start_node(*symbol);
+            builder.finish_node();
+        }
+    }
+}
+
+/// A node of a red tree: a green node, along with its parent and its position in the text.
+pub struct SyntaxNode<K>(Rc<NodeData<K>>);
+
+struct NodeData<K> {
+    green: Rc<GreenNode<K>>,
+    parent: Option<SyntaxNode<K>>,
+    /// The index of this node among the children of its parent.
+    index: usize,
+    offset: usize,
+}
+
+impl<K> Clone for SyntaxNode<K> {
+    fn clone(&self) -> Self {
+        Self(Rc::clone(&self.0))
+    }
+}
+
+impl<K: Copy> SyntaxNode<K> {
+    /// View a green tree from its root.
+    pub fn new_root(green: Rc<GreenNode<K>>) -> Self {
+        Self(Rc::new(NodeData {
+            green,
+            parent: None,
+            index: 0,
+            offset: 0,
+        }))
+    }
+
+    /// The green node this node views.
+    pub fn green(&self) -> &Rc<GreenNode<K>> {
+        &self.0.green
+    }
+
+    /// The kind of this node.
+    pub fn kind(&self) -> K {
+        self.0.green.kind()
+    }
+
+    /// The node this node is a child of, unless it is the root.
+    pub fn parent(&self) -> Option<SyntaxNode<K>> {
+        self.0.parent.clone()
+    }
+
+    /// The position in the text where this node starts, including trivia.
+    pub fn offset(&self) -> usize {
+        self.0.offset
+    }
+
+    /// The span of the text covered by this node, including trivia.
+    pub fn range(&self) -> Range<usize> {
+        self.0.offset..self.0.offset + self.0.green.len()
+    }
+
+    /// The exact text covered by this node.
+    pub fn text(&self) -> String {
+        self.0.green.text()
+    }
+
+    /// The children of this node.
+    pub fn children(&self) -> Vec<SyntaxElement<K>> {
+        let mut offset = self.0.offset;
+        let mut children = Vec::new();
+
+        for (index, child) in self.0.green.children().iter().enumerate() {
+            children.push(match child {
+                GreenElement::Node(green) => SyntaxElement::Node(Self(Rc::new(NodeData {
+                    green: Rc::clone(green),
+                    parent: Some(self.clone()),
+                    index,
+                    offset,
+                }))),
+                GreenElement::Token(green) => SyntaxElement::Token(SyntaxToken {
+                    green: Rc::clone(green),
+                    parent: self.clone(),
+                    index,
+                    offset,
+                }),
+            });
+            offset += child.len();
+        }
+
+        children
+    }
+
+    /// Every token under this node, in the order they appear in the text.
+    pub fn tokens(&self) -> Vec<SyntaxToken<K>> {
+        let mut tokens = Vec::new();
+        for child in self.children() {
+            match child {
+                SyntaxElement::Node(node) => tokens.extend(node.tokens()),
+                SyntaxElement::Token(token) => tokens.push(token),
+            }
+        }
+        tokens
+    }
+
+    /// The root of a new tree, in which this node is replaced by another. The rest of the tree is
+    /// shared with this one.
+    pub fn replace_with(&self, green: Rc<GreenNode<K>>) -> SyntaxNode<K> {
+        self.replace_element(GreenElement::Node(green))
+    }
+
+    fn replace_element(&self, element: GreenElement<K>) -> SyntaxNode<K> {
+        match &self.0.parent {
+            None => match element {
+                GreenElement::Node(green) => Self::new_root(green),
+                GreenElement::Token(_) => panic!("the root of a tree must be a node"),
+            },
+            Some(parent) => {
+                let green = parent.0.green.with_child(self.0.index, element);
+                parent.replace_element(GreenElement::Node(Rc::new(green)))
+            }
+        }
+    }
+}
+
+impl<K: Copy + fmt::Debug> fmt::Debug for SyntaxNode<K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}@{:?}", self.kind(), self.range())
+    }
+}
+
+/// A token of a red tree: a green token, along with its parent and its position in the text.
+#[derive(Clone)]
+pub struct SyntaxToken<K> {
+    green: Rc<GreenToken<K>>,
+    parent: SyntaxNode<K>,
+    index: usize,
+    /// Where the leading trivia starts.
+    offset: usize,
+}
+
+impl<K: Copy> SyntaxToken<K> {
+    /// The green token this token views.
+    pub fn green(&self) -> &Rc<GreenToken<K>> {
+        &self.green
+    }
+
+    /// The kind of this token.
+    pub fn kind(&self) -> K {
+        self.green.kind()
+    }
+
+    /// The node this token is a child of.
+    pub fn parent(&self) -> SyntaxNode<K> {
+        self.parent.clone()
+    }
+
+    /// The text of this token, without its trivia.
+    pub fn text(&self) -> &str {
+        self.green.text()
+    }
+
+    /// The trivia before this token.
+    pub fn leading(&self) -> &str {
+        self.green.leading()
+    }
+
+    /// The trivia after this token.
+    pub fn trailing(&self) -> &str {
+        self.green.trailing()
+    }
+
+    /// The position in the text where this token starts, after its leading trivia.
+    pub fn offset(&self) -> usize {
+        self.offset + self.green.leading().len()
+    }
+
+    /// The span of the text of this token, without its trivia.
+    pub fn range(&self) -> Range<usize> {
+        self.offset()..self.offset() + self.green.text().len()
+    }
+
+    /// The root of a new tree, in which this token is replaced by another. The rest of the tree
+    /// is shared with this one.
+    pub fn replace_with(&self, green: GreenToken<K>) -> SyntaxNode<K> {
+        let parent = self
+            .parent
+            .0
+            .green
+            .with_child(self.index, GreenElement::Token(Rc::new(green)));
+        self.parent
+            .replace_element(GreenElement::Node(Rc::new(parent)))
+    }
+}
+
+impl<K: Copy + fmt::Debug> fmt::Debug for SyntaxToken<K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}@{:?} {:?}", self.kind(), self.range(), self.text())
+    }
+}
+
+/// A child of a red node.
+#[derive(Clone)]
+pub enum SyntaxElement<K> {
+    /// An interior node.
+    Node(SyntaxNode<K>),
+    /// A token.
+    Token(SyntaxToken<K>),
+}
+
+impl<K: Copy + fmt::Debug> fmt::Debug for SyntaxElement<K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Node(node) => node.fmt(f),
+            Self::Token(token) => token.fmt(f),
+        }
+    }
+}
@@ -0,0 +1,50 @@
+//! A lexer for the examples in the documentation. It is not part of the public interface.
+
+use crate::{
+    grammar::Terminal,
+    token::{State, Step},
+};
+
+/// Reads identifiers of lowercase letters, and any other character but whitespace as a token of
+/// its own, except for `!`, which it cannot recognize.
+#[derive(Default)]
+pub struct Lex(bool);
+
+/// An identifier, which is the terminal `id`, or punctuation, which is the terminal of its text.
+#[derive(Clone, Copy)]
+pub enum Tok {
+    Id,
+    Punct,
+}
+
+impl Terminal for Tok {
+    fn name<'a>(&'a self, lexeme: &'a str) -> &'a str {
+        match self {
+            Self::Id => "id",
+            Self::Punct => lexeme,
+        }
+    }
+}
+
+impl State for Lex {
+    type Token = Tok;
+    type Error = char;
+
+    fn handle_char(&self, c: char) -> Step<Self> {
+        match (self.0, c) {
+            (_, 'a'..='z') => Step::Continue(Some(Self(true))),
+            (true, _) => Step::Finish(Tok::Id, false),
+            (_, c) if c.is_whitespace() => Step::Discard,
+            (_, '!') => Step::Abort('!'),
+            (_, _) => Step::Finish(Tok::Punct, true),
+        }
+    }
+
+    fn try_finish(&self) -> Option<Tok> {
+        if self.0 {
+            Some(Tok::Id)
+        } else {
+            None
+        }
+    }
+}
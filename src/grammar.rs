@@ -0,0 +1,570 @@
+//! Context-free grammars.
+//!
+//! ## Usage
+//!
+//! A [`Grammar`] can be assembled by hand with [`add_terminal`], [`add_nonterminal`] and
+//! [`add_production`], or parsed from BNF-like text:
+//!
+//! - each rule is written `lhs -> alternative | alternative | ...`, and may span several lines;
+//! - symbols are separated by whitespace, and any symbol that appears on the left of a rule is a
+//!   nonterminal - every other symbol is a terminal;
+//! - terminals may be quoted (`'|'` or `"->"`) to use characters that are otherwise special;
+//! - an empty alternative, or one written `ε`, derives the empty string;
+//! - `#` starts a comment that runs to the end of the line.
+//!
+//! The left side of the first rule is the start symbol. Every grammar has a terminal named `$`
+//! that stands for the end of the input, numbered [`Grammar::END`].
+//!
+//! The [`Sets`] of a grammar record which nonterminals can derive the empty string, and the FIRST
+//! and FOLLOW sets that table-driven parsers are built from. The [`transform`] module rewrites
+//! grammars into equivalent ones that suit particular parsing methods.
+//!
+//! [`Grammar`]: ./struct.Grammar.html
+//! [`add_terminal`]: ./struct.Grammar.html#method.add_terminal
+//! [`add_nonterminal`]: ./struct.Grammar.html#method.add_nonterminal
+//! [`add_production`]: ./struct.Grammar.html#method.add_production
+//! [`Grammar::END`]: ./struct.Grammar.html#associatedconstant.END
+//! [`Sets`]: ./struct.Sets.html
+//! [`transform`]: ./transform/index.html
+//!
+//! ## Example: the grammar of `simple`
+//!
+//! ```
+//! # use dragon::grammar::*;
+//! let grammar: Grammar = "
+//!     expr   -> expr + term | expr - term | term
+//!     term   -> term * factor | term / factor
+//!             | term div factor | term mod factor
+//!             | factor
+//!     factor -> '(' expr ')' | num | id
+//! "
+//! .parse()
+//! .unwrap();
+//!
+//! assert_eq!(grammar.nonterminals(), ["expr", "term", "factor"]);
+//! assert_eq!(grammar.terminals(), ["$", "+", "-", "*", "/", "div", "mod", "(", ")", "num", "id"]);
+//! assert_eq!(grammar.productions().len(), 11);
+//! assert_eq!(grammar.show_production(8), "factor -> ( expr )");
+//! ```
+
+pub mod transform;
+
+use {
+    crate::token::{self, State, Step},
+    std::{collections::BTreeSet, fmt, str::FromStr},
+};
+
+/// A terminal or nonterminal, identified by its index in the grammar.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Symbol {
+    Terminal(usize),
+    Nonterminal(usize),
+}
+
+/// A rule that allows a nonterminal to be replaced with a string of symbols.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Production {
+    /// The nonterminal on the left side.
+    pub lhs: usize,
+    /// The symbols on the right side.
+    pub rhs: Vec<Symbol>,
+}
+
+/// Tokens that stand for terminals of a [`Grammar`](./struct.Grammar.html).
+///
+/// Implement this for the token type of a [`token::State`] to feed its [`token::Lexer`] to the
+/// parsers in [`parse`].
+///
+/// [`token::State`]: ../token/trait.State.html
+/// [`token::Lexer`]: ../token/struct.Lexer.html
+/// [`parse`]: ../parse/index.html
+pub trait Terminal {
+    /// Name of the terminal, as it is written in the grammar.
+    ///
+    /// The lexeme is provided so that tokens which share a type, like keywords and identifiers,
+    /// can be told apart.
+    fn name<'a>(&'a self, lexeme: &'a str) -> &'a str;
+}
+
+impl Terminal for &str {
+    fn name<'a>(&'a self, _: &'a str) -> &'a str {
+        self
+    }
+}
+
+impl Terminal for String {
+    fn name<'a>(&'a self, _: &'a str) -> &'a str {
+        self
+    }
+}
+
+/// A context-free grammar.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Grammar {
+    terminals: Vec<String>,
+    nonterminals: Vec<String>,
+    productions: Vec<Production>,
+    start: usize,
+}
+
+impl Default for Grammar {
+    fn default() -> Self {
+        Self {
+            terminals: vec!["$".to_string()],
+            nonterminals: Vec::new(),
+            productions: Vec::new(),
+            start: 0,
+        }
+    }
+}
+
+impl Grammar {
+    /// The terminal that marks the end of the input.
+    pub const END: usize = 0;
+
+    /// Create a grammar with no productions.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Find or create a terminal with the given name.
+    pub fn add_terminal(&mut self, name: &str) -> usize {
+        find_or_push(&mut self.terminals, name)
+    }
+
+    /// Find or create a nonterminal with the given name.
+    ///
+    /// The first nonterminal to be created becomes the start symbol.
+    pub fn add_nonterminal(&mut self, name: &str) -> usize {
+        find_or_push(&mut self.nonterminals, name)
+    }
+
+    /// Add a production and return its index.
+    pub fn add_production(&mut self, lhs: usize, rhs: Vec<Symbol>) -> usize {
+        self.productions.push(Production { lhs, rhs });
+        self.productions.len() - 1
+    }
+
+    /// Change the start symbol.
+    pub fn set_start(&mut self, nonterminal: usize) {
+        self.start = nonterminal;
+    }
+
+    /// The start symbol.
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// Names of the terminals, by index.
+    pub fn terminals(&self) -> &[String] {
+        &self.terminals
+    }
+
+    /// Names of the nonterminals, by index.
+    pub fn nonterminals(&self) -> &[String] {
+        &self.nonterminals
+    }
+
+    /// All productions, by index.
+    pub fn productions(&self) -> &[Production] {
+        &self.productions
+    }
+
+    /// The productions with a particular nonterminal on the left, along with their indices.
+    pub fn productions_of(&self, nonterminal: usize) -> impl Iterator<Item = (usize, &Production)> {
+        self.productions
+            .iter()
+            .enumerate()
+            .filter(move |(_, p)| p.lhs == nonterminal)
+    }
+
+    /// Look up a terminal by name.
+    pub fn terminal(&self, name: &str) -> Option<usize> {
+        self.terminals.iter().position(|t| t == name)
+    }
+
+    /// Look up a nonterminal by name.
+    pub fn nonterminal(&self, name: &str) -> Option<usize> {
+        self.nonterminals.iter().position(|n| n == name)
+    }
+
+    /// The name of a symbol.
+    pub fn name(&self, symbol: Symbol) -> &str {
+        match symbol {
+            Symbol::Terminal(t) => &self.terminals[t],
+            Symbol::Nonterminal(n) => &self.nonterminals[n],
+        }
+    }
+
+    /// Render a production in the notation accepted by the grammar parser.
+    pub fn show_production(&self, index: usize) -> String {
+        let production = &self.productions[index];
+        let mut out = format!("{} ->", self.nonterminals[production.lhs]);
+
+        if production.rhs.is_empty() {
+            out.push_str(" ε");
+        }
+
+        for &symbol in &production.rhs {
+            out.push(' ');
+            out.push_str(&self.quoted(symbol));
+        }
+
+        out
+    }
+
+    fn quoted(&self, symbol: Symbol) -> String {
+        let name = self.name(symbol);
+
+        match symbol {
+            Symbol::Terminal(_)
+                if self.nonterminal(name).is_some()
+                    || ["", "->", "→", "::=", "ε", "|"].contains(&name)
+                    || name.starts_with(&['\'', '"'][..])
+                    || name.contains(|c: char| c.is_whitespace() || c == '|' || c == '#') =>
+            {
+                if name.contains('\'') {
+                    format!("\"{}\"", name)
+                } else {
+                    format!("'{}'", name)
+                }
+            }
+            _ => name.to_string(),
+        }
+    }
+}
+
+/// Nullability, FIRST and FOLLOW sets of the nonterminals of a grammar.
+///
+/// - A nonterminal is *nullable* if it can derive the empty string.
+/// - FIRST(A) holds the terminals that can begin a string derived from `A`.
+/// - FOLLOW(A) holds the terminals that can appear immediately to the right of `A` in some
+///   sentential form. The end marker `$` follows the start symbol.
+///
+/// ## Example
+///
+/// ```
+/// # use dragon::grammar::*;
+/// let grammar: Grammar = "
+///     E  -> T E'
+///     E' -> + T E' | ε
+///     T  -> F T'
+///     T' -> * F T' | ε
+///     F  -> ( E ) | id
+/// "
+/// .parse()
+/// .unwrap();
+///
+/// let sets = Sets::new(&grammar);
+/// let n = |name| grammar.nonterminal(name).unwrap();
+/// let names = |set: &std::collections::BTreeSet<usize>| {
+///     set.iter().map(|&t| grammar.terminals()[t].as_str()).collect::<Vec<_>>()
+/// };
+///
+/// assert!(sets.nullable(n("T'")) && !sets.nullable(n("T")));
+/// assert_eq!(names(sets.first(n("E"))), ["(", "id"]);
+/// assert_eq!(names(sets.first(n("E'"))), ["+"]);
+/// assert_eq!(names(sets.follow(n("E"))), ["$", ")"]);
+/// assert_eq!(names(sets.follow(n("T"))), ["$", "+", ")"]);
+/// assert_eq!(names(sets.follow(n("F"))), ["$", "+", "*", ")"]);
+///
+/// let (first, nullable) = sets.first_of(&grammar.productions()[3].rhs);
+/// assert_eq!((names(&first), nullable), (vec!["(", "id"], false));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Sets {
+    nullable: Vec<bool>,
+    first: Vec<BTreeSet<usize>>,
+    follow: Vec<BTreeSet<usize>>,
+}
+
+impl Sets {
+    /// Compute the sets for every nonterminal of a grammar, by iterating to a fixed point.
+    pub fn new(grammar: &Grammar) -> Self {
+        let nonterminals = grammar.nonterminals().len();
+        let mut sets = Self {
+            nullable: vec![false; nonterminals],
+            first: vec![BTreeSet::new(); nonterminals],
+            follow: vec![BTreeSet::new(); nonterminals],
+        };
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+
+            for production in grammar.productions() {
+                let (first, nullable) = sets.first_of(&production.rhs);
+
+                if nullable && !sets.nullable[production.lhs] {
+                    sets.nullable[production.lhs] = true;
+                    changed = true;
+                }
+
+                for t in first {
+                    changed |= sets.first[production.lhs].insert(t);
+                }
+            }
+        }
+
+        if nonterminals > 0 {
+            sets.follow[grammar.start()].insert(Grammar::END);
+        }
+
+        changed = true;
+        while changed {
+            changed = false;
+
+            for production in grammar.productions() {
+                for (i, &symbol) in production.rhs.iter().enumerate() {
+                    if let Symbol::Nonterminal(n) = symbol {
+                        let (mut follow, nullable) = sets.first_of(&production.rhs[i + 1..]);
+
+                        if nullable {
+                            follow.extend(sets.follow[production.lhs].iter().copied());
+                        }
+
+                        for t in follow {
+                            changed |= sets.follow[n].insert(t);
+                        }
+                    }
+                }
+            }
+        }
+
+        sets
+    }
+
+    /// Whether a nonterminal can derive the empty string.
+    pub fn nullable(&self, nonterminal: usize) -> bool {
+        self.nullable[nonterminal]
+    }
+
+    /// The terminals that can begin a string derived from a nonterminal.
+    pub fn first(&self, nonterminal: usize) -> &BTreeSet<usize> {
+        &self.first[nonterminal]
+    }
+
+    /// The terminals that can follow a nonterminal.
+    pub fn follow(&self, nonterminal: usize) -> &BTreeSet<usize> {
+        &self.follow[nonterminal]
+    }
+
+    /// The terminals that can begin a string derived from a string of symbols, and whether the
+    /// string of symbols can derive the empty string.
+    pub fn first_of(&self, symbols: &[Symbol]) -> (BTreeSet<usize>, bool) {
+        let mut first = BTreeSet::new();
+
+        for &symbol in symbols {
+            match symbol {
+                Symbol::Terminal(t) => {
+                    first.insert(t);
+                    return (first, false);
+                }
+                Symbol::Nonterminal(n) => {
+                    first.extend(self.first[n].iter().copied());
+                    if !self.nullable[n] {
+                        return (first, false);
+                    }
+                }
+            }
+        }
+
+        (first, true)
+    }
+}
+
+fn find_or_push(names: &mut Vec<String>, name: &str) -> usize {
+    if let Some(index) = names.iter().position(|n| n == name) {
+        index
+    } else {
+        names.push(name.to_string());
+        names.len() - 1
+    }
+}
+
+impl fmt::Display for Grammar {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (nonterminal, name) in self.nonterminals.iter().enumerate() {
+            let mut alternatives = self.productions_of(nonterminal).peekable();
+
+            if alternatives.peek().is_none() {
+                continue;
+            }
+
+            write!(f, "{} ->", name)?;
+
+            for (i, (_, production)) in alternatives.enumerate() {
+                if i > 0 {
+                    write!(f, " |")?;
+                }
+
+                if production.rhs.is_empty() {
+                    write!(f, " ε")?;
+                }
+
+                for &symbol in &production.rhs {
+                    write!(f, " {}", self.quoted(symbol))?;
+                }
+            }
+
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Reasons the text of a grammar could not be parsed.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The text contains no rules.
+    Empty,
+    /// A rule did not begin with a symbol followed by `->`.
+    ExpectedRule(String),
+    /// A quoted terminal was not closed before the end of the input.
+    UnterminatedQuote(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "the grammar has no rules"),
+            Self::ExpectedRule(s) => write!(f, "expected the start of a rule, found `{}`", s),
+            Self::UnterminatedQuote(s) => write!(f, "unterminated quoted terminal `{}`", s),
+        }
+    }
+}
+
+impl FromStr for Grammar {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens = token::lex::<BnfState>(s)
+            .map(|(token, lexeme)| match token {
+                Ok(BnfToken::Word) if ["->", "→", "::="].contains(&lexeme) => {
+                    (Bnf::Arrow, lexeme)
+                }
+                Ok(BnfToken::Word) if lexeme == "ε" => (Bnf::Epsilon, lexeme),
+                Ok(BnfToken::Word) => (Bnf::Bare, lexeme),
+                Ok(BnfToken::Quoted) => (Bnf::Quoted, &lexeme[1..lexeme.len() - 1]),
+                Ok(BnfToken::Pipe) => (Bnf::Pipe, lexeme),
+                Ok(BnfToken::Unterminated) | Err(()) => (Bnf::Unterminated, lexeme),
+            })
+            .collect::<Vec<_>>();
+
+        let starts_rule = |i: usize| {
+            tokens.get(i).map(|t| t.0) == Some(Bnf::Bare)
+                && tokens.get(i + 1).map(|t| t.0) == Some(Bnf::Arrow)
+        };
+
+        let mut rules = Vec::new();
+        let mut i = 0;
+
+        while i < tokens.len() {
+            if !starts_rule(i) {
+                return Err(Error::ExpectedRule(tokens[i].1.to_string()));
+            }
+
+            let lhs = tokens[i].1;
+            let mut alternatives = vec![Vec::new()];
+            i += 2;
+
+            while i < tokens.len() && !starts_rule(i) {
+                match tokens[i] {
+                    (Bnf::Pipe, _) => alternatives.push(Vec::new()),
+                    (Bnf::Epsilon, _) => (),
+                    (Bnf::Bare, name) => alternatives.last_mut().unwrap().push((name, false)),
+                    (Bnf::Quoted, name) => alternatives.last_mut().unwrap().push((name, true)),
+                    (Bnf::Arrow, s) => return Err(Error::ExpectedRule(s.to_string())),
+                    (Bnf::Unterminated, s) => return Err(Error::UnterminatedQuote(s.to_string())),
+                }
+                i += 1;
+            }
+
+            rules.push((lhs, alternatives));
+        }
+
+        if rules.is_empty() {
+            return Err(Error::Empty);
+        }
+
+        let mut grammar = Self::new();
+
+        for (lhs, _) in &rules {
+            grammar.add_nonterminal(lhs);
+        }
+
+        for (lhs, alternatives) in rules {
+            let lhs = grammar.add_nonterminal(lhs);
+
+            for alternative in alternatives {
+                let rhs = alternative
+                    .into_iter()
+                    .map(|(name, quoted)| match grammar.nonterminal(name) {
+                        Some(n) if !quoted => Symbol::Nonterminal(n),
+                        _ => Symbol::Terminal(grammar.add_terminal(name)),
+                    })
+                    .collect();
+
+                grammar.add_production(lhs, rhs);
+            }
+        }
+
+        Ok(grammar)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Bnf {
+    Arrow,
+    Epsilon,
+    Bare,
+    Quoted,
+    Pipe,
+    Unterminated,
+}
+
+enum BnfToken {
+    Word,
+    Quoted,
+    Pipe,
+    Unterminated,
+}
+
+#[derive(Default)]
+enum BnfState {
+    #[default]
+    Start,
+    Word,
+    Quoted(char),
+    Comment,
+}
+
+impl State for BnfState {
+    type Token = BnfToken;
+    type Error = ();
+
+    fn handle_char(&self, c: char) -> Step<Self> {
+        match (self, c) {
+            (Self::Start, '#') => Step::Continue(Some(Self::Comment)),
+            (Self::Comment, '\n') => Step::Discard,
+            (Self::Comment, _) => Step::Continue(None),
+            (Self::Start, c) if c.is_whitespace() => Step::Discard,
+            (Self::Start, '|') => Step::Finish(BnfToken::Pipe, true),
+            (Self::Start, '\'') | (Self::Start, '"') => Step::Continue(Some(Self::Quoted(c))),
+            (Self::Start, _) => Step::Continue(Some(Self::Word)),
+            (Self::Word, c) if c.is_whitespace() || c == '|' => Step::Finish(BnfToken::Word, false),
+            (Self::Word, _) => Step::Continue(None),
+            (Self::Quoted(q), c) if *q == c => Step::Finish(BnfToken::Quoted, true),
+            (Self::Quoted(_), _) => Step::Continue(None),
+        }
+    }
+
+    fn try_finish(&self) -> Option<Self::Token> {
+        match self {
+            Self::Word => Some(BnfToken::Word),
+            Self::Quoted(_) => Some(BnfToken::Unterminated),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,482 @@
+//! Rewriting grammars into equivalent forms that suit top-down parsing.
+//!
+//! A grammar with left recursion (`expr -> expr + term`) or with alternatives that share a
+//! prefix (`stmt -> if expr then stmt | if expr then stmt else stmt`) can never be LL(1). Both
+//! problems can be removed mechanically:
+//!
+//! - [`eliminate_left_recursion`] orders the nonterminals, substitutes the productions of earlier
+//!   nonterminals into productions that begin with them, and then replaces immediate left
+//!   recursion `A -> A α | β` with `A -> β A'` and `A' -> α A' | ε`;
+//! - [`left_factor`] replaces `A -> α β₁ | α β₂` with `A -> α A'` and `A' -> β₁ | β₂`.
+//!
+//! New nonterminals are named after the one they were split from, followed by a prime. The
+//! result is guaranteed to be free of left recursion only if the grammar has no cycles
+//! (`A ⇒+ A`) and no ε-productions.
+//!
+//! A [`Transformed`] grammar remembers how each of its productions was built from those of the
+//! original grammar. A parse tree for the transformed grammar can be turned back into a tree for
+//! the original grammar with [`restore`], so that semantic actions written for the original
+//! productions can still be applied - with left-associative operators grouped to the left.
+//!
+//! [`eliminate_left_recursion`]: ./struct.Transformed.html#method.eliminate_left_recursion
+//! [`left_factor`]: ./struct.Transformed.html#method.left_factor
+//! [`Transformed`]: ./struct.Transformed.html
+//! [`restore`]: ./struct.Transformed.html#method.restore
+//!
+//! ## Example
+//!
+//! ```
+//! # use dragon::{doctest::*, grammar::{*, transform::*}, parse::ll, token::*};
+//! let grammar: Grammar = "
+//!     expr   -> expr + term | expr - term | term
+//!     term   -> term * factor | factor
+//!     factor -> '(' expr ')' | id
+//! "
+//! .parse()
+//! .unwrap();
+//!
+//! let transformed = Transformed::new(&grammar).eliminate_left_recursion();
+//! assert_eq!(
+//!     transformed.grammar().to_string(),
+//!     "expr -> term expr'\n\
+//!      term -> factor term'\n\
+//!      factor -> ( expr ) | id\n\
+//!      expr' -> + term expr' | - term expr' | ε\n\
+//!      term' -> * factor term' | ε\n",
+//! );
+//! assert_eq!(transformed.grammar().show_production(5), "expr' -> - term expr'");
+//! assert_eq!(transformed.origins(5), [1]);
+//!
+//! let table = ll::Table::new(transformed.grammar()).unwrap();
+//! let tree = table.parse(lex::<Lex>("a - b - c")).unwrap();
+//! assert_eq!(
+//!     transformed.restore(&tree).display(&grammar).to_string(),
+//!     "(expr (expr (expr (term (factor a))) - (term (factor b))) - (term (factor c)))",
+//! );
+//!
+//! let grammar: Grammar = "
+//!     stmt -> if id then stmt | if id then stmt else stmt | id
+//! "
+//! .parse()
+//! .unwrap();
+//!
+//! let transformed = Transformed::new(&grammar).left_factor();
+//! assert_eq!(
+//!     transformed.grammar().to_string(),
+//!     "stmt -> if id then stmt stmt' | id\n\
+//!      stmt' -> ε | else stmt\n",
+//! );
+//! ```
+
+use {
+    super::{Grammar, Symbol},
+    crate::parse::Tree,
+};
+
+/// How to rebuild a tree for the original grammar from a node of the transformed grammar.
+///
+/// Nodes for some new nonterminals need trees built from elsewhere in the transformed tree (the
+/// left operand accumulated so far, or the common prefix that was factored out): these are
+/// *inherited* by the node.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Shape {
+    /// The tree rebuilt from a child of the node.
+    Child(usize),
+    /// One of the trees inherited by the node.
+    Inherited(usize),
+    /// An application of a production of the original grammar.
+    Node(usize, Vec<Shape>),
+    /// The tree rebuilt from a child of the node, which inherits the given trees.
+    Pass(usize, Vec<Shape>),
+}
+
+impl Shape {
+    /// Rebuild the tree for the production that this shape describes when its first child is a
+    /// nonterminal that is replaced by the right side of a production with shape `first`, which
+    /// has `len` symbols.
+    fn substitute(&self, first: &Shape, len: usize) -> Self {
+        let shift = |i: usize| i + len - 1;
+
+        match self {
+            Self::Child(0) => first.clone(),
+            Self::Child(i) => Self::Child(shift(*i)),
+            Self::Inherited(i) => Self::Inherited(*i),
+            Self::Node(p, shapes) => Self::Node(
+                *p,
+                shapes.iter().map(|s| s.substitute(first, len)).collect(),
+            ),
+            Self::Pass(0, shapes) => {
+                let passed = shapes
+                    .iter()
+                    .map(|s| s.substitute(first, len))
+                    .collect::<Vec<_>>();
+                first.replace_inherited(&passed)
+            }
+            Self::Pass(i, shapes) => Self::Pass(
+                shift(*i),
+                shapes.iter().map(|s| s.substitute(first, len)).collect(),
+            ),
+        }
+    }
+
+    fn replace_inherited(&self, inherited: &[Shape]) -> Self {
+        match self {
+            Self::Inherited(i) => inherited[*i].clone(),
+            Self::Child(i) => Self::Child(*i),
+            Self::Node(p, shapes) => Self::Node(
+                *p,
+                shapes
+                    .iter()
+                    .map(|s| s.replace_inherited(inherited))
+                    .collect(),
+            ),
+            Self::Pass(i, shapes) => Self::Pass(
+                *i,
+                shapes
+                    .iter()
+                    .map(|s| s.replace_inherited(inherited))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Renumber the children of the node.
+    fn renumber(&self, child: &impl Fn(usize) -> Shape) -> Self {
+        match self {
+            Self::Child(i) => child(*i),
+            Self::Inherited(i) => Self::Inherited(*i),
+            Self::Node(p, shapes) => {
+                Self::Node(*p, shapes.iter().map(|s| s.renumber(child)).collect())
+            }
+            Self::Pass(i, shapes) => match child(*i) {
+                Self::Child(i) => Self::Pass(i, shapes.iter().map(|s| s.renumber(child)).collect()),
+                _ => unreachable!("only the last child of a new production inherits trees"),
+            },
+        }
+    }
+
+    /// The lowest-numbered child that inherits trees, if any.
+    fn first_pass(&self) -> usize {
+        match self {
+            Self::Pass(i, shapes) => shapes.iter().map(Self::first_pass).fold(*i, usize::min),
+            Self::Node(_, shapes) => shapes
+                .iter()
+                .map(Self::first_pass)
+                .fold(usize::MAX, usize::min),
+            _ => usize::MAX,
+        }
+    }
+
+    fn collect_origins(&self, origins: &mut Vec<usize>) {
+        match self {
+            Self::Node(p, shapes) => {
+                origins.push(*p);
+                shapes.iter().for_each(|s| s.collect_origins(origins));
+            }
+            Self::Pass(_, shapes) => shapes.iter().for_each(|s| s.collect_origins(origins)),
+            _ => (),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Rule {
+    lhs: usize,
+    rhs: Vec<Symbol>,
+    shape: Shape,
+}
+
+/// A grammar obtained by transforming another, which remembers where its productions came from.
+#[derive(Clone, Debug)]
+pub struct Transformed {
+    original: Grammar,
+    nonterminals: Vec<String>,
+    /// For each nonterminal, the nonterminal of the original grammar it was split from.
+    split_from: Vec<usize>,
+    rules: Vec<Rule>,
+    grammar: Grammar,
+}
+
+impl Transformed {
+    /// Start transforming a grammar. No productions are changed yet.
+    pub fn new(grammar: &Grammar) -> Self {
+        let rules = grammar
+            .productions()
+            .iter()
+            .enumerate()
+            .map(|(index, production)| Rule {
+                lhs: production.lhs,
+                rhs: production.rhs.clone(),
+                shape: Shape::Node(index, (0..production.rhs.len()).map(Shape::Child).collect()),
+            })
+            .collect();
+
+        Self {
+            original: grammar.clone(),
+            nonterminals: grammar.nonterminals().to_vec(),
+            split_from: (0..grammar.nonterminals().len()).collect(),
+            rules,
+            grammar: grammar.clone(),
+        }
+    }
+
+    /// The grammar that was transformed.
+    pub fn original(&self) -> &Grammar {
+        &self.original
+    }
+
+    /// The grammar after transformation.
+    pub fn grammar(&self) -> &Grammar {
+        &self.grammar
+    }
+
+    /// The productions of the original grammar that a production of the transformed grammar
+    /// takes part in. Empty for productions like `A' -> ε`, which only end a chain of others.
+    pub fn origins(&self, production: usize) -> Vec<usize> {
+        let mut origins = Vec::new();
+        self.rules[production].shape.collect_origins(&mut origins);
+        origins
+    }
+
+    /// Eliminate immediate and indirect left recursion.
+    pub fn eliminate_left_recursion(mut self) -> Self {
+        let count = self.nonterminals.len();
+
+        for i in 0..count {
+            for j in 0..i {
+                let replacements = self
+                    .rules
+                    .iter()
+                    .filter(|r| r.lhs == j)
+                    .cloned()
+                    .collect::<Vec<_>>();
+                let mut rules = Vec::new();
+
+                for rule in self.rules.drain(..) {
+                    if rule.lhs != i || rule.rhs.first() != Some(&Symbol::Nonterminal(j)) {
+                        rules.push(rule);
+                        continue;
+                    }
+
+                    rules.extend(replacements.iter().map(|r| Rule {
+                        lhs: i,
+                        rhs: r.rhs.iter().chain(&rule.rhs[1..]).copied().collect(),
+                        shape: rule.shape.substitute(&r.shape, r.rhs.len()),
+                    }));
+                }
+
+                self.rules = rules;
+            }
+
+            self.eliminate_immediate(i);
+        }
+
+        self.rebuild()
+    }
+
+    fn eliminate_immediate(&mut self, nonterminal: usize) {
+        let recursive = Symbol::Nonterminal(nonterminal);
+        let is_recursive =
+            |rule: &Rule| rule.lhs == nonterminal && rule.rhs.first() == Some(&recursive);
+
+        if !self.rules.iter().any(is_recursive) {
+            return;
+        }
+
+        let tail = self.fresh(nonterminal);
+        let mut rules = Vec::new();
+        let mut tails = Vec::new();
+
+        for rule in self.rules.drain(..) {
+            if is_recursive(&rule) {
+                // A -> A α becomes A' -> α A', which builds `A -> A α` around the tree it inherits
+                if rule.rhs.len() > 1 {
+                    let len = rule.rhs.len() - 1;
+                    let shape = rule.shape.renumber(&|i| match i {
+                        0 => Shape::Inherited(0),
+                        i => Shape::Child(i - 1),
+                    });
+
+                    let mut rhs = rule.rhs[1..].to_vec();
+                    rhs.push(Symbol::Nonterminal(tail));
+                    tails.push(Rule {
+                        lhs: tail,
+                        rhs,
+                        shape: Shape::Pass(len, vec![shape]),
+                    });
+                }
+            } else if rule.lhs == nonterminal {
+                // A -> β becomes A -> β A', which passes the tree for `A -> β` along
+                let len = rule.rhs.len();
+                let mut rhs = rule.rhs;
+                rhs.push(Symbol::Nonterminal(tail));
+                rules.push(Rule {
+                    lhs: nonterminal,
+                    rhs,
+                    shape: Shape::Pass(len, vec![rule.shape]),
+                });
+            } else {
+                rules.push(rule);
+            }
+        }
+
+        rules.extend(tails);
+        rules.push(Rule {
+            lhs: tail,
+            rhs: Vec::new(),
+            shape: Shape::Inherited(0),
+        });
+
+        self.rules = rules;
+    }
+
+    /// Factor out prefixes that are shared by more than one alternative.
+    pub fn left_factor(mut self) -> Self {
+        let mut pending = (0..self.nonterminals.len()).collect::<Vec<_>>();
+
+        while let Some(nonterminal) = pending.pop() {
+            let alternatives = self
+                .rules
+                .iter()
+                .filter(|r| r.lhs == nonterminal)
+                .collect::<Vec<_>>();
+
+            // children that inherit trees must stay with the rest of their production
+            let mut prefix: &[Symbol] = &[];
+            for (i, a) in alternatives.iter().enumerate() {
+                for b in &alternatives[i + 1..] {
+                    let common = a.rhs.iter().zip(&b.rhs).take_while(|(x, y)| x == y).count();
+                    let common = common.min(a.shape.first_pass()).min(b.shape.first_pass());
+                    if common > prefix.len() {
+                        prefix = &a.rhs[..common];
+                    }
+                }
+            }
+
+            if prefix.is_empty() {
+                continue;
+            }
+
+            let prefix = prefix.to_vec();
+            let len = prefix.len();
+            let factored = self.fresh(nonterminal);
+            let mut rules = Vec::new();
+            let mut suffixes = Vec::new();
+
+            for rule in self.rules.drain(..) {
+                if rule.lhs != nonterminal
+                    || !rule.rhs.starts_with(&prefix)
+                    || rule.shape.first_pass() < len
+                {
+                    rules.push(rule);
+                    continue;
+                }
+
+                if suffixes.is_empty() {
+                    let mut rhs = prefix.clone();
+                    rhs.push(Symbol::Nonterminal(factored));
+                    rules.push(Rule {
+                        lhs: nonterminal,
+                        rhs,
+                        shape: Shape::Pass(len, (0..len).map(Shape::Child).collect()),
+                    });
+                }
+
+                suffixes.push(Rule {
+                    lhs: factored,
+                    rhs: rule.rhs[len..].to_vec(),
+                    shape: rule.shape.renumber(&|i| {
+                        if i < len {
+                            Shape::Inherited(i)
+                        } else {
+                            Shape::Child(i - len)
+                        }
+                    }),
+                });
+            }
+
+            rules.extend(suffixes);
+            self.rules = rules;
+            pending.push(nonterminal);
+            pending.push(factored);
+        }
+
+        self.rebuild()
+    }
+
+    /// Create a new nonterminal split from an existing one.
+    fn fresh(&mut self, from: usize) -> usize {
+        let mut name = format!("{}'", self.nonterminals[from]);
+        while self.nonterminals.contains(&name) || self.original.terminals().contains(&name) {
+            name.push('\'');
+        }
+
+        self.nonterminals.push(name);
+        self.split_from.push(self.split_from[from]);
+        self.nonterminals.len() - 1
+    }
+
+    fn rebuild(mut self) -> Self {
+        let mut grammar = Grammar::new();
+
+        for terminal in self.original.terminals() {
+            grammar.add_terminal(terminal);
+        }
+
+        for nonterminal in &self.nonterminals {
+            grammar.add_nonterminal(nonterminal);
+        }
+
+        for rule in &self.rules {
+            grammar.add_production(rule.lhs, rule.rhs.clone());
+        }
+
+        grammar.set_start(self.original.start());
+        self.grammar = grammar;
+        self
+    }
+
+    /// Turn a parse tree for the transformed grammar into one for the original grammar.
+    pub fn restore<'src>(&self, tree: &Tree<'src>) -> Tree<'src> {
+        self.rebuild_tree(tree, &[])
+    }
+
+    fn rebuild_tree<'src>(&self, tree: &Tree<'src>, inherited: &[Tree<'src>]) -> Tree<'src> {
+        match tree {
+            Tree::Node(production, children) => {
+                self.evaluate(&self.rules[*production].shape, children, inherited)
+            }
+            Tree::Leaf(token) => Tree::Leaf(*token),
+            Tree::Missing(Symbol::Nonterminal(n)) => {
+                Tree::Missing(Symbol::Nonterminal(self.split_from[*n]))
+            }
+            Tree::Missing(symbol) => Tree::Missing(*symbol),
+        }
+    }
+
+    fn evaluate<'src>(
+        &self,
+        shape: &Shape,
+        children: &[Tree<'src>],
+        inherited: &[Tree<'src>],
+    ) -> Tree<'src> {
+        match shape {
+            Shape::Child(i) => self.rebuild_tree(&children[*i], &[]),
+            Shape::Inherited(i) => inherited[*i].clone(),
+            Shape::Node(p, shapes) => Tree::Node(
+                *p,
+                shapes
+                    .iter()
+                    .map(|s| self.evaluate(s, children, inherited))
+                    .collect(),
+            ),
+            Shape::Pass(i, shapes) => {
+                let passed = shapes
+                    .iter()
+                    .map(|s| self.evaluate(s, children, inherited))
+                    .collect::<Vec<_>>();
+                self.rebuild_tree(&children[*i], &passed)
+            }
+        }
+    }
+}
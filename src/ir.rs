@@ -0,0 +1,906 @@
+//! Three-address code: an intermediate representation that sits between a front end and the
+//! optimizations and code generation behind it.
+//!
+//! Each [`Instr`] applies at most one operator, to at most two operands, and stores the result
+//! in a named variable or a compiler-generated *temporary*. Control flow is by jumps to *labels*,
+//! which mark positions in the instruction list:
+//!
+//! | Instruction          | Meaning                                                     |
+//! |----------------------|-------------------------------------------------------------|
+//! | `x = y op z`         | binary operator                                             |
+//! | `x = op y`           | unary operator, including conversions such as `inttofloat`  |
+//! | `x = y`              | copy                                                        |
+//! | `x = a[i]`, `a[i] = y` | indexed copies; `i` is an offset in bytes from the start of `a` |
+//! | `goto L`             | unconditional jump                                          |
+//! | `if x goto L`, `ifFalse x goto L` | jump if `x` is true, or false                  |
+//! | `if x relop y goto L` | jump if a comparison holds                                 |
+//! | `param x`, `call p, n`, `x = call p, n` | procedure call with `n` arguments        |
+//!
+//! A [`Program`] is a list of instructions, written out one per line by its `Display`
+//! implementation. It can also be laid out as a table of *quadruples* (operator, two arguments,
+//! result) or *triples* (operator and two arguments, where a temporary is replaced by the
+//! position of the triple that computes it), with jumps to positions rather than labels.
+//!
+//! [`Program::run`] interprets a program. The only procedure it knows is `print`, which appends
+//! its arguments to the output.
+//!
+//! The [`flow`] module splits a program into basic blocks and builds its flow graph.
+//!
+//! [`flow`]: ./flow/index.html
+//! [`Instr`]: ./enum.Instr.html
+//! [`Program`]: ./struct.Program.html
+//! [`Program::run`]: ./struct.Program.html#method.run
+//!
+//! ## Example
+//!
+//! `a = b * - c + b * - c`:
+//!
+//! ```
+//! # use dragon::ir::*;
+//! let var = |name: &str| Operand::Name(Name::Var(name.to_string()));
+//! let mut program = Program::new();
+//!
+//! let t1 = program.temp();
+//! program.emit(Instr::Unary(t1.clone(), UnaryOp::Neg, var("c")));
+//! let t2 = program.temp();
+//! program.emit(Instr::Binary(t2.clone(), BinaryOp::Mul, var("b"), Operand::Name(t1)));
+//! let t3 = program.temp();
+//! program.emit(Instr::Unary(t3.clone(), UnaryOp::Neg, var("c")));
+//! let t4 = program.temp();
+//! program.emit(Instr::Binary(t4.clone(), BinaryOp::Mul, var("b"), Operand::Name(t3)));
+//! let t5 = program.temp();
+//! program.emit(Instr::Binary(
+//!     t5.clone(),
+//!     BinaryOp::Add,
+//!     Operand::Name(t2),
+//!     Operand::Name(t4),
+//! ));
+//! program.emit(Instr::Copy(Name::Var("a".to_string()), Operand::Name(t5)));
+//!
+//! assert_eq!(
+//!     program.to_string(),
+//!     "    t1 = minus c
+//!     t2 = b * t1
+//!     t3 = minus c
+//!     t4 = b * t3
+//!     t5 = t2 + t4
+//!     a = t5
+//! ",
+//! );
+//!
+//! let quadruples = program.quadruples();
+//! assert_eq!(quadruples[1].to_string(), "*       b       t1      t2");
+//! assert_eq!(quadruples[5].to_string(), "=       t5              a");
+//!
+//! let triples = program.triples();
+//! assert_eq!(
+//!     triples.iter().map(|t| t.to_string()).collect::<Vec<_>>(),
+//!     [
+//!         "minus   c",
+//!         "*       b       (0)",
+//!         "minus   c",
+//!         "*       b       (2)",
+//!         "+       (1)     (3)",
+//!         "=       a       (4)",
+//!     ],
+//! );
+//!
+//! let mut state = State::default();
+//! state.vars.insert(Name::Var("b".to_string()), Value::Int(3));
+//! state.vars.insert(Name::Var("c".to_string()), Value::Int(2));
+//! program.run(&mut state, 100).unwrap();
+//! assert_eq!(state.vars[&Name::Var("a".to_string())], Value::Int(-12));
+//! ```
+//!
+//! A loop that prints `0 1 2`:
+//!
+//! ```
+//! # use dragon::ir::*;
+//! let i = Name::Var("i".to_string());
+//! let mut program = Program::new();
+//! let top = program.label();
+//! let done = program.label();
+//!
+//! program.emit(Instr::Copy(i.clone(), Operand::Const(Value::Int(0))));
+//! program.emit(Instr::Label(top));
+//! program.emit(Instr::IfRel(
+//!     Operand::Name(i.clone()),
+//!     BinaryOp::Ge,
+//!     Operand::Const(Value::Int(3)),
+//!     done,
+//! ));
+//! program.emit(Instr::Param(Operand::Name(i.clone())));
+//! program.emit(Instr::Call(None, "print".to_string(), 1));
+//! program.emit(Instr::Binary(
+//!     i.clone(),
+//!     BinaryOp::Add,
+//!     Operand::Name(i.clone()),
+//!     Operand::Const(Value::Int(1)),
+//! ));
+//! program.emit(Instr::Goto(top));
+//! program.emit(Instr::Label(done));
+//!
+//! assert_eq!(
+//!     program.to_string(),
+//!     "    i = 0
+//! L1:
+//!     if i >= 3 goto L2
+//!     param i
+//!     call print, 1
+//!     i = i + 1
+//!     goto L1
+//! L2:
+//! ",
+//! );
+//! assert_eq!(program.quadruples()[1].to_string(), "if>=    i       3       (6)");
+//! assert_eq!(
+//!     program.triples().iter().map(|t| t.to_string()).collect::<Vec<_>>(),
+//!     [
+//!         "=       i       0",
+//!         ">=      i       3",
+//!         "if      (1)     (8)",
+//!         "param   i",
+//!         "call    print   1",
+//!         "+       i       1",
+//!         "=       i       (5)",
+//!         "goto    (1)",
+//!     ],
+//! );
+//!
+//! let mut state = State::default();
+//! program.run(&mut state, 100).unwrap();
+//! assert_eq!(state.output, [Value::Int(0), Value::Int(1), Value::Int(2)]);
+//! assert_eq!(program.run(&mut State::default(), 10), Err(RunError::OutOfFuel));
+//! ```
+
+pub mod dag;
+pub mod dataflow;
+pub mod flow;
+pub mod global;
+pub mod loops;
+
+use std::{
+    collections::HashMap,
+    fmt,
+    hash::{Hash, Hasher},
+};
+
+/// A position in a program that jumps can go to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Label(pub usize);
+
+impl fmt::Display for Label {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "L{}", self.0)
+    }
+}
+
+/// A place that holds a value.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Name {
+    /// A variable of the source program.
+    Var(String),
+    /// A temporary generated by the compiler, numbered.
+    Temp(usize),
+}
+
+impl fmt::Display for Name {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Var(name) => write!(f, "{}", name),
+            Self::Temp(n) => write!(f, "t{}", n),
+        }
+    }
+}
+
+/// A constant, or the value of a name while a program runs.
+///
+/// Floating-point values are compared bit for bit, so that values can be used as keys.
+#[derive(Clone, Copy, Debug)]
+pub enum Value {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Int(a), Self::Int(b)) => a == b,
+            (Self::Float(a), Self::Float(b)) => a.to_bits() == b.to_bits(),
+            (Self::Bool(a), Self::Bool(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Value {}
+
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self {
+            Self::Int(n) => (0, n).hash(state),
+            Self::Float(x) => (1, x.to_bits()).hash(state),
+            Self::Bool(b) => (2, b).hash(state),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Int(n) => write!(f, "{}", n),
+            Self::Float(x) => write!(f, "{:?}", x),
+            Self::Bool(b) => write!(f, "{}", b),
+        }
+    }
+}
+
+/// An argument of an instruction.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Operand {
+    Name(Name),
+    Const(Value),
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Name(name) => write!(f, "{}", name),
+            Self::Const(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+/// Operators with two operands.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    /// Division, which truncates integers.
+    Div,
+    /// Integer division.
+    IntDiv,
+    Mod,
+    Lt,
+    Le,
+    Eq,
+    Ne,
+    Ge,
+    Gt,
+}
+
+impl BinaryOp {
+    /// Whether this operator compares its operands, producing a Boolean.
+    pub fn is_relational(self) -> bool {
+        matches!(
+            self,
+            Self::Lt | Self::Le | Self::Eq | Self::Ne | Self::Ge | Self::Gt
+        )
+    }
+
+    /// Apply this operator to two values.
+    pub fn apply(self, a: Value, b: Value) -> Result<Value, RunError> {
+        use Value::*;
+
+        let mismatch = || RunError::Type(format!("cannot apply `{}` to {} and {}", self, a, b));
+        Ok(match (self, a, b) {
+            (Self::Add, Int(a), Int(b)) => Int(a.wrapping_add(b)),
+            (Self::Sub, Int(a), Int(b)) => Int(a.wrapping_sub(b)),
+            (Self::Mul, Int(a), Int(b)) => Int(a.wrapping_mul(b)),
+            (Self::Div, Int(_), Int(0))
+            | (Self::IntDiv, Int(_), Int(0))
+            | (Self::Mod, Int(_), Int(0)) => return Err(RunError::DivideByZero),
+            (Self::Div, Int(a), Int(b)) | (Self::IntDiv, Int(a), Int(b)) => Int(a.wrapping_div(b)),
+            (Self::Mod, Int(a), Int(b)) => Int(a.wrapping_rem(b)),
+            (Self::Add, Float(a), Float(b)) => Float(a + b),
+            (Self::Sub, Float(a), Float(b)) => Float(a - b),
+            (Self::Mul, Float(a), Float(b)) => Float(a * b),
+            (Self::Div, Float(a), Float(b)) => Float(a / b),
+            (op, Int(a), Int(b)) if op.is_relational() => Bool(op.compare(a.cmp(&b))),
+            (op, Float(a), Float(b)) if op.is_relational() => {
+                Bool(a.partial_cmp(&b).map_or(op == Self::Ne, |o| op.compare(o)))
+            }
+            (Self::Eq, Bool(a), Bool(b)) => Bool(a == b),
+            (Self::Ne, Bool(a), Bool(b)) => Bool(a != b),
+            _ => return Err(mismatch()),
+        })
+    }
+
+    fn compare(self, ordering: std::cmp::Ordering) -> bool {
+        use std::cmp::Ordering::*;
+
+        match self {
+            Self::Lt => ordering == Less,
+            Self::Le => ordering != Greater,
+            Self::Eq => ordering == Equal,
+            Self::Ne => ordering != Equal,
+            Self::Ge => ordering != Less,
+            Self::Gt => ordering == Greater,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl fmt::Display for BinaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self {
+            Self::Add => "+",
+            Self::Sub => "-",
+            Self::Mul => "*",
+            Self::Div => "/",
+            Self::IntDiv => "div",
+            Self::Mod => "mod",
+            Self::Lt => "<",
+            Self::Le => "<=",
+            Self::Eq => "==",
+            Self::Ne => "!=",
+            Self::Ge => ">=",
+            Self::Gt => ">",
+        };
+        write!(f, "{}", op)
+    }
+}
+
+/// Operators with one operand.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+    IntToFloat,
+}
+
+impl UnaryOp {
+    /// Apply this operator to a value.
+    pub fn apply(self, a: Value) -> Result<Value, RunError> {
+        match (self, a) {
+            (Self::Neg, Value::Int(n)) => Ok(Value::Int(n.wrapping_neg())),
+            (Self::Neg, Value::Float(x)) => Ok(Value::Float(-x)),
+            (Self::Not, Value::Bool(b)) => Ok(Value::Bool(!b)),
+            (Self::IntToFloat, Value::Int(n)) => Ok(Value::Float(n as f64)),
+            _ => Err(RunError::Type(format!("cannot apply `{}` to {}", self, a))),
+        }
+    }
+}
+
+impl fmt::Display for UnaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self {
+            Self::Neg => "minus",
+            Self::Not => "not",
+            Self::IntToFloat => "inttofloat",
+        };
+        write!(f, "{}", op)
+    }
+}
+
+/// A three-address instruction.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Instr {
+    /// Marks the position of the next instruction as the target of jumps to the label.
+    Label(Label),
+    /// `x = y op z`
+    Binary(Name, BinaryOp, Operand, Operand),
+    /// `x = op y`
+    Unary(Name, UnaryOp, Operand),
+    /// `x = y`
+    Copy(Name, Operand),
+    /// `x = a[i]`
+    Load(Name, Name, Operand),
+    /// `a[i] = y`
+    Store(Name, Operand, Operand),
+    /// `goto L`
+    Goto(Label),
+    /// `if x goto L`
+    If(Operand, Label),
+    /// `ifFalse x goto L`
+    IfFalse(Operand, Label),
+    /// `if x relop y goto L`
+    IfRel(Operand, BinaryOp, Operand, Label),
+    /// `param x`
+    Param(Operand),
+    /// `x = call p, n`, or `call p, n` if the result is not used.
+    Call(Option<Name>, String, usize),
+}
+
+impl Instr {
+    /// The label this instruction may jump to.
+    pub fn target(&self) -> Option<Label> {
+        match self {
+            Self::Goto(label)
+            | Self::If(_, label)
+            | Self::IfFalse(_, label)
+            | Self::IfRel(_, _, _, label) => Some(*label),
+            _ => None,
+        }
+    }
+
+    /// The label this instruction may jump to, to be changed, as when backpatching.
+    pub fn target_mut(&mut self) -> Option<&mut Label> {
+        match self {
+            Self::Goto(label)
+            | Self::If(_, label)
+            | Self::IfFalse(_, label)
+            | Self::IfRel(_, _, _, label) => Some(label),
+            _ => None,
+        }
+    }
+
+    /// The name this instruction assigns to, if any.
+    pub fn assigned(&self) -> Option<&Name> {
+        match self {
+            Self::Binary(x, ..) | Self::Unary(x, ..) | Self::Copy(x, _) | Self::Load(x, ..) => {
+                Some(x)
+            }
+            Self::Call(x, ..) => x.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// The operands this instruction reads, from left to right. The array of an indexed copy is
+    /// not one of them.
+    pub fn operands(&self) -> Vec<&Operand> {
+        match self {
+            Self::Binary(_, _, y, z) | Self::Store(_, y, z) | Self::IfRel(y, _, z, _) => vec![y, z],
+            Self::Unary(_, _, y)
+            | Self::Copy(_, y)
+            | Self::Load(_, _, y)
+            | Self::If(y, _)
+            | Self::IfFalse(y, _)
+            | Self::Param(y) => vec![y],
+            Self::Label(_) | Self::Goto(_) | Self::Call(..) => Vec::new(),
+        }
+    }
+
+    /// The operands this instruction reads, to be changed.
+    pub fn operands_mut(&mut self) -> Vec<&mut Operand> {
+        match self {
+            Self::Binary(_, _, y, z) | Self::Store(_, y, z) | Self::IfRel(y, _, z, _) => vec![y, z],
+            Self::Unary(_, _, y)
+            | Self::Copy(_, y)
+            | Self::Load(_, _, y)
+            | Self::If(y, _)
+            | Self::IfFalse(y, _)
+            | Self::Param(y) => vec![y],
+            Self::Label(_) | Self::Goto(_) | Self::Call(..) => Vec::new(),
+        }
+    }
+
+    /// The parts of this instruction as a quadruple, with jumps to the given positions.
+    fn quadruple(&self, position: impl Fn(Label) -> usize) -> Quadruple {
+        let s = |x: &dyn fmt::Display| x.to_string();
+        let at = |label: Label| format!("({})", position(label));
+        let (op, arg1, arg2, result) = match self {
+            Self::Label(_) => unreachable!(),
+            Self::Binary(x, op, y, z) => (s(op), s(y), s(z), s(x)),
+            Self::Unary(x, op, y) => (s(op), s(y), String::new(), s(x)),
+            Self::Copy(x, y) => ("=".to_string(), s(y), String::new(), s(x)),
+            Self::Load(x, a, i) => ("=[]".to_string(), s(a), s(i), s(x)),
+            Self::Store(a, i, y) => ("[]=".to_string(), s(i), s(y), s(a)),
+            Self::Goto(label) => ("goto".to_string(), String::new(), String::new(), at(*label)),
+            Self::If(x, label) => ("if".to_string(), s(x), String::new(), at(*label)),
+            Self::IfFalse(x, label) => ("ifFalse".to_string(), s(x), String::new(), at(*label)),
+            Self::IfRel(x, op, y, label) => (format!("if{}", op), s(x), s(y), at(*label)),
+            Self::Param(x) => ("param".to_string(), s(x), String::new(), String::new()),
+            Self::Call(x, p, n) => (
+                "call".to_string(),
+                p.clone(),
+                n.to_string(),
+                x.as_ref().map_or(String::new(), |x| s(x)),
+            ),
+        };
+        Quadruple {
+            op,
+            arg1,
+            arg2,
+            result,
+        }
+    }
+}
+
+impl fmt::Display for Instr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Label(label) => write!(f, "{}:", label),
+            Self::Binary(x, op, y, z) => write!(f, "{} = {} {} {}", x, y, op, z),
+            Self::Unary(x, op, y) => write!(f, "{} = {} {}", x, op, y),
+            Self::Copy(x, y) => write!(f, "{} = {}", x, y),
+            Self::Load(x, a, i) => write!(f, "{} = {}[{}]", x, a, i),
+            Self::Store(a, i, y) => write!(f, "{}[{}] = {}", a, i, y),
+            Self::Goto(label) => write!(f, "goto {}", label),
+            Self::If(x, label) => write!(f, "if {} goto {}", x, label),
+            Self::IfFalse(x, label) => write!(f, "ifFalse {} goto {}", x, label),
+            Self::IfRel(x, op, y, label) => write!(f, "if {} {} {} goto {}", x, op, y, label),
+            Self::Param(x) => write!(f, "param {}", x),
+            Self::Call(None, p, n) => write!(f, "call {}, {}", p, n),
+            Self::Call(Some(x), p, n) => write!(f, "{} = call {}, {}", x, p, n),
+        }
+    }
+}
+
+/// A list of three-address instructions, along with the numbers of the temporaries and labels
+/// created for it.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Program {
+    pub instrs: Vec<Instr>,
+    temps: usize,
+    labels: usize,
+}
+
+impl Program {
+    /// An empty program.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A temporary that has not been used in this program.
+    pub fn temp(&mut self) -> Name {
+        self.temps += 1;
+        Name::Temp(self.temps)
+    }
+
+    /// A label that has not been used in this program.
+    pub fn label(&mut self) -> Label {
+        self.labels += 1;
+        Label(self.labels)
+    }
+
+    /// Append an instruction, returning its index.
+    pub fn emit(&mut self, instr: Instr) -> usize {
+        self.instrs.push(instr);
+        self.instrs.len() - 1
+    }
+
+    /// The index of the instruction that a label marks, which is the length of the program if
+    /// it is at the end.
+    pub fn position(&self, label: Label) -> Option<usize> {
+        self.instrs
+            .iter()
+            .position(|instr| *instr == Instr::Label(label))
+    }
+
+    /// The instructions as quadruples. Labels are left out, and jumps go to the positions of
+    /// quadruples instead.
+    ///
+    /// # Panics
+    ///
+    /// If a jump goes to a label that is not in the program.
+    pub fn quadruples(&self) -> Vec<Quadruple> {
+        let positions = self.label_positions(|_| 1);
+        self.instrs
+            .iter()
+            .filter(|instr| !matches!(instr, Instr::Label(_)))
+            .map(|instr| instr.quadruple(|label| positions[&label]))
+            .collect()
+    }
+
+    /// The instructions as triples. A temporary that is computed by one instruction and never
+    /// assigned again is replaced by the position of the triple that computes it; other
+    /// assignments take a triple of their own. Labels are left out, and jumps go to positions.
+    ///
+    /// # Panics
+    ///
+    /// If a jump goes to a label that is not in the program.
+    pub fn triples(&self) -> Vec<Triple> {
+        let mut definitions = HashMap::new();
+        for instr in &self.instrs {
+            if let Some(Name::Temp(t)) = instr.assigned() {
+                *definitions.entry(*t).or_insert(0) += 1;
+            }
+        }
+        let elided = |x: &Name| match (x, self.defines_value(x)) {
+            (Name::Temp(t), true) => definitions[t] == 1,
+            _ => false,
+        };
+
+        // Assignments to names that are not elided take a second triple, as do stores and
+        // relational jumps.
+        let width = |instr: &Instr| match instr {
+            Instr::Binary(x, ..) | Instr::Unary(x, ..) | Instr::Load(x, ..) if !elided(x) => 2,
+            Instr::Call(Some(x), ..) if !elided(x) => 2,
+            Instr::Store(..) | Instr::IfRel(..) => 2,
+            _ => 1,
+        };
+        let positions = self.label_positions(width);
+
+        let mut triples: Vec<Triple> = Vec::new();
+        let mut computed: HashMap<Name, usize> = HashMap::new();
+        let arg = |x: &Operand, computed: &HashMap<Name, usize>| match x {
+            Operand::Name(name) if computed.contains_key(name) => format!("({})", computed[name]),
+            x => x.to_string(),
+        };
+        let at = |label: &Label| format!("({})", positions[label]);
+
+        for instr in &self.instrs {
+            let (op, arg1, arg2, result) = match instr {
+                Instr::Label(_) => continue,
+                Instr::Binary(x, op, y, z) => (
+                    op.to_string(),
+                    arg(y, &computed),
+                    arg(z, &computed),
+                    Some(x),
+                ),
+                Instr::Unary(x, op, y) => {
+                    (op.to_string(), arg(y, &computed), String::new(), Some(x))
+                }
+                Instr::Load(x, a, i) => {
+                    ("=[]".to_string(), a.to_string(), arg(i, &computed), Some(x))
+                }
+                Instr::Call(x, p, n) => ("call".to_string(), p.clone(), n.to_string(), x.as_ref()),
+                Instr::Copy(x, y) => ("=".to_string(), x.to_string(), arg(y, &computed), None),
+                Instr::Store(a, i, y) => {
+                    triples.push(Triple {
+                        op: "[]=".to_string(),
+                        arg1: a.to_string(),
+                        arg2: arg(i, &computed),
+                    });
+                    let element = format!("({})", triples.len() - 1);
+                    ("=".to_string(), element, arg(y, &computed), None)
+                }
+                Instr::Goto(label) => ("goto".to_string(), at(label), String::new(), None),
+                Instr::If(x, label) => ("if".to_string(), arg(x, &computed), at(label), None),
+                Instr::IfFalse(x, label) => {
+                    ("ifFalse".to_string(), arg(x, &computed), at(label), None)
+                }
+                Instr::IfRel(x, op, y, label) => {
+                    // The comparison is a triple of its own, and the jump tests it.
+                    triples.push(Triple {
+                        op: op.to_string(),
+                        arg1: arg(x, &computed),
+                        arg2: arg(y, &computed),
+                    });
+                    let test = format!("({})", triples.len() - 1);
+                    ("if".to_string(), test, at(label), None)
+                }
+                Instr::Param(x) => ("param".to_string(), arg(x, &computed), String::new(), None),
+            };
+
+            triples.push(Triple { op, arg1, arg2 });
+            let position = triples.len() - 1;
+            if let Some(x) = result {
+                computed.remove(x);
+                if elided(x) {
+                    computed.insert(x.clone(), position);
+                } else {
+                    triples.push(Triple {
+                        op: "=".to_string(),
+                        arg1: x.to_string(),
+                        arg2: format!("({})", position),
+                    });
+                }
+            } else if let Instr::Copy(x, _) = instr {
+                computed.remove(x);
+            }
+        }
+
+        triples
+    }
+
+    /// Run the program, stopping with an error if it has not finished after `fuel` instructions.
+    pub fn run(&self, state: &mut State, mut fuel: usize) -> Result<(), RunError> {
+        let mut positions = HashMap::new();
+        for (i, instr) in self.instrs.iter().enumerate() {
+            if let Instr::Label(label) = instr {
+                positions.insert(*label, i);
+            }
+        }
+        let jump = |label: &Label| {
+            positions
+                .get(label)
+                .copied()
+                .ok_or(RunError::NoLabel(*label))
+        };
+
+        let mut params = Vec::new();
+        let mut pc = 0;
+        while let Some(instr) = self.instrs.get(pc) {
+            if fuel == 0 {
+                return Err(RunError::OutOfFuel);
+            }
+            fuel -= 1;
+            pc += 1;
+
+            match instr {
+                Instr::Label(_) => (),
+                Instr::Binary(x, op, y, z) => {
+                    let value = op.apply(state.get(y)?, state.get(z)?)?;
+                    state.vars.insert(x.clone(), value);
+                }
+                Instr::Unary(x, op, y) => {
+                    let value = op.apply(state.get(y)?)?;
+                    state.vars.insert(x.clone(), value);
+                }
+                Instr::Copy(x, y) => {
+                    let value = state.get(y)?;
+                    state.vars.insert(x.clone(), value);
+                }
+                Instr::Load(x, a, i) => {
+                    let offset = state.offset(i)?;
+                    let value = *state
+                        .memory
+                        .get(&(a.clone(), offset))
+                        .ok_or_else(|| RunError::Uninitialized(a.clone(), offset))?;
+                    state.vars.insert(x.clone(), value);
+                }
+                Instr::Store(a, i, y) => {
+                    let offset = state.offset(i)?;
+                    let value = state.get(y)?;
+                    state.memory.insert((a.clone(), offset), value);
+                }
+                Instr::Goto(label) => pc = jump(label)?,
+                Instr::If(x, label) | Instr::IfFalse(x, label) => {
+                    let condition = match state.get(x)? {
+                        Value::Bool(b) => b,
+                        value => return Err(RunError::Type(format!("{} is not a Boolean", value))),
+                    };
+                    if condition == matches!(instr, Instr::If(..)) {
+                        pc = jump(label)?;
+                    }
+                }
+                Instr::IfRel(x, op, y, label) => {
+                    if op.apply(state.get(x)?, state.get(y)?)? == Value::Bool(true) {
+                        pc = jump(label)?;
+                    }
+                }
+                Instr::Param(x) => params.push(state.get(x)?),
+                Instr::Call(x, p, n) => {
+                    if p != "print" || x.is_some() || *n > params.len() {
+                        return Err(RunError::UnknownProcedure(p.clone(), *n));
+                    }
+                    let args = params.split_off(params.len() - n);
+                    state.output.extend(args);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The index in the triples of each label, given the number of triples for each
+    /// instruction.
+    fn label_positions(&self, width: impl Fn(&Instr) -> usize) -> HashMap<Label, usize> {
+        let mut positions = HashMap::new();
+        let mut position = 0;
+        for instr in &self.instrs {
+            match instr {
+                Instr::Label(label) => {
+                    positions.insert(*label, position);
+                }
+                instr => position += width(instr),
+            }
+        }
+        positions
+    }
+
+    /// Whether every assignment to a name computes a value, rather than copying one.
+    fn defines_value(&self, x: &Name) -> bool {
+        !self
+            .instrs
+            .iter()
+            .any(|instr| matches!(instr, Instr::Copy(y, _) if y == x))
+    }
+}
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for instr in &self.instrs {
+            match instr {
+                Instr::Label(_) => writeln!(f, "{}", instr)?,
+                _ => writeln!(f, "    {}", instr)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// An instruction as an operator, up to two arguments and a result. Empty strings stand for
+/// fields that are not used.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Quadruple {
+    pub op: String,
+    pub arg1: String,
+    pub arg2: String,
+    pub result: String,
+}
+
+impl fmt::Display for Quadruple {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            columns(&[&self.op, &self.arg1, &self.arg2, &self.result])
+        )
+    }
+}
+
+/// An instruction as an operator and up to two arguments, which may be positions of other
+/// triples, such as `(3)`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Triple {
+    pub op: String,
+    pub arg1: String,
+    pub arg2: String,
+}
+
+impl fmt::Display for Triple {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", columns(&[&self.op, &self.arg1, &self.arg2]))
+    }
+}
+
+/// Lay out fields in columns 8 characters wide, moving any field that would not fit with a space
+/// after it to the next column.
+fn columns(fields: &[&str]) -> String {
+    let mut row = String::new();
+    for field in fields {
+        let width = (field.len() / 8 + 1) * 8;
+        row.push_str(&format!("{:width$}", field, width = width));
+    }
+    row.trim_end().to_string()
+}
+
+/// The memory of a running program.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct State {
+    /// The values of variables and temporaries.
+    pub vars: HashMap<Name, Value>,
+    /// The elements of arrays, by array and offset.
+    pub memory: HashMap<(Name, i64), Value>,
+    /// The arguments of every call to `print`, in order.
+    pub output: Vec<Value>,
+}
+
+impl State {
+    fn get(&self, x: &Operand) -> Result<Value, RunError> {
+        match x {
+            Operand::Const(value) => Ok(*value),
+            Operand::Name(name) => self
+                .vars
+                .get(name)
+                .copied()
+                .ok_or_else(|| RunError::Undefined(name.clone())),
+        }
+    }
+
+    fn offset(&self, i: &Operand) -> Result<i64, RunError> {
+        match self.get(i)? {
+            Value::Int(offset) => Ok(offset),
+            value => Err(RunError::Type(format!("{} is not an offset", value))),
+        }
+    }
+}
+
+/// Reasons a program stopped before reaching its end.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RunError {
+    /// A name was used before being given a value.
+    Undefined(Name),
+    /// An element of an array was read before being given a value.
+    Uninitialized(Name, i64),
+    /// An operator was applied to values of the wrong types.
+    Type(String),
+    DivideByZero,
+    /// A jump went to a label that is not in the program.
+    NoLabel(Label),
+    /// A procedure other than `print` was called, or was called with too few parameters.
+    UnknownProcedure(String, usize),
+    /// The program ran for more instructions than it was allowed.
+    OutOfFuel,
+}
+
+impl fmt::Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Undefined(name) => write!(f, "`{}` is used before it is assigned", name),
+            Self::Uninitialized(a, offset) => {
+                write!(f, "`{}[{}]` is used before it is assigned", a, offset)
+            }
+            Self::Type(message) => write!(f, "{}", message),
+            Self::DivideByZero => write!(f, "division by zero"),
+            Self::NoLabel(label) => write!(f, "no instruction is labeled {}", label),
+            Self::UnknownProcedure(p, n) => write!(f, "cannot call `{}` with {} argument(s)", p, n),
+            Self::OutOfFuel => write!(f, "the program ran for too long"),
+        }
+    }
+}
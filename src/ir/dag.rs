@@ -0,0 +1,545 @@
+//! Local optimization of basic blocks, by way of directed acyclic graphs.
+//!
+//! A [`Dag`] has a node for each value a block computes. A leaf is a constant, or the value a
+//! name has when the block starts, and an interior node applies an operator to its children.
+//! Before a node is made, the DAG looks for one that applies the same operator to the same
+//! children, so that a *common subexpression* is only computed once. Along the way, operators on
+//! constants are folded, the identities `x + 0`, `x - 0`, `x * 1` and `x / 1` make no node at
+//! all, and `x * 2` is reduced in strength to `x + x`. Copies make no node either: the name
+//! copied to just gets the node of the value.
+//!
+//! Stores, calls and jumps are kept in order along with the nodes. A store to an array keeps the
+//! loads from it before the store from being reused after it, and so does a call for every load.
+//!
+//! [`Dag::instrs`] puts a block back together from its DAG, which leaves out each node that is
+//! not needed by a *live* name (one that may be used after the block) or by a store, call or
+//! jump: this is *dead code*. Each node is computed into a live name that holds it at the end of
+//! the block, if one can be overwritten by then, and copies are made for the other live names.
+//!
+//! [`Dag`]: ./struct.Dag.html
+//! [`Dag::instrs`]: ./struct.Dag.html#method.instrs
+//!
+//! ## Example
+//!
+//! ```
+//! # use dragon::ir::{dag::*, *};
+//! let var = |name: &str| Name::Var(name.to_string());
+//! let operand = |name: &str| Operand::Name(var(name));
+//!
+//! // a = b + c; b = a - d; c = b + c; d = a - d
+//! let block = [
+//!     Instr::Binary(var("a"), BinaryOp::Add, operand("b"), operand("c")),
+//!     Instr::Binary(var("b"), BinaryOp::Sub, operand("a"), operand("d")),
+//!     Instr::Binary(var("c"), BinaryOp::Add, operand("b"), operand("c")),
+//!     Instr::Binary(var("d"), BinaryOp::Sub, operand("a"), operand("d")),
+//! ];
+//! let dag = Dag::new(&block);
+//! assert_eq!(dag.len(), 6);
+//!
+//! let unused = || -> Name { unreachable!() };
+//! let code = |instrs: Vec<Instr>| instrs.iter().map(|i| i.to_string()).collect::<Vec<_>>();
+//!
+//! let live = |x: &Name| *x != var("b");
+//! assert_eq!(
+//!     code(dag.instrs(live, unused)),
+//!     ["a = b + c", "d = a - d", "c = d + c"],
+//! );
+//!
+//! let live = |_: &Name| true;
+//! assert_eq!(
+//!     code(dag.instrs(live, unused)),
+//!     ["a = b + c", "b = a - d", "c = b + c", "d = b"],
+//! );
+//! ```
+//!
+//! `x = a * b + a * b * 1 + 0; y = x * 2` as it comes out of a front end:
+//!
+//! ```
+//! # use dragon::ir::{dag::*, *};
+//! let var = |name: &str| Operand::Name(Name::Var(name.to_string()));
+//! let temp = |t: usize| Name::Temp(t);
+//! let int = |n: i64| Operand::Const(Value::Int(n));
+//!
+//! let block = [
+//!     Instr::Binary(temp(1), BinaryOp::Mul, var("a"), var("b")),
+//!     Instr::Binary(temp(2), BinaryOp::Mul, var("a"), var("b")),
+//!     Instr::Binary(temp(3), BinaryOp::Mul, Operand::Name(temp(2)), int(1)),
+//!     Instr::Binary(temp(4), BinaryOp::Add, Operand::Name(temp(1)), Operand::Name(temp(3))),
+//!     Instr::Binary(temp(5), BinaryOp::Add, Operand::Name(temp(4)), int(0)),
+//!     Instr::Copy(Name::Var("x".to_string()), Operand::Name(temp(5))),
+//!     Instr::Binary(temp(6), BinaryOp::Mul, var("x"), int(2)),
+//!     Instr::Copy(Name::Var("y".to_string()), Operand::Name(temp(6))),
+//! ];
+//!
+//! let live = |x: &Name| matches!(x, Name::Var(_));
+//! let instrs = Dag::new(&block).instrs(live, || unreachable!());
+//! assert_eq!(
+//!     instrs.iter().map(|i| i.to_string()).collect::<Vec<_>>(),
+//!     ["t1 = a * b", "x = t1 + t1", "y = x + x"],
+//! );
+//! ```
+
+use {
+    super::{flow::Graph, BinaryOp, Instr, Name, Operand, UnaryOp, Value},
+    std::collections::{BTreeMap, HashMap, HashSet},
+};
+
+/// What a node of a DAG computes.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum Kind {
+    Const(Value),
+    /// The value a name has when the block starts.
+    Initial(Name),
+    Binary(BinaryOp, usize, usize),
+    Unary(UnaryOp, usize),
+    /// `a[i]`
+    Load(Name, usize),
+    /// The result of the call at this position among the items, which is never shared.
+    Call(usize),
+}
+
+#[derive(Clone, Debug)]
+struct Node {
+    kind: Kind,
+    /// The name that the instruction that made the node assigned to.
+    target: Option<Name>,
+}
+
+/// A step of a block, in order.
+#[derive(Clone, Debug)]
+enum Item {
+    /// Compute an interior node.
+    Node(usize),
+    /// An instruction that does more than assign a value: a store, call or jump. The nodes are
+    /// the values of its operands, in order, and its result, if it has one.
+    Effect(Instr, Vec<usize>, Option<usize>),
+}
+
+/// The DAG of a basic block.
+#[derive(Clone, Debug)]
+pub struct Dag {
+    nodes: Vec<Node>,
+    items: Vec<Item>,
+    /// The node for the value each name has, once it is used or assigned.
+    current: HashMap<Name, usize>,
+    /// The nodes that can be reused, by what they compute.
+    lookup: HashMap<Kind, usize>,
+}
+
+impl Dag {
+    /// Build the DAG of the instructions of a block. Labels are ignored.
+    pub fn new(instrs: &[Instr]) -> Self {
+        let mut dag = Self {
+            nodes: Vec::new(),
+            items: Vec::new(),
+            current: HashMap::new(),
+            lookup: HashMap::new(),
+        };
+
+        for instr in instrs {
+            dag.add(instr);
+        }
+
+        dag
+    }
+
+    /// The number of nodes, including leaves.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Whether the block computes nothing and uses nothing.
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    fn add(&mut self, instr: &Instr) {
+        match instr {
+            Instr::Label(_) => (),
+            Instr::Binary(x, op, y, z) => {
+                let (y, z) = (self.operand(y), self.operand(z));
+                let n = self.binary(*op, y, z, x);
+                self.current.insert(x.clone(), n);
+            }
+            Instr::Unary(x, op, y) => {
+                let y = self.operand(y);
+                let n = match self.constant(y).map(|y| op.apply(y)) {
+                    Some(Ok(value)) => self.leaf(Kind::Const(value)),
+                    _ => self.interior(Kind::Unary(*op, y), x),
+                };
+                self.current.insert(x.clone(), n);
+            }
+            Instr::Copy(x, y) => {
+                let n = self.operand(y);
+                self.current.insert(x.clone(), n);
+            }
+            Instr::Load(x, a, i) => {
+                let i = self.operand(i);
+                let n = self.interior(Kind::Load(a.clone(), i), x);
+                self.current.insert(x.clone(), n);
+            }
+            Instr::Store(a, ..) => {
+                self.effect(instr, None);
+                self.lookup
+                    .retain(|kind, _| !matches!(kind, Kind::Load(b, _) if b == a));
+            }
+            Instr::Call(x, ..) => {
+                self.lookup
+                    .retain(|kind, _| !matches!(kind, Kind::Load(..)));
+                let result = x.as_ref().map(|x| {
+                    let n = self.push(Kind::Call(self.items.len()), Some(x.clone()));
+                    self.current.insert(x.clone(), n);
+                    n
+                });
+                self.effect(instr, result);
+            }
+            _ => self.effect(instr, None),
+        }
+    }
+
+    fn push(&mut self, kind: Kind, target: Option<Name>) -> usize {
+        self.nodes.push(Node { kind, target });
+        self.nodes.len() - 1
+    }
+
+    /// The node of a constant or of the value a name has when the block starts.
+    fn leaf(&mut self, kind: Kind) -> usize {
+        match self.lookup.get(&kind) {
+            Some(&n) => n,
+            None => {
+                let n = self.push(kind.clone(), None);
+                self.lookup.insert(kind, n);
+                n
+            }
+        }
+    }
+
+    /// The node that computes something, made and computed into `x` if there is none yet.
+    fn interior(&mut self, kind: Kind, x: &Name) -> usize {
+        match self.lookup.get(&kind) {
+            Some(&n) => n,
+            None => {
+                let n = self.push(kind.clone(), Some(x.clone()));
+                self.items.push(Item::Node(n));
+                self.lookup.insert(kind, n);
+                n
+            }
+        }
+    }
+
+    fn operand(&mut self, operand: &Operand) -> usize {
+        match operand {
+            Operand::Const(value) => self.leaf(Kind::Const(*value)),
+            Operand::Name(x) => match self.current.get(x) {
+                Some(&n) => n,
+                None => {
+                    let n = self.leaf(Kind::Initial(x.clone()));
+                    self.current.insert(x.clone(), n);
+                    n
+                }
+            },
+        }
+    }
+
+    fn constant(&self, n: usize) -> Option<Value> {
+        match self.nodes[n].kind {
+            Kind::Const(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    fn binary(&mut self, op: BinaryOp, y: usize, z: usize, x: &Name) -> usize {
+        if let (Some(a), Some(b)) = (self.constant(y), self.constant(z)) {
+            if let Ok(value) = op.apply(a, b) {
+                return self.leaf(Kind::Const(value));
+            }
+        }
+
+        let is = |n: usize, k: i64| match self.constant(n) {
+            Some(Value::Int(m)) => m == k,
+            Some(Value::Float(f)) => f == k as f64,
+            _ => false,
+        };
+        match op {
+            BinaryOp::Add if is(z, 0) => return y,
+            BinaryOp::Add if is(y, 0) => return z,
+            BinaryOp::Sub if is(z, 0) => return y,
+            BinaryOp::Mul if is(z, 1) => return y,
+            BinaryOp::Mul if is(y, 1) => return z,
+            BinaryOp::Div | BinaryOp::IntDiv if is(z, 1) => return y,
+            BinaryOp::Mul if is(z, 2) => return self.binary(BinaryOp::Add, y, y, x),
+            BinaryOp::Mul if is(y, 2) => return self.binary(BinaryOp::Add, z, z, x),
+            _ => (),
+        }
+
+        let commutative = matches!(
+            op,
+            BinaryOp::Add | BinaryOp::Mul | BinaryOp::Eq | BinaryOp::Ne
+        );
+        if commutative {
+            if let Some(&n) = self.lookup.get(&Kind::Binary(op, z, y)) {
+                return n;
+            }
+        }
+        self.interior(Kind::Binary(op, y, z), x)
+    }
+
+    fn effect(&mut self, instr: &Instr, result: Option<usize>) {
+        let operands: Vec<Operand> = instr.operands().into_iter().cloned().collect();
+        let uses = operands.iter().map(|o| self.operand(o)).collect();
+        self.items.push(Item::Effect(instr.clone(), uses, result));
+    }
+
+    fn children(&self, n: usize) -> Vec<usize> {
+        match self.nodes[n].kind {
+            Kind::Binary(_, y, z) => vec![y, z],
+            Kind::Unary(_, y) | Kind::Load(_, y) => vec![y],
+            _ => Vec::new(),
+        }
+    }
+
+    /// Put the block back together, given which names are live when it ends. New temporaries,
+    /// which are only needed when the values of live names are swapped, are made by `temp`.
+    pub fn instrs(&self, live: impl Fn(&Name) -> bool, temp: impl FnMut() -> Name) -> Vec<Instr> {
+        // The values the live names need to have at the end, other than the ones they start with.
+        let finals: BTreeMap<Name, usize> = self
+            .current
+            .iter()
+            .filter(|&(x, &n)| {
+                live(x) && !matches!(&self.nodes[n].kind, Kind::Initial(y) if y == x)
+            })
+            .map(|(x, &n)| (x.clone(), n))
+            .collect();
+
+        let mut needed = vec![false; self.nodes.len()];
+        let mut stack: Vec<usize> = finals.values().copied().collect();
+        for item in &self.items {
+            if let Item::Effect(_, uses, _) = item {
+                stack.extend(uses);
+            }
+        }
+        while let Some(n) = stack.pop() {
+            if !needed[n] {
+                needed[n] = true;
+                stack.extend(self.children(n));
+            }
+        }
+
+        let mut last_use = vec![None; self.nodes.len()];
+        for (p, item) in self.items.iter().enumerate() {
+            let uses = match item {
+                Item::Node(n) if needed[*n] => self.children(*n),
+                Item::Effect(_, uses, _) => uses.clone(),
+                Item::Node(_) => Vec::new(),
+            };
+            for n in uses {
+                last_use[n] = Some(p);
+            }
+        }
+
+        let holders = self
+            .nodes
+            .iter()
+            .enumerate()
+            .filter_map(|(n, node)| match &node.kind {
+                Kind::Initial(x) => Some((x.clone(), n)),
+                _ => None,
+            })
+            .collect();
+
+        let mut assembler = Assembler {
+            dag: self,
+            finals,
+            last_use,
+            holders,
+            temp,
+            instrs: Vec::new(),
+        };
+
+        // A jump at the end stays at the end, after the copies to live names.
+        let (items, jump) = match self.items.split_last() {
+            Some((Item::Effect(instr, uses, _), items)) if instr.target().is_some() => {
+                (items, Some((instr, uses)))
+            }
+            _ => (&self.items[..], None),
+        };
+
+        for (p, item) in items.iter().enumerate() {
+            match item {
+                Item::Node(n) if needed[*n] => {
+                    let x = assembler.home(*n, p);
+                    let operand = |y: usize| assembler.operand(y);
+                    let instr = match self.nodes[*n].kind {
+                        Kind::Binary(op, y, z) => {
+                            Instr::Binary(x.clone(), op, operand(y), operand(z))
+                        }
+                        Kind::Unary(op, y) => Instr::Unary(x.clone(), op, operand(y)),
+                        Kind::Load(ref a, i) => Instr::Load(x.clone(), a.clone(), operand(i)),
+                        _ => unreachable!(),
+                    };
+                    assembler.instrs.push(instr);
+                    assembler.holders.insert(x, *n);
+                }
+                Item::Node(_) => (),
+                Item::Effect(instr, uses, result) => {
+                    let mut instr = instr.clone();
+                    for (operand, &n) in instr.operands_mut().into_iter().zip(uses) {
+                        *operand = assembler.operand(n);
+                    }
+                    if let (Instr::Call(x, ..), Some(n)) = (&mut instr, *result) {
+                        *x = if needed[n] {
+                            let home = assembler.home(n, p);
+                            assembler.holders.insert(home.clone(), n);
+                            Some(home)
+                        } else {
+                            None
+                        };
+                    }
+                    assembler.instrs.push(instr);
+                }
+            }
+        }
+
+        let jump_uses: HashSet<usize> =
+            jump.map_or(HashSet::new(), |(_, uses)| uses.iter().copied().collect());
+        assembler.copy_finals(&jump_uses);
+
+        if let Some((instr, uses)) = jump {
+            let mut instr = instr.clone();
+            for (operand, &n) in instr.operands_mut().into_iter().zip(uses) {
+                *operand = assembler.operand(n);
+            }
+            assembler.instrs.push(instr);
+        }
+
+        assembler.instrs
+    }
+}
+
+/// The state of putting a block back together from its DAG.
+struct Assembler<'a, F> {
+    dag: &'a Dag,
+    finals: BTreeMap<Name, usize>,
+    /// The position of the last item that reads each node.
+    last_use: Vec<Option<usize>>,
+    /// The node each name holds so far.
+    holders: BTreeMap<Name, usize>,
+    temp: F,
+    instrs: Vec<Instr>,
+}
+
+impl<'a, F: FnMut() -> Name> Assembler<'a, F> {
+    fn operand(&self, n: usize) -> Operand {
+        if let Kind::Const(value) = self.dag.nodes[n].kind {
+            return Operand::Const(value);
+        }
+
+        let x = self
+            .holders
+            .iter()
+            .find(|&(_, &m)| m == n)
+            .map(|(x, _)| x.clone())
+            .expect("a value that is still needed is held by some name");
+        Operand::Name(x)
+    }
+
+    /// Whether some name other than `x` holds a node.
+    fn held_elsewhere(&self, n: usize, x: &Name) -> bool {
+        self.holders.iter().any(|(y, &m)| m == n && y != x)
+    }
+
+    /// Whether `x` can be assigned by the item at position `p`, without losing a value that is
+    /// read later or is needed at the end.
+    fn can_assign(&self, x: &Name, p: usize) -> bool {
+        match self.holders.get(x) {
+            None => true,
+            Some(&n) => {
+                let needed = self.last_use[n] > Some(p) || self.finals.values().any(|&m| m == n);
+                !needed || self.held_elsewhere(n, x)
+            }
+        }
+    }
+
+    /// The name to compute a node into at position `p`: a live name that holds it at the end,
+    /// the name the block first computed it into, or else a new temporary.
+    fn home(&mut self, n: usize, p: usize) -> Name {
+        let candidates = self
+            .finals
+            .iter()
+            .filter(|&(_, &m)| m == n)
+            .map(|(x, _)| x)
+            .chain(self.dag.nodes[n].target.as_ref());
+
+        for x in candidates {
+            if self.can_assign(x, p) {
+                return x.clone();
+            }
+        }
+        (self.temp)()
+    }
+
+    /// Copy the values the live names need to have at the end into them, keeping the ones that
+    /// are still to be copied, or read by the jump that ends the block.
+    fn copy_finals(&mut self, jump_uses: &HashSet<usize>) {
+        let mut pending: Vec<(Name, usize)> = self
+            .finals
+            .iter()
+            .filter(|&(x, n)| self.holders.get(x) != Some(n))
+            .map(|(x, &n)| (x.clone(), n))
+            .collect();
+
+        while !pending.is_empty() {
+            let free = pending.iter().position(|(x, _)| match self.holders.get(x) {
+                None => true,
+                Some(&m) => {
+                    let needed = jump_uses.contains(&m) || pending.iter().any(|&(_, n)| n == m);
+                    !needed || self.held_elsewhere(m, x)
+                }
+            });
+
+            match free {
+                Some(i) => {
+                    let (x, n) = pending.remove(i);
+                    let value = self.operand(n);
+                    self.instrs.push(Instr::Copy(x.clone(), value));
+                    self.holders.insert(x, n);
+                }
+                None => {
+                    // Every name to copy to holds a value that is still to be copied somewhere.
+                    let x = pending[0].0.clone();
+                    let t = (self.temp)();
+                    self.instrs
+                        .push(Instr::Copy(t.clone(), Operand::Name(x.clone())));
+                    self.holders.insert(t, self.holders[&x]);
+                }
+            }
+        }
+    }
+}
+
+/// Optimize each block of a flow graph by way of its DAG. Every variable is taken to be live at
+/// the end of every block, along with every temporary that some other block uses.
+pub fn optimize(graph: &mut Graph) {
+    let mut users: HashMap<Name, HashSet<usize>> = HashMap::new();
+    for (i, block) in graph.blocks.iter().enumerate() {
+        for instr in &block.instrs {
+            for operand in instr.operands() {
+                if let Operand::Name(x) = operand {
+                    users.entry(x.clone()).or_default().insert(i);
+                }
+            }
+        }
+    }
+
+    for i in 0..graph.blocks.len() {
+        let dag = Dag::new(&graph.blocks[i].instrs);
+        let live = |x: &Name| match x {
+            Name::Var(_) => true,
+            Name::Temp(_) => {
+                matches!(users.get(x), Some(blocks) if blocks.iter().any(|&j| j != i))
+            }
+        };
+        let instrs = dag.instrs(live, || graph.temp());
+        graph.blocks[i].instrs = instrs;
+    }
+}
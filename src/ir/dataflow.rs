@@ -0,0 +1,468 @@
+//! Iterative data-flow analysis.
+//!
+//! A data-flow analysis finds a *fact* at each point of a program, such as the set of variables
+//! that may be used before they are next assigned. An [`Analysis`] says which way facts flow
+//! through the [flow graph], what the fact is where they start, how an instruction changes a fact
+//! (its *transfer function*), and how the facts from several paths combine where the paths meet
+//! (the *meet* of a semilattice, with `top` as its identity). [`solve`] keeps a worklist of
+//! blocks, and applies the transfer functions of a block until the facts stop changing. With
+//! monotone transfer functions over a lattice of finite height, this ends with the greatest
+//! fixed point.
+//!
+//! [`ReachingDefinitions`], [`LiveVariables`] and [`AvailableExpressions`] are the classic
+//! analyses.
+//!
+//! [`Analysis`]: ./trait.Analysis.html
+//! [flow graph]: ../flow/struct.Graph.html
+//! [`solve`]: ./fn.solve.html
+//! [`ReachingDefinitions`]: ./struct.ReachingDefinitions.html
+//! [`LiveVariables`]: ./struct.LiveVariables.html
+//! [`AvailableExpressions`]: ./struct.AvailableExpressions.html
+//!
+//! ## Example
+//!
+//! ```
+//! # use dragon::ir::{dataflow::*, flow::*, *};
+//! # use std::collections::BTreeSet;
+//! let var = |name: &str| Name::Var(name.to_string());
+//! let operand = |name: &str| Operand::Name(var(name));
+//! let int = |n: i64| Operand::Const(Value::Int(n));
+//!
+//! // i = 0; L1: if i >= n goto L2; x = a * b; i = i + 1; goto L1; L2: param x; call print, 1
+//! let mut program = Program::new();
+//! let (top, done) = (program.label(), program.label());
+//! program.emit(Instr::Copy(var("i"), int(0)));
+//! program.emit(Instr::Label(top));
+//! program.emit(Instr::IfRel(operand("i"), BinaryOp::Ge, operand("n"), done));
+//! program.emit(Instr::Binary(var("x"), BinaryOp::Mul, operand("a"), operand("b")));
+//! program.emit(Instr::Binary(var("i"), BinaryOp::Add, operand("i"), int(1)));
+//! program.emit(Instr::Goto(top));
+//! program.emit(Instr::Label(done));
+//! program.emit(Instr::Param(operand("x")));
+//! program.emit(Instr::Call(None, "print".to_string(), 1));
+//! let graph = Graph::new(&program);
+//!
+//! let reaching = ReachingDefinitions::new(&graph);
+//! let solution = solve(&reaching, &graph);
+//! let at_loop: Vec<_> = solution.at_start(1).iter().map(|d| d.to_string()).collect();
+//! assert_eq!(at_loop, ["B1.1", "B3.1", "B3.2"]);
+//!
+//! let solution = solve(&LiveVariables, &graph);
+//! let names = |set: &BTreeSet<Name>| set.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+//! assert_eq!(names(solution.at_start(0)), ["a", "b", "n", "x"]);
+//! assert_eq!(names(solution.after(Point { block: 2, index: 0 })), ["a", "b", "i", "n", "x"]);
+//! assert_eq!(names(solution.at_end(3)), Vec::<String>::new());
+//!
+//! let available = AvailableExpressions::new(&graph);
+//! let solution = solve(&available, &graph);
+//! assert!(solution.at_start(1).is_empty());
+//! assert_eq!(solution.at_end(2).len(), 1);
+//! ```
+//!
+//! A new analysis only has to implement [`Analysis`]. Here, the variables that are certainly
+//! assigned, on every path from the entry:
+//!
+//! ```
+//! # use dragon::ir::{dataflow::*, flow::*, *};
+//! # use std::collections::BTreeSet;
+//! struct Assigned(BTreeSet<Name>);
+//!
+//! impl Analysis for Assigned {
+//!     type Fact = BTreeSet<Name>;
+//!     const DIRECTION: Direction = Direction::Forward;
+//!
+//!     fn boundary(&self) -> Self::Fact {
+//!         BTreeSet::new()
+//!     }
+//!
+//!     fn top(&self) -> Self::Fact {
+//!         self.0.clone()
+//!     }
+//!
+//!     fn meet(&self, fact: &mut Self::Fact, other: &Self::Fact) {
+//!         fact.retain(|x| other.contains(x));
+//!     }
+//!
+//!     fn transfer(&self, _: Point, instr: &Instr, fact: &mut Self::Fact) {
+//!         fact.extend(instr.assigned().cloned());
+//!     }
+//! }
+//!
+//! let var = |name: &str| Name::Var(name.to_string());
+//! let mut program = Program::new();
+//! let skip = program.label();
+//! program.emit(Instr::Copy(var("x"), Operand::Const(Value::Int(1))));
+//! program.emit(Instr::If(Operand::Name(var("b")), skip));
+//! program.emit(Instr::Copy(var("y"), Operand::Const(Value::Int(2))));
+//! program.emit(Instr::Label(skip));
+//! let graph = Graph::new(&program);
+//!
+//! let all = vec![var("x"), var("y")].into_iter().collect();
+//! let solution = solve(&Assigned(all), &graph);
+//! assert_eq!(solution.at_end(1).len(), 2);
+//! assert_eq!(solution.at_start(2).iter().collect::<Vec<_>>(), [&var("x")]);
+//! ```
+
+use {
+    super::{
+        flow::{Graph, Node},
+        BinaryOp, Instr, Name, Operand, UnaryOp,
+    },
+    std::{
+        collections::{BTreeSet, HashMap, HashSet, VecDeque},
+        fmt,
+    },
+};
+
+/// Which way facts flow through a program.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    /// From the entry, along the edges.
+    Forward,
+    /// From the exit, against the edges.
+    Backward,
+}
+
+/// The position of an instruction: its block, and its index among the block's instructions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Point {
+    pub block: usize,
+    pub index: usize,
+}
+
+impl fmt::Display for Point {
+    /// The block and the instruction in it, both counting from one, as in `B2.1`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", Node::Block(self.block), self.index + 1)
+    }
+}
+
+/// A data-flow analysis.
+pub trait Analysis {
+    /// What is known at a point of the program.
+    type Fact: Clone + PartialEq;
+
+    const DIRECTION: Direction;
+
+    /// The fact at the entry of a forward analysis, or at the exit of a backward one.
+    fn boundary(&self) -> Self::Fact;
+
+    /// The fact that is the identity of `meet`, which every block starts with.
+    fn top(&self) -> Self::Fact;
+
+    /// Combine the fact from another path into a fact.
+    fn meet(&self, fact: &mut Self::Fact, other: &Self::Fact);
+
+    /// Carry a fact across an instruction: from before it to after it in a forward analysis, and
+    /// from after it to before it in a backward one.
+    fn transfer(&self, point: Point, instr: &Instr, fact: &mut Self::Fact);
+}
+
+/// The facts an analysis found, at the start and end of each block and between its instructions.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Solution<F> {
+    /// The facts before each instruction of each block, and then at its end.
+    facts: Vec<Vec<F>>,
+}
+
+impl<F> Solution<F> {
+    /// The fact at the start of a block, before its first instruction.
+    pub fn at_start(&self, block: usize) -> &F {
+        &self.facts[block][0]
+    }
+
+    /// The fact at the end of a block, after its last instruction.
+    pub fn at_end(&self, block: usize) -> &F {
+        self.facts[block].last().unwrap()
+    }
+
+    /// The fact just before an instruction.
+    pub fn before(&self, point: Point) -> &F {
+        &self.facts[point.block][point.index]
+    }
+
+    /// The fact just after an instruction.
+    pub fn after(&self, point: Point) -> &F {
+        &self.facts[point.block][point.index + 1]
+    }
+}
+
+/// Solve a data-flow analysis over a flow graph.
+pub fn solve<A: Analysis>(analysis: &A, graph: &Graph) -> Solution<A::Fact> {
+    let forward = A::DIRECTION == Direction::Forward;
+    let boundary = analysis.boundary();
+
+    // The facts where the analysis leaves each block: at its end going forward, or at its start
+    // going backward.
+    let mut leaving = vec![analysis.top(); graph.blocks.len()];
+
+    // The blocks that can be reached are visited in an order that sees most of the edges into a
+    // block before the block, and the others after them.
+    let mut order: Vec<usize> = graph
+        .reverse_postorder()
+        .into_iter()
+        .filter_map(|node| match node {
+            Node::Block(b) => Some(b),
+            _ => None,
+        })
+        .collect();
+    let mut reached = vec![false; graph.blocks.len()];
+    for &b in &order {
+        reached[b] = true;
+    }
+    order.extend((0..graph.blocks.len()).filter(|&b| !reached[b]));
+    if !forward {
+        order.reverse();
+    }
+
+    let entering = |leaving: &[A::Fact], b: usize| {
+        let neighbors = if forward {
+            graph.predecessors(Node::Block(b))
+        } else {
+            graph.successors(Node::Block(b))
+        };
+
+        let mut fact: Option<A::Fact> = None;
+        for &node in neighbors {
+            let other = match node {
+                Node::Block(i) => &leaving[i],
+                _ => &boundary,
+            };
+            match fact {
+                Some(ref mut fact) => analysis.meet(fact, other),
+                None => fact = Some(other.clone()),
+            }
+        }
+        fact.unwrap_or_else(|| analysis.top())
+    };
+
+    let mut queued = vec![true; graph.blocks.len()];
+    let mut worklist: VecDeque<usize> = order.into_iter().collect();
+
+    while let Some(b) = worklist.pop_front() {
+        queued[b] = false;
+
+        let mut fact = entering(&leaving, b);
+        for_each_instr(graph, b, forward, |point, instr| {
+            analysis.transfer(point, instr, &mut fact)
+        });
+
+        if fact != leaving[b] {
+            leaving[b] = fact;
+
+            let neighbors = if forward {
+                graph.successors(Node::Block(b))
+            } else {
+                graph.predecessors(Node::Block(b))
+            };
+            for &node in neighbors {
+                if let Node::Block(i) = node {
+                    if !queued[i] {
+                        queued[i] = true;
+                        worklist.push_back(i);
+                    }
+                }
+            }
+        }
+    }
+
+    let facts = (0..graph.blocks.len())
+        .map(|b| {
+            let mut fact = entering(&leaving, b);
+            let mut facts = vec![fact.clone()];
+            for_each_instr(graph, b, forward, |point, instr| {
+                analysis.transfer(point, instr, &mut fact);
+                facts.push(fact.clone());
+            });
+            if !forward {
+                facts.reverse();
+            }
+            facts
+        })
+        .collect();
+
+    Solution { facts }
+}
+
+/// Visit the instructions of a block, from first to last if `forward`, or else from last to
+/// first.
+fn for_each_instr(graph: &Graph, block: usize, forward: bool, mut f: impl FnMut(Point, &Instr)) {
+    let instrs = graph.blocks[block].instrs.iter().enumerate();
+    let mut visit = |(index, instr)| f(Point { block, index }, instr);
+    if forward {
+        instrs.for_each(&mut visit);
+    } else {
+        instrs.rev().for_each(&mut visit);
+    }
+}
+
+/// The definitions that may reach each point without being overwritten on the way. A definition
+/// is an instruction that assigns to a name.
+#[derive(Clone, Debug)]
+pub struct ReachingDefinitions {
+    /// The definitions of each name.
+    definitions: HashMap<Name, Vec<Point>>,
+}
+
+impl ReachingDefinitions {
+    pub fn new(graph: &Graph) -> Self {
+        let mut definitions: HashMap<Name, Vec<Point>> = HashMap::new();
+        for (block, b) in graph.blocks.iter().enumerate() {
+            for (index, instr) in b.instrs.iter().enumerate() {
+                if let Some(x) = instr.assigned() {
+                    definitions
+                        .entry(x.clone())
+                        .or_default()
+                        .push(Point { block, index });
+                }
+            }
+        }
+
+        Self { definitions }
+    }
+
+    /// The definitions of a name.
+    pub fn definitions(&self, x: &Name) -> &[Point] {
+        self.definitions.get(x).map_or(&[], |points| &points[..])
+    }
+}
+
+impl Analysis for ReachingDefinitions {
+    type Fact = BTreeSet<Point>;
+    const DIRECTION: Direction = Direction::Forward;
+
+    fn boundary(&self) -> Self::Fact {
+        BTreeSet::new()
+    }
+
+    fn top(&self) -> Self::Fact {
+        BTreeSet::new()
+    }
+
+    fn meet(&self, fact: &mut Self::Fact, other: &Self::Fact) {
+        fact.extend(other);
+    }
+
+    fn transfer(&self, point: Point, instr: &Instr, fact: &mut Self::Fact) {
+        if let Some(x) = instr.assigned() {
+            for d in self.definitions(x) {
+                fact.remove(d);
+            }
+            fact.insert(point);
+        }
+    }
+}
+
+/// The names that may be used at or after each point before they are assigned again.
+#[derive(Clone, Copy, Debug)]
+pub struct LiveVariables;
+
+impl Analysis for LiveVariables {
+    type Fact = BTreeSet<Name>;
+    const DIRECTION: Direction = Direction::Backward;
+
+    fn boundary(&self) -> Self::Fact {
+        BTreeSet::new()
+    }
+
+    fn top(&self) -> Self::Fact {
+        BTreeSet::new()
+    }
+
+    fn meet(&self, fact: &mut Self::Fact, other: &Self::Fact) {
+        fact.extend(other.iter().cloned());
+    }
+
+    fn transfer(&self, _: Point, instr: &Instr, fact: &mut Self::Fact) {
+        if let Some(x) = instr.assigned() {
+            fact.remove(x);
+        }
+        for operand in instr.operands() {
+            if let Operand::Name(x) = operand {
+                fact.insert(x.clone());
+            }
+        }
+    }
+}
+
+/// The right side of an instruction that applies an operator.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Expression {
+    Binary(BinaryOp, Operand, Operand),
+    Unary(UnaryOp, Operand),
+}
+
+impl Expression {
+    /// The expression an instruction computes, if it applies an operator.
+    pub fn of(instr: &Instr) -> Option<Self> {
+        match instr {
+            Instr::Binary(_, op, y, z) => Some(Self::Binary(*op, y.clone(), z.clone())),
+            Instr::Unary(_, op, y) => Some(Self::Unary(*op, y.clone())),
+            _ => None,
+        }
+    }
+
+    /// Whether the expression reads a name, so that assigning to the name changes its value.
+    pub fn uses(&self, x: &Name) -> bool {
+        let operands = match self {
+            Self::Binary(_, y, z) => vec![y, z],
+            Self::Unary(_, y) => vec![y],
+        };
+        operands.contains(&&Operand::Name(x.clone()))
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Binary(op, y, z) => write!(f, "{} {} {}", y, op, z),
+            Self::Unary(op, y) => write!(f, "{} {}", op, y),
+        }
+    }
+}
+
+/// The expressions that are computed on every path to each point, without any of the names they
+/// read being assigned after.
+#[derive(Clone, Debug)]
+pub struct AvailableExpressions {
+    /// Every expression of the program.
+    all: HashSet<Expression>,
+}
+
+impl AvailableExpressions {
+    pub fn new(graph: &Graph) -> Self {
+        let all = graph
+            .blocks
+            .iter()
+            .flat_map(|block| block.instrs.iter().filter_map(Expression::of))
+            .collect();
+
+        Self { all }
+    }
+}
+
+impl Analysis for AvailableExpressions {
+    type Fact = HashSet<Expression>;
+    const DIRECTION: Direction = Direction::Forward;
+
+    fn boundary(&self) -> Self::Fact {
+        HashSet::new()
+    }
+
+    fn top(&self) -> Self::Fact {
+        self.all.clone()
+    }
+
+    fn meet(&self, fact: &mut Self::Fact, other: &Self::Fact) {
+        fact.retain(|e| other.contains(e));
+    }
+
+    fn transfer(&self, _: Point, instr: &Instr, fact: &mut Self::Fact) {
+        if let Some(e) = Expression::of(instr) {
+            fact.insert(e);
+        }
+        if let Some(x) = instr.assigned() {
+            fact.retain(|e| !e.uses(x));
+        }
+    }
+}
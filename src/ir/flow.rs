@@ -0,0 +1,508 @@
+//! Basic blocks and flow graphs.
+//!
+//! A *basic block* is a run of instructions that control only enters at the first of and only
+//! leaves at the last of. A program is split into blocks at its *leaders*: the first instruction,
+//! every instruction with a label (which is where jumps go), and every instruction just after a
+//! jump. A [`Graph`] has a node for each block, plus an *entry* node with an edge to the first
+//! block and an *exit* node that the blocks falling off the end of the program have edges to.
+//! Block `i` is written `B{i+1}`, so that the first block is `B1`.
+//!
+//! Node `d` *dominates* node `n` if every path from the entry to `n` goes through `d`. The
+//! nearest of the dominators of a node other than itself is its *immediate* dominator, and these
+//! make a tree rooted at the entry, given by [`Dominators`]. An edge `n -> d` where `d` dominates
+//! `n` is a *back edge*, and its *natural loop* is `d` along with every node that can reach `n`
+//! without going through `d`. [`Graph::loops`] finds these, with the loops that share a header
+//! merged into one.
+//!
+//! [`Graph`]: ./struct.Graph.html
+//! [`Dominators`]: ./struct.Dominators.html
+//! [`Graph::loops`]: ./struct.Graph.html#method.loops
+//!
+//! ## Example
+//!
+//! ```
+//! # use dragon::ir::{flow::*, *};
+//! let i = Name::Var("i".to_string());
+//! let mut program = Program::new();
+//! let top = program.label();
+//! let done = program.label();
+//!
+//! program.emit(Instr::Copy(i.clone(), Operand::Const(Value::Int(0))));
+//! program.emit(Instr::Label(top));
+//! program.emit(Instr::IfRel(
+//!     Operand::Name(i.clone()),
+//!     BinaryOp::Ge,
+//!     Operand::Const(Value::Int(3)),
+//!     done,
+//! ));
+//! program.emit(Instr::Param(Operand::Name(i.clone())));
+//! program.emit(Instr::Call(None, "print".to_string(), 1));
+//! program.emit(Instr::Binary(
+//!     i.clone(),
+//!     BinaryOp::Add,
+//!     Operand::Name(i.clone()),
+//!     Operand::Const(Value::Int(1)),
+//! ));
+//! program.emit(Instr::Goto(top));
+//! program.emit(Instr::Label(done));
+//!
+//! let graph = Graph::new(&program);
+//! assert_eq!(graph.blocks.len(), 4);
+//! assert_eq!(graph.blocks[2].to_string(), "    param i\n    call print, 1\n    i = i + 1\n    goto L1\n");
+//! assert_eq!(graph.successors(Node::Entry), [Node::Block(0)]);
+//! assert_eq!(graph.successors(Node::Block(1)), [Node::Block(3), Node::Block(2)]);
+//! assert_eq!(graph.predecessors(Node::Block(1)), [Node::Block(0), Node::Block(2)]);
+//! assert_eq!(graph.successors(Node::Block(3)), [Node::Exit]);
+//! assert_eq!(graph.program(), program);
+//!
+//! let dominators = graph.dominators();
+//! assert_eq!(dominators.immediate(Node::Block(2)), Some(Node::Block(1)));
+//! assert_eq!(dominators.immediate(Node::Exit), Some(Node::Block(3)));
+//! assert!(dominators.dominates(Node::Block(1), Node::Exit));
+//! assert!(!dominators.dominates(Node::Block(2), Node::Exit));
+//! assert_eq!(dominators.children(Node::Block(1)), [Node::Block(2), Node::Block(3)]);
+//!
+//! let loops = graph.loops(&dominators);
+//! assert_eq!(loops.len(), 1);
+//! assert_eq!(loops[0].header, Node::Block(1));
+//! assert_eq!(loops[0].latches, [Node::Block(2)]);
+//! assert_eq!(loops[0].to_string(), "B2: B2, B3");
+//!
+//! assert!(graph.dot().contains("\t2 -> 4 [label = T];\n"));
+//! ```
+
+use {
+    super::{Instr, Label, Program},
+    std::{
+        collections::{BTreeMap, BTreeSet, HashMap},
+        fmt,
+    },
+};
+
+/// A basic block: its labels, and the instructions after them.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Block {
+    pub labels: Vec<Label>,
+    pub instrs: Vec<Instr>,
+}
+
+impl fmt::Display for Block {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for label in &self.labels {
+            writeln!(f, "{}:", label)?;
+        }
+        for instr in &self.instrs {
+            writeln!(f, "    {}", instr)?;
+        }
+        Ok(())
+    }
+}
+
+/// A node of a flow graph.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Node {
+    Entry,
+    /// The block with this index.
+    Block(usize),
+    Exit,
+}
+
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Entry => write!(f, "ENTRY"),
+            Self::Block(i) => write!(f, "B{}", i + 1),
+            Self::Exit => write!(f, "EXIT"),
+        }
+    }
+}
+
+/// The flow graph of a program.
+///
+/// The edges are found when the graph is built. Changing the blocks keeps them, so a graph whose
+/// jumps have changed should be built again from its [`program`].
+///
+/// [`program`]: #method.program
+#[derive(Clone, Debug)]
+pub struct Graph {
+    pub blocks: Vec<Block>,
+    /// The successors and predecessors of each node, by its index.
+    successors: Vec<Vec<Node>>,
+    predecessors: Vec<Vec<Node>>,
+    /// The program the graph was built from, without its instructions, for the temporaries and
+    /// labels it has used.
+    program: Program,
+}
+
+impl Graph {
+    /// Split a program into basic blocks and find the edges between them.
+    ///
+    /// Panics if the program jumps to a label that it does not have.
+    pub fn new(program: &Program) -> Self {
+        let mut blocks = Vec::new();
+        let mut block = Block::default();
+
+        for instr in &program.instrs {
+            match instr {
+                Instr::Label(label) => {
+                    if !block.instrs.is_empty() {
+                        blocks.push(block);
+                        block = Block::default();
+                    }
+                    block.labels.push(*label);
+                }
+                _ => {
+                    block.instrs.push(instr.clone());
+                    if instr.target().is_some() {
+                        blocks.push(block);
+                        block = Block::default();
+                    }
+                }
+            }
+        }
+        if !block.labels.is_empty() || !block.instrs.is_empty() {
+            blocks.push(block);
+        }
+
+        let targets: HashMap<Label, usize> = blocks
+            .iter()
+            .enumerate()
+            .flat_map(|(i, block)| block.labels.iter().map(move |&label| (label, i)))
+            .collect();
+
+        let mut graph = Self {
+            successors: vec![Vec::new(); blocks.len() + 2],
+            predecessors: vec![Vec::new(); blocks.len() + 2],
+            blocks,
+            program: Program {
+                instrs: Vec::new(),
+                temps: program.temps,
+                labels: program.labels,
+            },
+        };
+
+        let first = if graph.blocks.is_empty() {
+            Node::Exit
+        } else {
+            Node::Block(0)
+        };
+        graph.edge(Node::Entry, first);
+
+        for i in 0..graph.blocks.len() {
+            let next = if i + 1 < graph.blocks.len() {
+                Node::Block(i + 1)
+            } else {
+                Node::Exit
+            };
+
+            let last = graph.blocks[i].instrs.last();
+            let conditional = !matches!(last, Some(Instr::Goto(_)));
+
+            match last.and_then(Instr::target) {
+                Some(label) => {
+                    let target = match targets.get(&label) {
+                        Some(&target) => Node::Block(target),
+                        None => panic!("jump to `{}`, which is not in the program", label),
+                    };
+
+                    graph.edge(Node::Block(i), target);
+                    if conditional {
+                        graph.edge(Node::Block(i), next);
+                    }
+                }
+                None => graph.edge(Node::Block(i), next),
+            }
+        }
+
+        graph
+    }
+
+    fn edge(&mut self, from: Node, to: Node) {
+        let (i, j) = (self.index(from), self.index(to));
+        if !self.successors[i].contains(&to) {
+            self.successors[i].push(to);
+            self.predecessors[j].push(from);
+        }
+    }
+
+    fn index(&self, node: Node) -> usize {
+        match node {
+            Node::Entry => 0,
+            Node::Block(i) => i + 1,
+            Node::Exit => self.blocks.len() + 1,
+        }
+    }
+
+    /// The nodes of the graph: the entry, the blocks in order, and the exit.
+    pub fn nodes(&self) -> impl Iterator<Item = Node> {
+        std::iter::once(Node::Entry)
+            .chain((0..self.blocks.len()).map(Node::Block))
+            .chain(std::iter::once(Node::Exit))
+    }
+
+    /// The nodes that control can go to from a node. For a block that ends in a conditional jump,
+    /// the target of the jump comes first.
+    pub fn successors(&self, node: Node) -> &[Node] {
+        &self.successors[self.index(node)]
+    }
+
+    /// The nodes that control can come to a node from.
+    pub fn predecessors(&self, node: Node) -> &[Node] {
+        &self.predecessors[self.index(node)]
+    }
+
+    /// The instructions of the blocks in order, as a program.
+    pub fn program(&self) -> Program {
+        let mut program = self.program.clone();
+        for block in &self.blocks {
+            program
+                .instrs
+                .extend(block.labels.iter().map(|&label| Instr::Label(label)));
+            program.instrs.extend(block.instrs.iter().cloned());
+        }
+        program
+    }
+
+    /// A temporary that has not been used in the program.
+    pub fn temp(&mut self) -> super::Name {
+        self.program.temp()
+    }
+
+    /// A label that has not been used in the program.
+    pub fn label(&mut self) -> Label {
+        self.program.label()
+    }
+
+    /// The nodes that can be reached from the entry, in reverse postorder: each node comes before
+    /// its successors, except along back edges.
+    pub fn reverse_postorder(&self) -> Vec<Node> {
+        let mut visited = vec![false; self.blocks.len() + 2];
+        let mut order = Vec::new();
+        let mut stack = vec![(Node::Entry, 0)];
+        visited[0] = true;
+
+        while let Some((node, next)) = stack.pop() {
+            match self.successors(node).get(next) {
+                Some(&successor) => {
+                    stack.push((node, next + 1));
+                    let i = self.index(successor);
+                    if !visited[i] {
+                        visited[i] = true;
+                        stack.push((successor, 0));
+                    }
+                }
+                None => order.push(node),
+            }
+        }
+
+        order.reverse();
+        order
+    }
+
+    /// The dominator tree of the nodes that can be reached from the entry.
+    pub fn dominators(&self) -> Dominators {
+        let order = self.reverse_postorder();
+        let position: HashMap<Node, usize> = order
+            .iter()
+            .enumerate()
+            .map(|(i, &node)| (node, i))
+            .collect();
+
+        let mut immediate = BTreeMap::new();
+        immediate.insert(Node::Entry, Node::Entry);
+
+        let intersect = |immediate: &BTreeMap<Node, Node>, mut a: Node, mut b: Node| {
+            while a != b {
+                while position[&a] > position[&b] {
+                    a = immediate[&a];
+                }
+                while position[&b] > position[&a] {
+                    b = immediate[&b];
+                }
+            }
+            a
+        };
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+
+            for &node in &order[1..] {
+                let mut dominator = None;
+                for &predecessor in self.predecessors(node) {
+                    if immediate.contains_key(&predecessor) {
+                        dominator = Some(match dominator {
+                            None => predecessor,
+                            Some(other) => intersect(&immediate, predecessor, other),
+                        });
+                    }
+                }
+
+                let dominator = dominator.unwrap();
+                if immediate.insert(node, dominator) != Some(dominator) {
+                    changed = true;
+                }
+            }
+        }
+
+        immediate.remove(&Node::Entry);
+        Dominators { immediate }
+    }
+
+    /// The natural loops of the graph, innermost first. Loops with the same header are merged.
+    pub fn loops(&self, dominators: &Dominators) -> Vec<Loop> {
+        let mut loops = BTreeMap::new();
+
+        for node in self.nodes() {
+            for &header in self.successors(node) {
+                if !dominators.dominates(header, node) {
+                    continue;
+                }
+
+                let l = loops.entry(header).or_insert_with(|| Loop {
+                    header,
+                    latches: Vec::new(),
+                    body: vec![header].into_iter().collect(),
+                });
+                l.latches.push(node);
+
+                let mut stack = vec![node];
+                while let Some(n) = stack.pop() {
+                    if dominators.dominates(header, n) && l.body.insert(n) {
+                        stack.extend(self.predecessors(n));
+                    }
+                }
+            }
+        }
+
+        let mut loops: Vec<_> = loops.into_values().collect();
+        loops.sort_by_key(|l| l.body.len());
+        loops
+    }
+
+    /// The graph in the Graphviz DOT language, with the instructions of each block in its node.
+    /// The edges out of a conditional jump are labeled `T` where it jumps and `F` where it does
+    /// not.
+    pub fn dot(&self) -> String {
+        let mut s = String::new();
+        s.push_str("strict digraph {\n");
+
+        for node in self.nodes() {
+            let i = self.index(node);
+            match node {
+                Node::Entry => s.push_str(&format!("\t{} [label = ENTRY, shape = circle];\n", i)),
+                Node::Exit => {
+                    s.push_str(&format!("\t{} [label = EXIT, shape = doublecircle];\n", i))
+                }
+                Node::Block(b) => {
+                    let mut label = format!("{}\\l", node);
+                    for line in self.blocks[b].to_string().lines() {
+                        label.push_str(&line.replace('\\', "\\\\").replace('"', "\\\""));
+                        label.push_str("\\l");
+                    }
+                    s.push_str(&format!("\t{} [label = \"{}\", shape = box];\n", i, label));
+                }
+            }
+        }
+
+        for node in self.nodes() {
+            let conditional = match node {
+                Node::Block(b) => match self.blocks[b].instrs.last() {
+                    Some(Instr::Goto(_)) => false,
+                    Some(instr) => instr.target().is_some(),
+                    None => false,
+                },
+                _ => false,
+            };
+
+            for (k, &successor) in self.successors(node).iter().enumerate() {
+                let (i, j) = (self.index(node), self.index(successor));
+                if conditional {
+                    let label = if k == 0 { 'T' } else { 'F' };
+                    s.push_str(&format!("\t{} -> {} [label = {}];\n", i, j, label));
+                } else {
+                    s.push_str(&format!("\t{} -> {};\n", i, j));
+                }
+            }
+        }
+
+        s.push_str("}\n");
+        s
+    }
+}
+
+/// The immediate dominators of the nodes of a flow graph.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Dominators {
+    /// The immediate dominator of each node that can be reached from the entry, other than the
+    /// entry.
+    immediate: BTreeMap<Node, Node>,
+}
+
+impl Dominators {
+    /// The immediate dominator of a node, or `None` for the entry and the nodes that cannot be
+    /// reached from it.
+    pub fn immediate(&self, node: Node) -> Option<Node> {
+        self.immediate.get(&node).copied()
+    }
+
+    /// Whether every path from the entry to `node` goes through `dominator`. Every node dominates
+    /// itself.
+    pub fn dominates(&self, dominator: Node, mut node: Node) -> bool {
+        loop {
+            if node == dominator {
+                return true;
+            }
+            match self.immediate(node) {
+                Some(parent) => node = parent,
+                None => return false,
+            }
+        }
+    }
+
+    /// The nodes that a node is the immediate dominator of, which are its children in the
+    /// dominator tree.
+    pub fn children(&self, node: Node) -> Vec<Node> {
+        self.immediate
+            .iter()
+            .filter(|&(_, &parent)| parent == node)
+            .map(|(&child, _)| child)
+            .collect()
+    }
+}
+
+impl fmt::Display for Dominators {
+    /// The dominator tree, one node per line, indented under its immediate dominator.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut stack = vec![(Node::Entry, 0)];
+        while let Some((node, depth)) = stack.pop() {
+            writeln!(f, "{:width$}{}", "", node, width = 4 * depth)?;
+            for child in self.children(node).into_iter().rev() {
+                stack.push((child, depth + 1));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A natural loop.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Loop {
+    /// The node that dominates the rest of the loop, where control enters it.
+    pub header: Node,
+    /// The nodes with back edges to the header.
+    pub latches: Vec<Node>,
+    /// The nodes of the loop, including the header.
+    pub body: BTreeSet<Node>,
+}
+
+impl fmt::Display for Loop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:", self.header)?;
+        for (i, node) in self.body.iter().enumerate() {
+            let sep = if i == 0 { " " } else { ", " };
+            write!(f, "{}{}", sep, node)?;
+        }
+        Ok(())
+    }
+}
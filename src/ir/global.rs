@@ -0,0 +1,601 @@
+//! Global optimizations, which look at a whole flow graph rather than a block at a time.
+//!
+//! - [`propagate_constants`] finds the names that hold the same constant whenever control reaches
+//!   a point, folds the instructions that only use constants, and replaces conditional jumps
+//!   whose conditions are constant. It is *conditional*: a block is only looked at once a jump
+//!   that may be taken leads to it, so a branch that is never taken does not spoil the constants
+//!   after it, and blocks that can never be reached are removed.
+//! - [`propagate_copies`] replaces a use of `x` with `y` where every path to it goes through a
+//!   copy `x = y`, with neither assigned since.
+//! - [`eliminate_common_subexpressions`] finds the instructions whose expressions are
+//!   *available*, computed on every path to them, and has them copy the value from a new
+//!   temporary that the earlier computations save it in.
+//! - [`remove_dead_stores`] removes the assignments to names that are not live after them.
+//!
+//! Each pass returns the [`Change`]s it made, at the points of the instructions before it made
+//! them. The passes leave work for each other: eliminating a common subexpression makes copies,
+//! propagating copies leaves them dead, and propagating constants can lead to more of both.
+//!
+//! [`propagate_constants`]: ./fn.propagate_constants.html
+//! [`propagate_copies`]: ./fn.propagate_copies.html
+//! [`eliminate_common_subexpressions`]: ./fn.eliminate_common_subexpressions.html
+//! [`remove_dead_stores`]: ./fn.remove_dead_stores.html
+//! [`Change`]: ./enum.Change.html
+//!
+//! ## Example
+//!
+//! ```
+//! # use dragon::ir::{flow::*, global::*, *};
+//! let var = |name: &str| Name::Var(name.to_string());
+//! let operand = |name: &str| Operand::Name(var(name));
+//! let int = |n: i64| Operand::Const(Value::Int(n));
+//!
+//! // x = 2; y = x * 3; if y > 5 goto L1; y = a * b; goto L2; L1: z = a * b; y = z;
+//! // L2: w = a * b; param y; call print, 1; param w; call print, 1
+//! let mut program = Program::new();
+//! let (big, done) = (program.label(), program.label());
+//! program.emit(Instr::Copy(var("x"), int(2)));
+//! program.emit(Instr::Binary(var("y"), BinaryOp::Mul, operand("x"), int(3)));
+//! program.emit(Instr::IfRel(operand("y"), BinaryOp::Gt, int(5), big));
+//! program.emit(Instr::Binary(var("y"), BinaryOp::Mul, operand("a"), operand("b")));
+//! program.emit(Instr::Goto(done));
+//! program.emit(Instr::Label(big));
+//! program.emit(Instr::Binary(var("z"), BinaryOp::Mul, operand("a"), operand("b")));
+//! program.emit(Instr::Copy(var("y"), operand("z")));
+//! program.emit(Instr::Label(done));
+//! program.emit(Instr::Binary(var("w"), BinaryOp::Mul, operand("a"), operand("b")));
+//! for x in &["y", "w"] {
+//!     program.emit(Instr::Param(operand(x)));
+//!     program.emit(Instr::Call(None, "print".to_string(), 1));
+//! }
+//!
+//! let mut graph = Graph::new(&program);
+//! let changes = propagate_constants(&mut graph);
+//! assert_eq!(changes[0].to_string(), "B1.2: `y = x * 3` became `y = 6`");
+//! assert_eq!(changes[1].to_string(), "B1.3: `if y > 5 goto L1` became `goto L1`");
+//! assert_eq!(changes[2].to_string(), "B2: removed, since it cannot be reached");
+//!
+//! assert_eq!(eliminate_common_subexpressions(&mut graph).len(), 2);
+//! assert_eq!(propagate_copies(&mut graph).len(), 3);
+//! assert_eq!(remove_dead_stores(&mut graph).len(), 5);
+//! assert_eq!(
+//!     graph.program().to_string(),
+//!     "    goto L1
+//! L1:
+//!     t1 = a * b
+//! L2:
+//!     param t1
+//!     call print, 1
+//!     param t1
+//!     call print, 1
+//! ",
+//! );
+//! ```
+
+use {
+    super::{
+        dataflow::{
+            self, Analysis, AvailableExpressions, Direction, Expression, LiveVariables, Point,
+        },
+        flow::{Graph, Node},
+        Instr, Name, Operand, Value,
+    },
+    std::{
+        collections::{BTreeMap, HashMap, HashSet},
+        fmt,
+    },
+};
+
+/// A change a pass made.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Change {
+    /// The instruction at a point was replaced by others, or by none.
+    Replace(Point, Instr, Vec<Instr>),
+    /// The block with this index was removed, since no path from the entry leads to it.
+    Unreachable(usize),
+    /// A block was inserted at this index, as the preheader of the loop whose header follows
+    /// it.
+    Preheader(usize),
+    /// Instructions were added to the end of the block with this index, before its jump.
+    Insert(usize, Vec<Instr>),
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Replace(point, old, new) if new.is_empty() => {
+                write!(f, "{}: removed `{}`", point, old)
+            }
+            Self::Replace(point, old, new) => {
+                let new: Vec<_> = new.iter().map(|instr| instr.to_string()).collect();
+                write!(f, "{}: `{}` became `{}`", point, old, new.join("; "))
+            }
+            Self::Unreachable(block) => {
+                write!(
+                    f,
+                    "{}: removed, since it cannot be reached",
+                    Node::Block(*block)
+                )
+            }
+            Self::Preheader(block) => write!(
+                f,
+                "{}: inserted as the preheader of {}",
+                Node::Block(*block),
+                Node::Block(block + 1)
+            ),
+            Self::Insert(block, new) => {
+                let new: Vec<_> = new.iter().map(|instr| instr.to_string()).collect();
+                write!(f, "{}: added `{}`", Node::Block(*block), new.join("; "))
+            }
+        }
+    }
+}
+
+/// Replace the instructions at some points, and return the changes.
+pub(super) fn apply(graph: &mut Graph, edits: BTreeMap<Point, Vec<Instr>>) -> Vec<Change> {
+    let mut changes = Vec::new();
+
+    // From the last point up, so that the indices before it stay valid.
+    for (point, new) in edits.into_iter().rev() {
+        let instrs = &mut graph.blocks[point.block].instrs;
+        let old = instrs
+            .splice(point.index..=point.index, new.iter().cloned())
+            .next()
+            .unwrap();
+        changes.push(Change::Replace(point, old, new));
+    }
+
+    changes.reverse();
+    changes
+}
+
+/// What is known about the value of a name at a point.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Constant {
+    /// No value has reached the point yet.
+    Undefined,
+    Value(Value),
+    /// Different values may reach the point.
+    Varying,
+}
+
+impl Constant {
+    fn meet(self, other: Self) -> Self {
+        match (self, other) {
+            (Self::Undefined, c) | (c, Self::Undefined) => c,
+            (Self::Value(a), Self::Value(b)) if a == b => self,
+            _ => Self::Varying,
+        }
+    }
+}
+
+/// The constants that names hold at a point. A name that is not in the map is undefined.
+type Constants = HashMap<Name, Constant>;
+
+fn constant(constants: &Constants, operand: &Operand) -> Constant {
+    match operand {
+        Operand::Const(value) => Constant::Value(*value),
+        Operand::Name(x) => constants.get(x).copied().unwrap_or(Constant::Undefined),
+    }
+}
+
+/// The value an instruction assigns, as a constant.
+fn evaluate(constants: &Constants, instr: &Instr) -> Constant {
+    if let Instr::Load(..) | Instr::Call(..) = instr {
+        return Constant::Varying;
+    }
+
+    let values = instr
+        .operands()
+        .into_iter()
+        .map(|operand| constant(constants, operand))
+        .collect::<Vec<_>>();
+
+    if values.contains(&Constant::Varying) {
+        return Constant::Varying;
+    }
+    if values.contains(&Constant::Undefined) {
+        return Constant::Undefined;
+    }
+    let value = |i: usize| match values[i] {
+        Constant::Value(value) => value,
+        _ => unreachable!(),
+    };
+
+    let result = match instr {
+        Instr::Binary(_, op, ..) | Instr::IfRel(_, op, ..) => op.apply(value(0), value(1)),
+        Instr::Unary(_, op, _) => op.apply(value(0)),
+        Instr::Copy(..) | Instr::If(..) | Instr::IfFalse(..) => Ok(value(0)),
+        _ => return Constant::Varying,
+    };
+    result.map_or(Constant::Varying, Constant::Value)
+}
+
+fn transfer(constants: &mut Constants, instr: &Instr) {
+    if let Some(x) = instr.assigned() {
+        match evaluate(constants, instr) {
+            Constant::Undefined => constants.remove(x),
+            c => constants.insert(x.clone(), c),
+        };
+    }
+}
+
+/// Whether a conditional jump is taken, if that is known.
+fn taken(constants: &Constants, instr: &Instr) -> Option<bool> {
+    let taken = match (instr, evaluate(constants, instr)) {
+        (Instr::Goto(_), _) => true,
+        (_, Constant::Value(Value::Bool(b))) => b,
+        _ => return None,
+    };
+    Some(if let Instr::IfFalse(..) = instr {
+        !taken
+    } else {
+        taken
+    })
+}
+
+/// Conditional constant propagation.
+///
+/// Names that are read before they are assigned, such as the inputs of a program, hold no
+/// constant on entry.
+///
+/// ```
+/// # use dragon::ir::{flow::*, global::*, *};
+/// let var = |name: &str| Name::Var(name.to_string());
+/// let operand = |name: &str| Operand::Name(var(name));
+///
+/// // if c goto L1; y = a; goto L2; L1: y = 6; L2: param y; call print, 1
+/// let mut program = Program::new();
+/// let (six, done) = (program.label(), program.label());
+/// program.emit(Instr::If(operand("c"), six));
+/// program.emit(Instr::Copy(var("y"), operand("a")));
+/// program.emit(Instr::Goto(done));
+/// program.emit(Instr::Label(six));
+/// program.emit(Instr::Copy(var("y"), Operand::Const(Value::Int(6))));
+/// program.emit(Instr::Label(done));
+/// program.emit(Instr::Param(operand("y")));
+/// program.emit(Instr::Call(None, "print".to_string(), 1));
+///
+/// let mut graph = Graph::new(&program);
+/// assert!(propagate_constants(&mut graph).is_empty());
+///
+/// let mut state = State::default();
+/// state.vars.insert(var("a"), Value::Int(5));
+/// state.vars.insert(var("c"), Value::Bool(false));
+/// graph.program().run(&mut state, 100).unwrap();
+/// assert_eq!(state.output, [Value::Int(5)]);
+/// ```
+pub fn propagate_constants(graph: &mut Graph) -> Vec<Change> {
+    let mut entering: Vec<Option<Constants>> = vec![None; graph.blocks.len()];
+    let mut leaving: Vec<Option<Constants>> = vec![None; graph.blocks.len()];
+    let mut executable: HashSet<(Node, Node)> = HashSet::new();
+    let mut worklist = Vec::new();
+
+    // Any name that is read may come from outside the graph.
+    let mut inputs = Constants::new();
+    for instr in graph.blocks.iter().flat_map(|block| &block.instrs) {
+        for operand in instr.operands() {
+            if let Operand::Name(x) = operand {
+                inputs.insert(x.clone(), Constant::Varying);
+            }
+        }
+    }
+
+    if !graph.blocks.is_empty() {
+        executable.insert((Node::Entry, Node::Block(0)));
+        worklist.push(0);
+    }
+
+    while let Some(b) = worklist.pop() {
+        let mut constants: Option<Constants> = None;
+        for &p in graph.predecessors(Node::Block(b)) {
+            if !executable.contains(&(p, Node::Block(b))) {
+                continue;
+            }
+
+            let other = match p {
+                Node::Block(i) => leaving[i].clone().unwrap_or_default(),
+                _ => inputs.clone(),
+            };
+            constants = Some(match constants {
+                None => other,
+                Some(mut constants) => {
+                    for (x, &c) in &other {
+                        let c = c.meet(constant(&constants, &Operand::Name(x.clone())));
+                        constants.insert(x.clone(), c);
+                    }
+                    constants
+                }
+            });
+        }
+        let mut constants = match constants {
+            Some(constants) => constants,
+            None => continue,
+        };
+        entering[b] = Some(constants.clone());
+
+        for instr in &graph.blocks[b].instrs {
+            transfer(&mut constants, instr);
+        }
+
+        // Only the successors that a jump at the end may go to are reached.
+        let successors = graph.successors(Node::Block(b));
+        let successors = match graph.blocks[b].instrs.last() {
+            Some(instr) if instr.target().is_some() && successors.len() == 2 => {
+                match taken(&constants, instr) {
+                    Some(true) => &successors[..1],
+                    Some(false) => &successors[1..],
+                    None => successors,
+                }
+            }
+            _ => successors,
+        };
+
+        let changed = leaving[b].as_ref() != Some(&constants);
+        leaving[b] = Some(constants);
+        for &s in successors {
+            if executable.insert((Node::Block(b), s)) || changed {
+                if let Node::Block(i) = s {
+                    worklist.push(i);
+                }
+            }
+        }
+    }
+
+    let mut edits = BTreeMap::new();
+    for (block, entering) in entering.iter().enumerate() {
+        let mut constants = match entering {
+            Some(constants) => constants.clone(),
+            None => continue,
+        };
+
+        for (index, instr) in graph.blocks[block].instrs.iter().enumerate() {
+            let mut new = instr.clone();
+            for operand in new.operands_mut() {
+                if let Constant::Value(value) = constant(&constants, operand) {
+                    *operand = Operand::Const(value);
+                }
+            }
+
+            match (instr, evaluate(&constants, instr)) {
+                (Instr::Binary(x, ..), Constant::Value(value))
+                | (Instr::Unary(x, ..), Constant::Value(value)) => {
+                    new = Instr::Copy(x.clone(), Operand::Const(value));
+                }
+                _ => (),
+            }
+
+            let new = match (instr.target(), taken(&constants, instr)) {
+                (Some(label), Some(true)) => vec![Instr::Goto(label)],
+                (Some(_), Some(false)) => Vec::new(),
+                _ => vec![new],
+            };
+            if new != [instr.clone()] {
+                edits.insert(Point { block, index }, new);
+            }
+
+            transfer(&mut constants, instr);
+        }
+    }
+
+    let mut changes = apply(graph, edits);
+
+    for b in (0..graph.blocks.len()).rev() {
+        if entering[b].is_none() {
+            graph.blocks.remove(b);
+            changes.push(Change::Unreachable(b));
+        }
+    }
+    *graph = Graph::new(&graph.program());
+
+    changes.sort_by_key(|change| match change {
+        Change::Replace(point, ..) => (point.block, Some(point.index)),
+        Change::Unreachable(block) | Change::Preheader(block) | Change::Insert(block, _) => {
+            (*block, None)
+        }
+    });
+    changes
+}
+
+/// The copies `x = y` that are made on every path to a point, with neither `x` nor `y` assigned
+/// after.
+struct AvailableCopies {
+    all: HashSet<(Name, Name)>,
+}
+
+impl Analysis for AvailableCopies {
+    type Fact = HashSet<(Name, Name)>;
+    const DIRECTION: Direction = Direction::Forward;
+
+    fn boundary(&self) -> Self::Fact {
+        HashSet::new()
+    }
+
+    fn top(&self) -> Self::Fact {
+        self.all.clone()
+    }
+
+    fn meet(&self, fact: &mut Self::Fact, other: &Self::Fact) {
+        fact.retain(|copy| other.contains(copy));
+    }
+
+    fn transfer(&self, _: Point, instr: &Instr, fact: &mut Self::Fact) {
+        if let Some(x) = instr.assigned() {
+            fact.retain(|(y, z)| y != x && z != x);
+        }
+        if let Some(copy) = copy(instr) {
+            fact.insert(copy);
+        }
+    }
+}
+
+/// The names an instruction copies to and from, if it copies one name to another.
+fn copy(instr: &Instr) -> Option<(Name, Name)> {
+    match instr {
+        Instr::Copy(x, Operand::Name(y)) if x != y => Some((x.clone(), y.clone())),
+        _ => None,
+    }
+}
+
+/// Copy propagation.
+pub fn propagate_copies(graph: &mut Graph) -> Vec<Change> {
+    let all = graph
+        .blocks
+        .iter()
+        .flat_map(|block| block.instrs.iter().filter_map(copy))
+        .collect();
+    let solution = dataflow::solve(&AvailableCopies { all }, graph);
+
+    let mut edits = BTreeMap::new();
+    for (block, b) in graph.blocks.iter().enumerate() {
+        for (index, instr) in b.instrs.iter().enumerate() {
+            let point = Point { block, index };
+            let copies = solution.before(point);
+
+            // Copies made one after another are all available, so follow them to the first name.
+            let mut new = instr.clone();
+            for operand in new.operands_mut() {
+                for _ in 0..copies.len() {
+                    match operand {
+                        Operand::Name(x) => match copies.iter().find(|(z, _)| z == x) {
+                            Some((_, y)) => *operand = Operand::Name(y.clone()),
+                            None => break,
+                        },
+                        Operand::Const(_) => break,
+                    }
+                }
+            }
+            if new != *instr {
+                edits.insert(point, vec![new]);
+            }
+        }
+    }
+
+    apply(graph, edits)
+}
+
+/// The points of the computations of an expression that reach a point where it is available:
+/// the last one before the point in its block, or else the last one in each block on the paths
+/// that lead to it.
+fn evaluations(graph: &Graph, e: &Expression, point: Point) -> Vec<Point> {
+    let last = |block: usize, end: usize| {
+        (0..end)
+            .rev()
+            .find(|&index| Expression::of(&graph.blocks[block].instrs[index]).as_ref() == Some(e))
+            .map(|index| Point { block, index })
+    };
+
+    if let Some(evaluation) = last(point.block, point.index) {
+        return vec![evaluation];
+    }
+
+    let mut evaluations = Vec::new();
+    let mut visited = HashSet::new();
+    let mut stack = graph.predecessors(Node::Block(point.block)).to_vec();
+    while let Some(node) = stack.pop() {
+        if let Node::Block(b) = node {
+            if visited.insert(b) {
+                match last(b, graph.blocks[b].instrs.len()) {
+                    Some(evaluation) => evaluations.push(evaluation),
+                    None => stack.extend(graph.predecessors(node)),
+                }
+            }
+        }
+    }
+    evaluations
+}
+
+/// Global common subexpression elimination.
+pub fn eliminate_common_subexpressions(graph: &mut Graph) -> Vec<Change> {
+    let solution = dataflow::solve(&AvailableExpressions::new(graph), graph);
+
+    // The computations to save in a temporary, and the ones to replace with a copy from it.
+    let mut saves: BTreeMap<Point, Name> = BTreeMap::new();
+    let mut reuses: BTreeMap<Point, Name> = BTreeMap::new();
+    let mut temps: HashMap<Expression, Name> = HashMap::new();
+
+    for block in 0..graph.blocks.len() {
+        for index in 0..graph.blocks[block].instrs.len() {
+            let point = Point { block, index };
+            let e = match Expression::of(&graph.blocks[block].instrs[index]) {
+                Some(e) if solution.before(point).contains(&e) => e,
+                _ => continue,
+            };
+
+            let evaluations = evaluations(graph, &e, point);
+            let u = match temps.get(&e) {
+                Some(u) => u.clone(),
+                None => {
+                    let u = graph.temp();
+                    temps.insert(e, u.clone());
+                    u
+                }
+            };
+            for evaluation in evaluations {
+                saves.insert(evaluation, u.clone());
+            }
+            reuses.insert(point, u);
+        }
+    }
+
+    let mut edits = BTreeMap::new();
+    for (point, u) in saves {
+        let instr = graph.blocks[point.block].instrs[point.index].clone();
+        let x = instr.assigned().unwrap().clone();
+        let mut save = instr;
+        match &mut save {
+            Instr::Binary(y, ..) | Instr::Unary(y, ..) => *y = u.clone(),
+            _ => unreachable!(),
+        }
+        edits.insert(point, vec![save, Instr::Copy(x, Operand::Name(u))]);
+    }
+    for (point, u) in reuses {
+        let x = graph.blocks[point.block].instrs[point.index]
+            .assigned()
+            .unwrap()
+            .clone();
+        edits.insert(point, vec![Instr::Copy(x, Operand::Name(u))]);
+    }
+
+    apply(graph, edits)
+}
+
+/// Dead-store elimination: remove the instructions that only assign to names that are not used
+/// after them, and copies from a name to itself, until there are none left.
+pub fn remove_dead_stores(graph: &mut Graph) -> Vec<Change> {
+    let mut changes = Vec::new();
+
+    loop {
+        let solution = dataflow::solve(&LiveVariables, graph);
+
+        let mut edits = BTreeMap::new();
+        for (block, b) in graph.blocks.iter().enumerate() {
+            for (index, instr) in b.instrs.iter().enumerate() {
+                let point = Point { block, index };
+                let dead = match instr.assigned() {
+                    Some(x) => !solution.after(point).contains(x),
+                    None => continue,
+                };
+
+                match instr {
+                    Instr::Copy(x, Operand::Name(y)) if x == y => {
+                        edits.insert(point, Vec::new());
+                    }
+                    Instr::Call(_, p, n) if dead => {
+                        edits.insert(point, vec![Instr::Call(None, p.clone(), *n)]);
+                    }
+                    Instr::Call(..) => (),
+                    _ if dead => {
+                        edits.insert(point, Vec::new());
+                    }
+                    _ => (),
+                }
+            }
+        }
+
+        if edits.is_empty() {
+            return changes;
+        }
+        changes.extend(apply(graph, edits));
+    }
+}
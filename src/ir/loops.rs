@@ -0,0 +1,907 @@
+//! Loop optimizations, for the natural loops of a flow graph.
+//!
+//! A loop's *preheader* is a block that control goes through just before entering the loop, and
+//! only then: it is the only node outside the loop with an edge to the header, and its only edge
+//! goes there. Code that is moved out of a loop goes to the end of its preheader, and a loop that
+//! does not have one is given one, just before its header, to hold the code.
+//!
+//! - [`move_invariants`] finds the instructions whose operands are constants, or are only
+//!   assigned outside the loop, or by one other instruction that is itself invariant. Such an
+//!   instruction is moved to the preheader if it is the only assignment in the loop to its name,
+//!   every use of the name in the loop gets its value from it, and either it runs before every
+//!   exit from the loop or it cannot fail and the name is not live after the loop.
+//! - [`induction_variables`] finds the names whose values go up or down in step with the
+//!   iterations of a loop. A *basic* induction variable `i` is only assigned in the loop by
+//!   adding or subtracting constants, as in `i = i + 1`. Another name `j` is in the *family* of
+//!   `i` if it is assigned once in the loop, as a constant times, plus or minus another induction
+//!   variable of the family, so that it is `c * i + d` for constants `c` and `d`.
+//! - [`reduce_strength`] replaces the multiplications that compute induction variables with
+//!   additions: the value `c * i + d` is kept in a new temporary, set in the preheader, and moved
+//!   by `c * n` wherever `i` is moved by `n`.
+//! - [`eliminate_induction_variables`] removes a basic induction variable `i` that is only used
+//!   to move itself and in comparisons with invariants, and that is not live after the loop, if
+//!   there is another basic induction variable that moves in step with it, as strength reduction
+//!   leaves. The comparisons test the other variable instead.
+//!
+//! Each pass returns the [`Change`]s it made, one loop at a time, at the points of the
+//! instructions as they were when it changed that loop.
+//!
+//! [`move_invariants`]: ./fn.move_invariants.html
+//! [`induction_variables`]: ./fn.induction_variables.html
+//! [`reduce_strength`]: ./fn.reduce_strength.html
+//! [`eliminate_induction_variables`]: ./fn.eliminate_induction_variables.html
+//! [`Change`]: ../global/enum.Change.html
+//!
+//! ## Example
+//!
+//! `a[i] = i` for each `i` below 10, then the sum of `a[j] * (x * y)` for each `j` below 10:
+//!
+//! ```
+//! # use dragon::ir::{flow::*, loops::*, *};
+//! let var = |name: &str| Name::Var(name.to_string());
+//! let operand = |name: &str| Operand::Name(var(name));
+//! let int = |n: i64| Operand::Const(Value::Int(n));
+//!
+//! let mut program = Program::new();
+//! let (fill, filled, sum, summed) = (
+//!     program.label(),
+//!     program.label(),
+//!     program.label(),
+//!     program.label(),
+//! );
+//! let temps: Vec<_> = (0..5).map(|_| program.temp()).collect();
+//! let temp = |t: usize| temps[t - 1].clone();
+//! program.emit(Instr::Copy(var("x"), int(2)));
+//! program.emit(Instr::Copy(var("y"), int(3)));
+//! program.emit(Instr::Copy(var("i"), int(0)));
+//! program.emit(Instr::Label(fill));
+//! program.emit(Instr::IfRel(operand("i"), BinaryOp::Ge, int(10), filled));
+//! program.emit(Instr::Binary(temp(1), BinaryOp::Mul, operand("i"), int(4)));
+//! program.emit(Instr::Store(var("a"), Operand::Name(temp(1)), operand("i")));
+//! program.emit(Instr::Binary(var("i"), BinaryOp::Add, operand("i"), int(1)));
+//! program.emit(Instr::Goto(fill));
+//! program.emit(Instr::Label(filled));
+//! program.emit(Instr::Copy(var("j"), int(0)));
+//! program.emit(Instr::Copy(var("s"), int(0)));
+//! program.emit(Instr::Label(sum));
+//! program.emit(Instr::IfRel(operand("j"), BinaryOp::Ge, int(10), summed));
+//! program.emit(Instr::Binary(temp(2), BinaryOp::Mul, operand("j"), int(4)));
+//! program.emit(Instr::Load(temp(3), var("a"), Operand::Name(temp(2))));
+//! program.emit(Instr::Binary(temp(4), BinaryOp::Mul, operand("x"), operand("y")));
+//! program.emit(Instr::Binary(
+//!     temp(5),
+//!     BinaryOp::Mul,
+//!     Operand::Name(temp(3)),
+//!     Operand::Name(temp(4)),
+//! ));
+//! program.emit(Instr::Binary(var("s"), BinaryOp::Add, operand("s"), Operand::Name(temp(5))));
+//! program.emit(Instr::Binary(var("j"), BinaryOp::Add, operand("j"), int(1)));
+//! program.emit(Instr::Goto(sum));
+//! program.emit(Instr::Label(summed));
+//! program.emit(Instr::Param(operand("s")));
+//! program.emit(Instr::Call(None, "print".to_string(), 1));
+//!
+//! let mut graph = Graph::new(&program);
+//! let loops = graph.loops(&graph.dominators());
+//! let sum_loop = loops.iter().find(|l| l.header == Node::Block(4)).unwrap();
+//! let family: Vec<_> = induction_variables(&graph, sum_loop)
+//!     .iter()
+//!     .map(|v| v.to_string())
+//!     .collect();
+//! assert_eq!(family, ["j: (j, 1, 0)", "t2: (j, 4, 0)"]);
+//!
+//! let changes = move_invariants(&mut graph);
+//! assert_eq!(changes[0].to_string(), "B6.3: removed `t4 = x * y`");
+//! assert_eq!(changes[1].to_string(), "B4: added `t4 = x * y`");
+//!
+//! let changes = reduce_strength(&mut graph);
+//! assert_eq!(changes[0].to_string(), "B3.1: `t1 = i * 4` became `t1 = t6`");
+//! assert_eq!(changes[1].to_string(), "B3.3: `i = i + 1` became `i = i + 1; t6 = t6 + 4`");
+//! assert_eq!(changes[2].to_string(), "B1: added `t6 = 4 * i`");
+//! assert_eq!(changes[3].to_string(), "B6.1: `t2 = j * 4` became `t2 = t7`");
+//! assert_eq!(changes.len(), 6);
+//!
+//! // `i` is stored in the array, but `j` is only counted and compared.
+//! let changes = eliminate_induction_variables(&mut graph);
+//! assert_eq!(changes[0].to_string(), "B5.1: `if j >= 10 goto L4` became `if t7 >= 40 goto L4`");
+//! assert_eq!(changes[1].to_string(), "B6.5: removed `j = j + 1`");
+//! assert_eq!(changes.len(), 2);
+//!
+//! assert_eq!(
+//!     graph.program().to_string(),
+//!     "    x = 2
+//!     y = 3
+//!     i = 0
+//!     t6 = 4 * i
+//! L1:
+//!     if i >= 10 goto L2
+//!     t1 = t6
+//!     a[t1] = i
+//!     i = i + 1
+//!     t6 = t6 + 4
+//!     goto L1
+//! L2:
+//!     j = 0
+//!     s = 0
+//!     t4 = x * y
+//!     t7 = 4 * j
+//! L3:
+//!     if t7 >= 40 goto L4
+//!     t2 = t7
+//!     t3 = a[t2]
+//!     t5 = t3 * t4
+//!     s = s + t5
+//!     t7 = t7 + 4
+//!     goto L3
+//! L4:
+//!     param s
+//!     call print, 1
+//! ",
+//! );
+//!
+//! let (mut before, mut after) = (State::default(), State::default());
+//! program.run(&mut before, 1000).unwrap();
+//! graph.program().run(&mut after, 1000).unwrap();
+//! assert_eq!(before.output, [Value::Int(270)]);
+//! assert_eq!(after.output, before.output);
+//! ```
+//!
+//! A loop whose header is the first block is given a preheader for the code moved out of it:
+//!
+//! ```
+//! # use dragon::ir::{flow::*, loops::*, *};
+//! let var = |name: &str| Name::Var(name.to_string());
+//! let operand = |name: &str| Operand::Name(var(name));
+//! let int = |n: i64| Operand::Const(Value::Int(n));
+//!
+//! // L1: k = n * 4; a[i] = k; i = i + 4; if i < k goto L1
+//! let mut program = Program::new();
+//! let top = program.label();
+//! program.emit(Instr::Label(top));
+//! program.emit(Instr::Binary(var("k"), BinaryOp::Mul, operand("n"), int(4)));
+//! program.emit(Instr::Store(var("a"), operand("i"), operand("k")));
+//! program.emit(Instr::Binary(var("i"), BinaryOp::Add, operand("i"), int(4)));
+//! program.emit(Instr::IfRel(operand("i"), BinaryOp::Lt, operand("k"), top));
+//!
+//! let mut graph = Graph::new(&program);
+//! let changes = move_invariants(&mut graph);
+//! assert_eq!(changes[0].to_string(), "B1.1: removed `k = n * 4`");
+//! assert_eq!(changes[1].to_string(), "B1: inserted as the preheader of B2");
+//! assert_eq!(changes[2].to_string(), "B1: added `k = n * 4`");
+//! assert_eq!(
+//!     graph.program().to_string(),
+//!     "L2:
+//!     k = n * 4
+//! L1:
+//!     a[i] = k
+//!     i = i + 4
+//!     if i < k goto L1
+//! ",
+//! );
+//! ```
+//!
+//! The same passes run on programs in the source language of the `simple` front end, with its
+//! `--optimize` option. `examples/sum.s` is the first example above, and `examples/nested.s` fills
+//! an array in nested loops and sums it; `examples/sum.tac` and `examples/nested.tac` hold what
+//! `cargo run --bin simple -- --optimize < examples/sum.s` and the like print for them.
+
+use {
+    super::{
+        dataflow::{self, LiveVariables, Point, ReachingDefinitions, Solution},
+        flow::{Block, Dominators, Graph, Loop, Node},
+        global::{apply, Change},
+        BinaryOp, Instr, Name, Operand, UnaryOp, Value,
+    },
+    std::{
+        collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+        fmt,
+    },
+};
+
+/// The instructions of a loop, with their points, block by block.
+fn instrs<'a>(graph: &'a Graph, l: &'a Loop) -> impl Iterator<Item = (Point, &'a Instr)> + 'a {
+    l.body.iter().flat_map(move |node| {
+        let block = match node {
+            Node::Block(block) => *block,
+            _ => unreachable!(),
+        };
+        graph.blocks[block]
+            .instrs
+            .iter()
+            .enumerate()
+            .map(move |(index, instr)| (Point { block, index }, instr))
+    })
+}
+
+/// The edges that leave a loop.
+fn exits(graph: &Graph, l: &Loop) -> Vec<(Node, Node)> {
+    l.body
+        .iter()
+        .flat_map(|&node| {
+            graph
+                .successors(node)
+                .iter()
+                .filter(|successor| !l.body.contains(successor))
+                .map(move |&successor| (node, successor))
+        })
+        .collect()
+}
+
+/// Whether a name may be live when control leaves a loop.
+fn live_after(graph: &Graph, l: &Loop, live: &Solution<BTreeSet<Name>>, x: &Name) -> bool {
+    exits(graph, l).iter().any(|&(_, node)| match node {
+        Node::Block(b) => live.at_start(b).contains(x),
+        _ => false,
+    })
+}
+
+/// The definitions of a name that reach a point.
+fn reaching(
+    definitions: &ReachingDefinitions,
+    solution: &Solution<BTreeSet<Point>>,
+    x: &Name,
+    point: Point,
+) -> Vec<Point> {
+    definitions
+        .definitions(x)
+        .iter()
+        .filter(|d| solution.before(point).contains(d))
+        .copied()
+        .collect()
+}
+
+/// The preheader of a loop, if it has one. A block that ends in a conditional jump is not one,
+/// since the code added to it would come before the jump.
+fn find_preheader(graph: &Graph, l: &Loop) -> Option<usize> {
+    let outside: Vec<Node> = graph
+        .predecessors(l.header)
+        .iter()
+        .filter(|node| !l.body.contains(node))
+        .copied()
+        .collect();
+
+    match outside[..] {
+        [Node::Block(p)] if graph.successors(Node::Block(p)) == [l.header] => {
+            match graph.blocks[p].instrs.last() {
+                Some(instr) if instr.target().is_some() && !matches!(instr, Instr::Goto(_)) => None,
+                _ => Some(p),
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Insert a preheader with some instructions just before the header of a loop, returning its
+/// index, and have the jumps into the loop from outside it go there. A block of the loop that
+/// came just before the header, and so fell through to it, jumps to it instead.
+fn insert_preheader(graph: &mut Graph, l: &Loop, instrs: Vec<Instr>) -> usize {
+    let h = match l.header {
+        Node::Block(h) => h,
+        _ => unreachable!(),
+    };
+
+    let label = graph.label();
+    let header_labels = graph.blocks[h].labels.clone();
+    for node in graph.predecessors(l.header).to_vec() {
+        if let Node::Block(p) = node {
+            if l.body.contains(&node) {
+                continue;
+            }
+            if let Some(target) = graph.blocks[p]
+                .instrs
+                .last_mut()
+                .and_then(Instr::target_mut)
+            {
+                if header_labels.contains(target) {
+                    *target = label;
+                }
+            }
+        }
+    }
+
+    let falls_through = h > 0
+        && l.body.contains(&Node::Block(h - 1))
+        && !matches!(graph.blocks[h - 1].instrs.last(), Some(Instr::Goto(_)));
+    if falls_through {
+        let target = match header_labels.first() {
+            Some(&target) => target,
+            None => {
+                let target = graph.label();
+                graph.blocks[h].labels.push(target);
+                target
+            }
+        };
+        graph.blocks[h - 1].instrs.push(Instr::Goto(target));
+    }
+
+    graph.blocks.insert(
+        h,
+        Block {
+            labels: vec![label],
+            instrs,
+        },
+    );
+    *graph = Graph::new(&graph.program());
+    h
+}
+
+/// Add instructions to the end of the preheader of a loop, before the jump that ends it, or
+/// insert a preheader with them if the loop has none.
+fn add_to_preheader(graph: &mut Graph, l: &Loop, new: Vec<Instr>) -> Vec<Change> {
+    match find_preheader(graph, l) {
+        Some(p) => {
+            let instrs = &mut graph.blocks[p].instrs;
+            let at = match instrs.last() {
+                Some(Instr::Goto(_)) => instrs.len() - 1,
+                _ => instrs.len(),
+            };
+            instrs.splice(at..at, new.iter().cloned());
+            vec![Change::Insert(p, new)]
+        }
+        None => {
+            let p = insert_preheader(graph, l, new.clone());
+            vec![Change::Preheader(p), Change::Insert(p, new)]
+        }
+    }
+}
+
+/// Whether running an instruction can stop the program, as a division by a name or a load of an
+/// element that was never stored can.
+fn may_fail(instr: &Instr) -> bool {
+    match instr {
+        Instr::Binary(_, BinaryOp::Div | BinaryOp::IntDiv | BinaryOp::Mod, _, z) => {
+            !matches!(z, Operand::Const(Value::Int(n)) if *n != 0)
+                && !matches!(z, Operand::Const(Value::Float(_)))
+        }
+        Instr::Load(..) => true,
+        _ => false,
+    }
+}
+
+/// The invariant instructions of a loop that can be moved to its preheader, in an order that
+/// computes each before it is used.
+fn movable(
+    graph: &Graph,
+    l: &Loop,
+    dominators: &Dominators,
+    (definitions, solution): (&ReachingDefinitions, &Solution<BTreeSet<Point>>),
+    live: &Solution<BTreeSet<Name>>,
+) -> Vec<Point> {
+    let inside = |d: &Point| l.body.contains(&Node::Block(d.block));
+    let stored: HashSet<&Name> = instrs(graph, l)
+        .filter_map(|(_, instr)| match instr {
+            Instr::Store(a, ..) => Some(a),
+            _ => None,
+        })
+        .collect();
+
+    let mut invariant: Vec<Point> = Vec::new();
+    loop {
+        let found = invariant.len();
+        for (point, instr) in instrs(graph, l) {
+            let computes = match instr {
+                Instr::Binary(..) | Instr::Unary(..) | Instr::Copy(..) => true,
+                Instr::Load(_, a, _) => !stored.contains(a),
+                _ => false,
+            };
+            if !computes || invariant.contains(&point) {
+                continue;
+            }
+
+            let operands_invariant = instr.operands().into_iter().all(|operand| match operand {
+                Operand::Const(_) => true,
+                Operand::Name(x) => {
+                    let reaching = reaching(definitions, solution, x, point);
+                    reaching.iter().all(|d| !inside(d))
+                        || (reaching.len() == 1 && invariant.contains(&reaching[0]))
+                }
+            });
+            if operands_invariant {
+                invariant.push(point);
+            }
+        }
+        if invariant.len() == found {
+            break;
+        }
+    }
+
+    let exits = exits(graph, l);
+    let mut moves: Vec<Point> = Vec::new();
+    for point in invariant {
+        let instr = &graph.blocks[point.block].instrs[point.index];
+        let x = instr.assigned().unwrap();
+
+        let before_exits = exits
+            .iter()
+            .all(|&(node, _)| dominators.dominates(Node::Block(point.block), node));
+        let safe = before_exits || (!may_fail(instr) && !live_after(graph, l, live, x));
+
+        let only = instrs(graph, l)
+            .filter(|(_, other)| other.assigned() == Some(x))
+            .all(|(other, _)| other == point);
+        let used_from_here = instrs(graph, l).all(|(other, instr)| {
+            !instr.operands().contains(&&Operand::Name(x.clone()))
+                || reaching(definitions, solution, x, other) == [point]
+        });
+        let operands_moved = instr.operands().into_iter().all(|operand| match operand {
+            Operand::Name(y) => reaching(definitions, solution, y, point)
+                .iter()
+                .all(|d| !inside(d) || moves.contains(d)),
+            Operand::Const(_) => true,
+        });
+
+        if safe && only && used_from_here && operands_moved {
+            moves.push(point);
+        }
+    }
+    moves
+}
+
+/// Loop-invariant code motion, from the innermost loops out.
+pub fn move_invariants(graph: &mut Graph) -> Vec<Change> {
+    let mut changes = Vec::new();
+
+    'restart: loop {
+        let dominators = graph.dominators();
+        let definitions = ReachingDefinitions::new(graph);
+        let solution = dataflow::solve(&definitions, graph);
+        let live = dataflow::solve(&LiveVariables, graph);
+
+        for l in graph.loops(&dominators) {
+            let moves = movable(graph, &l, &dominators, (&definitions, &solution), &live);
+            if moves.is_empty() {
+                continue;
+            }
+
+            let moved = moves
+                .iter()
+                .map(|point| graph.blocks[point.block].instrs[point.index].clone())
+                .collect();
+            let edits = moves.into_iter().map(|point| (point, Vec::new())).collect();
+            changes.extend(apply(graph, edits));
+            changes.extend(add_to_preheader(graph, &l, moved));
+            continue 'restart;
+        }
+
+        return changes;
+    }
+}
+
+/// An induction variable of a loop, whose value is `factor * basic + offset` whenever it is
+/// assigned in the loop.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InductionVariable {
+    pub name: Name,
+    /// The basic induction variable of its family, which is `name` itself if it is basic.
+    pub basic: Name,
+    pub factor: i64,
+    pub offset: i64,
+}
+
+impl InductionVariable {
+    pub fn is_basic(&self) -> bool {
+        self.name == self.basic
+    }
+}
+
+impl fmt::Display for InductionVariable {
+    /// The name and its triple, as in `t1: (i, 4, 0)`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: ({}, {}, {})",
+            self.name, self.basic, self.factor, self.offset
+        )
+    }
+}
+
+/// The induction variables of a loop.
+struct Families {
+    /// The basic induction variables, with the point of each assignment to one and what it adds.
+    increments: BTreeMap<Name, Vec<(Point, i64)>>,
+    /// The other induction variables, with the points where they are assigned, in the order they
+    /// were found.
+    derived: Vec<(Point, InductionVariable)>,
+}
+
+/// What an instruction adds to the name it assigns, if it only adds a constant to it.
+fn step(instr: &Instr) -> Option<i64> {
+    let (x, op, y, z) = match instr {
+        Instr::Binary(x, op, y, z) => (x, op, y, z),
+        _ => return None,
+    };
+    let is_x = |operand: &Operand| *operand == Operand::Name(x.clone());
+
+    match (op, y, z) {
+        (BinaryOp::Add, y, Operand::Const(Value::Int(n))) if is_x(y) => Some(*n),
+        (BinaryOp::Add, Operand::Const(Value::Int(n)), z) if is_x(z) => Some(*n),
+        (BinaryOp::Sub, y, Operand::Const(Value::Int(n))) if is_x(y) => Some(n.wrapping_neg()),
+        _ => None,
+    }
+}
+
+/// The name an instruction computes its value from, and the factor and offset it applies, if it
+/// is `factor * k + offset` for a name `k`.
+fn linear(instr: &Instr) -> Option<(&Name, i64, i64)> {
+    use Operand::{Const, Name as N};
+
+    match instr {
+        Instr::Binary(_, BinaryOp::Mul, N(k), Const(Value::Int(c)))
+        | Instr::Binary(_, BinaryOp::Mul, Const(Value::Int(c)), N(k)) => Some((k, *c, 0)),
+        Instr::Binary(_, BinaryOp::Add, N(k), Const(Value::Int(d)))
+        | Instr::Binary(_, BinaryOp::Add, Const(Value::Int(d)), N(k)) => Some((k, 1, *d)),
+        Instr::Binary(_, BinaryOp::Add, N(k), N(other)) if k == other => Some((k, 2, 0)),
+        Instr::Binary(_, BinaryOp::Sub, N(k), Const(Value::Int(d))) => {
+            Some((k, 1, d.wrapping_neg()))
+        }
+        Instr::Binary(_, BinaryOp::Sub, Const(Value::Int(d)), N(k)) => Some((k, -1, *d)),
+        Instr::Unary(_, UnaryOp::Neg, N(k)) => Some((k, -1, 0)),
+        _ => None,
+    }
+}
+
+/// Whether an instruction multiplies: `x + x` is how a DAG writes `x * 2`.
+fn multiplies(instr: &Instr) -> bool {
+    match instr {
+        Instr::Binary(_, BinaryOp::Mul, ..) => true,
+        Instr::Binary(_, BinaryOp::Add, y, z) => y == z,
+        _ => false,
+    }
+}
+
+fn families(
+    graph: &Graph,
+    l: &Loop,
+    (definitions, solution): (&ReachingDefinitions, &Solution<BTreeSet<Point>>),
+) -> Families {
+    let mut assignments: BTreeMap<&Name, Vec<(Point, &Instr)>> = BTreeMap::new();
+    for (point, instr) in instrs(graph, l) {
+        if let Some(x) = instr.assigned() {
+            assignments.entry(x).or_default().push((point, instr));
+        }
+    }
+
+    let increments: BTreeMap<Name, Vec<(Point, i64)>> = assignments
+        .iter()
+        .filter_map(|(&x, assignments)| {
+            let steps = assignments
+                .iter()
+                .map(|&(point, instr)| step(instr).map(|n| (point, n)))
+                .collect::<Option<Vec<_>>>()?;
+            Some((x.clone(), steps))
+        })
+        .collect();
+
+    let mut derived: Vec<(Point, InductionVariable)> = Vec::new();
+    loop {
+        let found = derived.len();
+
+        for (&x, assignments) in &assignments {
+            if assignments.len() != 1
+                || increments.contains_key(x)
+                || derived.iter().any(|(_, v)| v.name == *x)
+            {
+                continue;
+            }
+            let (point, instr) = assignments[0];
+            let (k, factor, offset) = match linear(instr) {
+                Some(linear) => linear,
+                None => continue,
+            };
+
+            let family = if increments.contains_key(k) {
+                Some((k.clone(), 1, 0))
+            } else {
+                // `k` is in a family too, so long as its value here is the one it was given in
+                // the loop, with the basic variable unchanged since.
+                derived
+                    .iter()
+                    .find(|(_, v)| v.name == *k)
+                    .filter(|(d, v)| {
+                        d.block == point.block
+                            && d.index < point.index
+                            && (d.index + 1..point.index).all(|index| {
+                                graph.blocks[point.block].instrs[index].assigned() != Some(&v.basic)
+                            })
+                            && reaching(definitions, solution, k, point) == [*d]
+                    })
+                    .map(|(_, v)| (v.basic.clone(), v.factor, v.offset))
+            };
+
+            if let Some((basic, c, d)) = family {
+                let v = InductionVariable {
+                    name: x.clone(),
+                    basic,
+                    factor: factor.wrapping_mul(c),
+                    offset: factor.wrapping_mul(d).wrapping_add(offset),
+                };
+                derived.push((point, v));
+            }
+        }
+
+        if derived.len() == found {
+            break;
+        }
+    }
+
+    Families {
+        increments,
+        derived,
+    }
+}
+
+/// The induction variables of a loop: first the basic ones, and then the rest of their families.
+pub fn induction_variables(graph: &Graph, l: &Loop) -> Vec<InductionVariable> {
+    let definitions = ReachingDefinitions::new(graph);
+    let solution = dataflow::solve(&definitions, graph);
+    let families = families(graph, l, (&definitions, &solution));
+
+    let basic = families.increments.into_keys().map(|i| InductionVariable {
+        name: i.clone(),
+        basic: i,
+        factor: 1,
+        offset: 0,
+    });
+    basic
+        .chain(families.derived.into_iter().map(|(_, v)| v))
+        .collect()
+}
+
+/// `x = y + n`, or `x = y - n` if `n` is negative.
+fn add(x: Name, y: Operand, n: i64) -> Instr {
+    if n < 0 {
+        Instr::Binary(
+            x,
+            BinaryOp::Sub,
+            y,
+            Operand::Const(Value::Int(n.wrapping_neg())),
+        )
+    } else {
+        Instr::Binary(x, BinaryOp::Add, y, Operand::Const(Value::Int(n)))
+    }
+}
+
+/// `x = c * y + d`, in as few instructions as it takes.
+fn scale(x: &Name, y: Operand, c: i64, d: i64) -> Vec<Instr> {
+    let mut instrs = vec![if c == 1 {
+        Instr::Copy(x.clone(), y)
+    } else {
+        Instr::Binary(x.clone(), BinaryOp::Mul, Operand::Const(Value::Int(c)), y)
+    }];
+    if d != 0 {
+        instrs.push(add(x.clone(), Operand::Name(x.clone()), d));
+    }
+    instrs
+}
+
+/// Strength reduction of the induction variables that are computed by multiplying.
+pub fn reduce_strength(graph: &mut Graph) -> Vec<Change> {
+    let mut changes = Vec::new();
+
+    'restart: loop {
+        let dominators = graph.dominators();
+        let definitions = ReachingDefinitions::new(graph);
+        let solution = dataflow::solve(&definitions, graph);
+
+        for l in graph.loops(&dominators) {
+            let families = families(graph, &l, (&definitions, &solution));
+            let reducible: Vec<_> = families
+                .derived
+                .into_iter()
+                .filter(|(point, v)| {
+                    v.factor != 0 && multiplies(&graph.blocks[point.block].instrs[point.index])
+                })
+                .collect();
+            if reducible.is_empty() {
+                continue;
+            }
+
+            // One temporary for each triple, shared by the variables that have it.
+            let mut temps: HashMap<(Name, i64, i64), Name> = HashMap::new();
+            let mut edits: BTreeMap<Point, Vec<Instr>> = BTreeMap::new();
+            let mut init = Vec::new();
+
+            for (point, v) in reducible {
+                let key = (v.basic.clone(), v.factor, v.offset);
+                let s = match temps.get(&key) {
+                    Some(s) => s.clone(),
+                    None => {
+                        let s = graph.temp();
+                        init.extend(scale(
+                            &s,
+                            Operand::Name(v.basic.clone()),
+                            v.factor,
+                            v.offset,
+                        ));
+                        for &(increment, n) in &families.increments[&v.basic] {
+                            edits
+                                .entry(increment)
+                                .or_insert_with(|| {
+                                    vec![graph.blocks[increment.block].instrs[increment.index]
+                                        .clone()]
+                                })
+                                .push(add(
+                                    s.clone(),
+                                    Operand::Name(s.clone()),
+                                    v.factor.wrapping_mul(n),
+                                ));
+                        }
+                        temps.insert(key, s.clone());
+                        s
+                    }
+                };
+                edits.insert(point, vec![Instr::Copy(v.name, Operand::Name(s))]);
+            }
+
+            changes.extend(apply(graph, edits));
+            changes.extend(add_to_preheader(graph, &l, init));
+            continue 'restart;
+        }
+
+        return changes;
+    }
+}
+
+/// The factor and offset that make `s` equal to `factor * i + offset` when a loop is entered:
+/// the preheader last sets `s` from `i`, which it does not change after.
+fn relation(graph: &Graph, preheader: usize, s: &Name, i: &Name) -> Option<(i64, i64)> {
+    let instrs = &graph.blocks[preheader].instrs;
+    let last = instrs
+        .iter()
+        .rposition(|instr| instr.assigned() == Some(s))?;
+    if instrs[last + 1..]
+        .iter()
+        .any(|instr| instr.assigned() == Some(i))
+    {
+        return None;
+    }
+
+    let from = |instr: &Instr| match instr {
+        Instr::Copy(_, Operand::Name(y)) if y == i => Some(1),
+        Instr::Binary(_, BinaryOp::Mul, Operand::Const(Value::Int(c)), Operand::Name(y))
+        | Instr::Binary(_, BinaryOp::Mul, Operand::Name(y), Operand::Const(Value::Int(c)))
+            if y == i =>
+        {
+            Some(*c)
+        }
+        _ => None,
+    };
+
+    match (from(&instrs[last]), step(&instrs[last])) {
+        (Some(c), _) => Some((c, 0)),
+        (None, Some(d)) if last > 0 && instrs[last - 1].assigned() == Some(s) => {
+            from(&instrs[last - 1]).map(|c| (c, d))
+        }
+        _ => None,
+    }
+}
+
+/// The relational operator that gives the same result with its operands swapped.
+fn mirror(op: BinaryOp) -> BinaryOp {
+    match op {
+        BinaryOp::Lt => BinaryOp::Gt,
+        BinaryOp::Le => BinaryOp::Ge,
+        BinaryOp::Ge => BinaryOp::Le,
+        BinaryOp::Gt => BinaryOp::Lt,
+        op => op,
+    }
+}
+
+/// Induction-variable elimination.
+pub fn eliminate_induction_variables(graph: &mut Graph) -> Vec<Change> {
+    let mut changes = Vec::new();
+
+    'restart: loop {
+        let dominators = graph.dominators();
+        let definitions = ReachingDefinitions::new(graph);
+        let solution = dataflow::solve(&definitions, graph);
+        let live = dataflow::solve(&LiveVariables, graph);
+
+        for l in graph.loops(&dominators) {
+            let p = match find_preheader(graph, &l) {
+                Some(p) => p,
+                None => continue,
+            };
+            let increments = families(graph, &l, (&definitions, &solution)).increments;
+            let assigned: HashSet<&Name> = instrs(graph, &l)
+                .filter_map(|(_, instr)| instr.assigned())
+                .collect();
+
+            for (i, steps) in &increments {
+                if live_after(graph, &l, &live, i) {
+                    continue;
+                }
+
+                // Another basic induction variable, moved right after `i` by `c` times as much.
+                let in_step = increments.iter().find_map(|(s, s_steps)| {
+                    let (c, d) = relation(graph, p, s, i).filter(|&(c, _)| c != 0 && s != i)?;
+                    let follows = s_steps.len() == steps.len()
+                        && steps.iter().all(|&(point, n)| {
+                            let next = Point {
+                                index: point.index + 1,
+                                ..point
+                            };
+                            s_steps.contains(&(next, c.wrapping_mul(n)))
+                        });
+                    if follows {
+                        Some((s, c, d))
+                    } else {
+                        None
+                    }
+                });
+                let (s, c, d) = match in_step {
+                    Some(in_step) => in_step,
+                    None => continue,
+                };
+
+                // The comparisons of `i` with invariants, as `i op x`.
+                let i_operand = Operand::Name(i.clone());
+                let mut tests = Vec::new();
+                let only_tested = instrs(graph, &l).all(|(point, instr)| {
+                    if !instr.operands().contains(&&i_operand)
+                        || steps.iter().any(|&(step, _)| step == point)
+                    {
+                        return true;
+                    }
+                    let (op, x, label) = match instr {
+                        Instr::IfRel(y, op, x, label) if *y == i_operand => (*op, x, *label),
+                        Instr::IfRel(x, op, y, label) if *y == i_operand => {
+                            (mirror(*op), x, *label)
+                        }
+                        _ => return false,
+                    };
+                    let invariant = match x {
+                        Operand::Const(Value::Int(_)) => true,
+                        Operand::Name(x) => !assigned.contains(x),
+                        Operand::Const(_) => false,
+                    };
+                    tests.push((point, op, x.clone(), label));
+                    invariant
+                });
+                if !only_tested {
+                    continue;
+                }
+
+                let mut edits: BTreeMap<Point, Vec<Instr>> = BTreeMap::new();
+                let mut init = Vec::new();
+                let mut scaled: HashMap<Name, Name> = HashMap::new();
+
+                for (point, op, x, label) in tests {
+                    let op = if c < 0 { mirror(op) } else { op };
+                    let x = match x {
+                        Operand::Const(Value::Int(n)) => {
+                            Operand::Const(Value::Int(c.wrapping_mul(n).wrapping_add(d)))
+                        }
+                        Operand::Name(x) if c == 1 && d == 0 => Operand::Name(x),
+                        Operand::Name(x) => {
+                            let r = match scaled.get(&x) {
+                                Some(r) => r.clone(),
+                                None => {
+                                    let r = graph.temp();
+                                    init.extend(scale(&r, Operand::Name(x.clone()), c, d));
+                                    scaled.insert(x, r.clone());
+                                    r
+                                }
+                            };
+                            Operand::Name(r)
+                        }
+                        Operand::Const(_) => unreachable!(),
+                    };
+                    let test = Instr::IfRel(Operand::Name(s.clone()), op, x, label);
+                    edits.insert(point, vec![test]);
+                }
+                for &(point, _) in steps {
+                    edits.insert(point, Vec::new());
+                }
+
+                changes.extend(apply(graph, edits));
+                if !init.is_empty() {
+                    changes.extend(add_to_preheader(graph, &l, init));
+                }
+                continue 'restart;
+            }
+        }
+
+        return changes;
+    }
+}
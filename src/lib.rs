@@ -1,3 +1,13 @@
+pub mod attr;
+pub mod combinator;
+pub mod cst;
+#[doc(hidden)]
+pub mod doctest;
+pub mod grammar;
+pub mod ir;
+pub mod parse;
+pub mod types;
+
 pub mod token {
     //! Framework for building a lexical analyzer by simulating a deterministic finite automaton
     //! (DFA).
@@ -8,8 +18,13 @@ pub mod token {
     //! DFA. Implement the [`State`] trait for your type, then you can call the [`lex`] function to
     //! obtain an iterator over a stream of tokens recognized in an input string.
     //!
+    //! Text that the automaton discards, such as whitespace and comments, does not appear in the
+    //! stream. Tools that have to reproduce the input exactly can call [`Lexer::with_trivia`] to
+    //! have it attached to the tokens around it instead.
+    //!
     //! [`State`]: ./trait.State.html
     //! [`lex`]: ./fn.lex.html
+    //! [`Lexer::with_trivia`]: ./struct.Lexer.html#method.with_trivia
     //!
     //! ## Example: floating-point numbers
     //!
@@ -76,7 +91,7 @@ pub mod token {
     /// parametric type.
     ///
     /// [`State`]: ./trait.State.html
-    pub fn lex<S: State>(src: &str) -> Lexer<S> {
+    pub fn lex<S: State>(src: &str) -> Lexer<'_, S> {
         Lexer {
             src,
             iter: src.char_indices().peekable(),
@@ -100,6 +115,55 @@ pub mod token {
     }
 
     impl<'src, S: State> Lexer<'src, S> {
+        /// Keep the text that would be discarded, as *trivia* attached to the tokens around it.
+        ///
+        /// The trivia after a token, up to and including the end of its line, is attached to that
+        /// token. The rest is attached to the token that follows it, and everything after the
+        /// last token is attached to the last token. If the input has no tokens at all, its text
+        /// is returned as the leading trivia of a single `Lossless` with no token. Joining the
+        /// trivia and lexemes of every token gives back the input.
+        ///
+        /// ```
+        /// # use dragon::token::*;
+        /// # #[derive(Default)]
+        /// # struct Words;
+        /// # impl State for Words {
+        /// #     type Token = ();
+        /// #     type Error = ();
+        /// #     fn handle_char(&self, c: char) -> Step<Self> {
+        /// #         if c.is_whitespace() { Step::Discard } else { Step::Finish((), true) }
+        /// #     }
+        /// #     fn try_finish(&self) -> Option<()> { None }
+        /// # }
+        /// let tokens = lex::<Words>("  a b \n\n c\n").with_trivia().collect::<Vec<_>>();
+        /// assert_eq!(
+        ///     tokens
+        ///         .iter()
+        ///         .map(|t| (t.leading, t.token.unwrap().1, t.trailing))
+        ///         .collect::<Vec<_>>(),
+        ///     [("  ", "a", " "), ("", "b", " \n"), ("\n ", "c", "\n")],
+        /// );
+        ///
+        /// let blank = lex::<Words>(" \n ").with_trivia().collect::<Vec<_>>();
+        /// assert_eq!(blank.len(), 1);
+        /// assert_eq!((blank[0].leading, blank[0].token), (" \n ", None));
+        /// ```
+        pub fn with_trivia(mut self) -> WithTrivia<'src, S> {
+            let next = self.next_with_start();
+            WithTrivia {
+                lexer: self,
+                next,
+                end: 0,
+            }
+        }
+
+        /// The next token, and the index where its lexeme starts.
+        fn next_with_start(&mut self) -> Option<Located<'src, S>> {
+            let token = self.next()?;
+            let start = self.start - token.1.len();
+            Some((token, start))
+        }
+
         fn current_index(&mut self) -> usize {
             self.iter.peek().map_or(self.src.len(), |(i, _)| *i)
         }
@@ -164,17 +228,80 @@ pub mod token {
 
             self.done = true;
 
-            if let Some(t) = self.state.try_finish() {
-                Some(self.finish_token(Ok(t), false))
-            } else {
-                None
-            }
+            self.state
+                .try_finish()
+                .map(|t| self.finish_token(Ok(t), false))
         }
     }
 
     /// Returned from the `next` method on [`Lexer`](./struct.Lexer.html).
     pub type TokenResult<'a, T, E> = (Result<T, E>, &'a str);
 
+    /// An iterator that produces tokens along with the discarded text around them.
+    ///
+    /// Obtain one via [`Lexer::with_trivia`](./struct.Lexer.html#method.with_trivia).
+    pub struct WithTrivia<'src, S: State> {
+        lexer: Lexer<'src, S>,
+        /// The token after the current one, lexed ahead to find where the current one's trivia
+        /// ends.
+        next: Option<Located<'src, S>>,
+        /// The index of the first character not yet returned.
+        end: usize,
+    }
+
+    /// A token, and the index where its lexeme starts.
+    type Located<'src, S> = (
+        TokenResult<'src, <S as State>::Token, <S as State>::Error>,
+        usize,
+    );
+
+    impl<'src, S: State> Iterator for WithTrivia<'src, S> {
+        type Item = Lossless<'src, S::Token, S::Error>;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            let src = self.lexer.src;
+            let (token, start) = match self.next.take() {
+                Some(next) => next,
+                // Only an input with no tokens has text left over after the last one.
+                None if self.end < src.len() => {
+                    return Some(Lossless {
+                        leading: &src[mem::replace(&mut self.end, src.len())..],
+                        token: None,
+                        trailing: "",
+                    })
+                }
+                None => return None,
+            };
+            let token_end = start + token.1.len();
+
+            self.next = self.lexer.next_with_start();
+            let trailing_end = match self.next {
+                Some((_, next_start)) => src[token_end..next_start]
+                    .find('\n')
+                    .map_or(next_start, |newline| token_end + newline + 1),
+                None => src.len(),
+            };
+
+            let leading = &src[mem::replace(&mut self.end, trailing_end)..start];
+            Some(Lossless {
+                leading,
+                token: Some(token),
+                trailing: &src[token_end..trailing_end],
+            })
+        }
+    }
+
+    /// A token with the trivia around it, so that no text is lost.
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct Lossless<'src, T, E> {
+        /// Discarded text before the token.
+        pub leading: &'src str,
+        /// The token itself, or `None` if the input has no tokens and this is all of its text.
+        pub token: Option<TokenResult<'src, T, E>>,
+        /// Discarded text after the token.
+        pub trailing: &'src str,
+    }
+
     /// Actions to take when processing a character.
     #[non_exhaustive]
     pub enum Step<S: State> {
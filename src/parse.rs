@@ -0,0 +1,179 @@
+//! Syntax analyzers that consume the token streams produced by [`token::Lexer`].
+//!
+//! The analyzers driven by a [`Grammar`] expect the token type of the lexer to implement
+//! [`Terminal`], so that each token can be matched with a terminal of the grammar, and they
+//! produce a [`Tree`] whose interior nodes are labeled with the productions that were applied.
+//!
+//! [`token::Lexer`]: ../token/struct.Lexer.html
+//! [`Grammar`]: ../grammar/struct.Grammar.html
+//! [`Terminal`]: ../grammar/trait.Terminal.html
+//! [`Tree`]: ./enum.Tree.html
+
+pub mod earley;
+pub mod forest;
+pub mod glr;
+pub mod ll;
+pub mod lr;
+pub mod pratt;
+pub mod precedence;
+
+use {
+    crate::{
+        grammar::{Grammar, Symbol, Terminal},
+        token::TokenResult,
+    },
+    std::fmt,
+};
+
+/// How a chain of infix operators with the same precedence is grouped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Assoc {
+    /// `a ~ b ~ c` means `(a ~ b) ~ c`.
+    Left,
+    /// `a ~ b ~ c` means `a ~ (b ~ c)`.
+    Right,
+    /// `a ~ b ~ c` is an error.
+    Neither,
+}
+
+/// A terminal recognized in the input, along with the text it was recognized from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Token<'src> {
+    /// Index of the terminal in the grammar.
+    pub terminal: usize,
+    /// The text of the token. Empty for the end of the input.
+    pub lexeme: &'src str,
+}
+
+/// A parse tree.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Tree<'src> {
+    /// A terminal read from the input.
+    Leaf(Token<'src>),
+    /// The application of a production (identified by its index), with one child per symbol on
+    /// its right side.
+    Node(usize, Vec<Tree<'src>>),
+    /// A symbol that was expected but not found, inserted during error recovery.
+    Missing(Symbol),
+}
+
+impl<'src> Tree<'src> {
+    /// Format this tree as a parenthesized expression, with each node labeled by the nonterminal
+    /// on the left side of its production.
+    pub fn display<'a>(&'a self, grammar: &'a Grammar) -> impl fmt::Display + 'a {
+        DisplayTree(self, grammar)
+    }
+
+    /// The text covered by the leaves of this tree, separated by spaces.
+    pub fn text(&self) -> String {
+        let mut words = Vec::new();
+        self.collect_lexemes(&mut words);
+        words.join(" ")
+    }
+
+    fn collect_lexemes(&self, words: &mut Vec<&'src str>) {
+        match self {
+            Self::Leaf(token) => words.push(token.lexeme),
+            Self::Node(_, children) => children.iter().for_each(|c| c.collect_lexemes(words)),
+            Self::Missing(_) => (),
+        }
+    }
+}
+
+struct DisplayTree<'a, 'src>(&'a Tree<'src>, &'a Grammar);
+
+impl fmt::Display for DisplayTree<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Tree::Leaf(token) if token.lexeme.is_empty() => {
+                write!(f, "{}", self.1.terminals()[token.terminal])
+            }
+            Tree::Leaf(token) => write!(f, "{}", token.lexeme),
+            Tree::Node(production, children) => {
+                let lhs = self.1.productions()[*production].lhs;
+                write!(f, "({}", self.1.nonterminals()[lhs])?;
+                for child in children {
+                    write!(f, " {}", DisplayTree(child, self.1))?;
+                }
+                write!(f, ")")
+            }
+            Tree::Missing(symbol) => write!(f, "?{}", self.1.name(*symbol)),
+        }
+    }
+}
+
+/// Reasons the input could not be parsed.
+#[derive(Debug, PartialEq)]
+pub enum Error<'src, E> {
+    /// The lexer could not recognize a token.
+    Lex(E, &'src str),
+    /// A token does not stand for any terminal of the grammar.
+    UnknownTerminal(String, &'src str),
+    /// A token appeared where the grammar does not allow it. The terminals that would have been
+    /// allowed are listed, when they are known.
+    Unexpected(Token<'src>, Vec<usize>),
+}
+
+impl<'src, E: fmt::Debug> Error<'src, E> {
+    /// Describe this error, using the names of terminals from a grammar.
+    pub fn message(&self, grammar: &Grammar) -> String {
+        match self {
+            Self::Lex(e, s) => format!("could not recognize a token at `{}` ({:?})", s, e),
+            Self::UnknownTerminal(name, s) => {
+                format!("token `{}` ({}) is not a terminal of the grammar", s, name)
+            }
+            Self::Unexpected(token, expected) => {
+                let found = if token.terminal == Grammar::END {
+                    "end of input".to_string()
+                } else {
+                    format!("`{}`", token.lexeme)
+                };
+
+                if expected.is_empty() {
+                    format!("unexpected {}", found)
+                } else {
+                    let names = expected
+                        .iter()
+                        .map(|&t| grammar.terminals()[t].as_str())
+                        .collect::<Vec<_>>();
+                    format!("unexpected {}, expected one of: {}", found, names.join(" "))
+                }
+            }
+        }
+    }
+}
+
+/// Matches tokens with the terminals of a grammar, and reports the end of the input as
+/// [`Grammar::END`](../grammar/struct.Grammar.html#associatedconstant.END) as many times as it is
+/// asked.
+pub(crate) struct Input<'g, I> {
+    grammar: &'g Grammar,
+    tokens: I,
+}
+
+impl<'g, 'src, T, E, I> Input<'g, I>
+where
+    T: Terminal,
+    I: Iterator<Item = TokenResult<'src, T, E>>,
+{
+    pub(crate) fn new(grammar: &'g Grammar, tokens: I) -> Self {
+        Self { grammar, tokens }
+    }
+
+    pub(crate) fn next_token(&mut self) -> Result<Token<'src>, Error<'src, E>> {
+        match self.tokens.next() {
+            None => Ok(Token {
+                terminal: Grammar::END,
+                lexeme: "",
+            }),
+            Some((Err(e), lexeme)) => Err(Error::Lex(e, lexeme)),
+            Some((Ok(token), lexeme)) => match self.grammar.terminal(token.name(lexeme)) {
+                Some(terminal) => Ok(Token { terminal, lexeme }),
+                None => Err(Error::UnknownTerminal(
+                    token.name(lexeme).to_string(),
+                    lexeme,
+                )),
+            },
+        }
+    }
+}
@@ -0,0 +1,394 @@
+//! Earley parsing for any context-free grammar.
+//!
+//! An Earley parser keeps one set of items for each position in the input: productions with a
+//! dot, each with the position where the production began. Items are *predicted* for the
+//! nonterminal after a dot, *scanned* over a matching token into the next set, and *completed*
+//! by advancing the items that were waiting for the nonterminal on the left side. When predicting
+//! a nullable nonterminal, the dot is also moved over it right away, so that empty productions
+//! need no special treatment during completion.
+//!
+//! Since no choice is ever made between items, ambiguous grammars are no problem: every parse of
+//! the input is kept in a shared packed parse [`Forest`]. Nodes for partially recognized
+//! productions keep every node of the forest to at most two children, so the forest stays
+//! polynomial in size even when the number of trees is exponential.
+//!
+//! [`Forest`]: ../forest/struct.Forest.html
+//!
+//! ## Example
+//!
+//! ```
+//! # use dragon::{doctest::*, grammar::*, parse::earley::*, token::*};
+//! let grammar: Grammar = "E -> E + E | E * E | id".parse().unwrap();
+//! let parser = Parser::new(&grammar);
+//!
+//! let forest = parser.parse(lex::<Lex>("a + b * c")).unwrap();
+//! assert!(forest.is_ambiguous());
+//! assert_eq!(forest.count(), Some(2));
+//! assert_eq!(
+//!     forest
+//!         .trees()
+//!         .iter()
+//!         .map(|tree| tree.display(&grammar).to_string())
+//!         .collect::<Vec<_>>(),
+//!     [
+//!         "(E (E a) + (E (E b) * (E c)))",
+//!         "(E (E (E a) + (E b)) * (E c))",
+//!     ],
+//! );
+//!
+//! // The number of ways to group n operators is the nth Catalan number.
+//! let forest = parser.parse(lex::<Lex>("a + b + c + d + e + f")).unwrap();
+//! assert_eq!(forest.count(), Some(42));
+//!
+//! let error = parser.parse(lex::<Lex>("a + * b")).unwrap_err();
+//! assert_eq!(error.message(&grammar), "unexpected `*`, expected one of: id");
+//!
+//! // Nullable nonterminals are handled, and so are cycles, which allow infinitely many trees.
+//! // Only the trees that do not go around a cycle are listed.
+//! let grammar: Grammar = "
+//!     S -> A A x
+//!     A -> a | B
+//!     B -> A | ε
+//! "
+//! .parse()
+//! .unwrap();
+//! let forest = Parser::new(&grammar).parse(vec![
+//!     (Ok::<_, ()>("a"), "a"),
+//!     (Ok("x"), "x"),
+//! ]).unwrap();
+//! assert_eq!(forest.count(), None);
+//! assert_eq!(
+//!     forest
+//!         .trees()
+//!         .iter()
+//!         .map(|tree| tree.display(&grammar).to_string())
+//!         .collect::<Vec<_>>(),
+//!     [
+//!         "(S (A (B)) (A a) x)",
+//!         "(S (A a) (A (B)) x)",
+//!     ],
+//! );
+//! ```
+
+pub use super::forest::{Forest, Label, Node, Packed};
+
+use {
+    super::{Error, Input, Token},
+    crate::{
+        grammar::{Grammar, Sets, Symbol, Terminal},
+        token::TokenResult,
+    },
+    std::collections::{BTreeSet, HashMap, HashSet},
+};
+
+/// A production with a dot, and the position in the input where the production began.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct Item {
+    production: usize,
+    dot: usize,
+    origin: usize,
+}
+
+/// An Earley parser for a grammar.
+#[derive(Clone, Debug)]
+pub struct Parser {
+    grammar: Grammar,
+    sets: Sets,
+}
+
+impl Parser {
+    /// Prepare to parse with a grammar.
+    pub fn new(grammar: &Grammar) -> Self {
+        Self {
+            grammar: grammar.clone(),
+            sets: Sets::new(grammar),
+        }
+    }
+
+    /// The grammar this parser was created for.
+    pub fn grammar(&self) -> &Grammar {
+        &self.grammar
+    }
+
+    /// Parse a stream of tokens into a forest of all its parse trees.
+    pub fn parse<'src, T, E, I>(&self, tokens: I) -> Result<Forest<'src>, Error<'src, E>>
+    where
+        T: Terminal,
+        I: IntoIterator<Item = TokenResult<'src, T, E>>,
+    {
+        let mut input = Input::new(&self.grammar, tokens.into_iter());
+        let mut tokens = Vec::new();
+        loop {
+            let token = input.next_token()?;
+            if token.terminal == Grammar::END {
+                break;
+            }
+            tokens.push(token);
+        }
+
+        let sets = self.recognize(&tokens)?;
+
+        let mut builder = Builder {
+            grammar: &self.grammar,
+            tokens: &tokens,
+            sets: &sets,
+            index: HashMap::new(),
+            nodes: Vec::new(),
+        };
+        let root = builder.node(
+            Label::Symbol(Symbol::Nonterminal(self.grammar.start())),
+            0,
+            tokens.len(),
+        );
+
+        Ok(Forest::new(builder.nodes, root, tokens))
+    }
+
+    /// Build the item sets, failing at the first token that no item can scan.
+    fn recognize<'src, E>(
+        &self,
+        tokens: &[Token<'src>],
+    ) -> Result<Vec<HashSet<Item>>, Error<'src, E>> {
+        let grammar = &self.grammar;
+        let mut sets = vec![Vec::new(); tokens.len() + 1];
+        let mut members = vec![HashSet::new(); tokens.len() + 1];
+
+        for (production, _) in grammar.productions_of(grammar.start()) {
+            let item = Item {
+                production,
+                dot: 0,
+                origin: 0,
+            };
+            members[0].insert(item);
+            sets[0].push(item);
+        }
+
+        for position in 0..=tokens.len() {
+            let mut next = 0;
+
+            while next < sets[position].len() {
+                let item = sets[position][next];
+                next += 1;
+
+                let production = &grammar.productions()[item.production];
+                let mut added = Vec::new();
+
+                match production.rhs.get(item.dot) {
+                    Some(&Symbol::Nonterminal(n)) => {
+                        for (predicted, _) in grammar.productions_of(n) {
+                            added.push((
+                                position,
+                                Item {
+                                    production: predicted,
+                                    dot: 0,
+                                    origin: position,
+                                },
+                            ));
+                        }
+
+                        if self.sets.nullable(n) {
+                            added.push((
+                                position,
+                                Item {
+                                    dot: item.dot + 1,
+                                    ..item
+                                },
+                            ));
+                        }
+                    }
+                    Some(&Symbol::Terminal(t)) => {
+                        if matches!(tokens.get(position), Some(token) if token.terminal == t) {
+                            added.push((
+                                position + 1,
+                                Item {
+                                    dot: item.dot + 1,
+                                    ..item
+                                },
+                            ));
+                        }
+                    }
+                    None => {
+                        let lhs = Symbol::Nonterminal(production.lhs);
+                        for waiting in &sets[item.origin] {
+                            let rhs = &grammar.productions()[waiting.production].rhs;
+                            if rhs.get(waiting.dot) == Some(&lhs) {
+                                added.push((
+                                    position,
+                                    Item {
+                                        dot: waiting.dot + 1,
+                                        ..*waiting
+                                    },
+                                ));
+                            }
+                        }
+                    }
+                }
+
+                for (target, item) in added {
+                    if members[target].insert(item) {
+                        sets[target].push(item);
+                    }
+                }
+            }
+
+            let stuck = if position < tokens.len() {
+                sets[position + 1].is_empty()
+            } else {
+                !sets[position].iter().any(|item| {
+                    let production = &grammar.productions()[item.production];
+                    item.origin == 0
+                        && production.lhs == grammar.start()
+                        && item.dot == production.rhs.len()
+                })
+            };
+
+            if stuck {
+                let expected = sets[position]
+                    .iter()
+                    .filter_map(|item| {
+                        match grammar.productions()[item.production].rhs.get(item.dot) {
+                            Some(&Symbol::Terminal(t)) => Some(t),
+                            _ => None,
+                        }
+                    })
+                    .collect::<BTreeSet<_>>();
+                let found = tokens.get(position).copied().unwrap_or(Token {
+                    terminal: Grammar::END,
+                    lexeme: "",
+                });
+                return Err(Error::Unexpected(found, expected.into_iter().collect()));
+            }
+        }
+
+        Ok(members)
+    }
+}
+
+/// Builds the nodes of a forest that are reachable from the root, from completed item sets.
+struct Builder<'a, 'src> {
+    grammar: &'a Grammar,
+    tokens: &'a [Token<'src>],
+    sets: &'a [HashSet<Item>],
+    index: HashMap<(Label, usize, usize), usize>,
+    nodes: Vec<Node>,
+}
+
+impl Builder<'_, '_> {
+    fn node(&mut self, label: Label, start: usize, end: usize) -> usize {
+        if let Some(&node) = self.index.get(&(label, start, end)) {
+            return node;
+        }
+
+        let node = self.nodes.len();
+        self.index.insert((label, start, end), node);
+        self.nodes.push(Node {
+            label,
+            start,
+            end,
+            packed: Vec::new(),
+        });
+
+        let mut packed = Vec::new();
+        match label {
+            Label::Symbol(Symbol::Terminal(_)) => (),
+            Label::Symbol(Symbol::Nonterminal(n)) => {
+                let productions = self
+                    .grammar
+                    .productions_of(n)
+                    .map(|(p, production)| (p, production.rhs.len()))
+                    .collect::<Vec<_>>();
+
+                for (production, len) in productions {
+                    if self.has_item(end, production, len, start) {
+                        for children in self.splits(production, len, start, end) {
+                            packed.push(Packed {
+                                production,
+                                children,
+                            });
+                        }
+                    }
+                }
+            }
+            Label::Partial { production, dot } => {
+                for children in self.splits(production, dot, start, end) {
+                    packed.push(Packed {
+                        production,
+                        children,
+                    });
+                }
+            }
+        }
+
+        self.nodes[node].packed = packed;
+        node
+    }
+
+    /// The ways the first `dot` symbols of a production derive the span from `start` to `end`,
+    /// as lists of child nodes.
+    fn splits(
+        &mut self,
+        production: usize,
+        dot: usize,
+        start: usize,
+        end: usize,
+    ) -> Vec<Vec<usize>> {
+        if dot == 0 {
+            return if start == end {
+                vec![Vec::new()]
+            } else {
+                Vec::new()
+            };
+        }
+
+        let last = self.grammar.productions()[production].rhs[dot - 1];
+        let mut splits = Vec::new();
+
+        for middle in start..=end {
+            let prefix = if dot == 1 {
+                middle == start
+            } else {
+                self.has_item(middle, production, dot - 1, start)
+            };
+
+            if prefix && self.derives(last, middle, end) {
+                let last = self.node(Label::Symbol(last), middle, end);
+                if dot == 1 {
+                    splits.push(vec![last]);
+                } else {
+                    let partial = self.node(
+                        Label::Partial {
+                            production,
+                            dot: dot - 1,
+                        },
+                        start,
+                        middle,
+                    );
+                    splits.push(vec![partial, last]);
+                }
+            }
+        }
+
+        splits
+    }
+
+    fn derives(&self, symbol: Symbol, start: usize, end: usize) -> bool {
+        match symbol {
+            Symbol::Terminal(t) => {
+                end == start + 1
+                    && matches!(self.tokens.get(start), Some(token) if token.terminal == t)
+            }
+            Symbol::Nonterminal(n) => self
+                .grammar
+                .productions_of(n)
+                .any(|(p, production)| self.has_item(end, p, production.rhs.len(), start)),
+        }
+    }
+
+    fn has_item(&self, set: usize, production: usize, dot: usize, origin: usize) -> bool {
+        self.sets[set].contains(&Item {
+            production,
+            dot,
+            origin,
+        })
+    }
+}
@@ -0,0 +1,215 @@
+//! Shared packed parse forests, which hold every parse tree of an ambiguous input.
+//!
+//! Each node of a [`Forest`] stands for a symbol deriving a span of the input, and has one
+//! *packed* alternative for each way it can do so. Nodes for the same symbol and span are shared
+//! between alternatives, so a forest can be polynomial in size even when the number of trees is
+//! exponential. Forests are produced by the [Earley](../earley/index.html) and
+//! [GLR](../glr/index.html) parsers.
+//!
+//! [`Forest`]: ./struct.Forest.html
+
+use {
+    super::{Token, Tree},
+    crate::grammar::Symbol,
+};
+
+/// What a node of a [`Forest`](./struct.Forest.html) stands for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Label {
+    /// A terminal or nonterminal.
+    Symbol(Symbol),
+    /// The symbols before the dot in a production.
+    Partial { production: usize, dot: usize },
+}
+
+/// One way of deriving the span of a node.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Packed {
+    /// The production that was applied.
+    pub production: usize,
+    /// Indices of the child nodes, covering the span from left to right. A node for a partial
+    /// production may come first, followed by a node for the next symbol.
+    pub children: Vec<usize>,
+}
+
+/// A node of a [`Forest`](./struct.Forest.html).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Node {
+    pub label: Label,
+    /// The position of the first token in the span.
+    pub start: usize,
+    /// The position after the last token in the span.
+    pub end: usize,
+    /// The ways of deriving the span. Empty for terminals.
+    pub packed: Vec<Packed>,
+}
+
+/// A shared packed parse forest: every parse tree of an input, sharing common subtrees.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Forest<'src> {
+    nodes: Vec<Node>,
+    root: usize,
+    tokens: Vec<Token<'src>>,
+}
+
+impl<'src> Forest<'src> {
+    /// Assemble a forest, keeping only the nodes that can be reached from the root.
+    pub(crate) fn new(nodes: Vec<Node>, root: usize, tokens: Vec<Token<'src>>) -> Self {
+        let mut renumbered = vec![None; nodes.len()];
+        let mut order = vec![root];
+        renumbered[root] = Some(0);
+
+        let mut next = 0;
+        while next < order.len() {
+            for alternative in &nodes[order[next]].packed {
+                for &child in &alternative.children {
+                    if renumbered[child].is_none() {
+                        renumbered[child] = Some(order.len());
+                        order.push(child);
+                    }
+                }
+            }
+            next += 1;
+        }
+
+        let nodes = order
+            .iter()
+            .map(|&old| {
+                let mut node = nodes[old].clone();
+                for alternative in &mut node.packed {
+                    for child in &mut alternative.children {
+                        *child = renumbered[*child].unwrap();
+                    }
+                }
+                node
+            })
+            .collect();
+
+        Self {
+            nodes,
+            root: 0,
+            tokens,
+        }
+    }
+
+    /// All nodes of the forest.
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    /// The index of the node for the start symbol deriving the whole input.
+    pub fn root(&self) -> usize {
+        self.root
+    }
+
+    /// The tokens of the input, by position.
+    pub fn tokens(&self) -> &[Token<'src>] {
+        &self.tokens
+    }
+
+    /// Whether the input has more than one parse tree.
+    pub fn is_ambiguous(&self) -> bool {
+        self.nodes.iter().any(|node| node.packed.len() > 1)
+    }
+
+    /// The number of parse trees, or `None` if there are infinitely many because of a cycle in
+    /// the grammar.
+    pub fn count(&self) -> Option<u128> {
+        fn visit(forest: &Forest, node: usize, counts: &mut Vec<Count>) -> Option<u128> {
+            match counts[node] {
+                Count::Done(count) => return Some(count),
+                Count::Visiting => return None,
+                Count::Unknown => counts[node] = Count::Visiting,
+            }
+
+            let packed = &forest.nodes[node].packed;
+            let mut count: u128 = if packed.is_empty() { 1 } else { 0 };
+            for alternative in packed {
+                let mut product: u128 = 1;
+                for &child in &alternative.children {
+                    product = product.saturating_mul(visit(forest, child, counts)?);
+                }
+                count = count.saturating_add(product);
+            }
+
+            counts[node] = Count::Done(count);
+            Some(count)
+        }
+
+        visit(self, self.root, &mut vec![Count::Unknown; self.nodes.len()])
+    }
+
+    /// Every parse tree, except those that would repeat a node along one of their paths. The
+    /// number of trees can grow exponentially with the length of the input.
+    pub fn trees(&self) -> Vec<Tree<'src>> {
+        self.expand(self.root, &mut Vec::new())
+    }
+
+    fn expand(&self, node: usize, path: &mut Vec<usize>) -> Vec<Tree<'src>> {
+        if path.contains(&node) {
+            return Vec::new();
+        }
+
+        let this = &self.nodes[node];
+        if let Label::Symbol(Symbol::Terminal(_)) = this.label {
+            return vec![Tree::Leaf(self.tokens[this.start])];
+        }
+
+        path.push(node);
+        let mut trees = Vec::new();
+        for alternative in &this.packed {
+            for children in self.sequences(&alternative.children, path) {
+                trees.push(Tree::Node(alternative.production, children));
+            }
+        }
+        path.pop();
+
+        trees
+    }
+
+    /// The lists of trees for a list of nodes, flattening partial productions.
+    fn sequences(&self, nodes: &[usize], path: &mut Vec<usize>) -> Vec<Vec<Tree<'src>>> {
+        let mut sequences = vec![Vec::new()];
+
+        for &node in nodes {
+            let alternatives = match self.nodes[node].label {
+                Label::Partial { .. } if path.contains(&node) => Vec::new(),
+                Label::Partial { .. } => {
+                    path.push(node);
+                    let alternatives = self.nodes[node]
+                        .packed
+                        .iter()
+                        .flat_map(|alternative| self.sequences(&alternative.children, path))
+                        .collect();
+                    path.pop();
+                    alternatives
+                }
+                Label::Symbol(_) => self
+                    .expand(node, path)
+                    .into_iter()
+                    .map(|tree| vec![tree])
+                    .collect::<Vec<_>>(),
+            };
+
+            sequences = sequences
+                .iter()
+                .flat_map(|prefix| {
+                    alternatives.iter().map(move |suffix| {
+                        let mut sequence = prefix.clone();
+                        sequence.extend(suffix.iter().cloned());
+                        sequence
+                    })
+                })
+                .collect();
+        }
+
+        sequences
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Count {
+    Unknown,
+    Visiting,
+    Done(u128),
+}
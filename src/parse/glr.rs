@@ -0,0 +1,308 @@
+//! Generalized LR parsing, which follows every action of an LR table with conflicts.
+//!
+//! Where an LR parser would have to choose between the actions in a conflicting entry, a GLR
+//! parser takes all of them, forking its stack. The stacks are kept together in a
+//! *graph-structured stack*: stacks that reach the same state at the same position in the input
+//! are merged into one node, and stacks that share a bottom share the nodes for it. A fork that
+//! leads nowhere dies out when no action applies to its state.
+//!
+//! The edges of the graph are labeled with nodes of a shared packed parse [`Forest`], so every
+//! parse of an ambiguous input is kept. When a reduction creates an edge to a node that already
+//! had reductions done through it, those reductions are repeated through the new edge.
+//!
+//! [`Forest`]: ../forest/struct.Forest.html
+//!
+//! ## Example
+//!
+//! In C, `a * b;` declares `b` as a pointer if `a` names a type, and multiplies otherwise. Without
+//! a symbol table the statement has two parses, and LR tables for such a grammar have conflicts.
+//!
+//! ```
+//! # use dragon::{doctest::*, grammar::*, parse::glr::*, token::*};
+//! let grammar: Grammar = "
+//!     Stmt -> Decl ; | Expr ;
+//!     Decl -> Type Declarator
+//!     Declarator -> * Declarator | id
+//!     Type -> id
+//!     Expr -> Expr * Expr | id
+//! "
+//! .parse()
+//! .unwrap();
+//!
+//! let parser = Parser::new(&grammar);
+//! assert!(!parser.table().conflicts().is_empty());
+//!
+//! let forest = parser.parse(lex::<Lex>("a * b ;")).unwrap();
+//! assert_eq!(
+//!     forest
+//!         .trees()
+//!         .iter()
+//!         .map(|tree| tree.display(&grammar).to_string())
+//!         .collect::<Vec<_>>(),
+//!     [
+//!         "(Stmt (Expr (Expr a) * (Expr b)) ;)",
+//!         "(Stmt (Decl (Type a) (Declarator * (Declarator b))) ;)",
+//!     ],
+//! );
+//!
+//! // `b * c` is no declarator, so only the two groupings of the product are left.
+//! let forest = parser.parse(lex::<Lex>("a * b * c ;")).unwrap();
+//! assert_eq!(forest.count(), Some(2));
+//!
+//! let error = parser.parse(lex::<Lex>("a * ;")).unwrap_err();
+//! assert_eq!(error.message(&grammar), "unexpected `;`, expected one of: * id");
+//!
+//! // Empty productions and hidden left recursion are no problem either.
+//! let grammar: Grammar = "S -> A S b | x \n A -> ε".parse().unwrap();
+//! let forest = Parser::new(&grammar).parse(vec![
+//!     (Ok::<_, ()>("x"), "x"),
+//!     (Ok("b"), "b"),
+//!     (Ok("b"), "b"),
+//! ]).unwrap();
+//! assert_eq!(
+//!     forest.trees()[0].display(&grammar).to_string(),
+//!     "(S (A) (S (A) (S x) b) b)",
+//! );
+//! ```
+
+use {
+    super::{
+        forest::{Forest, Label, Node, Packed},
+        lr::{Action, Method, Table},
+        Error, Input,
+    },
+    crate::{
+        grammar::{Grammar, Symbol, Terminal},
+        token::TokenResult,
+    },
+    std::collections::{BTreeSet, HashMap},
+};
+
+/// A node of the graph-structured stack.
+struct Vertex {
+    state: usize,
+    position: usize,
+    /// Links toward the bottom of the stack, each labeled by the forest node for the symbol
+    /// between the two vertices.
+    edges: Vec<(usize, usize)>,
+}
+
+/// A GLR parser, using LALR(1) tables.
+#[derive(Clone, Debug)]
+pub struct Parser {
+    table: Table,
+}
+
+impl Parser {
+    /// Build the tables for a grammar. Conflicts are kept, rather than resolved.
+    pub fn new(grammar: &Grammar) -> Self {
+        Self::with_table(Table::new(grammar, Method::Lalr))
+    }
+
+    /// Use tables built some other way, such as with precedence declarations that resolve only
+    /// some of the conflicts.
+    pub fn with_table(table: Table) -> Self {
+        Self { table }
+    }
+
+    /// The tables that drive the parser.
+    pub fn table(&self) -> &Table {
+        &self.table
+    }
+
+    /// Parse a stream of tokens into a forest of all its parse trees.
+    pub fn parse<'src, T, E, I>(&self, tokens: I) -> Result<Forest<'src>, Error<'src, E>>
+    where
+        T: Terminal,
+        I: IntoIterator<Item = TokenResult<'src, T, E>>,
+    {
+        let mut input = Input::new(self.table.grammar(), tokens.into_iter());
+        let mut run = Run {
+            table: &self.table,
+            vertices: vec![Vertex {
+                state: 0,
+                position: 0,
+                edges: Vec::new(),
+            }],
+            nodes: Vec::new(),
+            index: HashMap::new(),
+        };
+        let mut frontier = vec![0];
+        let mut tokens = Vec::new();
+
+        loop {
+            let lookahead = input.next_token()?;
+            let position = tokens.len();
+            run.reduce_all(&mut frontier, lookahead.terminal, position);
+
+            if lookahead.terminal == Grammar::END {
+                for &vertex in &frontier {
+                    let state = run.vertices[vertex].state;
+                    if self
+                        .table
+                        .actions(state, Grammar::END)
+                        .contains(&Action::Accept)
+                    {
+                        let root = run.vertices[vertex].edges[0].1;
+                        return Ok(Forest::new(run.nodes, root, tokens));
+                    }
+                }
+            }
+
+            let next = run.shift_all(&frontier, lookahead.terminal, position);
+            if next.is_empty() {
+                let expected = frontier
+                    .iter()
+                    .flat_map(|&v| self.table.expected(run.vertices[v].state))
+                    .collect::<BTreeSet<_>>();
+                return Err(Error::Unexpected(lookahead, expected.into_iter().collect()));
+            }
+
+            tokens.push(lookahead);
+            frontier = next;
+        }
+    }
+}
+
+/// The state of one parse: the graph-structured stack, and the forest built so far.
+struct Run<'t> {
+    table: &'t Table,
+    vertices: Vec<Vertex>,
+    nodes: Vec<Node>,
+    index: HashMap<(Symbol, usize, usize), usize>,
+}
+
+impl Run<'_> {
+    /// Do every reduction on the lookahead from the vertices at the current position, until no
+    /// more vertices, edges or alternatives are created.
+    fn reduce_all(&mut self, frontier: &mut Vec<usize>, lookahead: usize, position: usize) {
+        let grammar = self.table.grammar();
+
+        loop {
+            let mut changed = false;
+            let mut next = 0;
+
+            while next < frontier.len() {
+                let vertex = frontier[next];
+                next += 1;
+
+                let state = self.vertices[vertex].state;
+                for &action in self.table.actions(state, lookahead) {
+                    let production = match action {
+                        Action::Reduce(production) => production,
+                        _ => continue,
+                    };
+                    let lhs = grammar.productions()[production].lhs;
+                    let len = grammar.productions()[production].rhs.len();
+
+                    for (bottom, children) in self.paths(vertex, len) {
+                        let start = self.vertices[bottom].position;
+                        let node = self.node(Symbol::Nonterminal(lhs), start, position);
+                        let alternative = Packed {
+                            production,
+                            children,
+                        };
+                        if !self.nodes[node].packed.contains(&alternative) {
+                            self.nodes[node].packed.push(alternative);
+                            changed = true;
+                        }
+
+                        let goto = match self.table.goto(self.vertices[bottom].state, lhs) {
+                            Some(goto) => goto,
+                            None => continue,
+                        };
+
+                        let top = match frontier.iter().find(|&&v| self.vertices[v].state == goto) {
+                            Some(&top) => top,
+                            None => {
+                                self.vertices.push(Vertex {
+                                    state: goto,
+                                    position,
+                                    edges: Vec::new(),
+                                });
+                                frontier.push(self.vertices.len() - 1);
+                                changed = true;
+                                self.vertices.len() - 1
+                            }
+                        };
+
+                        if !self.vertices[top].edges.contains(&(bottom, node)) {
+                            self.vertices[top].edges.push((bottom, node));
+                            changed = true;
+                        }
+                    }
+                }
+            }
+
+            if !changed {
+                return;
+            }
+        }
+    }
+
+    /// Shift the lookahead from every vertex at the current position that can, merging the
+    /// vertices for the same state.
+    fn shift_all(&mut self, frontier: &[usize], lookahead: usize, position: usize) -> Vec<usize> {
+        let mut next: Vec<usize> = Vec::new();
+        let mut leaf = None;
+
+        for &vertex in frontier {
+            let state = self.vertices[vertex].state;
+            for &action in self.table.actions(state, lookahead) {
+                let target = match action {
+                    Action::Shift(target) => target,
+                    _ => continue,
+                };
+
+                let leaf = *leaf.get_or_insert_with(|| {
+                    self.node(Symbol::Terminal(lookahead), position, position + 1)
+                });
+
+                match next.iter().find(|&&v| self.vertices[v].state == target) {
+                    Some(&top) => self.vertices[top].edges.push((vertex, leaf)),
+                    None => {
+                        self.vertices.push(Vertex {
+                            state: target,
+                            position: position + 1,
+                            edges: vec![(vertex, leaf)],
+                        });
+                        next.push(self.vertices.len() - 1);
+                    }
+                }
+            }
+        }
+
+        next
+    }
+
+    /// Every path of `len` edges down from a vertex: the vertex it ends at, and the labels of its
+    /// edges from bottom to top.
+    fn paths(&self, vertex: usize, len: usize) -> Vec<(usize, Vec<usize>)> {
+        if len == 0 {
+            return vec![(vertex, Vec::new())];
+        }
+
+        let mut paths = Vec::new();
+        for &(below, node) in &self.vertices[vertex].edges {
+            for (bottom, mut children) in self.paths(below, len - 1) {
+                children.push(node);
+                paths.push((bottom, children));
+            }
+        }
+        paths
+    }
+
+    /// The forest node for a symbol deriving a span, created if need be.
+    fn node(&mut self, symbol: Symbol, start: usize, end: usize) -> usize {
+        let nodes = &mut self.nodes;
+        *self.index.entry((symbol, start, end)).or_insert_with(|| {
+            nodes.push(Node {
+                label: Label::Symbol(symbol),
+                start,
+                end,
+                packed: Vec::new(),
+            });
+            nodes.len() - 1
+        })
+    }
+}
@@ -0,0 +1,373 @@
+//! Table-driven predictive parsing for LL(1) grammars.
+//!
+//! The parsing table has a row for each nonterminal and a column for each terminal. The entry
+//! `M[A, a]` names the production to expand `A` with when `a` is the next token of the input:
+//!
+//! - for each production `A -> α`, `M[A, a]` is `A -> α` for every terminal `a` in FIRST(α);
+//! - if `α` can derive the empty string, `M[A, b]` is `A -> α` for every `b` in FOLLOW(A).
+//!
+//! A grammar is LL(1) when no entry names more than one production. The parser keeps the
+//! symbols it still expects to see on an explicit stack, rather than recursing.
+//!
+//! With [`parse_with_recovery`], the parser does not stop at the first syntax error. It uses
+//! *panic mode*: input tokens are skipped until one appears in the synchronizing set of the
+//! nonterminal on top of the stack (its FOLLOW set), at which point the nonterminal is abandoned.
+//! Symbols abandoned this way appear in the parse tree as [`Tree::Missing`].
+//!
+//! [`parse_with_recovery`]: ./struct.Table.html#method.parse_with_recovery
+//! [`Tree::Missing`]: ../enum.Tree.html#variant.Missing
+//!
+//! ## Example
+//!
+//! ```
+//! # use dragon::{doctest::*, grammar::*, parse::ll::*, token::*};
+//! let grammar: Grammar = "
+//!     E  -> T E'
+//!     E' -> + T E' | ε
+//!     T  -> F T'
+//!     T' -> * F T' | ε
+//!     F  -> ( E ) | id
+//! "
+//! .parse()
+//! .unwrap();
+//!
+//! let table = Table::new(&grammar).unwrap();
+//! let tree = table.parse(lex::<Lex>("a + b * c")).unwrap();
+//! assert_eq!(
+//!     tree.display(&grammar).to_string(),
+//!     "(E (T (F a) (T')) (E' + (T (F b) (T' * (F c) (T'))) (E')))",
+//! );
+//!
+//! let (tree, errors) = table.parse_with_recovery(lex::<Lex>("a + * b )"));
+//! assert_eq!(
+//!     errors.iter().map(|e| e.message(&grammar)).collect::<Vec<_>>(),
+//!     [
+//!         "unexpected `*`, expected one of: ( id",
+//!         "unexpected `)`, expected one of: $",
+//!     ],
+//! );
+//! assert_eq!(tree.text(), "a + b");
+//!
+//! let left_recursive: Grammar = "E -> E + id | id".parse().unwrap();
+//! let conflicts = Table::new(&left_recursive).unwrap_err();
+//! assert_eq!(
+//!     conflicts[0].message(&left_recursive),
+//!     "FIRST/FIRST conflict for E on `id` between `E -> E + id` and `E -> id`",
+//! );
+//!
+//! let dangling_else: Grammar = "S -> if S S' | x \n S' -> else S | ε".parse().unwrap();
+//! let conflicts = Table::new(&dangling_else).unwrap_err();
+//! assert_eq!(
+//!     conflicts[0].message(&dangling_else),
+//!     "FIRST/FOLLOW conflict for S' on `else` between `S' -> else S` and `S' -> ε`",
+//! );
+//!
+//! // `A -> B` belongs in `M[A, b]` both ways, but only `B` has a conflict.
+//! let nullable: Grammar = "S -> A b \n A -> B \n B -> b | ε".parse().unwrap();
+//! let conflicts = Table::new(&nullable).unwrap_err();
+//! assert_eq!(
+//!     conflicts.iter().map(|c| c.message(&nullable)).collect::<Vec<_>>(),
+//!     ["FIRST/FOLLOW conflict for B on `b` between `B -> b` and `B -> ε`"],
+//! );
+//! ```
+
+use {
+    super::{Error, Input, Token, Tree},
+    crate::{
+        grammar::{Grammar, Sets, Symbol, Terminal},
+        token::TokenResult,
+    },
+};
+
+/// The kinds of conflict that prevent a grammar from being LL(1).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConflictKind {
+    /// The terminal can begin strings derived from more than one of the productions.
+    FirstFirst,
+    /// The terminal can begin a string derived from one production, and follow the nonterminal
+    /// when another production derives the empty string.
+    FirstFollow,
+}
+
+/// More than one production belongs in an entry of the parsing table.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Conflict {
+    /// The row of the entry.
+    pub nonterminal: usize,
+    /// The column of the entry.
+    pub terminal: usize,
+    /// Indices of the productions that compete for the entry.
+    pub productions: Vec<usize>,
+    /// Why the productions compete.
+    pub kind: ConflictKind,
+}
+
+impl Conflict {
+    /// Describe this conflict, using the names of symbols from the grammar.
+    pub fn message(&self, grammar: &Grammar) -> String {
+        let productions = self
+            .productions
+            .iter()
+            .map(|&p| format!("`{}`", grammar.show_production(p)))
+            .collect::<Vec<_>>();
+
+        format!(
+            "{} conflict for {} on `{}` between {}",
+            match self.kind {
+                ConflictKind::FirstFirst => "FIRST/FIRST",
+                ConflictKind::FirstFollow => "FIRST/FOLLOW",
+            },
+            grammar.nonterminals()[self.nonterminal],
+            grammar.terminals()[self.terminal],
+            productions.join(" and ")
+        )
+    }
+}
+
+/// An LL(1) parsing table.
+#[derive(Clone, Debug)]
+pub struct Table {
+    grammar: Grammar,
+    sets: Sets,
+    entries: Vec<Vec<Option<usize>>>,
+}
+
+enum Entry {
+    Symbol(Symbol),
+    /// All children of the node on top of the tree stack have been parsed.
+    Close,
+}
+
+impl Table {
+    /// Build the parsing table for a grammar, or report every entry with a conflict.
+    pub fn new(grammar: &Grammar) -> Result<Self, Vec<Conflict>> {
+        let sets = Sets::new(grammar);
+        let terminals = grammar.terminals().len();
+        let mut cells = vec![vec![Vec::new(); terminals]; grammar.nonterminals().len()];
+
+        for (index, production) in grammar.productions().iter().enumerate() {
+            let (first, nullable) = sets.first_of(&production.rhs);
+
+            for t in first {
+                cells[production.lhs][t].push((index, ConflictKind::FirstFirst));
+            }
+
+            if nullable {
+                for &t in sets.follow(production.lhs) {
+                    let cell = &mut cells[production.lhs][t];
+                    match cell.iter_mut().find(|(p, _)| *p == index) {
+                        Some(entry) => entry.1 = ConflictKind::FirstFollow,
+                        None => cell.push((index, ConflictKind::FirstFollow)),
+                    }
+                }
+            }
+        }
+
+        let mut conflicts = Vec::new();
+        let entries = cells
+            .into_iter()
+            .enumerate()
+            .map(|(nonterminal, row)| {
+                row.into_iter()
+                    .enumerate()
+                    .map(|(terminal, cell)| {
+                        if cell.len() > 1 {
+                            let kind = cell
+                                .iter()
+                                .map(|&(_, kind)| kind)
+                                .find(|&kind| kind == ConflictKind::FirstFollow)
+                                .unwrap_or(ConflictKind::FirstFirst);
+
+                            conflicts.push(Conflict {
+                                nonterminal,
+                                terminal,
+                                productions: cell.iter().map(|&(p, _)| p).collect(),
+                                kind,
+                            });
+                        }
+
+                        cell.first().map(|&(p, _)| p)
+                    })
+                    .collect()
+            })
+            .collect();
+
+        if !conflicts.is_empty() {
+            return Err(conflicts);
+        }
+
+        Ok(Self {
+            grammar: grammar.clone(),
+            sets,
+            entries,
+        })
+    }
+
+    /// The grammar this table was built from.
+    pub fn grammar(&self) -> &Grammar {
+        &self.grammar
+    }
+
+    /// The nullability, FIRST and FOLLOW sets of the grammar.
+    pub fn sets(&self) -> &Sets {
+        &self.sets
+    }
+
+    /// The production to expand a nonterminal with, when a terminal is next in the input.
+    pub fn entry(&self, nonterminal: usize, terminal: usize) -> Option<usize> {
+        self.entries[nonterminal][terminal]
+    }
+
+    /// Parse a stream of tokens, stopping at the first error.
+    pub fn parse<'src, T, E, I>(&self, tokens: I) -> Result<Tree<'src>, Error<'src, E>>
+    where
+        T: Terminal,
+        I: IntoIterator<Item = TokenResult<'src, T, E>>,
+    {
+        let (tree, mut errors) = self.run(tokens, false);
+
+        if errors.is_empty() {
+            Ok(tree)
+        } else {
+            Err(errors.remove(0))
+        }
+    }
+
+    /// Parse a stream of tokens, recovering from syntax errors in panic mode.
+    ///
+    /// Every error is reported, except for those detected before the parser has shifted another
+    /// token after recovering from the last one. The returned tree covers the whole input,
+    /// except for skipped tokens.
+    pub fn parse_with_recovery<'src, T, E, I>(&self, tokens: I) -> (Tree<'src>, Vec<Error<'src, E>>)
+    where
+        T: Terminal,
+        I: IntoIterator<Item = TokenResult<'src, T, E>>,
+    {
+        self.run(tokens, true)
+    }
+
+    fn run<'src, T, E, I>(&self, tokens: I, recover: bool) -> (Tree<'src>, Vec<Error<'src, E>>)
+    where
+        T: Terminal,
+        I: IntoIterator<Item = TokenResult<'src, T, E>>,
+    {
+        let start = Symbol::Nonterminal(self.grammar.start());
+        let mut input = Input::new(&self.grammar, tokens.into_iter());
+        let mut stack = vec![
+            Entry::Symbol(Symbol::Terminal(Grammar::END)),
+            Entry::Symbol(start),
+        ];
+        let mut nodes: Vec<(usize, Vec<Tree<'src>>)> = Vec::new();
+        let mut root = None;
+        let mut errors = Vec::new();
+        let mut panicking = false;
+
+        let mut add = |nodes: &mut Vec<(usize, Vec<Tree<'src>>)>, tree| match nodes.last_mut() {
+            Some((_, children)) => children.push(tree),
+            None => root = Some(tree),
+        };
+
+        let mut lookahead = next_token(&mut input, &mut errors, recover);
+
+        while let Some(current) = lookahead {
+            let mut report = |errors: &mut Vec<_>, expected| {
+                if !panicking {
+                    errors.push(Error::Unexpected(current, expected));
+                }
+                panicking = true;
+            };
+
+            match stack.last() {
+                Some(Entry::Close) => {
+                    stack.pop();
+                    let (production, children) = nodes.pop().unwrap();
+                    add(&mut nodes, Tree::Node(production, children));
+                }
+                Some(&Entry::Symbol(Symbol::Terminal(t))) if t == current.terminal => {
+                    if t == Grammar::END {
+                        break;
+                    }
+
+                    stack.pop();
+                    add(&mut nodes, Tree::Leaf(current));
+                    panicking = false;
+                    lookahead = next_token(&mut input, &mut errors, recover);
+                }
+                Some(&Entry::Symbol(Symbol::Terminal(t))) => {
+                    report(&mut errors, vec![t]);
+
+                    if t == Grammar::END {
+                        lookahead = next_token(&mut input, &mut errors, recover);
+                    } else {
+                        stack.pop();
+                        add(&mut nodes, Tree::Missing(Symbol::Terminal(t)));
+                    }
+                }
+                Some(&Entry::Symbol(Symbol::Nonterminal(n))) => {
+                    if let Some(production) = self.entries[n][current.terminal] {
+                        stack.pop();
+                        stack.push(Entry::Close);
+                        stack.extend(
+                            self.grammar.productions()[production]
+                                .rhs
+                                .iter()
+                                .rev()
+                                .map(|&s| Entry::Symbol(s)),
+                        );
+                        nodes.push((production, Vec::new()));
+                        continue;
+                    }
+
+                    let expected = (0..self.grammar.terminals().len())
+                        .filter(|&t| self.entries[n][t].is_some())
+                        .collect();
+                    report(&mut errors, expected);
+
+                    if current.terminal == Grammar::END
+                        || self.sets.follow(n).contains(&current.terminal)
+                    {
+                        stack.pop();
+                        add(&mut nodes, Tree::Missing(Symbol::Nonterminal(n)));
+                    } else {
+                        lookahead = next_token(&mut input, &mut errors, recover);
+                    }
+                }
+                None => break,
+            }
+
+            if !recover && !errors.is_empty() {
+                break;
+            }
+        }
+
+        while let Some((production, children)) = nodes.pop() {
+            add(&mut nodes, Tree::Node(production, children));
+        }
+
+        (root.unwrap_or(Tree::Missing(start)), errors)
+    }
+}
+
+/// Read the next token, skipping over (and reporting) any the grammar does not recognize when
+/// recovering from errors.
+fn next_token<'src, T, E, I>(
+    input: &mut Input<'_, I>,
+    errors: &mut Vec<Error<'src, E>>,
+    recover: bool,
+) -> Option<Token<'src>>
+where
+    T: Terminal,
+    I: Iterator<Item = TokenResult<'src, T, E>>,
+{
+    loop {
+        match input.next_token() {
+            Ok(token) => return Some(token),
+            Err(e) => {
+                errors.push(e);
+                if !recover {
+                    return None;
+                }
+            }
+        }
+    }
+}
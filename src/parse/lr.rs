@@ -0,0 +1,706 @@
+//! Bottom-up shift-reduce parsing with LR tables.
+//!
+//! An LR parser keeps a stack of states of an [`Automaton`] whose items describe how much of
+//! each production has been seen so far. The ACTION table tells it, for the state on top of the
+//! stack and the next token of the input, whether to *shift* the token onto the stack, *reduce*
+//! the symbols on top of the stack by a production, or *accept* the input. After a reduction, the
+//! GOTO table gives the state to push for the nonterminal on the left of the production.
+//!
+//! Tables built by the [`Slr`] method reduce by `A -> α` on every terminal in FOLLOW(A). The
+//! [`Canonical`] and [`Lalr`] methods reduce only on the lookaheads of LR(1) items, so they
+//! handle more grammars, at the price of more states for the [`Canonical`] method. Comparing
+//! [`len`] between tables built for the same grammar shows the difference.
+//!
+//! When more than one action belongs in an entry of the ACTION table, the grammar is not in the
+//! class that the method handles. Every such [`Conflict`] is recorded, and the parser resolves it
+//! like yacc does: shifting in preference to reducing, and otherwise reducing by the production
+//! that comes first in the grammar.
+//!
+//! Ambiguous grammars for expressions are much shorter than unambiguous ones, and their
+//! shift/reduce conflicts can be resolved by declaring the [`Precedence`] and associativity of
+//! operators, as with yacc's `%left`, `%right`, `%nonassoc` and `%prec`. Conflicts resolved this
+//! way are not reported as conflicts, but each one is recorded as a [`Resolution`].
+//!
+//! The [`recovery`] module extends the parser to continue past syntax errors, either with yacc's
+//! error productions or by repairing the input.
+//!
+//! [`Automaton`]: ./automaton/struct.Automaton.html
+//! [`Slr`]: ./enum.Method.html#variant.Slr
+//! [`Canonical`]: ./enum.Method.html#variant.Canonical
+//! [`Lalr`]: ./enum.Method.html#variant.Lalr
+//! [`len`]: ./struct.Table.html#method.len
+//! [`Conflict`]: ./struct.Conflict.html
+//! [`Precedence`]: ./struct.Precedence.html
+//! [`Resolution`]: ./struct.Resolution.html
+//! [`recovery`]: ./recovery/index.html
+//!
+//! ## Example
+//!
+//! ```
+//! # use dragon::{doctest::*, grammar::*, parse::lr::*, token::*};
+//! let grammar: Grammar = "
+//!     E -> E + T | T
+//!     T -> T * F | F
+//!     F -> ( E ) | id
+//! "
+//! .parse()
+//! .unwrap();
+//!
+//! let table = Table::new(&grammar, Method::Slr);
+//! assert_eq!(table.len(), 12);
+//! assert!(table.conflicts().is_empty());
+//!
+//! let tree = table.parse(lex::<Lex>("a + b * (c + d)")).unwrap();
+//! assert_eq!(
+//!     tree.display(&grammar).to_string(),
+//!     "(E (E (T (F a))) + (T (T (F b)) * (F ( (E (E (T (F c))) + (T (F d))) ))))",
+//! );
+//!
+//! let error = table.parse(lex::<Lex>("a + * b")).unwrap_err();
+//! assert_eq!(error.message(&grammar), "unexpected `*`, expected one of: ( id");
+//!
+//! let ambiguous: Grammar = "E -> E + E | id".parse().unwrap();
+//! let table = Table::new(&ambiguous, Method::Slr);
+//! assert_eq!(
+//!     table.conflicts()[0].message(&table),
+//!     "state 4: shift/reduce conflict on `+`\n\
+//!      \x20 shift to state 3\n\
+//!      \x20 reduce by `E -> E + E`\n\
+//!      \x20 items:\n\
+//!      \x20   E -> E · + E\n\
+//!      \x20   E -> E + E ·",
+//! );
+//! ```
+//!
+//! ## Example: comparing methods
+//!
+//! ```
+//! # use dragon::{grammar::*, parse::lr::*};
+//! // not SLR(1): the `=` in FOLLOW(R) causes a shift/reduce conflict in the SLR table
+//! let grammar: Grammar = "
+//!     S -> L = R | R
+//!     L -> * R | id
+//!     R -> L
+//! "
+//! .parse()
+//! .unwrap();
+//!
+//! let slr = Table::new(&grammar, Method::Slr);
+//! let lalr = Table::new(&grammar, Method::Lalr);
+//! let canonical = Table::new(&grammar, Method::Canonical);
+//!
+//! assert_eq!((slr.len(), lalr.len(), canonical.len()), (10, 10, 14));
+//! assert_eq!(slr.conflicts().len(), 1);
+//! assert!(lalr.conflicts().is_empty() && canonical.conflicts().is_empty());
+//!
+//! // LR(1), but merging states with the same core introduces a reduce/reduce conflict
+//! let grammar: Grammar = "
+//!     S -> a A d | b B d | a B e | b A e
+//!     A -> c
+//!     B -> c
+//! "
+//! .parse()
+//! .unwrap();
+//!
+//! assert!(Table::new(&grammar, Method::Canonical).conflicts().is_empty());
+//!
+//! let lalr = Table::new(&grammar, Method::Lalr);
+//! let conflict = &lalr.conflicts()[0];
+//! assert!(conflict.introduced_by_merging);
+//! assert_eq!(
+//!     conflict.message(&lalr).lines().next().unwrap(),
+//!     "state 6: reduce/reduce conflict on `d`, introduced by merging LR(1) states 6 and 9",
+//! );
+//! ```
+//!
+//! ## Example: precedence declarations
+//!
+//! ```
+//! # use dragon::{doctest::*, grammar::*, parse::lr::*, token::*};
+//! let grammar: Grammar = "
+//!     E -> E + E | E - E | E * E | E / E | E < E | - E | ( E ) | id
+//! "
+//! .parse()
+//! .unwrap();
+//!
+//! let precedence = Precedence::new()
+//!     .nonassoc(&["<"])
+//!     .left(&["+", "-"])
+//!     .left(&["*", "/"])
+//!     .right(&["UMINUS"])
+//!     .prec(5, "UMINUS");
+//!
+//! let table = Table::with_precedence(&grammar, Method::Lalr, &precedence);
+//! assert!(table.conflicts().is_empty());
+//! assert_eq!(table.resolutions().len(), 30);
+//! assert_eq!(
+//!     table.resolutions()[0].message(&table),
+//!     "state 10: conflict between `E -> - E` and `+` resolved as reduce \
+//!      (`E -> - E` has higher precedence)",
+//! );
+//!
+//! let tree = table.parse(lex::<Lex>("-a - b * c - d")).unwrap();
+//! assert_eq!(
+//!     tree.display(&grammar).to_string(),
+//!     "(E (E (E - (E a)) - (E (E b) * (E c))) - (E d))",
+//! );
+//!
+//! let error = table.parse(lex::<Lex>("a < b < c")).unwrap_err();
+//! assert_eq!(error.message(&grammar), "unexpected `<`, expected one of: $ + - * / )");
+//! ```
+
+pub mod automaton;
+pub mod recovery;
+
+use {
+    self::automaton::Automaton,
+    super::{Assoc, Error, Input, Tree},
+    crate::{
+        grammar::{Grammar, Sets, Symbol, Terminal},
+        token::TokenResult,
+    },
+};
+
+/// Ways of building the ACTION table.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Method {
+    /// Simple LR: states are sets of LR(0) items, and reductions are made on FOLLOW sets.
+    Slr,
+    /// Lookahead LR: states are sets of LR(1) items with the same core merged together.
+    Lalr,
+    /// Canonical LR: states are sets of LR(1) items.
+    Canonical,
+}
+
+/// An entry of the ACTION table.
+///
+/// Actions are ordered by preference when resolving conflicts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Action {
+    /// Push the next token and move to a state.
+    Shift(usize),
+    /// Replace the right side of a production on top of the stack with its left side.
+    Reduce(usize),
+    /// The input is a sentence of the grammar.
+    Accept,
+}
+
+/// More than one action belongs in an entry of the ACTION table.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Conflict {
+    /// The state (row) of the entry.
+    pub state: usize,
+    /// The lookahead terminal (column) of the entry.
+    pub terminal: usize,
+    /// Every action that belongs in the entry, in order of preference.
+    pub actions: Vec<Action>,
+    /// Whether this is a reduce/reduce conflict in an LALR(1) table that none of the LR(1) states
+    /// merged into its state had.
+    pub introduced_by_merging: bool,
+}
+
+impl Conflict {
+    /// Whether one of the actions is a shift.
+    pub fn is_shift_reduce(&self) -> bool {
+        self.actions.iter().any(|a| matches!(a, Action::Shift(_)))
+    }
+
+    /// Describe this conflict, listing the items of the state it occurs in.
+    pub fn message(&self, table: &Table) -> String {
+        let automaton = table.automaton();
+        let grammar = automaton.grammar();
+        let mut out = format!(
+            "state {}: {} conflict on `{}`",
+            self.state,
+            if self.is_shift_reduce() {
+                "shift/reduce"
+            } else {
+                "reduce/reduce"
+            },
+            grammar.terminals()[self.terminal]
+        );
+
+        if self.introduced_by_merging {
+            let merged = automaton
+                .merged(self.state)
+                .iter()
+                .map(|s| s.to_string())
+                .collect::<Vec<_>>();
+            out.push_str(", introduced by merging LR(1) states ");
+            out.push_str(&merged.join(" and "));
+        }
+
+        for action in &self.actions {
+            out.push_str("\n  ");
+            out.push_str(&show_action(grammar, *action));
+        }
+
+        out.push_str("\n  items:");
+        let state = &automaton.states()[self.state];
+        for (item, lookaheads) in &state.items[..state.kernel] {
+            out.push_str("\n    ");
+            out.push_str(&automaton.show_item(item, lookaheads));
+        }
+
+        out
+    }
+}
+
+fn show_action(grammar: &Grammar, action: Action) -> String {
+    match action {
+        Action::Shift(s) => format!("shift to state {}", s),
+        Action::Reduce(p) => format!("reduce by `{}`", grammar.show_production(p)),
+        Action::Accept => "accept".to_string(),
+    }
+}
+
+/// Precedence and associativity declarations for terminals, used to resolve shift/reduce
+/// conflicts.
+///
+/// Each declaration introduces a new precedence level, higher than all those before it. A
+/// production takes the precedence of the last terminal on its right side, unless it is given
+/// the precedence of another terminal with [`prec`](#method.prec). Terminals are identified by
+/// name, so a declared terminal (like `UMINUS`) need not appear in the grammar at all.
+///
+/// A shift/reduce conflict on a terminal is resolved when both the terminal and the production
+/// have a precedence. If one is higher, its action is chosen: shift for the terminal, reduce for
+/// the production. If they are equal, associativity decides: reduce for `%left`, shift for
+/// `%right`, and neither (a syntax error) for `%nonassoc`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Precedence {
+    levels: Vec<(Assoc, Vec<String>)>,
+    overrides: Vec<(usize, String)>,
+}
+
+impl Precedence {
+    /// Create an empty set of declarations.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Declare a precedence level of left-associative terminals, like `%left`.
+    pub fn left(self, terminals: &[&str]) -> Self {
+        self.level(Assoc::Left, terminals)
+    }
+
+    /// Declare a precedence level of right-associative terminals, like `%right`.
+    pub fn right(self, terminals: &[&str]) -> Self {
+        self.level(Assoc::Right, terminals)
+    }
+
+    /// Declare a precedence level of non-associative terminals, like `%nonassoc`.
+    pub fn nonassoc(self, terminals: &[&str]) -> Self {
+        self.level(Assoc::Neither, terminals)
+    }
+
+    /// Declare a precedence level with any associativity.
+    pub fn level(mut self, assoc: Assoc, terminals: &[&str]) -> Self {
+        self.levels
+            .push((assoc, terminals.iter().map(|t| t.to_string()).collect()));
+        self
+    }
+
+    /// Give a production (by index) the precedence of a terminal, like `%prec`.
+    pub fn prec(mut self, production: usize, terminal: &str) -> Self {
+        self.overrides.push((production, terminal.to_string()));
+        self
+    }
+
+    /// The precedence level (higher binds tighter) and associativity of a terminal.
+    pub fn of_terminal(&self, name: &str) -> Option<(usize, Assoc)> {
+        self.levels
+            .iter()
+            .enumerate()
+            .find(|(_, (_, names))| names.iter().any(|n| n == name))
+            .map(|(level, (assoc, _))| (level + 1, *assoc))
+    }
+
+    /// The precedence level and associativity of a production.
+    pub fn of_production(&self, grammar: &Grammar, production: usize) -> Option<(usize, Assoc)> {
+        if let Some((_, name)) = self.overrides.iter().rev().find(|(p, _)| *p == production) {
+            return self.of_terminal(name);
+        }
+
+        grammar.productions()[production]
+            .rhs
+            .iter()
+            .rev()
+            .find_map(|&symbol| match symbol {
+                Symbol::Terminal(t) => Some(t),
+                Symbol::Nonterminal(_) => None,
+            })
+            .and_then(|t| self.of_terminal(&grammar.terminals()[t]))
+    }
+
+    fn resolve(
+        &self,
+        grammar: &Grammar,
+        state: usize,
+        terminal: usize,
+        cell: &mut Vec<Action>,
+        resolutions: &mut Vec<Resolution>,
+    ) {
+        let shift = match cell.first() {
+            Some(&Action::Shift(s)) if cell.len() > 1 => s,
+            _ => return,
+        };
+
+        let token = match self.of_terminal(&grammar.terminals()[terminal]) {
+            Some(token) => token,
+            None => return,
+        };
+
+        let mut keep_shift = true;
+        let mut kept = Vec::new();
+
+        for &action in &cell[1..] {
+            let production = match action {
+                Action::Reduce(p) => p,
+                _ => {
+                    kept.push(action);
+                    continue;
+                }
+            };
+
+            let rule = match self.of_production(grammar, production) {
+                Some(rule) => rule,
+                None => {
+                    kept.push(action);
+                    continue;
+                }
+            };
+
+            let (outcome, reason) = match rule.0.cmp(&token.0) {
+                std::cmp::Ordering::Greater => (Outcome::Reduce, Reason::Production),
+                std::cmp::Ordering::Less => (Outcome::Shift, Reason::Terminal),
+                std::cmp::Ordering::Equal => match token.1 {
+                    Assoc::Left => (Outcome::Reduce, Reason::Assoc(Assoc::Left)),
+                    Assoc::Right => (Outcome::Shift, Reason::Assoc(Assoc::Right)),
+                    Assoc::Neither => (Outcome::Error, Reason::Assoc(Assoc::Neither)),
+                },
+            };
+
+            match outcome {
+                Outcome::Reduce => {
+                    keep_shift = false;
+                    kept.push(action);
+                }
+                Outcome::Error => keep_shift = false,
+                Outcome::Shift => (),
+            }
+
+            resolutions.push(Resolution {
+                state,
+                terminal,
+                shift,
+                production,
+                outcome,
+                reason,
+            });
+        }
+
+        cell.clear();
+        if keep_shift {
+            cell.push(Action::Shift(shift));
+        }
+        cell.extend(kept);
+    }
+}
+
+/// The action chosen when a shift/reduce conflict is resolved by precedence.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Shift,
+    Reduce,
+    /// Neither: the terminal is a syntax error in this state.
+    Error,
+}
+
+/// Why a shift/reduce conflict was resolved the way it was.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reason {
+    /// The terminal has a higher precedence than the production.
+    Terminal,
+    /// The production has a higher precedence than the terminal.
+    Production,
+    /// Both have the same precedence, with this associativity.
+    Assoc(Assoc),
+}
+
+/// A shift/reduce conflict that was resolved by precedence declarations.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Resolution {
+    /// The state (row) of the entry.
+    pub state: usize,
+    /// The lookahead terminal (column) of the entry.
+    pub terminal: usize,
+    /// The state that a shift would have moved to.
+    pub shift: usize,
+    /// The production that a reduction would have been by.
+    pub production: usize,
+    /// The action that was chosen.
+    pub outcome: Outcome,
+    /// Why it was chosen.
+    pub reason: Reason,
+}
+
+impl Resolution {
+    /// Describe how this conflict was resolved.
+    pub fn message(&self, table: &Table) -> String {
+        let grammar = table.grammar();
+        let production = grammar.show_production(self.production);
+        let terminal = &grammar.terminals()[self.terminal];
+
+        format!(
+            "state {}: conflict between `{}` and `{}` resolved as {} ({})",
+            self.state,
+            production,
+            terminal,
+            match self.outcome {
+                Outcome::Shift => "shift",
+                Outcome::Reduce => "reduce",
+                Outcome::Error => "an error",
+            },
+            match self.reason {
+                Reason::Terminal => format!("`{}` has higher precedence", terminal),
+                Reason::Production => format!("`{}` has higher precedence", production),
+                Reason::Assoc(Assoc::Left) => "%left".to_string(),
+                Reason::Assoc(Assoc::Right) => "%right".to_string(),
+                Reason::Assoc(Assoc::Neither) => "%nonassoc".to_string(),
+            }
+        )
+    }
+}
+
+/// ACTION and GOTO tables for an LR parser.
+#[derive(Clone, Debug)]
+pub struct Table {
+    automaton: Automaton,
+    actions: Vec<Vec<Vec<Action>>>,
+    gotos: Vec<Vec<Option<usize>>>,
+    conflicts: Vec<Conflict>,
+    resolutions: Vec<Resolution>,
+}
+
+impl Table {
+    /// Build the tables for a grammar.
+    pub fn new(grammar: &Grammar, method: Method) -> Self {
+        Self::with_precedence(grammar, method, &Precedence::new())
+    }
+
+    /// Build the tables for a grammar, resolving shift/reduce conflicts with precedence
+    /// declarations.
+    pub fn with_precedence(grammar: &Grammar, method: Method, precedence: &Precedence) -> Self {
+        let automaton = match method {
+            Method::Slr => Automaton::lr0(grammar),
+            Method::Lalr => Automaton::lalr1(grammar),
+            Method::Canonical => Automaton::lr1(grammar),
+        };
+
+        let augmented = automaton.grammar();
+        let sets = Sets::new(augmented);
+        let accept = augmented.productions().len() - 1;
+        let terminals = augmented.terminals().len();
+
+        let mut actions = Vec::new();
+        let mut gotos = Vec::new();
+        let mut resolutions = Vec::new();
+
+        for (index, state) in automaton.states().iter().enumerate() {
+            let mut row = vec![Vec::new(); terminals];
+            let mut goto_row = vec![None; augmented.nonterminals().len()];
+
+            for (&symbol, &target) in &state.goto {
+                match symbol {
+                    Symbol::Terminal(t) => row[t].push(Action::Shift(target)),
+                    Symbol::Nonterminal(n) => goto_row[n] = Some(target),
+                }
+            }
+
+            for (item, lookaheads) in &state.items {
+                let production = &augmented.productions()[item.production];
+                if item.dot < production.rhs.len() {
+                    continue;
+                }
+
+                if item.production == accept {
+                    row[Grammar::END].push(Action::Accept);
+                    continue;
+                }
+
+                let follow = match method {
+                    Method::Slr => sets.follow(production.lhs),
+                    Method::Lalr | Method::Canonical => lookaheads,
+                };
+
+                for &t in follow {
+                    let reduce = Action::Reduce(item.production);
+                    if !row[t].contains(&reduce) {
+                        row[t].push(reduce);
+                    }
+                }
+            }
+
+            for (terminal, cell) in row.iter_mut().enumerate() {
+                cell.sort();
+                precedence.resolve(augmented, index, terminal, cell, &mut resolutions);
+            }
+
+            actions.push(row);
+            gotos.push(goto_row);
+        }
+
+        let conflicts = conflicts(&automaton, &actions);
+
+        Self {
+            automaton,
+            actions,
+            gotos,
+            conflicts,
+            resolutions,
+        }
+    }
+
+    /// The augmented grammar the tables were built for.
+    pub fn grammar(&self) -> &Grammar {
+        self.automaton.grammar()
+    }
+
+    /// The automaton whose states are the rows of the tables.
+    pub fn automaton(&self) -> &Automaton {
+        &self.automaton
+    }
+
+    /// The number of states.
+    pub fn len(&self) -> usize {
+        self.actions.len()
+    }
+
+    /// Whether the tables have no states. This is never the case.
+    pub fn is_empty(&self) -> bool {
+        self.actions.is_empty()
+    }
+
+    /// Every action in an entry of the ACTION table, in order of preference.
+    pub fn actions(&self, state: usize, terminal: usize) -> &[Action] {
+        &self.actions[state][terminal]
+    }
+
+    /// The action the parser takes in a state when a terminal is next in the input.
+    pub fn action(&self, state: usize, terminal: usize) -> Option<Action> {
+        self.actions[state][terminal].first().copied()
+    }
+
+    /// The state to move to after reducing to a nonterminal, in a state.
+    pub fn goto(&self, state: usize, nonterminal: usize) -> Option<usize> {
+        self.gotos[state][nonterminal]
+    }
+
+    /// The entries of the ACTION table with more than one action.
+    pub fn conflicts(&self) -> &[Conflict] {
+        &self.conflicts
+    }
+
+    /// The shift/reduce conflicts that were resolved by precedence declarations.
+    pub fn resolutions(&self) -> &[Resolution] {
+        &self.resolutions
+    }
+
+    /// The terminals with an action in a state.
+    pub fn expected(&self, state: usize) -> Vec<usize> {
+        (0..self.actions[state].len())
+            .filter(|&t| !self.actions[state][t].is_empty())
+            .collect()
+    }
+
+    /// Parse a stream of tokens.
+    pub fn parse<'src, T, E, I>(&self, tokens: I) -> Result<Tree<'src>, Error<'src, E>>
+    where
+        T: Terminal,
+        I: IntoIterator<Item = TokenResult<'src, T, E>>,
+    {
+        let mut input = Input::new(self.grammar(), tokens.into_iter());
+        let mut states = vec![0];
+        let mut trees = Vec::new();
+        let mut lookahead = input.next_token()?;
+
+        loop {
+            let top = *states.last().unwrap();
+
+            match self.action(top, lookahead.terminal) {
+                Some(Action::Shift(state)) => {
+                    states.push(state);
+                    trees.push(Tree::Leaf(lookahead));
+                    lookahead = input.next_token()?;
+                }
+                Some(Action::Reduce(production)) => {
+                    let node = self.reduce(production, &mut states, &mut trees);
+                    trees.push(node);
+                }
+                Some(Action::Accept) => return Ok(trees.pop().unwrap()),
+                None => return Err(Error::Unexpected(lookahead, self.expected(top))),
+            }
+        }
+    }
+
+    /// Pop the right side of a production, and push the state for its left side.
+    fn reduce<'src>(
+        &self,
+        production: usize,
+        states: &mut Vec<usize>,
+        trees: &mut Vec<Tree<'src>>,
+    ) -> Tree<'src> {
+        let production_ref = &self.grammar().productions()[production];
+        let len = production_ref.rhs.len();
+
+        states.truncate(states.len() - len);
+        let children = trees.split_off(trees.len() - len);
+
+        let top = *states.last().unwrap();
+        states.push(self.gotos[top][production_ref.lhs].unwrap());
+
+        Tree::Node(production, children)
+    }
+}
+
+fn conflicts(automaton: &Automaton, actions: &[Vec<Vec<Action>>]) -> Vec<Conflict> {
+    let mut conflicts = Vec::new();
+
+    for (state, row) in actions.iter().enumerate() {
+        for (terminal, cell) in row.iter().enumerate() {
+            if cell.len() > 1 {
+                let reduces = cell
+                    .iter()
+                    .filter(|a| matches!(a, Action::Reduce(_)))
+                    .count();
+
+                let introduced_by_merging = match automaton.canonical() {
+                    Some(canonical) if reduces > 1 => {
+                        automaton.merged(state).iter().all(|&source| {
+                            canonical.states()[source]
+                                .items
+                                .iter()
+                                .filter(|(item, lookaheads)| {
+                                    let rhs =
+                                        &canonical.grammar().productions()[item.production].rhs;
+                                    item.dot == rhs.len() && lookaheads.contains(&terminal)
+                                })
+                                .count()
+                                < 2
+                        })
+                    }
+                    _ => false,
+                };
+
+                conflicts.push(Conflict {
+                    state,
+                    terminal,
+                    actions: cell.clone(),
+                    introduced_by_merging,
+                });
+            }
+        }
+    }
+
+    conflicts
+}
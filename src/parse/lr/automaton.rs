@@ -0,0 +1,315 @@
+//! Sets of LR items, and the automata whose states they are.
+//!
+//! An *item* is a production with a dot somewhere on its right side, marking how much of the
+//! production has been seen. The *closure* of a set of items adds an item `B -> ·γ` for every
+//! item with the dot in front of a nonterminal `B`, and the *goto* on a symbol `X` moves the dot
+//! over `X` in every item where it is possible. Starting from the closure of the item for the
+//! augmented start production `S' -> ·S`, repeated gotos produce the *canonical collection* of
+//! item sets: the states of an automaton that recognizes viable prefixes of the grammar.
+//!
+//! LR(1) items also carry a lookahead: a terminal that may follow the production once it has
+//! been recognized. The canonical collection of sets of LR(1) items can be much larger than the
+//! LR(0) collection, because states with the same items (the same *core*) are kept apart when
+//! their lookaheads differ. The LALR(1) collection merges the states of the LR(1) collection that
+//! have the same core, taking the union of their lookaheads. It has as many states as the LR(0)
+//! collection, but merging can introduce reduce/reduce conflicts that the LR(1) collection did
+//! not have.
+
+use {
+    crate::grammar::{Grammar, Sets, Symbol},
+    std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque},
+};
+
+/// A production with a position in its right side.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Item {
+    /// Index of the production.
+    pub production: usize,
+    /// Number of symbols on the right side before the dot.
+    pub dot: usize,
+}
+
+/// A state of an LR automaton.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ItemSet {
+    /// The items of the state, kernel items first, each with the set of terminals that may follow
+    /// it (always empty in an LR(0) automaton).
+    pub items: Vec<(Item, BTreeSet<usize>)>,
+    /// The number of kernel items: the initial item, and those whose dot is not at the start.
+    pub kernel: usize,
+    /// Transitions to other states, by grammar symbol.
+    pub goto: BTreeMap<Symbol, usize>,
+}
+
+/// The canonical collection of sets of items for a grammar.
+#[derive(Clone, Debug)]
+pub struct Automaton {
+    grammar: Grammar,
+    states: Vec<ItemSet>,
+    canonical: Option<Box<Automaton>>,
+    merged: Vec<Vec<usize>>,
+}
+
+type Kernel = BTreeMap<Item, BTreeSet<usize>>;
+
+impl Automaton {
+    /// Build the canonical collection of sets of LR(0) items.
+    pub fn lr0(grammar: &Grammar) -> Self {
+        Self::build(augment(grammar), |_, _, _| BTreeSet::new())
+    }
+
+    /// Build the canonical collection of sets of LR(1) items.
+    pub fn lr1(grammar: &Grammar) -> Self {
+        let grammar = augment(grammar);
+        let sets = Sets::new(&grammar);
+
+        Self::build(grammar, |_, rest, follow| {
+            let (mut first, nullable) = sets.first_of(rest);
+            if nullable {
+                first.extend(follow.iter().copied());
+            }
+            first
+        })
+    }
+
+    /// Build the collection of sets of LALR(1) items, by merging the states of the canonical
+    /// LR(1) collection that have the same core.
+    pub fn lalr1(grammar: &Grammar) -> Self {
+        let canonical = Self::lr1(grammar);
+        let mut cores = HashMap::new();
+        let mut merged: Vec<Vec<usize>> = Vec::new();
+        let mut renumber = Vec::new();
+
+        for state in &canonical.states {
+            let core = state
+                .items
+                .iter()
+                .map(|(item, _)| *item)
+                .collect::<Vec<_>>();
+            let next = merged.len();
+            let index = *cores.entry(core).or_insert_with(|| {
+                merged.push(Vec::new());
+                next
+            });
+
+            merged[index].push(renumber.len());
+            renumber.push(index);
+        }
+
+        let states = merged
+            .iter()
+            .map(|sources| {
+                let mut state = canonical.states[sources[0]].clone();
+
+                for &source in &sources[1..] {
+                    for (to, (_, from)) in
+                        state.items.iter_mut().zip(&canonical.states[source].items)
+                    {
+                        to.1.extend(from.iter().copied());
+                    }
+                }
+
+                for target in state.goto.values_mut() {
+                    *target = renumber[*target];
+                }
+
+                state
+            })
+            .collect();
+
+        Self {
+            grammar: canonical.grammar.clone(),
+            states,
+            canonical: Some(Box::new(canonical)),
+            merged,
+        }
+    }
+
+    fn build<F>(grammar: Grammar, lookaheads: F) -> Self
+    where
+        F: Fn(&Grammar, &[Symbol], &BTreeSet<usize>) -> BTreeSet<usize>,
+    {
+        let start = grammar.productions().len() - 1;
+        let mut initial = Kernel::new();
+        initial.insert(
+            Item {
+                production: start,
+                dot: 0,
+            },
+            lookaheads(&grammar, &[], &[Grammar::END].iter().copied().collect()),
+        );
+
+        let mut states: Vec<ItemSet> = Vec::new();
+        let mut index = HashMap::new();
+        let mut pending = VecDeque::from(vec![initial.clone()]);
+        index.insert(initial, 0);
+
+        while let Some(kernel) = pending.pop_front() {
+            let items = closure(&grammar, &kernel, &lookaheads);
+            let mut gotos = BTreeMap::new();
+            let mut order = Vec::new();
+
+            for (item, follow) in &items {
+                if let Some(&symbol) = next_symbol(&grammar, item) {
+                    let advanced = Item {
+                        production: item.production,
+                        dot: item.dot + 1,
+                    };
+
+                    if !gotos.contains_key(&symbol) {
+                        order.push(symbol);
+                    }
+
+                    gotos
+                        .entry(symbol)
+                        .or_insert_with(Kernel::new)
+                        .entry(advanced)
+                        .or_insert_with(BTreeSet::new)
+                        .extend(follow.iter().copied());
+                }
+            }
+
+            let mut goto = BTreeMap::new();
+            for symbol in order {
+                let target = gotos.remove(&symbol).unwrap();
+                let next = index.len();
+                let target = *index.entry(target.clone()).or_insert_with(|| {
+                    pending.push_back(target);
+                    next
+                });
+                goto.insert(symbol, target);
+            }
+
+            states.push(ItemSet {
+                kernel: kernel.len(),
+                items,
+                goto,
+            });
+        }
+
+        Self {
+            grammar,
+            states,
+            canonical: None,
+            merged: Vec::new(),
+        }
+    }
+
+    /// The augmented grammar: the grammar the automaton was built for, with an additional start
+    /// symbol whose only production (the last one) derives the original start symbol.
+    pub fn grammar(&self) -> &Grammar {
+        &self.grammar
+    }
+
+    /// The states of the automaton. State 0 is the initial state.
+    pub fn states(&self) -> &[ItemSet] {
+        &self.states
+    }
+
+    /// For an LALR(1) collection, the canonical LR(1) collection it was built from.
+    pub fn canonical(&self) -> Option<&Automaton> {
+        self.canonical.as_deref()
+    }
+
+    /// For an LALR(1) collection, the states of the canonical LR(1) collection that were merged to
+    /// form a state. Empty for other collections.
+    pub fn merged(&self, state: usize) -> &[usize] {
+        self.merged.get(state).map_or(&[], |m| m)
+    }
+
+    /// Render an item, with its lookaheads if it has any.
+    pub fn show_item(&self, item: &Item, lookaheads: &BTreeSet<usize>) -> String {
+        let production = &self.grammar.productions()[item.production];
+        let mut out = format!("{} ->", self.grammar.nonterminals()[production.lhs]);
+
+        for (i, &symbol) in production.rhs.iter().enumerate() {
+            if i == item.dot {
+                out.push_str(" ·");
+            }
+            out.push(' ');
+            out.push_str(self.grammar.name(symbol));
+        }
+
+        if item.dot == production.rhs.len() {
+            out.push_str(" ·");
+        }
+
+        if !lookaheads.is_empty() {
+            let names = lookaheads
+                .iter()
+                .map(|&t| self.grammar.terminals()[t].as_str())
+                .collect::<Vec<_>>();
+            out.push_str(", ");
+            out.push_str(&names.join("/"));
+        }
+
+        out
+    }
+}
+
+/// Add a new start symbol to a grammar, with a production (placed last) that derives the old one.
+pub(crate) fn augment(grammar: &Grammar) -> Grammar {
+    let mut augmented = grammar.clone();
+    let mut name = format!("{}'", grammar.nonterminals()[grammar.start()]);
+
+    while augmented.nonterminal(&name).is_some() {
+        name.push('\'');
+    }
+
+    let start = augmented.add_nonterminal(&name);
+    augmented.add_production(start, vec![Symbol::Nonterminal(grammar.start())]);
+    augmented.set_start(start);
+    augmented
+}
+
+fn next_symbol<'g>(grammar: &'g Grammar, item: &Item) -> Option<&'g Symbol> {
+    grammar.productions()[item.production].rhs.get(item.dot)
+}
+
+fn closure<F>(grammar: &Grammar, kernel: &Kernel, lookaheads: &F) -> Vec<(Item, BTreeSet<usize>)>
+where
+    F: Fn(&Grammar, &[Symbol], &BTreeSet<usize>) -> BTreeSet<usize>,
+{
+    let mut items = kernel
+        .iter()
+        .map(|(item, follow)| (*item, follow.clone()))
+        .collect::<Vec<_>>();
+    let mut index = items
+        .iter()
+        .enumerate()
+        .map(|(i, (item, _))| (*item, i))
+        .collect::<HashMap<_, _>>();
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+
+        for i in 0..items.len() {
+            let (item, follow) = items[i].clone();
+            let production = &grammar.productions()[item.production];
+
+            if let Some(&Symbol::Nonterminal(n)) = production.rhs.get(item.dot) {
+                let new = lookaheads(grammar, &production.rhs[item.dot + 1..], &follow);
+
+                for (p, _) in grammar.productions_of(n) {
+                    let added = Item {
+                        production: p,
+                        dot: 0,
+                    };
+
+                    let at = *index.entry(added).or_insert_with(|| {
+                        items.push((added, BTreeSet::new()));
+                        changed = true;
+                        items.len() - 1
+                    });
+
+                    for &t in &new {
+                        changed |= items[at].1.insert(t);
+                    }
+                }
+            }
+        }
+    }
+
+    items
+}
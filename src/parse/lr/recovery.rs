@@ -0,0 +1,553 @@
+//! Recovering from syntax errors in LR parsers.
+//!
+//! Recovery is driven by *error productions*, as in yacc: productions with the special terminal
+//! [`ERROR`] on their right side, like `stmt -> error ;`. When the parser detects an error, it
+//! pops states until it reaches one that can shift `error`, shifts it, and then discards input
+//! tokens until one of them can follow. Errors detected before three more tokens have been
+//! shifted are not reported, since they are likely to be caused by the last recovery.
+//!
+//! The parser can also try to *repair* the input at the point of an error, by inserting,
+//! deleting or replacing tokens. Repairs are tried in order of cost (the number of tokens
+//! changed), and the first one that lets the parser shift the next few tokens is applied. Only
+//! when no repair is found does the parser fall back to error productions.
+//!
+//! [`ERROR`]: ./constant.ERROR.html
+//!
+//! ## Example
+//!
+//! ```
+//! # use dragon::{doctest::*, grammar::*, parse::lr::*, token::*};
+//! let grammar: Grammar = "
+//!     L -> L S | S
+//!     S -> E ; | error ;
+//!     E -> E + E | E * E | ( E ) | id
+//! "
+//! .parse()
+//! .unwrap();
+//!
+//! let precedence = Precedence::new().left(&["+"]).left(&["*"]);
+//! let table = Table::with_precedence(&grammar, Method::Lalr, &precedence);
+//!
+//! let (tree, diagnostics) = table.parse_with_recovery(lex::<Lex>("a + ; b * c ; d e f ;"));
+//! assert_eq!(
+//!     diagnostics
+//!         .iter()
+//!         .map(|d| d.message(&grammar))
+//!         .collect::<Vec<_>>(),
+//!     [
+//!         "unexpected `;`, expected one of: ( id; resumed at an error production",
+//!         "unexpected `e`, expected one of: ; + * ); \
+//!          resumed at an error production after discarding `e f`",
+//!     ],
+//! );
+//! assert_eq!(
+//!     tree.unwrap().display(&grammar).to_string(),
+//!     "(L (L (L (S error ;)) (S (E (E b) * (E c)) ;)) (S error ;))",
+//! );
+//!
+//! let (tree, diagnostics) = table.parse_with_repair(lex::<Lex>("a + * b ; ( c ; d"), 2);
+//! assert_eq!(
+//!     diagnostics
+//!         .iter()
+//!         .map(|d| d.message(&grammar))
+//!         .collect::<Vec<_>>(),
+//!     [
+//!         "unexpected `*`, expected one of: ( id; inserting `id` gives `id * b ;`",
+//!         "unexpected `;`, expected one of: + * ); inserting `)` gives `) ; d`",
+//!         "unexpected end of input, expected one of: ; + * ); inserting `;` gives `;`",
+//!     ],
+//! );
+//! assert_eq!(tree.unwrap().text(), "a + * b ; ( c ; d");
+//!
+//! let (tree, diagnostics) = table.parse_with_recovery(lex::<Lex>("a ; ! b"));
+//! assert_eq!(
+//!     diagnostics[0].message(&grammar),
+//!     "could not recognize a token at `!` ('!'); skipped",
+//! );
+//! assert!(tree.is_some());
+//! ```
+
+use {
+    super::{Action, Table},
+    crate::{
+        grammar::{Grammar, Symbol, Terminal},
+        parse::{Error, Input, Token, Tree},
+        token::TokenResult,
+    },
+    std::collections::VecDeque,
+};
+
+/// The name of the terminal that stands for erroneous input in error productions.
+pub const ERROR: &str = "error";
+
+/// The number of tokens that must be shifted after recovering before errors are reported again.
+const SHIFTS_TO_RECOVER: usize = 3;
+
+/// The number of tokens following a repair that the parser must be able to shift.
+const REPAIR_WINDOW: usize = 3;
+
+/// A change to the input made to repair a syntax error.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Edit<'src> {
+    /// Insert a terminal before the next token.
+    Insert(usize),
+    /// Delete the next token.
+    Delete(Token<'src>),
+    /// Replace the next token with a terminal.
+    Replace(Token<'src>, usize),
+}
+
+/// How the parser got past a syntax error.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Recovery<'src> {
+    /// The input was edited. The tokens that follow the repair point are listed, with inserted
+    /// terminals having an empty lexeme.
+    Repaired(Vec<Edit<'src>>, Vec<Token<'src>>),
+    /// An `error` token was shifted, after which these tokens were discarded.
+    Resumed(Vec<Token<'src>>),
+    /// The token that could not be recognized was skipped.
+    Skipped,
+    /// The parser could not recover, and stopped.
+    Failed,
+}
+
+/// An error, and how the parser recovered from it.
+#[derive(Debug, PartialEq)]
+pub struct Diagnostic<'src, E> {
+    pub error: Error<'src, E>,
+    pub recovery: Recovery<'src>,
+}
+
+impl<'src, E: std::fmt::Debug> Diagnostic<'src, E> {
+    /// Describe the error and its recovery, using the names of terminals from a grammar.
+    pub fn message(&self, grammar: &Grammar) -> String {
+        let mut out = self.error.message(grammar);
+        let show = |token: &Token| show_token(grammar, token);
+
+        match &self.recovery {
+            Recovery::Repaired(edits, tokens) => {
+                let edits = edits
+                    .iter()
+                    .map(|edit| match edit {
+                        Edit::Insert(t) => format!("inserting `{}`", grammar.terminals()[*t]),
+                        Edit::Delete(token) => format!("deleting `{}`", show(token)),
+                        Edit::Replace(token, t) => format!(
+                            "replacing `{}` with `{}`",
+                            show(token),
+                            grammar.terminals()[*t]
+                        ),
+                    })
+                    .collect::<Vec<_>>();
+                let tokens = tokens.iter().map(show).collect::<Vec<_>>();
+                out.push_str(&format!(
+                    "; {} gives `{}`",
+                    edits.join(" and "),
+                    tokens.join(" ")
+                ));
+            }
+            Recovery::Resumed(discarded) if discarded.is_empty() => {
+                out.push_str("; resumed at an error production")
+            }
+            Recovery::Resumed(discarded) => {
+                let discarded = discarded.iter().map(show).collect::<Vec<_>>();
+                out.push_str(&format!(
+                    "; resumed at an error production after discarding `{}`",
+                    discarded.join(" ")
+                ));
+            }
+            Recovery::Skipped => out.push_str("; skipped"),
+            Recovery::Failed => out.push_str("; could not recover"),
+        }
+
+        out
+    }
+}
+
+fn show_token(grammar: &Grammar, token: &Token) -> String {
+    if token.lexeme.is_empty() {
+        grammar.terminals()[token.terminal].clone()
+    } else {
+        token.lexeme.to_string()
+    }
+}
+
+/// The outcome of feeding a terminal to a parser that only tracks its states.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Simulated {
+    Shifted,
+    Accepted,
+    Rejected,
+}
+
+/// Tokens read ahead of the parser, for trying out repairs.
+struct Buffer<'g, 'src, I, E> {
+    input: Input<'g, I>,
+    tokens: VecDeque<Result<Token<'src>, Error<'src, E>>>,
+}
+
+impl<'g, 'src, T, E, I> Buffer<'g, 'src, I, E>
+where
+    T: Terminal,
+    I: Iterator<Item = TokenResult<'src, T, E>>,
+{
+    fn fill(&mut self, len: usize) {
+        while self.tokens.len() < len {
+            if let Some(Ok(token)) = self.tokens.back() {
+                if token.terminal == Grammar::END {
+                    return;
+                }
+            }
+            self.tokens.push_back(self.input.next_token());
+        }
+    }
+
+    fn peek(&mut self) -> &Result<Token<'src>, Error<'src, E>> {
+        self.fill(1);
+        &self.tokens[0]
+    }
+
+    fn pop(&mut self) -> Result<Token<'src>, Error<'src, E>> {
+        self.fill(1);
+        self.tokens.pop_front().unwrap()
+    }
+
+    /// Up to `len` tokens, stopping at one that could not be recognized.
+    fn window(&mut self, len: usize) -> Vec<Token<'src>> {
+        self.fill(len);
+        self.tokens
+            .iter()
+            .map_while(|token| token.as_ref().ok().copied())
+            .collect()
+    }
+}
+
+impl Table {
+    /// The `error` terminal, if the grammar has any error productions.
+    pub fn error_terminal(&self) -> Option<usize> {
+        self.grammar().terminal(ERROR)
+    }
+
+    /// Parse a stream of tokens, recovering from syntax errors with error productions.
+    ///
+    /// The tree is missing when the parser could not recover from an error.
+    pub fn parse_with_recovery<'src, T, E, I>(
+        &self,
+        tokens: I,
+    ) -> (Option<Tree<'src>>, Vec<Diagnostic<'src, E>>)
+    where
+        T: Terminal,
+        I: IntoIterator<Item = TokenResult<'src, T, E>>,
+    {
+        self.recover(tokens, 0)
+    }
+
+    /// Parse a stream of tokens, repairing syntax errors with edits of at most `max_cost`
+    /// tokens, or else recovering from them with error productions.
+    ///
+    /// Inserted and replacing terminals appear in the tree as [`Tree::Missing`].
+    ///
+    /// [`Tree::Missing`]: ../enum.Tree.html#variant.Missing
+    pub fn parse_with_repair<'src, T, E, I>(
+        &self,
+        tokens: I,
+        max_cost: usize,
+    ) -> (Option<Tree<'src>>, Vec<Diagnostic<'src, E>>)
+    where
+        T: Terminal,
+        I: IntoIterator<Item = TokenResult<'src, T, E>>,
+    {
+        self.recover(tokens, max_cost)
+    }
+
+    fn recover<'src, T, E, I>(
+        &self,
+        tokens: I,
+        max_cost: usize,
+    ) -> (Option<Tree<'src>>, Vec<Diagnostic<'src, E>>)
+    where
+        T: Terminal,
+        I: IntoIterator<Item = TokenResult<'src, T, E>>,
+    {
+        let mut input = Buffer {
+            input: Input::new(self.grammar(), tokens.into_iter()),
+            tokens: VecDeque::new(),
+        };
+        let mut inserted = VecDeque::new();
+        let mut states = vec![0];
+        let mut trees = Vec::new();
+        let mut diagnostics = Vec::new();
+        let mut suppressed: usize = 0;
+
+        loop {
+            let lookahead = match inserted.front() {
+                Some(&terminal) => Token {
+                    terminal,
+                    lexeme: "",
+                },
+                None => match input.peek() {
+                    Ok(token) => *token,
+                    Err(_) => {
+                        let error = input.pop().unwrap_err();
+                        diagnostics.push(Diagnostic {
+                            error,
+                            recovery: Recovery::Skipped,
+                        });
+                        continue;
+                    }
+                },
+            };
+
+            let top = *states.last().unwrap();
+            match self.action(top, lookahead.terminal) {
+                Some(Action::Shift(state)) => {
+                    states.push(state);
+                    if inserted.pop_front().is_some() {
+                        trees.push(Tree::Missing(Symbol::Terminal(lookahead.terminal)));
+                    } else {
+                        input.pop().ok();
+                        trees.push(Tree::Leaf(lookahead));
+                        suppressed = suppressed.saturating_sub(1);
+                    }
+                }
+                Some(Action::Reduce(production)) => {
+                    let node = self.reduce(production, &mut states, &mut trees);
+                    trees.push(node);
+                }
+                Some(Action::Accept) => return (trees.pop(), diagnostics),
+                None => {
+                    let error = Error::Unexpected(lookahead, self.expected(top));
+                    inserted.clear();
+
+                    if suppressed == SHIFTS_TO_RECOVER {
+                        // Nothing was shifted since the last recovery, so this token must go.
+                        if lookahead.terminal == Grammar::END {
+                            diagnostics.push(Diagnostic {
+                                error,
+                                recovery: Recovery::Failed,
+                            });
+                            return (None, diagnostics);
+                        }
+                        input.pop().ok();
+                        continue;
+                    }
+
+                    if suppressed == 0 && max_cost > 0 {
+                        let window = input.window(REPAIR_WINDOW + max_cost);
+                        if let Some(edits) = self.repair(&states, &window, max_cost) {
+                            let mut cursor = 0;
+                            for edit in &edits {
+                                match *edit {
+                                    Edit::Insert(t) => inserted.push_back(t),
+                                    Edit::Delete(_) => cursor += 1,
+                                    Edit::Replace(_, t) => {
+                                        inserted.push_back(t);
+                                        cursor += 1;
+                                    }
+                                }
+                            }
+
+                            for _ in 0..cursor {
+                                input.pop().ok();
+                            }
+
+                            let repaired = inserted
+                                .iter()
+                                .map(|&terminal| Token {
+                                    terminal,
+                                    lexeme: "",
+                                })
+                                .chain(window[cursor..].iter().copied().take(REPAIR_WINDOW))
+                                .filter(|token| token.terminal != Grammar::END)
+                                .collect();
+
+                            diagnostics.push(Diagnostic {
+                                error,
+                                recovery: Recovery::Repaired(edits, repaired),
+                            });
+                            continue;
+                        }
+                    }
+
+                    let resumed = self.resume(&mut states, &mut trees, &mut input);
+                    if suppressed == 0 || resumed.is_none() {
+                        diagnostics.push(Diagnostic {
+                            error,
+                            recovery: resumed.clone().map_or(Recovery::Failed, Recovery::Resumed),
+                        });
+                    }
+
+                    if resumed.is_none() {
+                        return (None, diagnostics);
+                    }
+                    suppressed = SHIFTS_TO_RECOVER;
+                }
+            }
+        }
+    }
+
+    /// Pop states until one shifts `error`, shift it, and discard tokens until one can follow.
+    /// Returns the discarded tokens, or `None` if there is no such state.
+    fn resume<'src, T, E, I>(
+        &self,
+        states: &mut Vec<usize>,
+        trees: &mut Vec<Tree<'src>>,
+        input: &mut Buffer<'_, 'src, I, E>,
+    ) -> Option<Vec<Token<'src>>>
+    where
+        T: Terminal,
+        I: Iterator<Item = TokenResult<'src, T, E>>,
+    {
+        let error = self.error_terminal()?;
+
+        loop {
+            let top = *states.last().unwrap();
+            let shift = self
+                .actions(top, error)
+                .iter()
+                .find_map(|action| match action {
+                    Action::Shift(state) => Some(*state),
+                    _ => None,
+                });
+
+            if let Some(state) = shift {
+                states.push(state);
+                trees.push(Tree::Leaf(Token {
+                    terminal: error,
+                    lexeme: "",
+                }));
+                break;
+            } else if states.len() == 1 {
+                return None;
+            }
+
+            states.pop();
+            trees.pop();
+        }
+
+        let top = *states.last().unwrap();
+        let mut discarded = Vec::new();
+
+        loop {
+            match input.peek() {
+                Ok(token) if self.action(top, token.terminal).is_some() => return Some(discarded),
+                Ok(token) if token.terminal == Grammar::END => return None,
+                Ok(token) => discarded.push(*token),
+                Err(_) => (),
+            }
+            input.pop().ok();
+        }
+    }
+
+    /// Find the cheapest edits at the front of `window` that let the parser continue.
+    fn repair<'src>(
+        &self,
+        states: &[usize],
+        window: &[Token<'src>],
+        max_cost: usize,
+    ) -> Option<Vec<Edit<'src>>> {
+        let error = self.error_terminal();
+        let terminals = (0..self.grammar().terminals().len())
+            .filter(|&t| t != Grammar::END && Some(t) != error)
+            .collect::<Vec<_>>();
+
+        (1..=max_cost).find_map(|cost| {
+            let mut edits = Vec::new();
+            if self.search(states.to_vec(), window, &terminals, cost, &mut edits) {
+                Some(edits)
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Try every sequence of `cost` edits, preferring insertions, then replacements, then
+    /// deletions.
+    fn search<'src>(
+        &self,
+        states: Vec<usize>,
+        window: &[Token<'src>],
+        terminals: &[usize],
+        cost: usize,
+        edits: &mut Vec<Edit<'src>>,
+    ) -> bool {
+        let cursor = edits
+            .iter()
+            .filter(|edit| !matches!(edit, Edit::Insert(_)))
+            .count();
+
+        if cost == 0 {
+            // The following tokens must be shifted, but the input only has to be accepted if
+            // the repair was at its very end: a later error can be repaired later.
+            let mut states = states;
+            for (i, token) in window[cursor..].iter().take(REPAIR_WINDOW).enumerate() {
+                match self.simulate(&mut states, token.terminal) {
+                    Simulated::Shifted | Simulated::Accepted => (),
+                    Simulated::Rejected => return token.terminal == Grammar::END && i > 0,
+                }
+            }
+            return true;
+        }
+
+        let after_delete = matches!(edits.last(), Some(Edit::Delete(_)));
+        let mut attempt = |edit: Edit<'src>, terminal: Option<usize>| {
+            let mut next = states.clone();
+            if let Some(t) = terminal {
+                if self.simulate(&mut next, t) != Simulated::Shifted {
+                    return false;
+                }
+            }
+
+            edits.push(edit);
+            if self.search(next, window, terminals, cost - 1, edits) {
+                return true;
+            }
+            edits.pop();
+            false
+        };
+
+        // Inserting after deleting is the same as replacing.
+        if !after_delete {
+            for &t in terminals {
+                if attempt(Edit::Insert(t), Some(t)) {
+                    return true;
+                }
+            }
+        }
+
+        if let Some(&token) = window.get(cursor) {
+            if token.terminal != Grammar::END {
+                for &t in terminals {
+                    if t != token.terminal && attempt(Edit::Replace(token, t), Some(t)) {
+                        return true;
+                    }
+                }
+
+                if attempt(Edit::Delete(token), None) {
+                    return true;
+                }
+            }
+        }
+
+        false
+    }
+
+    /// Feed a terminal to a parser that only tracks its states.
+    fn simulate(&self, states: &mut Vec<usize>, terminal: usize) -> Simulated {
+        loop {
+            match self.action(*states.last().unwrap(), terminal) {
+                Some(Action::Shift(state)) => {
+                    states.push(state);
+                    return Simulated::Shifted;
+                }
+                Some(Action::Reduce(production)) => {
+                    let production = &self.grammar().productions()[production];
+                    states.truncate(states.len() - production.rhs.len());
+                    let top = *states.last().unwrap();
+                    states.push(self.goto(top, production.lhs).unwrap());
+                }
+                Some(Action::Accept) => return Simulated::Accepted,
+                None => return Simulated::Rejected,
+            }
+        }
+    }
+}
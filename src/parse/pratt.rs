@@ -0,0 +1,309 @@
+//! Operator-precedence expression parsing by "top down operator precedence" (Pratt parsing).
+//!
+//! ## Usage
+//!
+//! Build a [`Pratt`] table by registering the tokens that begin operands (atoms and grouping
+//! brackets) and the prefix, infix and postfix operators of your expression language, along with
+//! their precedence levels. Operators with a higher precedence bind more tightly. Then call
+//! [`parse`] with a peekable stream of tokens (for example, a [`token::Lexer`]) to parse a single
+//! expression from its front. Tokens that cannot continue the expression are left in the stream.
+//!
+//! Each registered token carries a callback that builds the output value, so the same table can
+//! construct a syntax tree, evaluate the expression directly, or emit code.
+//!
+//! [`Pratt`]: ./struct.Pratt.html
+//! [`parse`]: ./struct.Pratt.html#method.parse
+//! [`token::Lexer`]: ../../token/struct.Lexer.html
+//!
+//! ## Example: integer arithmetic
+//!
+//! ```
+//! # use dragon::{parse::pratt::*, token::*};
+//! #[derive(Clone, Copy, Debug, PartialEq)]
+//! enum Tok { Num, Plus, Minus, Star, Caret, Bang, Open, Close }
+//!
+//! #[derive(Default)]
+//! struct MyState(bool);
+//!
+//! impl State for MyState {
+//!     type Token = Tok;
+//!     type Error = char;
+//!
+//!     fn handle_char(&self, c: char) -> Step<Self> {
+//!         match (self.0, c) {
+//!             (_, '0'..='9') => Step::Continue(Some(Self(true))),
+//!             (true, _) => Step::Finish(Tok::Num, false),
+//!             (_, '+') => Step::Finish(Tok::Plus, true),
+//!             (_, '-') => Step::Finish(Tok::Minus, true),
+//!             (_, '*') => Step::Finish(Tok::Star, true),
+//!             (_, '^') => Step::Finish(Tok::Caret, true),
+//!             (_, '!') => Step::Finish(Tok::Bang, true),
+//!             (_, '(') => Step::Finish(Tok::Open, true),
+//!             (_, ')') => Step::Finish(Tok::Close, true),
+//!             (_, c) if c.is_whitespace() => Step::Discard,
+//!             (_, c) => Step::Abort(c),
+//!         }
+//!     }
+//!
+//!     fn try_finish(&self) -> Option<Tok> {
+//!         if self.0 { Some(Tok::Num) } else { None }
+//!     }
+//! }
+//!
+//! let calc = Pratt::new()
+//!     .atom(Tok::Num, |s| s.parse::<i64>().unwrap())
+//!     .group(Tok::Open, Tok::Close)
+//!     .infix(Tok::Plus, 1, Assoc::Left, |_, l, r| l + r)
+//!     .infix(Tok::Minus, 1, Assoc::Left, |_, l, r| l - r)
+//!     .infix(Tok::Star, 2, Assoc::Left, |_, l, r| l * r)
+//!     .prefix(Tok::Minus, 3, |_, e| -e)
+//!     .infix(Tok::Caret, 4, Assoc::Right, |_, l, r| l.pow(r as u32))
+//!     .postfix(Tok::Bang, 5, |_, e| (1..=e).product());
+//!
+//! let eval = |src| calc.parse(&mut lex::<MyState>(src).peekable());
+//!
+//! assert_eq!(eval("1 - 2 - 3").unwrap(), -4);
+//! assert_eq!(eval("2 ^ 3 ^ 2").unwrap(), 512);
+//! assert_eq!(eval("-(1 + 2) * 3!").unwrap(), -18);
+//! assert_eq!(eval("-2 ^ 2").unwrap(), -4);
+//! assert!(matches!(eval("1 + * 2"), Err(Error::Unexpected(Tok::Star, "*"))));
+//! assert!(matches!(eval("(1 + 2"), Err(Error::UnexpectedEnd)));
+//! ```
+
+pub use super::Assoc;
+
+use {
+    crate::token::TokenResult,
+    std::{fmt, iter::Peekable},
+};
+
+/// Reasons an expression could not be parsed.
+#[derive(Debug)]
+pub enum Error<'src, T, E> {
+    /// The lexer could not recognize a token.
+    Lex(E, &'src str),
+    /// A token appeared where it cannot begin or continue an expression.
+    Unexpected(T, &'src str),
+    /// The token stream ended in the middle of an expression.
+    UnexpectedEnd,
+    /// A non-associative operator was chained with another of the same precedence.
+    NonAssociative(T, &'src str),
+}
+
+impl<'src, T: fmt::Debug, E: fmt::Debug> fmt::Display for Error<'src, T, E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Lex(e, s) => write!(f, "could not recognize a token at `{}` ({:?})", s, e),
+            Self::Unexpected(t, s) => write!(f, "unexpected token {:?} (`{}`)", t, s),
+            Self::UnexpectedEnd => write!(f, "unexpected end of input"),
+            Self::NonAssociative(t, s) => write!(
+                f,
+                "operator {:?} (`{}`) is non-associative and cannot be chained",
+                t, s
+            ),
+        }
+    }
+}
+
+type Atom<O> = Box<dyn Fn(&str) -> O>;
+type Unary<O> = Box<dyn Fn(&str, O) -> O>;
+type Binary<O> = Box<dyn Fn(&str, O, O) -> O>;
+
+struct Infix<T, O> {
+    token: T,
+    precedence: u32,
+    assoc: Assoc,
+    build: Binary<O>,
+}
+
+/// A table of operators that drives an expression parser.
+///
+/// See the [module documentation](./index.html) for an example.
+pub struct Pratt<T, O> {
+    atoms: Vec<(T, Atom<O>)>,
+    groups: Vec<(T, T)>,
+    prefix: Vec<(T, u32, Unary<O>)>,
+    infix: Vec<Infix<T, O>>,
+    postfix: Vec<(T, u32, Unary<O>)>,
+}
+
+impl<T, O> Default for Pratt<T, O> {
+    fn default() -> Self {
+        Self {
+            atoms: Vec::new(),
+            groups: Vec::new(),
+            prefix: Vec::new(),
+            infix: Vec::new(),
+            postfix: Vec::new(),
+        }
+    }
+}
+
+impl<T: PartialEq, O> Pratt<T, O> {
+    /// Create an empty operator table.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a token that forms a complete operand by itself, like a number or identifier.
+    ///
+    /// The callback receives the lexeme of the token.
+    pub fn atom(mut self, token: T, build: impl Fn(&str) -> O + 'static) -> Self {
+        self.atoms.push((token, Box::new(build)));
+        self
+    }
+
+    /// Register a pair of brackets that may surround any expression.
+    pub fn group(mut self, open: T, close: T) -> Self {
+        self.groups.push((open, close));
+        self
+    }
+
+    /// Register an operator that precedes its operand.
+    ///
+    /// The callback receives the lexeme of the operator and the value of its operand.
+    pub fn prefix(
+        mut self,
+        token: T,
+        precedence: u32,
+        build: impl Fn(&str, O) -> O + 'static,
+    ) -> Self {
+        self.prefix.push((token, precedence, Box::new(build)));
+        self
+    }
+
+    /// Register an operator that appears between its two operands.
+    ///
+    /// The callback receives the lexeme of the operator and the values of both operands.
+    pub fn infix(
+        mut self,
+        token: T,
+        precedence: u32,
+        assoc: Assoc,
+        build: impl Fn(&str, O, O) -> O + 'static,
+    ) -> Self {
+        self.infix.push(Infix {
+            token,
+            precedence,
+            assoc,
+            build: Box::new(build),
+        });
+        self
+    }
+
+    /// Register an operator that follows its operand.
+    ///
+    /// The callback receives the lexeme of the operator and the value of its operand.
+    pub fn postfix(
+        mut self,
+        token: T,
+        precedence: u32,
+        build: impl Fn(&str, O) -> O + 'static,
+    ) -> Self {
+        self.postfix.push((token, precedence, Box::new(build)));
+        self
+    }
+
+    /// Parse one expression from the front of a token stream.
+    ///
+    /// Parsing stops at the first token that cannot continue the expression, which remains in
+    /// the stream for the caller to inspect.
+    pub fn parse<'src, E, I>(&self, tokens: &mut Peekable<I>) -> Result<O, Error<'src, T, E>>
+    where
+        I: Iterator<Item = TokenResult<'src, T, E>>,
+    {
+        self.expr(tokens, 0)
+    }
+
+    fn expr<'src, E, I>(
+        &self,
+        tokens: &mut Peekable<I>,
+        min_power: u32,
+    ) -> Result<O, Error<'src, T, E>>
+    where
+        I: Iterator<Item = TokenResult<'src, T, E>>,
+    {
+        let mut lhs = self.operand(tokens)?;
+        let mut last_non_assoc = None;
+
+        loop {
+            let (token, lexeme) = match tokens.peek() {
+                Some((Ok(token), lexeme)) => (token, *lexeme),
+                Some((Err(_), _)) => return Err(lex_error(tokens)),
+                None => break,
+            };
+
+            if let Some((_, precedence, build)) = self.postfix.iter().find(|p| p.0 == *token) {
+                if precedence * 2 < min_power {
+                    break;
+                }
+
+                tokens.next();
+                lhs = build(lexeme, lhs);
+            } else if let Some(op) = self.infix.iter().find(|i| i.token == *token) {
+                let (left_power, right_power) = match op.assoc {
+                    Assoc::Left | Assoc::Neither => (op.precedence * 2, op.precedence * 2 + 1),
+                    Assoc::Right => (op.precedence * 2 + 1, op.precedence * 2),
+                };
+
+                if left_power < min_power {
+                    break;
+                }
+
+                if let Some((Ok(token), _)) = tokens.next() {
+                    if op.assoc == Assoc::Neither {
+                        if last_non_assoc == Some(op.precedence) {
+                            return Err(Error::NonAssociative(token, lexeme));
+                        }
+                        last_non_assoc = Some(op.precedence);
+                    }
+                }
+
+                let rhs = self.expr(tokens, right_power)?;
+                lhs = (op.build)(lexeme, lhs, rhs);
+            } else {
+                break;
+            }
+        }
+
+        Ok(lhs)
+    }
+
+    fn operand<'src, E, I>(&self, tokens: &mut Peekable<I>) -> Result<O, Error<'src, T, E>>
+    where
+        I: Iterator<Item = TokenResult<'src, T, E>>,
+    {
+        let (token, lexeme) = match tokens.next() {
+            Some((Ok(token), lexeme)) => (token, lexeme),
+            Some((Err(e), lexeme)) => return Err(Error::Lex(e, lexeme)),
+            None => return Err(Error::UnexpectedEnd),
+        };
+
+        if let Some((_, build)) = self.atoms.iter().find(|a| a.0 == token) {
+            Ok(build(lexeme))
+        } else if let Some((_, precedence, build)) = self.prefix.iter().find(|p| p.0 == token) {
+            let operand = self.expr(tokens, precedence * 2)?;
+            Ok(build(lexeme, operand))
+        } else if let Some((_, close)) = self.groups.iter().find(|g| g.0 == token) {
+            let inner = self.expr(tokens, 0)?;
+            match tokens.next() {
+                Some((Ok(t), _)) if t == *close => Ok(inner),
+                Some((Ok(t), lexeme)) => Err(Error::Unexpected(t, lexeme)),
+                Some((Err(e), lexeme)) => Err(Error::Lex(e, lexeme)),
+                None => Err(Error::UnexpectedEnd),
+            }
+        } else {
+            Err(Error::Unexpected(token, lexeme))
+        }
+    }
+}
+
+fn lex_error<'src, T, E, I>(tokens: &mut I) -> Error<'src, T, E>
+where
+    I: Iterator<Item = TokenResult<'src, T, E>>,
+{
+    match tokens.next() {
+        Some((Err(e), lexeme)) => Error::Lex(e, lexeme),
+        _ => Error::UnexpectedEnd,
+    }
+}
@@ -0,0 +1,514 @@
+//! Operator-precedence parsing from precedence relations between terminals.
+//!
+//! An *operator grammar* has no ε-productions and no right side with two adjacent nonterminals.
+//! For such a grammar, three relations between terminals guide a shift-reduce parser:
+//!
+//! - `a <· b` when `b` begins a handle that follows `a`;
+//! - `a ≐ b` when `a` and `b` belong to the same handle;
+//! - `a ·> b` when `a` ends a handle that is followed by `b`.
+//!
+//! The relations are computed from the LEADING and TRAILING sets of each nonterminal: the
+//! terminals that can appear first or last in a string it derives, ignoring at most one
+//! nonterminal. The grammar is an operator-precedence grammar when at most one relation holds
+//! between any pair of terminals. The relations can often be encoded more compactly as a pair of
+//! precedence functions `f` and `g`, such that `f(a) < g(b)` whenever `a <· b`, and so on.
+//!
+//! Because operator-precedence parsing treats all nonterminals alike, productions with a single
+//! nonterminal on their right side (like `expr -> term`) are never applied, and do not appear in
+//! the resulting parse tree.
+//!
+//! ## Example: the grammar of `simple`
+//!
+//! ```
+//! # use dragon::{grammar::*, parse::precedence::*, token::*};
+//! # #[derive(Default)]
+//! # struct Lex(Option<Tok>);
+//! #[derive(Clone, Copy)]
+//! enum Tok { Word, Num, Punct }
+//!
+//! impl Terminal for Tok {
+//!     fn name<'a>(&'a self, lexeme: &'a str) -> &'a str {
+//!         match self {
+//!             Self::Word if lexeme == "div" || lexeme == "mod" => lexeme,
+//!             Self::Word => "id",
+//!             Self::Num => "num",
+//!             Self::Punct => lexeme,
+//!         }
+//!     }
+//! }
+//! # impl State for Lex {
+//! #     type Token = Tok;
+//! #     type Error = char;
+//! #     fn handle_char(&self, c: char) -> Step<Self> {
+//! #         match (self.0, c) {
+//! #             (Some(Tok::Word), 'a'..='z') | (Some(_), '0'..='9') => Step::Continue(None),
+//! #             (Some(t), _) => Step::Finish(t, false),
+//! #             (None, 'a'..='z') => Step::Continue(Some(Self(Some(Tok::Word)))),
+//! #             (None, '0'..='9') => Step::Continue(Some(Self(Some(Tok::Num)))),
+//! #             (None, c) if c.is_whitespace() => Step::Discard,
+//! #             (None, _) => Step::Finish(Tok::Punct, true),
+//! #         }
+//! #     }
+//! #     fn try_finish(&self) -> Option<Tok> { self.0 }
+//! # }
+//!
+//! let grammar: Grammar = "
+//!     expr   -> expr + term | expr - term | term
+//!     term   -> term * factor | term / factor
+//!             | term div factor | term mod factor
+//!             | factor
+//!     factor -> '(' expr ')' | num | id
+//! "
+//! .parse()
+//! .unwrap();
+//!
+//! let table = Table::new(&grammar).unwrap();
+//! let t = |name| grammar.terminal(name).unwrap();
+//!
+//! assert_eq!(table.relation(t("+"), t("*")), Some(Relation::Yields));
+//! assert_eq!(table.relation(t("*"), t("+")), Some(Relation::Takes));
+//! assert_eq!(table.relation(t("("), t(")")), Some(Relation::Equal));
+//! assert_eq!(table.relation(t("id"), t("num")), None);
+//!
+//! let functions = table.functions().unwrap();
+//! assert!(functions.f[t("+")] < functions.g[t("*")]);
+//! assert!(functions.f[t("mod")] > functions.g[t("-")]);
+//!
+//! let tree = table.parse(lex::<Lex>("a - b div (2 + c) - 1")).unwrap();
+//! assert_eq!(
+//!     tree.display(&grammar).to_string(),
+//!     "(expr (expr (factor a) - (term (factor b) div (factor ( (expr (factor 2) + (factor c)) )))) \
+//!      - (factor 1))",
+//! );
+//!
+//! let error = table.parse(lex::<Lex>("a + b c")).unwrap_err();
+//! assert_eq!(
+//!     error.message(&grammar),
+//!     "unexpected `c`, expected one of: $ + - * / div mod )",
+//! );
+//! ```
+
+use {
+    super::{Error, Input, Tree},
+    crate::{
+        grammar::{Grammar, Symbol, Terminal},
+        token::TokenResult,
+    },
+    std::{collections::BTreeSet, fmt},
+};
+
+/// A precedence relation between two terminals.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Relation {
+    /// `<·`: the terminal on the left yields precedence to the one on the right.
+    Yields,
+    /// `≐`: both terminals have the same precedence.
+    Equal,
+    /// `·>`: the terminal on the left takes precedence over the one on the right.
+    Takes,
+}
+
+impl fmt::Display for Relation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Yields => write!(f, "<·"),
+            Self::Equal => write!(f, "≐"),
+            Self::Takes => write!(f, "·>"),
+        }
+    }
+}
+
+/// More than one relation holds between a pair of terminals.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Conflict {
+    /// The terminal on the left of the relations.
+    pub left: usize,
+    /// The terminal on the right of the relations.
+    pub right: usize,
+    /// Every relation that holds between them.
+    pub relations: Vec<Relation>,
+}
+
+/// Reasons a precedence table could not be built for a grammar.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TableError {
+    /// The production with this index has an empty right side, or two adjacent nonterminals.
+    NotOperatorGrammar(usize),
+    /// Some pairs of terminals are related in more than one way.
+    Conflicts(Vec<Conflict>),
+}
+
+impl TableError {
+    /// Describe this error, using the names of symbols from the grammar.
+    pub fn message(&self, grammar: &Grammar) -> String {
+        match self {
+            Self::NotOperatorGrammar(p) => format!(
+                "`{}` is not allowed in an operator grammar",
+                grammar.show_production(*p)
+            ),
+            Self::Conflicts(conflicts) => conflicts
+                .iter()
+                .map(|c| {
+                    let relations = c
+                        .relations
+                        .iter()
+                        .map(|r| r.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    format!(
+                        "conflicting relations between `{}` and `{}`: {}",
+                        grammar.terminals()[c.left],
+                        grammar.terminals()[c.right],
+                        relations
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+        }
+    }
+}
+
+/// Precedence functions that encode a table of relations.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Functions {
+    /// Precedence of each terminal when it is on the left of a relation.
+    pub f: Vec<usize>,
+    /// Precedence of each terminal when it is on the right of a relation.
+    pub g: Vec<usize>,
+}
+
+impl Functions {
+    /// The relation encoded between two terminals.
+    ///
+    /// Unlike a table, precedence functions relate every pair of terminals, so some syntax errors
+    /// are only detected later in the parse (or when no production matches a handle).
+    pub fn relation(&self, left: usize, right: usize) -> Relation {
+        match self.f[left].cmp(&self.g[right]) {
+            std::cmp::Ordering::Less => Relation::Yields,
+            std::cmp::Ordering::Equal => Relation::Equal,
+            std::cmp::Ordering::Greater => Relation::Takes,
+        }
+    }
+}
+
+/// The precedence relations of an operator grammar.
+#[derive(Clone, Debug)]
+pub struct Table {
+    grammar: Grammar,
+    leading: Vec<BTreeSet<usize>>,
+    trailing: Vec<BTreeSet<usize>>,
+    relations: Vec<Vec<Option<Relation>>>,
+}
+
+impl Table {
+    /// Compute the precedence relations of an operator grammar.
+    pub fn new(grammar: &Grammar) -> Result<Self, TableError> {
+        for (index, production) in grammar.productions().iter().enumerate() {
+            let adjacent = production
+                .rhs
+                .windows(2)
+                .any(|pair| matches!(pair, [Symbol::Nonterminal(_), Symbol::Nonterminal(_)]));
+
+            if production.rhs.is_empty() || adjacent {
+                return Err(TableError::NotOperatorGrammar(index));
+            }
+        }
+
+        let leading = edge_sets(grammar, |rhs| Box::new(rhs.iter()));
+        let trailing = edge_sets(grammar, |rhs| Box::new(rhs.iter().rev()));
+
+        let terminals = grammar.terminals().len();
+        let mut found = vec![vec![BTreeSet::new(); terminals]; terminals];
+
+        for production in grammar.productions() {
+            for (i, &symbol) in production.rhs.iter().enumerate() {
+                let next = production.rhs.get(i + 1).copied();
+
+                match (symbol, next) {
+                    (Symbol::Terminal(a), Some(Symbol::Terminal(b))) => {
+                        found[a][b].insert(Relation::Equal);
+                    }
+                    (Symbol::Terminal(a), Some(Symbol::Nonterminal(n))) => {
+                        if let Some(&Symbol::Terminal(b)) = production.rhs.get(i + 2) {
+                            found[a][b].insert(Relation::Equal);
+                        }
+
+                        for &b in &leading[n] {
+                            found[a][b].insert(Relation::Yields);
+                        }
+                    }
+                    (Symbol::Nonterminal(n), Some(Symbol::Terminal(b))) => {
+                        for &a in &trailing[n] {
+                            found[a][b].insert(Relation::Takes);
+                        }
+                    }
+                    _ => (),
+                }
+            }
+        }
+
+        for &b in &leading[grammar.start()] {
+            found[Grammar::END][b].insert(Relation::Yields);
+        }
+
+        for &a in &trailing[grammar.start()] {
+            found[a][Grammar::END].insert(Relation::Takes);
+        }
+
+        let mut conflicts = Vec::new();
+        let mut relations = vec![vec![None; terminals]; terminals];
+
+        for (left, row) in found.into_iter().enumerate() {
+            for (right, cell) in row.into_iter().enumerate() {
+                if cell.len() > 1 {
+                    conflicts.push(Conflict {
+                        left,
+                        right,
+                        relations: cell.into_iter().collect(),
+                    });
+                } else {
+                    relations[left][right] = cell.into_iter().next();
+                }
+            }
+        }
+
+        if !conflicts.is_empty() {
+            return Err(TableError::Conflicts(conflicts));
+        }
+
+        Ok(Self {
+            grammar: grammar.clone(),
+            leading,
+            trailing,
+            relations,
+        })
+    }
+
+    /// The grammar this table was computed from.
+    pub fn grammar(&self) -> &Grammar {
+        &self.grammar
+    }
+
+    /// The terminals that can begin a string derived from a nonterminal, possibly after one
+    /// leading nonterminal.
+    pub fn leading(&self, nonterminal: usize) -> &BTreeSet<usize> {
+        &self.leading[nonterminal]
+    }
+
+    /// The terminals that can end a string derived from a nonterminal, possibly before one
+    /// trailing nonterminal.
+    pub fn trailing(&self, nonterminal: usize) -> &BTreeSet<usize> {
+        &self.trailing[nonterminal]
+    }
+
+    /// The relation between two terminals, if there is one.
+    pub fn relation(&self, left: usize, right: usize) -> Option<Relation> {
+        self.relations[left][right]
+    }
+
+    /// Construct precedence functions for this table, if possible.
+    ///
+    /// Each terminal `a` has two nodes in a graph, `f_a` and `g_a`. Nodes are merged when their
+    /// terminals are related by `≐`, and an edge is drawn from the node of the terminal that
+    /// takes precedence to the node of the one that yields. The functions do not exist if the
+    /// graph has a cycle; otherwise each function value is the length of the longest path from
+    /// the corresponding node.
+    pub fn functions(&self) -> Option<Functions> {
+        let terminals = self.relations.len();
+        let mut group = (0..terminals * 2).collect::<Vec<_>>();
+
+        fn find(group: &mut [usize], node: usize) -> usize {
+            if group[node] != node {
+                group[node] = find(group, group[node]);
+            }
+            group[node]
+        }
+
+        for a in 0..terminals {
+            for b in 0..terminals {
+                if self.relations[a][b] == Some(Relation::Equal) {
+                    let (f, g) = (find(&mut group, a), find(&mut group, terminals + b));
+                    group[f] = g;
+                }
+            }
+        }
+
+        let mut edges = vec![Vec::new(); terminals * 2];
+        for a in 0..terminals {
+            for b in 0..terminals {
+                let (f, g) = (find(&mut group, a), find(&mut group, terminals + b));
+                match self.relations[a][b] {
+                    Some(Relation::Takes) => edges[f].push(g),
+                    Some(Relation::Yields) => edges[g].push(f),
+                    _ => (),
+                }
+            }
+        }
+
+        // 0 = unvisited, 1 = on the current path, 2 = finished
+        let mut mark = vec![0; terminals * 2];
+        let mut longest = vec![0; terminals * 2];
+
+        fn visit(
+            node: usize,
+            edges: &[Vec<usize>],
+            mark: &mut [u8],
+            longest: &mut [usize],
+        ) -> bool {
+            match mark[node] {
+                1 => return false,
+                2 => return true,
+                _ => mark[node] = 1,
+            }
+
+            for &next in &edges[node] {
+                if !visit(next, edges, mark, longest) {
+                    return false;
+                }
+                longest[node] = longest[node].max(longest[next] + 1);
+            }
+
+            mark[node] = 2;
+            true
+        }
+
+        for node in 0..terminals * 2 {
+            let node = find(&mut group, node);
+            if !visit(node, &edges, &mut mark, &mut longest) {
+                return None;
+            }
+        }
+
+        let mut value = |node| longest[find(&mut group, node)];
+        Some(Functions {
+            f: (0..terminals).map(&mut value).collect(),
+            g: (terminals..terminals * 2).map(&mut value).collect(),
+        })
+    }
+
+    /// Parse a stream of tokens.
+    pub fn parse<'src, T, E, I>(&self, tokens: I) -> Result<Tree<'src>, Error<'src, E>>
+    where
+        T: Terminal,
+        I: IntoIterator<Item = TokenResult<'src, T, E>>,
+    {
+        let mut input = Input::new(&self.grammar, tokens.into_iter());
+        let mut stack = Vec::new();
+        let mut lookahead = input.next_token()?;
+
+        loop {
+            let top = top_terminal(&stack);
+
+            if top == Grammar::END && lookahead.terminal == Grammar::END {
+                return match stack.pop() {
+                    Some(tree @ Tree::Node(..)) if stack.is_empty() => Ok(tree),
+                    _ => Err(Error::Unexpected(lookahead, Vec::new())),
+                };
+            }
+
+            match self.relations[top][lookahead.terminal] {
+                Some(Relation::Yields) | Some(Relation::Equal) => {
+                    stack.push(Tree::Leaf(lookahead));
+                    lookahead = input.next_token()?;
+                }
+                Some(Relation::Takes) => {
+                    let node = self
+                        .reduce(&mut stack)
+                        .ok_or_else(|| Error::Unexpected(lookahead, Vec::new()))?;
+                    stack.push(node);
+                }
+                None => {
+                    let expected = (0..self.relations.len())
+                        .filter(|&b| self.relations[top][b].is_some())
+                        .collect();
+                    return Err(Error::Unexpected(lookahead, expected));
+                }
+            }
+        }
+    }
+
+    fn reduce<'src>(&self, stack: &mut Vec<Tree<'src>>) -> Option<Tree<'src>> {
+        let mut handle = Vec::new();
+
+        loop {
+            let entry = stack.pop()?;
+            let popped = match entry {
+                Tree::Leaf(token) => Some(token.terminal),
+                _ => None,
+            };
+            handle.push(entry);
+
+            if let Some(terminal) = popped {
+                if self.relations[top_terminal(stack)][terminal] != Some(Relation::Equal) {
+                    break;
+                }
+            }
+        }
+
+        if let Some(Tree::Node(..)) = stack.last() {
+            handle.extend(stack.pop());
+        }
+
+        handle.reverse();
+
+        let production = self.grammar.productions().iter().position(|p| {
+            p.rhs.len() == handle.len()
+                && p.rhs.iter().zip(&handle).all(|pair| match pair {
+                    (Symbol::Terminal(t), Tree::Leaf(token)) => *t == token.terminal,
+                    (Symbol::Nonterminal(_), Tree::Node(..)) => true,
+                    _ => false,
+                })
+        })?;
+
+        Some(Tree::Node(production, handle))
+    }
+}
+
+fn top_terminal(stack: &[Tree]) -> usize {
+    stack
+        .iter()
+        .rev()
+        .find_map(|entry| match entry {
+            Tree::Leaf(token) => Some(token.terminal),
+            _ => None,
+        })
+        .unwrap_or(Grammar::END)
+}
+
+/// Compute LEADING (or TRAILING, if `ends` walks right sides backwards) for every nonterminal.
+fn edge_sets<F>(grammar: &Grammar, ends: F) -> Vec<BTreeSet<usize>>
+where
+    F: for<'a> Fn(&'a [Symbol]) -> Box<dyn Iterator<Item = &'a Symbol> + 'a>,
+{
+    let mut sets = vec![BTreeSet::new(); grammar.nonterminals().len()];
+    let mut changed = true;
+
+    while changed {
+        changed = false;
+
+        for production in grammar.productions() {
+            let mut symbols = ends(&production.rhs);
+            let mut add = BTreeSet::new();
+
+            match (symbols.next(), symbols.next()) {
+                (Some(&Symbol::Terminal(a)), _) => {
+                    add.insert(a);
+                }
+                (Some(&Symbol::Nonterminal(n)), next) => {
+                    add.extend(sets[n].iter().copied());
+                    if let Some(&Symbol::Terminal(a)) = next {
+                        add.insert(a);
+                    }
+                }
+                (None, _) => (),
+            }
+
+            let set = &mut sets[production.lhs];
+            for a in add {
+                changed |= set.insert(a);
+            }
+        }
+    }
+
+    sets
+}
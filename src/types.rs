@@ -0,0 +1,486 @@
+//! Type expressions, and the checks a type checker makes with them.
+//!
+//! A type expression is a basic type, a type name, or a *type constructor* applied to type
+//! expressions:
+//!
+//! - `array(n, t)` is an array of `n` elements of type `t`;
+//! - `pointer(t)` is a pointer to an object of type `t`;
+//! - `(s, t) -> u` is a function from arguments of types `s` and `t` to a result of type `u`;
+//! - `record(x: s, y: t)` is a record with fields named `x` and `y`, of types `s` and `t`.
+//!
+//! Two type expressions are *structurally equivalent* when they are the same basic type, or the
+//! same constructor applied to equivalent types. Type names are given meanings by [`Names`], and
+//! stand for their definitions; since a definition may refer to its own name through a pointer,
+//! the check assumes the names it is already comparing are equivalent.
+//!
+//! Operands of different numeric types are converted to the wider of the two before an operator
+//! is applied, along the widening hierarchy `char` < `int` < `float`.
+//!
+//! Type expressions may also contain *type variables*, which stand for unknown types. A
+//! [`Substitution`] gives types to variables, and *unifies* two type expressions by extending
+//! itself until both stand for the same type, if it can. The [`infer`] module uses unification to
+//! find the most general types of expressions in a small functional language.
+//!
+//! [`Names`]: ./struct.Names.html
+//! [`Substitution`]: ./struct.Substitution.html
+//! [`infer`]: ./infer/index.html
+//!
+//! ## Example
+//!
+//! ```
+//! # use dragon::types::*;
+//! let matrix = Type::array(2, Type::array(3, Type::INT));
+//! assert_eq!(matrix.to_string(), "array(2, array(3, int))");
+//!
+//! let mut names = Names::new();
+//! assert_eq!(names.width(&matrix), Some(24));
+//!
+//! // Two linked lists, declared under different names.
+//! names.define("link", Type::pointer(Type::name("cell")));
+//! names.define(
+//!     "cell",
+//!     Type::record(&[("info", Type::INT), ("next", Type::name("link"))]),
+//! );
+//! names.define(
+//!     "node",
+//!     Type::record(&[("info", Type::INT), ("next", Type::pointer(Type::name("node")))]),
+//! );
+//! assert!(names.equivalent(&Type::name("cell"), &Type::name("node")));
+//! assert!(!names.equivalent(&Type::name("cell"), &Type::name("link")));
+//! assert_eq!(names.width(&Type::name("cell")), Some(12));
+//!
+//! assert_eq!(Type::INT.max(&Type::FLOAT), Some(Type::FLOAT));
+//! assert_eq!(Type::INT.max(&Type::BOOL), None);
+//! assert!(Type::CHAR.widens_to(&Type::FLOAT));
+//!
+//! // Unify `'a -> int` with `bool -> 'b`.
+//! let mut substitution = Substitution::new();
+//! let a = Type::function(vec![Type::Var(0)], Type::INT);
+//! let b = Type::function(vec![Type::BOOL], Type::Var(1));
+//! substitution.unify(&a, &b).unwrap();
+//! assert_eq!(substitution.apply(&a).to_string(), "bool -> int");
+//!
+//! // No type is a pointer to itself.
+//! let error = substitution.unify(&Type::Var(2), &Type::pointer(Type::Var(2)));
+//! assert_eq!(error, Err(UnifyError::Occurs(2, Type::pointer(Type::Var(2)))));
+//! ```
+
+pub mod infer;
+
+use std::{collections::HashMap, fmt};
+
+/// Types that are not built from other types.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Basic {
+    Bool,
+    Char,
+    Int,
+    Float,
+    /// The absence of a value, such as the result of a function called only for its effects.
+    Void,
+}
+
+impl Basic {
+    /// The position of a numeric type in the widening hierarchy.
+    fn rank(self) -> Option<usize> {
+        match self {
+            Self::Char => Some(0),
+            Self::Int => Some(1),
+            Self::Float => Some(2),
+            Self::Bool | Self::Void => None,
+        }
+    }
+
+    /// The number of bytes taken by a value of this type.
+    fn width(self) -> Option<usize> {
+        match self {
+            Self::Bool | Self::Char => Some(1),
+            Self::Int => Some(4),
+            Self::Float => Some(8),
+            Self::Void => None,
+        }
+    }
+}
+
+impl fmt::Display for Basic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Bool => "bool",
+            Self::Char => "char",
+            Self::Int => "int",
+            Self::Float => "float",
+            Self::Void => "void",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A type expression.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Type {
+    Basic(Basic),
+    /// A name for a type, defined in [`Names`](./struct.Names.html).
+    Name(String),
+    /// A number of elements, and their type.
+    Array(usize, Box<Type>),
+    /// The type pointed to.
+    Pointer(Box<Type>),
+    /// The types of the arguments, and the type of the result.
+    Function(Vec<Type>, Box<Type>),
+    /// The names and types of the fields, in order.
+    Record(Vec<(String, Type)>),
+    /// A type variable, numbered.
+    Var(usize),
+}
+
+/// The number of bytes taken by a pointer.
+const POINTER_WIDTH: usize = 8;
+
+impl Type {
+    pub const BOOL: Self = Self::Basic(Basic::Bool);
+    pub const CHAR: Self = Self::Basic(Basic::Char);
+    pub const INT: Self = Self::Basic(Basic::Int);
+    pub const FLOAT: Self = Self::Basic(Basic::Float);
+    pub const VOID: Self = Self::Basic(Basic::Void);
+
+    /// A type name.
+    pub fn name(name: &str) -> Self {
+        Self::Name(name.to_string())
+    }
+
+    /// `array(len, element)`.
+    pub fn array(len: usize, element: Self) -> Self {
+        Self::Array(len, Box::new(element))
+    }
+
+    /// `pointer(target)`.
+    pub fn pointer(target: Self) -> Self {
+        Self::Pointer(Box::new(target))
+    }
+
+    /// `(arguments) -> result`.
+    pub fn function(arguments: Vec<Self>, result: Self) -> Self {
+        Self::Function(arguments, Box::new(result))
+    }
+
+    /// `record(fields)`.
+    pub fn record(fields: &[(&str, Self)]) -> Self {
+        Self::Record(
+            fields
+                .iter()
+                .map(|(name, t)| (name.to_string(), t.clone()))
+                .collect(),
+        )
+    }
+
+    /// Whether this is a numeric basic type.
+    pub fn is_numeric(&self) -> bool {
+        matches!(self, Self::Basic(b) if b.rank().is_some())
+    }
+
+    /// The wider of two numeric types, which both can be converted to.
+    pub fn max(&self, other: &Self) -> Option<Self> {
+        match (self, other) {
+            (Self::Basic(a), Self::Basic(b)) if a.rank()? >= b.rank()? => Some(self.clone()),
+            (Self::Basic(a), Self::Basic(b)) if a.rank()? < b.rank()? => Some(other.clone()),
+            _ => None,
+        }
+    }
+
+    /// Whether a value of this type can be converted implicitly to another type: either they are
+    /// the same, or both are numeric and the other is wider.
+    pub fn widens_to(&self, target: &Self) -> bool {
+        self == target || self.max(target).as_ref() == Some(target)
+    }
+
+    /// The type of a field of a record.
+    pub fn field(&self, name: &str) -> Option<&Self> {
+        match self {
+            Self::Record(fields) => fields.iter().find(|(n, _)| n == name).map(|(_, t)| t),
+            _ => None,
+        }
+    }
+
+    /// The type variables in this type expression, in the order they first appear.
+    pub fn variables(&self) -> Vec<usize> {
+        let mut variables = Vec::new();
+        self.collect_variables(&mut variables);
+        variables
+    }
+
+    fn collect_variables(&self, variables: &mut Vec<usize>) {
+        match self {
+            Self::Var(v) if !variables.contains(v) => variables.push(*v),
+            Self::Basic(_) | Self::Name(_) | Self::Var(_) => (),
+            Self::Array(_, t) | Self::Pointer(t) => t.collect_variables(variables),
+            Self::Function(arguments, result) => {
+                for argument in arguments {
+                    argument.collect_variables(variables);
+                }
+                result.collect_variables(variables);
+            }
+            Self::Record(fields) => {
+                for (_, t) in fields {
+                    t.collect_variables(variables);
+                }
+            }
+        }
+    }
+
+    /// Whether a type variable appears in this type expression.
+    pub fn contains(&self, variable: usize) -> bool {
+        self.variables().contains(&variable)
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Basic(b) => write!(f, "{}", b),
+            Self::Name(name) => write!(f, "{}", name),
+            Self::Array(len, element) => write!(f, "array({}, {})", len, element),
+            Self::Pointer(target) => write!(f, "pointer({})", target),
+            Self::Function(arguments, result) => match arguments.as_slice() {
+                [argument @ Self::Function(..)] => write!(f, "({}) -> {}", argument, result),
+                [argument] => write!(f, "{} -> {}", argument, result),
+                _ => {
+                    write!(f, "(")?;
+                    for (i, argument) in arguments.iter().enumerate() {
+                        if i > 0 {
+                            write!(f, ", ")?;
+                        }
+                        write!(f, "{}", argument)?;
+                    }
+                    write!(f, ") -> {}", result)
+                }
+            },
+            Self::Record(fields) => {
+                write!(f, "record(")?;
+                for (i, (name, t)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", name, t)?;
+                }
+                write!(f, ")")
+            }
+            Self::Var(v) => {
+                write!(f, "'{}", (b'a' + (v % 26) as u8) as char)?;
+                if *v >= 26 {
+                    write!(f, "{}", v / 26)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Definitions of type names.
+#[derive(Clone, Debug, Default)]
+pub struct Names {
+    definitions: HashMap<String, Type>,
+}
+
+impl Names {
+    /// No type names.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Give a name to a type, returning the type it named before, if any.
+    pub fn define(&mut self, name: &str, definition: Type) -> Option<Type> {
+        self.definitions.insert(name.to_string(), definition)
+    }
+
+    /// The type a name stands for.
+    pub fn get(&self, name: &str) -> Option<&Type> {
+        self.definitions.get(name)
+    }
+
+    /// Follow type names until reaching a type expression that is not a name. Returns `None` if
+    /// a name is undefined, or defined as itself.
+    pub fn resolve<'a>(&'a self, mut t: &'a Type) -> Option<&'a Type> {
+        for _ in 0..=self.definitions.len() {
+            match t {
+                Type::Name(name) => t = self.get(name)?,
+                _ => return Some(t),
+            }
+        }
+        None
+    }
+
+    /// Whether two type expressions are structurally equivalent.
+    pub fn equivalent(&self, a: &Type, b: &Type) -> bool {
+        self.equivalent_assuming(a, b, &mut Vec::new())
+    }
+
+    fn equivalent_assuming<'a>(
+        &'a self,
+        a: &'a Type,
+        b: &'a Type,
+        assumed: &mut Vec<(&'a Type, &'a Type)>,
+    ) -> bool {
+        if a == b {
+            return true;
+        }
+
+        if let (Type::Name(_), _) | (_, Type::Name(_)) = (a, b) {
+            if assumed.contains(&(a, b)) {
+                return true;
+            }
+            assumed.push((a, b));
+            return match (self.resolve(a), self.resolve(b)) {
+                (Some(a), Some(b)) => self.equivalent_assuming(a, b, assumed),
+                _ => false,
+            };
+        }
+
+        match (a, b) {
+            (Type::Array(m, s), Type::Array(n, t)) => {
+                m == n && self.equivalent_assuming(s, t, assumed)
+            }
+            (Type::Pointer(s), Type::Pointer(t)) => self.equivalent_assuming(s, t, assumed),
+            (Type::Function(s, u), Type::Function(t, v)) => {
+                s.len() == t.len()
+                    && s.iter()
+                        .zip(t)
+                        .all(|(s, t)| self.equivalent_assuming(s, t, assumed))
+                    && self.equivalent_assuming(u, v, assumed)
+            }
+            (Type::Record(s), Type::Record(t)) => {
+                s.len() == t.len()
+                    && s.iter()
+                        .zip(t)
+                        .all(|((m, s), (n, t))| m == n && self.equivalent_assuming(s, t, assumed))
+            }
+            _ => false,
+        }
+    }
+
+    /// The number of bytes taken by a value of a type, or `None` for `void`, functions and
+    /// undefined names.
+    pub fn width(&self, t: &Type) -> Option<usize> {
+        match self.resolve(t)? {
+            Type::Basic(b) => b.width(),
+            Type::Name(_) => None,
+            Type::Array(len, element) => Some(len * self.width(element)?),
+            Type::Pointer(_) => Some(POINTER_WIDTH),
+            Type::Function(..) | Type::Var(_) => None,
+            Type::Record(fields) => fields.iter().map(|(_, t)| self.width(t)).sum(),
+        }
+    }
+}
+
+/// Types for type variables, built up by unification.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Substitution {
+    types: HashMap<usize, Type>,
+}
+
+/// Reasons two type expressions cannot be unified.
+#[derive(Clone, Debug, PartialEq)]
+pub enum UnifyError {
+    /// The types of some part of the two expressions differ.
+    Mismatch(Type, Type),
+    /// A type variable would have to stand for a type expression that contains it.
+    Occurs(usize, Type),
+}
+
+impl Substitution {
+    /// Give no type variable a type.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The type given to a type variable, if any.
+    pub fn get(&self, variable: usize) -> Option<&Type> {
+        self.types.get(&variable)
+    }
+
+    /// Give a type to a type variable that has none.
+    ///
+    /// # Panics
+    ///
+    /// If the variable already has a type.
+    pub fn bind(&mut self, variable: usize, t: Type) {
+        assert!(
+            self.types.insert(variable, t).is_none(),
+            "'{} already has a type",
+            variable
+        );
+    }
+
+    /// Replace every type variable in a type expression by its type, until only variables with
+    /// no type are left.
+    pub fn apply(&self, t: &Type) -> Type {
+        match t {
+            Type::Var(v) => match self.types.get(v) {
+                Some(t) => self.apply(t),
+                None => t.clone(),
+            },
+            Type::Basic(_) | Type::Name(_) => t.clone(),
+            Type::Array(len, element) => Type::array(*len, self.apply(element)),
+            Type::Pointer(target) => Type::pointer(self.apply(target)),
+            Type::Function(arguments, result) => Type::function(
+                arguments.iter().map(|t| self.apply(t)).collect(),
+                self.apply(result),
+            ),
+            Type::Record(fields) => Type::Record(
+                fields
+                    .iter()
+                    .map(|(name, t)| (name.clone(), self.apply(t)))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Give types to type variables so that two type expressions stand for the same type.
+    ///
+    /// Type names are compared by name, not by their definitions. On failure, the variables
+    /// unified before the mismatch was found keep their types.
+    pub fn unify(&mut self, a: &Type, b: &Type) -> Result<(), UnifyError> {
+        let a = self.shallow(a);
+        let b = self.shallow(b);
+
+        match (&a, &b) {
+            (Type::Var(x), Type::Var(y)) if x == y => Ok(()),
+            (Type::Var(v), t) | (t, Type::Var(v)) => {
+                let t = self.apply(t);
+                if t.contains(*v) {
+                    return Err(UnifyError::Occurs(*v, t));
+                }
+                self.types.insert(*v, t);
+                Ok(())
+            }
+            (Type::Array(m, s), Type::Array(n, t)) if m == n => self.unify(s, t),
+            (Type::Pointer(s), Type::Pointer(t)) => self.unify(s, t),
+            (Type::Function(s, u), Type::Function(t, v)) if s.len() == t.len() => {
+                for (s, t) in s.iter().zip(t) {
+                    self.unify(s, t)?;
+                }
+                self.unify(u, v)
+            }
+            (Type::Record(s), Type::Record(t))
+                if s.len() == t.len() && s.iter().zip(t).all(|((m, _), (n, _))| m == n) =>
+            {
+                for ((_, s), (_, t)) in s.iter().zip(t) {
+                    self.unify(s, t)?;
+                }
+                Ok(())
+            }
+            _ if a == b => Ok(()),
+            _ => Err(UnifyError::Mismatch(self.apply(&a), self.apply(&b))),
+        }
+    }
+
+    /// Follow a type variable to the type it stands for, without looking inside that type.
+    fn shallow(&self, t: &Type) -> Type {
+        match t {
+            Type::Var(v) => match self.types.get(v) {
+                Some(t) => self.shallow(t),
+                None => t.clone(),
+            },
+            _ => t.clone(),
+        }
+    }
+}
@@ -0,0 +1,677 @@
+//! Hindley-Milner type inference for a small functional language.
+//!
+//! Expressions are integer and Boolean literals, variables, functions `fun x y -> e`,
+//! applications `f x`, `if c then a else b`, and `let x = e in b`. Nothing about the types of
+//! variables is declared: each unknown type starts out as a fresh type variable, and the checks
+//! that the types of an expression's parts fit together are made by unifying their type
+//! expressions (Algorithm W).
+//!
+//! A variable bound by `let` is *polymorphic*: the type variables in its type that are not also in
+//! the types of the variables around it are *generalized*, and each use of the variable gets fresh
+//! copies of them. So `let id = fun x -> x in id id` is fine, although `fun id -> id id` is not.
+//!
+//! Every expression records its *span*, the range of bytes of the source it was parsed from, and
+//! errors point at the part of the source they were found in.
+//!
+//! ## Example
+//!
+//! ```
+//! # use dragon::types::{infer::*, Type};
+//! let mut env = Environment::new();
+//! env.bind(
+//!     "add",
+//!     Scheme::mono(Type::function(
+//!         vec![Type::INT],
+//!         Type::function(vec![Type::INT], Type::INT),
+//!     )),
+//! );
+//!
+//! let check = |src: &str| -> Result<String, String> {
+//!     let expr: Expr = src.parse().map_err(|e: Error| e.render(src))?;
+//!     infer(&env, &expr)
+//!         .map(|scheme| scheme.to_string())
+//!         .map_err(|e| e.render(src))
+//! };
+//!
+//! assert_eq!(check("fun f x -> f (f x)"), Ok("('a -> 'a) -> 'a -> 'a".to_string()));
+//! assert_eq!(check("fun x y -> x").unwrap(), "'a -> 'b -> 'a");
+//! assert_eq!(
+//!     check("let id = fun x -> x in if id true then id 1 else add 2 3").unwrap(),
+//!     "int",
+//! );
+//!
+//! assert_eq!(
+//!     check("let f = fun x -> add x 1 in f true").unwrap_err(),
+//!     "1:31: type mismatch: expected `int`, found `bool`\n\
+//!      let f = fun x -> add x 1 in f true\n\
+//!      \x20                             ^^^^",
+//! );
+//! assert_eq!(
+//!     check("fun x -> x x").unwrap_err(),
+//!     "1:10: infinite type: `'a` would be `'a -> 'b`\n\
+//!      fun x -> x x\n\
+//!      \x20        ^",
+//! );
+//! assert_eq!(
+//!     check("if 1 then x else 2").unwrap_err(),
+//!     "1:4: type mismatch: expected `bool`, found `int`\n\
+//!      if 1 then x else 2\n\
+//!      \x20  ^",
+//! );
+//! assert_eq!(
+//!     check("let y = 1 in\nadd y z").unwrap_err(),
+//!     "2:7: unbound variable `z`\n\
+//!      add y z\n\
+//!      \x20     ^",
+//! );
+//! assert_eq!(
+//!     check("fun x -> (x").unwrap_err(),
+//!     "1:12: expected `)`, found end of input\n\
+//!      fun x -> (x\n\
+//!      \x20          ^",
+//! );
+//! ```
+
+use {
+    super::{Substitution, Type, UnifyError},
+    std::{
+        collections::{BTreeSet, HashMap},
+        fmt,
+        iter::Peekable,
+        str::{CharIndices, FromStr},
+    },
+};
+
+/// A range of bytes in the source.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    /// The smallest span covering two spans.
+    pub fn to(self, other: Self) -> Self {
+        Self {
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+        }
+    }
+}
+
+/// An expression, and the span of the source it was parsed from.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+/// The forms of expressions.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExprKind {
+    Int(i64),
+    Bool(bool),
+    Var(String),
+    /// A function of one parameter.
+    Lambda(String, Box<Expr>),
+    /// A function, and its argument.
+    Apply(Box<Expr>, Box<Expr>),
+    /// A variable, its value, and the expression it is bound in.
+    Let(String, Box<Expr>, Box<Expr>),
+    /// A condition, and the expressions for when it is true and false.
+    If(Box<Expr>, Box<Expr>, Box<Expr>),
+}
+
+const KEYWORDS: [&str; 8] = ["fun", "let", "in", "if", "then", "else", "true", "false"];
+
+impl FromStr for Expr {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            src: s,
+            chars: s.char_indices().peekable(),
+            next: None,
+        };
+        let expr = parser.expr()?;
+        match parser.peek()? {
+            (Tok::End, _) => Ok(expr),
+            (tok, span) => Err(Error::syntax(format!("unexpected {}", tok), span)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Tok<'s> {
+    Ident(&'s str),
+    Int(i64),
+    Punct(&'s str),
+    End,
+}
+
+impl fmt::Display for Tok<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ident(s) | Self::Punct(s) => write!(f, "`{}`", s),
+            Self::Int(n) => write!(f, "`{}`", n),
+            Self::End => write!(f, "end of input"),
+        }
+    }
+}
+
+/// A recursive-descent parser for expressions, reading one token ahead.
+struct Parser<'s> {
+    src: &'s str,
+    chars: Peekable<CharIndices<'s>>,
+    next: Option<(Tok<'s>, Span)>,
+}
+
+impl<'s> Parser<'s> {
+    fn peek(&mut self) -> Result<(Tok<'s>, Span), Error> {
+        if self.next.is_none() {
+            self.next = Some(self.lex()?);
+        }
+        Ok(self.next.unwrap())
+    }
+
+    fn advance(&mut self) -> Result<(Tok<'s>, Span), Error> {
+        let token = self.peek()?;
+        self.next = None;
+        Ok(token)
+    }
+
+    fn lex(&mut self) -> Result<(Tok<'s>, Span), Error> {
+        while let Some(&(_, c)) = self.chars.peek() {
+            if !c.is_whitespace() {
+                break;
+            }
+            self.chars.next();
+        }
+
+        let (start, c) = match self.chars.next() {
+            Some(next) => next,
+            None => {
+                let end = self.src.len();
+                return Ok((Tok::End, Span { start: end, end }));
+            }
+        };
+
+        let mut end = start + c.len_utf8();
+        let mut take_while = |chars: &mut Peekable<CharIndices<'s>>, f: fn(char) -> bool| {
+            while let Some(&(i, c)) = chars.peek() {
+                if !f(c) {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+        };
+
+        let tok = match c {
+            c if c.is_alphabetic() || c == '_' => {
+                take_while(&mut self.chars, |c| {
+                    c.is_alphanumeric() || c == '_' || c == '\''
+                });
+                Tok::Ident(&self.src[start..end])
+            }
+            '0'..='9' => {
+                take_while(&mut self.chars, |c| c.is_ascii_digit());
+                match self.src[start..end].parse() {
+                    Ok(n) => Tok::Int(n),
+                    Err(_) => {
+                        return Err(Error::syntax(
+                            "integer literal is too large".to_string(),
+                            Span { start, end },
+                        ))
+                    }
+                }
+            }
+            '-' if self.chars.peek().map(|&(_, c)| c) == Some('>') => {
+                self.chars.next();
+                end += 1;
+                Tok::Punct("->")
+            }
+            '(' | ')' | '=' => Tok::Punct(&self.src[start..end]),
+            c => {
+                return Err(Error::syntax(
+                    format!("unexpected character `{}`", c),
+                    Span { start, end },
+                ))
+            }
+        };
+
+        Ok((tok, Span { start, end }))
+    }
+
+    fn expect(&mut self, punct: &str) -> Result<Span, Error> {
+        match self.advance()? {
+            (Tok::Punct(p), span) | (Tok::Ident(p), span) if p == punct => Ok(span),
+            (tok, span) => Err(Error::syntax(
+                format!("expected `{}`, found {}", punct, tok),
+                span,
+            )),
+        }
+    }
+
+    fn ident(&mut self) -> Result<(String, Span), Error> {
+        match self.advance()? {
+            (Tok::Ident(name), span) if !KEYWORDS.contains(&name) => Ok((name.to_string(), span)),
+            (tok, span) => Err(Error::syntax(
+                format!("expected a variable, found {}", tok),
+                span,
+            )),
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, Error> {
+        let (tok, start) = self.peek()?;
+
+        match tok {
+            Tok::Ident("fun") => {
+                self.advance()?;
+                let mut params = vec![self.ident()?.0];
+                while let (Tok::Ident(name), _) = self.peek()? {
+                    if KEYWORDS.contains(&name) {
+                        break;
+                    }
+                    params.push(self.ident()?.0);
+                }
+                self.expect("->")?;
+
+                let body = self.expr()?;
+                let span = start.to(body.span);
+                Ok(params.into_iter().rev().fold(body, |body, param| Expr {
+                    kind: ExprKind::Lambda(param, Box::new(body)),
+                    span,
+                }))
+            }
+            Tok::Ident("let") => {
+                self.advance()?;
+                let (name, _) = self.ident()?;
+                self.expect("=")?;
+                let value = self.expr()?;
+                self.expect("in")?;
+                let body = self.expr()?;
+                Ok(Expr {
+                    span: start.to(body.span),
+                    kind: ExprKind::Let(name, Box::new(value), Box::new(body)),
+                })
+            }
+            Tok::Ident("if") => {
+                self.advance()?;
+                let condition = self.expr()?;
+                self.expect("then")?;
+                let then = self.expr()?;
+                self.expect("else")?;
+                let otherwise = self.expr()?;
+                Ok(Expr {
+                    span: start.to(otherwise.span),
+                    kind: ExprKind::If(Box::new(condition), Box::new(then), Box::new(otherwise)),
+                })
+            }
+            _ => {
+                let mut function = self.atom()?;
+                while self.starts_atom()? {
+                    let argument = self.atom()?;
+                    function = Expr {
+                        span: function.span.to(argument.span),
+                        kind: ExprKind::Apply(Box::new(function), Box::new(argument)),
+                    };
+                }
+                Ok(function)
+            }
+        }
+    }
+
+    fn starts_atom(&mut self) -> Result<bool, Error> {
+        Ok(match self.peek()?.0 {
+            Tok::Ident(name) => !KEYWORDS.contains(&name) || name == "true" || name == "false",
+            Tok::Int(_) | Tok::Punct("(") => true,
+            _ => false,
+        })
+    }
+
+    fn atom(&mut self) -> Result<Expr, Error> {
+        let (tok, span) = self.advance()?;
+        let kind = match tok {
+            Tok::Int(n) => ExprKind::Int(n),
+            Tok::Ident("true") => ExprKind::Bool(true),
+            Tok::Ident("false") => ExprKind::Bool(false),
+            Tok::Ident(name) if !KEYWORDS.contains(&name) => ExprKind::Var(name.to_string()),
+            Tok::Punct("(") => {
+                let inner = self.expr()?;
+                let end = self.expect(")")?;
+                return Ok(Expr {
+                    kind: inner.kind,
+                    span: span.to(end),
+                });
+            }
+            tok => {
+                return Err(Error::syntax(
+                    format!("expected an expression, found {}", tok),
+                    span,
+                ))
+            }
+        };
+        Ok(Expr { kind, span })
+    }
+}
+
+/// A type in which some type variables stand for any type.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Scheme {
+    /// The type variables that are generalized.
+    pub variables: Vec<usize>,
+    pub body: Type,
+}
+
+impl Scheme {
+    /// A type with no generalized type variables.
+    pub fn mono(body: Type) -> Self {
+        Self {
+            variables: Vec::new(),
+            body,
+        }
+    }
+
+    /// A type in which every type variable is generalized.
+    pub fn poly(body: Type) -> Self {
+        Self {
+            variables: body.variables(),
+            body,
+        }
+    }
+}
+
+impl fmt::Display for Scheme {
+    /// Writes the body, with the type variables renamed to `'a`, `'b`, ... in order. Generalized
+    /// variables are not marked.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", normalize(&[&self.body])[0])
+    }
+}
+
+/// The types of the variables in scope.
+#[derive(Clone, Debug, Default)]
+pub struct Environment {
+    schemes: HashMap<String, Scheme>,
+}
+
+impl Environment {
+    /// No variables.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Give a type to a variable, returning the type it had before, if any.
+    pub fn bind(&mut self, name: &str, scheme: Scheme) -> Option<Scheme> {
+        self.schemes.insert(name.to_string(), scheme)
+    }
+
+    /// The type of a variable.
+    pub fn get(&self, name: &str) -> Option<&Scheme> {
+        self.schemes.get(name)
+    }
+}
+
+/// Find the most general type of an expression, given the types of its free variables.
+///
+/// The type variables of the result are all generalized, and numbered from 0 in the order in
+/// which they appear.
+pub fn infer(env: &Environment, expr: &Expr) -> Result<Scheme, Error> {
+    let next = env
+        .schemes
+        .values()
+        .flat_map(|scheme| scheme.body.variables())
+        .max()
+        .map_or(0, |v| v + 1);
+    let mut inference = Inference {
+        substitution: Substitution::new(),
+        next,
+    };
+
+    let mut env = env.clone();
+    let t = inference.infer(&mut env, expr)?;
+    let t = inference.substitution.apply(&t);
+    Ok(Scheme::poly(normalize(&[&t]).remove(0)))
+}
+
+/// The state of Algorithm W: the substitution found so far, and the number of the next fresh
+/// type variable.
+struct Inference {
+    substitution: Substitution,
+    next: usize,
+}
+
+impl Inference {
+    fn fresh(&mut self) -> Type {
+        self.next += 1;
+        Type::Var(self.next - 1)
+    }
+
+    fn infer(&mut self, env: &mut Environment, expr: &Expr) -> Result<Type, Error> {
+        match &expr.kind {
+            ExprKind::Int(_) => Ok(Type::INT),
+            ExprKind::Bool(_) => Ok(Type::BOOL),
+            ExprKind::Var(name) => match env.get(name) {
+                Some(scheme) => Ok(self.instantiate(&scheme.clone())),
+                None => Err(Error {
+                    kind: ErrorKind::Unbound(name.clone()),
+                    span: expr.span,
+                }),
+            },
+            ExprKind::Lambda(param, body) => {
+                let t = self.fresh();
+                let result = self.scoped(env, param, Scheme::mono(t.clone()), |this, env| {
+                    this.infer(env, body)
+                })?;
+                Ok(Type::function(vec![t], result))
+            }
+            ExprKind::Apply(function, argument) => {
+                let f = self.infer(env, function)?;
+                let a = self.infer(env, argument)?;
+                let result = self.fresh();
+
+                // Blame the argument when the function's parameter type is known.
+                if let Type::Function(params, _) = self.substitution.apply(&f) {
+                    if params.len() == 1 {
+                        self.unify(&params[0], &a, argument.span)?;
+                    }
+                }
+
+                let expected = Type::function(vec![a], result.clone());
+                self.unify(&expected, &f, function.span)?;
+                Ok(result)
+            }
+            ExprKind::Let(name, value, body) => {
+                let t = self.infer(env, value)?;
+                let scheme = self.generalize(env, &t);
+                self.scoped(env, name, scheme, |this, env| this.infer(env, body))
+            }
+            ExprKind::If(condition, then, otherwise) => {
+                let c = self.infer(env, condition)?;
+                self.unify(&Type::BOOL, &c, condition.span)?;
+                let t = self.infer(env, then)?;
+                let u = self.infer(env, otherwise)?;
+                self.unify(&t, &u, otherwise.span)?;
+                Ok(t)
+            }
+        }
+    }
+
+    /// Infer a type with a variable bound, restoring the variable's previous type afterwards.
+    fn scoped(
+        &mut self,
+        env: &mut Environment,
+        name: &str,
+        scheme: Scheme,
+        f: impl FnOnce(&mut Self, &mut Environment) -> Result<Type, Error>,
+    ) -> Result<Type, Error> {
+        let previous = env.bind(name, scheme);
+        let result = f(self, env);
+        match previous {
+            Some(previous) => env.bind(name, previous),
+            None => env.schemes.remove(name),
+        };
+        result
+    }
+
+    /// Unify the type an expression should have with the type it was found to have.
+    fn unify(&mut self, expected: &Type, found: &Type, span: Span) -> Result<(), Error> {
+        self.substitution.unify(expected, found).map_err(|error| {
+            let kind = match error {
+                UnifyError::Mismatch(..) => {
+                    let expected = self.substitution.apply(expected);
+                    let found = self.substitution.apply(found);
+                    let mut types = normalize(&[&expected, &found]);
+                    let found = types.pop().unwrap();
+                    ErrorKind::Mismatch(types.pop().unwrap(), found)
+                }
+                UnifyError::Occurs(v, t) => {
+                    let mut types = normalize(&[&Type::Var(v), &t]);
+                    let t = types.pop().unwrap();
+                    ErrorKind::Infinite(types.pop().unwrap(), t)
+                }
+            };
+            Error { kind, span }
+        })
+    }
+
+    /// Give fresh type variables to the generalized variables of a scheme.
+    fn instantiate(&mut self, scheme: &Scheme) -> Type {
+        let mut fresh = Substitution::new();
+        for &v in &scheme.variables {
+            fresh.bind(v, self.fresh());
+        }
+        fresh.apply(&scheme.body)
+    }
+
+    /// Generalize the type variables of a type that are not free in the environment.
+    fn generalize(&self, env: &Environment, t: &Type) -> Scheme {
+        let t = self.substitution.apply(t);
+        let free = env
+            .schemes
+            .values()
+            .flat_map(|scheme| {
+                let body = self.substitution.apply(&scheme.body);
+                body.variables()
+                    .into_iter()
+                    .filter(|v| !scheme.variables.contains(v))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<BTreeSet<_>>();
+
+        Scheme {
+            variables: t
+                .variables()
+                .into_iter()
+                .filter(|v| !free.contains(v))
+                .collect(),
+            body: t,
+        }
+    }
+}
+
+/// Rename the type variables of some types to 0, 1, ... in the order they first appear.
+fn normalize(types: &[&Type]) -> Vec<Type> {
+    let mut variables = Vec::new();
+    for t in types {
+        for v in t.variables() {
+            if !variables.contains(&v) {
+                variables.push(v);
+            }
+        }
+    }
+
+    let mut renaming = Substitution::new();
+    for (i, v) in variables.into_iter().enumerate() {
+        renaming.bind(v, Type::Var(i));
+    }
+    // Not `apply`, which would rename again a variable renamed to the number of another one.
+    types.iter().map(|t| rename(&renaming, t)).collect()
+}
+
+fn rename(renaming: &Substitution, t: &Type) -> Type {
+    match t {
+        Type::Var(v) => renaming.get(*v).cloned().unwrap_or(Type::Var(*v)),
+        Type::Basic(_) | Type::Name(_) => t.clone(),
+        Type::Array(len, element) => Type::array(*len, rename(renaming, element)),
+        Type::Pointer(target) => Type::pointer(rename(renaming, target)),
+        Type::Function(arguments, result) => Type::function(
+            arguments.iter().map(|t| rename(renaming, t)).collect(),
+            rename(renaming, result),
+        ),
+        Type::Record(fields) => Type::Record(
+            fields
+                .iter()
+                .map(|(name, t)| (name.clone(), rename(renaming, t)))
+                .collect(),
+        ),
+    }
+}
+
+/// An error in an expression, and where it was found.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Error {
+    pub kind: ErrorKind,
+    pub span: Span,
+}
+
+/// Reasons an expression has no type.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ErrorKind {
+    /// The source is not an expression.
+    Syntax(String),
+    /// A variable is not in scope.
+    Unbound(String),
+    /// A part of the expression was expected to have one type, but has another.
+    Mismatch(Type, Type),
+    /// A type variable would have to stand for a type that contains it.
+    Infinite(Type, Type),
+}
+
+impl Error {
+    fn syntax(message: String, span: Span) -> Self {
+        Self {
+            kind: ErrorKind::Syntax(message),
+            span,
+        }
+    }
+
+    /// Describe this error.
+    pub fn message(&self) -> String {
+        match &self.kind {
+            ErrorKind::Syntax(message) => message.clone(),
+            ErrorKind::Unbound(name) => format!("unbound variable `{}`", name),
+            ErrorKind::Mismatch(expected, found) => {
+                format!("type mismatch: expected `{}`, found `{}`", expected, found)
+            }
+            ErrorKind::Infinite(v, t) => format!("infinite type: `{}` would be `{}`", v, t),
+        }
+    }
+
+    /// Describe this error with its line and column in the source, followed by the line it is on
+    /// with the span underlined.
+    pub fn render(&self, src: &str) -> String {
+        let line_start = src[..self.span.start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = src[line_start..]
+            .find('\n')
+            .map_or(src.len(), |i| line_start + i);
+        let line = src[..line_start].matches('\n').count() + 1;
+        let column = src[line_start..self.span.start].chars().count() + 1;
+        let width = src[self.span.start..self.span.end.min(line_end)]
+            .chars()
+            .count()
+            .max(1);
+
+        format!(
+            "{}:{}: {}\n{}\n{}{}",
+            line,
+            column,
+            self.message(),
+            &src[line_start..line_end],
+            " ".repeat(column - 1),
+            "^".repeat(width),
+        )
+    }
+}